@@ -0,0 +1,55 @@
+use std::{env, fs, path::Path};
+
+/// Embeds the up and down scripts of all migrations, so that binaries can run and revert them
+/// without access to the migrations directory
+///
+/// The migrations of the SQLite backend live in a directory of their own.
+fn main() {
+    embed(
+        "migrations",
+        "EMBEDDED_MIGRATIONS",
+        "embedded_migrations.rs",
+    );
+    embed(
+        "migrations_sqlite",
+        "EMBEDDED_SQLITE_MIGRATIONS",
+        "embedded_sqlite_migrations.rs",
+    );
+}
+
+fn embed(directory: &str, static_name: &str, file_name: &str) {
+    let migrations_dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join(directory);
+    println!("cargo:rerun-if-changed={}", migrations_dir.display());
+
+    let mut names: Vec<String> = fs::read_dir(&migrations_dir)
+        .expect("migrations directory")
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("up.sql").exists())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    let mut generated = format!("pub static {}: &[Migration] = &[\n", static_name);
+    for name in names {
+        let directory = migrations_dir.join(&name);
+        println!("cargo:rerun-if-changed={}", directory.display());
+        let version: String = name
+            .split('_')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+        generated.push_str(&format!(
+            "    Migration {{ version: {:?}, name: {:?}, up_sql: include_str!({:?}), down_sql: include_str!({:?}) }},\n",
+            version,
+            name,
+            directory.join("up.sql"),
+            directory.join("down.sql"),
+        ));
+    }
+    generated.push_str("];\n");
+
+    let out_file = Path::new(&env::var("OUT_DIR").unwrap()).join(file_name);
+    fs::write(out_file, generated).expect("generated migrations");
+}
@@ -0,0 +1,6 @@
+create table api_key(
+    id serial primary key,
+    key_hash varchar(64) unique not null,
+    role varchar not null check (role in ('viewer', 'clerk', 'admin')),
+    description text not null
+);
\ No newline at end of file
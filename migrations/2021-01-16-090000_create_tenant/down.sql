@@ -0,0 +1,36 @@
+drop policy tenant_isolation on warehouse;
+alter table warehouse no force row level security;
+alter table warehouse disable row level security;
+alter table warehouse drop column tenant_id;
+
+drop policy tenant_isolation on inventory_item;
+alter table inventory_item no force row level security;
+alter table inventory_item disable row level security;
+alter table inventory_item drop column tenant_id;
+
+drop policy tenant_isolation on product_category_rollup;
+alter table product_category_rollup no force row level security;
+alter table product_category_rollup disable row level security;
+alter table product_category_rollup drop column tenant_id;
+
+drop policy tenant_isolation on product_category_classification;
+alter table product_category_classification no force row level security;
+alter table product_category_classification disable row level security;
+alter table product_category_classification drop column tenant_id;
+
+drop policy tenant_isolation on product;
+alter table product no force row level security;
+alter table product disable row level security;
+alter table product drop column tenant_id;
+
+drop policy tenant_isolation on product_category;
+alter table product_category no force row level security;
+alter table product_category disable row level security;
+alter table product_category drop constraint product_category_tenant_id_name_key;
+alter table product_category drop column tenant_id;
+alter table product_category add constraint product_category_name_key unique (name);
+
+alter table api_key drop column tenant_id;
+
+drop function current_tenant_id();
+drop table tenant;
@@ -0,0 +1,63 @@
+create table tenant(
+    id serial primary key,
+    name varchar unique not null
+);
+
+insert into tenant(name) values ('default');
+
+-- The tenant of the current session. It is set by the application before serving a request and
+-- used both as the default tenant of inserted rows and by the row level security policies.
+create function current_tenant_id() returns integer as $$
+    select nullif(current_setting('app.tenant_id', true), '')::integer
+$$ language sql stable;
+
+alter table api_key add column tenant_id integer references tenant(id) on delete cascade;
+update api_key set tenant_id = 1;
+alter table api_key alter column tenant_id set not null;
+
+alter table product_category add column tenant_id integer not null default 1 references tenant(id) on delete restrict;
+alter table product_category alter column tenant_id set default current_tenant_id();
+alter table product_category drop constraint product_category_name_key;
+alter table product_category add constraint product_category_tenant_id_name_key unique (tenant_id, name);
+
+alter table product add column tenant_id integer not null default 1 references tenant(id) on delete restrict;
+alter table product alter column tenant_id set default current_tenant_id();
+
+alter table product_category_classification add column tenant_id integer not null default 1 references tenant(id) on delete restrict;
+alter table product_category_classification alter column tenant_id set default current_tenant_id();
+
+alter table product_category_rollup add column tenant_id integer not null default 1 references tenant(id) on delete restrict;
+alter table product_category_rollup alter column tenant_id set default current_tenant_id();
+
+alter table inventory_item add column tenant_id integer not null default 1 references tenant(id) on delete restrict;
+alter table inventory_item alter column tenant_id set default current_tenant_id();
+
+alter table warehouse add column tenant_id integer not null default 1 references tenant(id) on delete restrict;
+alter table warehouse alter column tenant_id set default current_tenant_id();
+
+-- Row level security is forced so that it applies to the owner of the tables as well. Note that
+-- superusers and roles with the BYPASSRLS attribute are still exempt, so the application has to
+-- connect with an ordinary role.
+alter table product_category enable row level security;
+alter table product_category force row level security;
+create policy tenant_isolation on product_category using (tenant_id = current_tenant_id());
+
+alter table product enable row level security;
+alter table product force row level security;
+create policy tenant_isolation on product using (tenant_id = current_tenant_id());
+
+alter table product_category_classification enable row level security;
+alter table product_category_classification force row level security;
+create policy tenant_isolation on product_category_classification using (tenant_id = current_tenant_id());
+
+alter table product_category_rollup enable row level security;
+alter table product_category_rollup force row level security;
+create policy tenant_isolation on product_category_rollup using (tenant_id = current_tenant_id());
+
+alter table inventory_item enable row level security;
+alter table inventory_item force row level security;
+create policy tenant_isolation on inventory_item using (tenant_id = current_tenant_id());
+
+alter table warehouse enable row level security;
+alter table warehouse force row level security;
+create policy tenant_isolation on warehouse using (tenant_id = current_tenant_id());
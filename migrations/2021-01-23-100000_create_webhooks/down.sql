@@ -0,0 +1,5 @@
+drop trigger queue_webhook_deliveries on outbox_event;
+drop function queue_webhook_deliveries();
+drop table webhook_delivery;
+drop table outbox_event;
+drop table webhook_subscription;
@@ -0,0 +1,59 @@
+create table webhook_subscription(
+    id serial primary key,
+    url text not null,
+    event_types text[] not null,
+    secret text not null,
+    active boolean not null default true,
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete cascade
+);
+
+-- Domain events are written in the same transaction as the change they describe
+create table outbox_event(
+    id bigserial primary key,
+    event_type varchar not null,
+    payload jsonb not null,
+    created_at timestamptz not null default now(),
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete cascade
+);
+
+create table webhook_delivery(
+    id bigserial primary key,
+    outbox_event_id bigint not null references outbox_event(id) on delete cascade,
+    webhook_subscription_id integer not null references webhook_subscription(id) on delete cascade,
+    status varchar not null default 'pending' check (status in ('pending', 'delivered', 'dead')),
+    attempts integer not null default 0,
+    next_attempt_at timestamptz not null default now(),
+    last_error text,
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete cascade
+);
+
+create index webhook_delivery_pending_idx on webhook_delivery(next_attempt_at) where status = 'pending';
+
+-- Every event is queued for delivery to the subscriptions interested in it, within the
+-- transaction which records the event
+create function queue_webhook_deliveries() returns trigger as $$
+begin
+    insert into webhook_delivery(outbox_event_id, webhook_subscription_id, tenant_id)
+    select new.id, subscription.id, new.tenant_id
+    from webhook_subscription subscription
+    where subscription.tenant_id = new.tenant_id
+        and subscription.active
+        and new.event_type = any(subscription.event_types);
+    return new;
+end;
+$$ language plpgsql;
+
+create trigger queue_webhook_deliveries after insert on outbox_event
+    for each row execute procedure queue_webhook_deliveries();
+
+alter table webhook_subscription enable row level security;
+alter table webhook_subscription force row level security;
+create policy tenant_isolation on webhook_subscription using (tenant_id = current_tenant_id());
+
+alter table outbox_event enable row level security;
+alter table outbox_event force row level security;
+create policy tenant_isolation on outbox_event using (tenant_id = current_tenant_id());
+
+alter table webhook_delivery enable row level security;
+alter table webhook_delivery force row level security;
+create policy tenant_isolation on webhook_delivery using (tenant_id = current_tenant_id());
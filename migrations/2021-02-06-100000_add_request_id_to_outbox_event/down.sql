@@ -0,0 +1,3 @@
+alter table outbox_event drop column request_id;
+
+drop function current_request_id();
@@ -0,0 +1,7 @@
+-- The request of the current session, set by the application while serving a request, so that
+-- every event can be traced back to the request which caused it.
+create function current_request_id() returns varchar as $$
+    select nullif(current_setting('app.request_id', true), '')
+$$ language sql stable;
+
+alter table outbox_event add column request_id varchar default current_request_id();
@@ -0,0 +1,18 @@
+-- Responses of requests made with an Idempotency-Key header, which are replayed when a client
+-- repeats the request. The response columns are null while the first request is in progress.
+create table idempotency_key(
+    key varchar not null,
+    fingerprint varchar not null,
+    response_status smallint,
+    response_content_type varchar,
+    response_body bytea,
+    created_at timestamptz not null default now(),
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete cascade,
+    primary key (tenant_id, key)
+);
+
+create index idempotency_key_created_at_idx on idempotency_key(created_at);
+
+alter table idempotency_key enable row level security;
+alter table idempotency_key force row level security;
+create policy tenant_isolation on idempotency_key using (tenant_id = current_tenant_id());
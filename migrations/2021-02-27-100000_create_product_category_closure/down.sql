@@ -0,0 +1,13 @@
+drop trigger product_category_rollup_closure_truncate on product_category_rollup;
+drop trigger product_category_rollup_closure_delete on product_category_rollup;
+drop trigger product_category_rollup_closure_change on product_category_rollup;
+drop trigger product_category_rollup_closure_insert on product_category_rollup;
+drop trigger product_category_closure_insert on product_category;
+drop function rebuild_product_category_closure_trigger();
+drop function move_product_category_rollup_in_closure();
+drop function remove_product_category_rollups_from_closure();
+drop function add_product_category_rollup_to_closure();
+drop function add_product_category_to_closure();
+drop function rebuild_product_category_closure();
+drop function refresh_product_category_closure(integer[]);
+drop table product_category_closure;
@@ -0,0 +1,131 @@
+-- Every category paired with itself and each of its descendants. The depth is the number of
+-- rollup links on the shortest path from the ancestor down to the descendant, so that ancestors
+-- and descendants of any depth are found with a single lookup instead of walking the hierarchy.
+create table product_category_closure(
+    ancestor_id integer not null references product_category(id) on delete cascade,
+    descendant_id integer not null references product_category(id) on delete cascade,
+    depth integer not null,
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete restrict,
+    primary key (ancestor_id, descendant_id)
+);
+
+create index product_category_closure_descendant_id_idx on product_category_closure(descendant_id);
+
+alter table product_category_closure enable row level security;
+alter table product_category_closure force row level security;
+create policy tenant_isolation on product_category_closure using (tenant_id = current_tenant_id());
+
+-- Recomputes the paths from the given categories to their descendants breadth first. Each level
+-- only adds the pairs which no shorter path connects yet, so every pair is found once at its
+-- shortest depth, and the work grows with the number of pairs instead of the number of paths.
+-- Cycles in the hierarchy end the expansion as well, as they only lead to known pairs.
+create function refresh_product_category_closure(ancestor_ids integer[]) returns void as $$
+declare
+    current_depth integer := 0;
+    found integer;
+begin
+    delete from product_category_closure
+     where ancestor_id = any(ancestor_ids)
+       and depth > 0;
+    loop
+        insert into product_category_closure(ancestor_id, descendant_id, depth, tenant_id)
+        select distinct path.ancestor_id, rollup.lower_category_id, current_depth + 1,
+               path.tenant_id
+          from product_category_closure path
+          join product_category_rollup rollup on rollup.upper_category_id = path.descendant_id
+         where path.ancestor_id = any(ancestor_ids)
+           and path.depth = current_depth
+           and not exists (select 1
+                             from product_category_closure shorter
+                            where shorter.ancestor_id = path.ancestor_id
+                              and shorter.descendant_id = rollup.lower_category_id);
+        get diagnostics found = row_count;
+        exit when found = 0;
+        current_depth := current_depth + 1;
+    end loop;
+end
+$$ language plpgsql volatile;
+
+create function rebuild_product_category_closure() returns void as $$
+    select refresh_product_category_closure(array(select id from product_category));
+$$ language sql volatile;
+
+create function add_product_category_to_closure() returns trigger as $$
+begin
+    insert into product_category_closure(ancestor_id, descendant_id, depth, tenant_id)
+    values (new.id, new.id, 0, new.tenant_id);
+    return null;
+end
+$$ language plpgsql;
+
+-- A new link connects every ancestor of the upper category with every descendant of the lower
+-- one, which keeps the shorter of an existing and the new path.
+create function add_product_category_rollup_to_closure() returns trigger as $$
+begin
+    insert into product_category_closure(ancestor_id, descendant_id, depth, tenant_id)
+    select upper.ancestor_id, lower.descendant_id, upper.depth + 1 + lower.depth, new.tenant_id
+      from product_category_closure upper, product_category_closure lower
+     where upper.descendant_id = new.upper_category_id
+       and lower.ancestor_id = new.lower_category_id
+    on conflict (ancestor_id, descendant_id)
+    do update set depth = least(product_category_closure.depth, excluded.depth);
+    return null;
+end
+$$ language plpgsql;
+
+-- Removed links only shorten or cut paths from the ancestors of their upper categories, so the
+-- paths of those ancestors are recomputed and the rest of the closure is kept.
+create function remove_product_category_rollups_from_closure() returns trigger as $$
+begin
+    perform refresh_product_category_closure(array(
+        select distinct closure.ancestor_id
+          from product_category_closure closure
+          join removed_rollups removed on removed.upper_category_id = closure.descendant_id));
+    return null;
+end
+$$ language plpgsql;
+
+-- A moved link is removed from the ancestors of its former upper category and added to those of
+-- the new one, whose paths are recomputed alike.
+create function move_product_category_rollup_in_closure() returns trigger as $$
+begin
+    perform refresh_product_category_closure(array(
+        select distinct ancestor_id
+          from product_category_closure
+         where descendant_id in (old.upper_category_id, new.upper_category_id)));
+    return null;
+end
+$$ language plpgsql;
+
+create function rebuild_product_category_closure_trigger() returns trigger as $$
+begin
+    perform rebuild_product_category_closure();
+    return null;
+end
+$$ language plpgsql;
+
+create trigger product_category_closure_insert
+    after insert on product_category
+    for each row execute procedure add_product_category_to_closure();
+
+create trigger product_category_rollup_closure_insert
+    after insert on product_category_rollup
+    for each row execute procedure add_product_category_rollup_to_closure();
+
+-- Other columns of the links, such as the positions among siblings, leave the closure as it is
+create trigger product_category_rollup_closure_change
+    after update of upper_category_id, lower_category_id on product_category_rollup
+    for each row execute procedure move_product_category_rollup_in_closure();
+
+create trigger product_category_rollup_closure_delete
+    after delete on product_category_rollup
+    referencing old table as removed_rollups
+    for each statement execute procedure remove_product_category_rollups_from_closure();
+
+create trigger product_category_rollup_closure_truncate
+    after truncate on product_category_rollup
+    for each statement execute procedure rebuild_product_category_closure_trigger();
+
+insert into product_category_closure(ancestor_id, descendant_id, depth, tenant_id)
+select id, id, 0, tenant_id from product_category;
+select rebuild_product_category_closure();
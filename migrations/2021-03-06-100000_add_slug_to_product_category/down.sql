@@ -0,0 +1 @@
+alter table product_category drop column slug;
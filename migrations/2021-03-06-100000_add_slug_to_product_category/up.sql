@@ -0,0 +1,19 @@
+-- Slugs are chosen by the application, which keeps them unique among the categories with the
+-- same parent. Existing categories get the slug of their name, numbered where names only differ
+-- in punctuation or case.
+alter table product_category add column slug varchar;
+
+with slugs as (
+    select id, tenant_id,
+           coalesce(nullif(trim(both '-' from regexp_replace(lower(name), '[^[:alnum:]]+', '-', 'g')), ''), 'category') as base
+      from product_category
+), numbered as (
+    select id, base, row_number() over (partition by tenant_id, base order by id) as number
+      from slugs
+)
+update product_category
+   set slug = case when numbered.number = 1 then numbered.base else numbered.base || '-' || numbered.number end
+  from numbered
+ where numbered.id = product_category.id;
+
+alter table product_category alter column slug set not null;
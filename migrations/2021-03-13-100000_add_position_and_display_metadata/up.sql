@@ -0,0 +1,16 @@
+-- Subcategories are shown in the order of the positions of their links. New links are appended
+-- by the application, existing ones keep the order in which they were created.
+alter table product_category_rollup add column position integer not null default 0;
+
+update product_category_rollup
+   set position = ordered.position
+  from (select id, row_number() over (partition by upper_category_id order by id) - 1 as position
+          from product_category_rollup) ordered
+ where ordered.id = product_category_rollup.id;
+
+create index product_category_rollup_upper_category_id_position_idx
+    on product_category_rollup(upper_category_id, position);
+
+alter table product_category add column description text;
+alter table product_category add column image_ref varchar;
+alter table product_category add column is_visible boolean not null default true;
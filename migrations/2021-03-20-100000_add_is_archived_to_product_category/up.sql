@@ -0,0 +1,2 @@
+-- Categories merged into others are archived instead of deleted, so that their ids stay known
+alter table product_category add column is_archived boolean not null default false;
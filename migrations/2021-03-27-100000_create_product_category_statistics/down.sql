@@ -0,0 +1,2 @@
+drop table product_category_statistics_stock;
+drop table product_category_statistics;
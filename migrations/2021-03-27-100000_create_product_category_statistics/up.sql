@@ -0,0 +1,30 @@
+-- Snapshot of the product counts and stock of every category, which is read instead of the live
+-- aggregates by the cached statistics endpoints. It is replaced as a whole when refreshed.
+create table product_category_statistics(
+    category_id integer primary key references product_category(id) on delete cascade,
+    direct_products bigint not null,
+    products bigint not null,
+    refreshed_at timestamptz not null default now(),
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete restrict
+);
+
+-- Units in stock of the products of a category and its subcategories, per warehouse. Items
+-- without a warehouse are counted in a row without one.
+create table product_category_statistics_stock(
+    id serial primary key,
+    category_id integer not null references product_category_statistics(category_id) on delete cascade,
+    warehouse_id integer references warehouse(id) on delete cascade,
+    quantity bigint not null,
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete restrict
+);
+
+create index product_category_statistics_stock_category_id_idx
+    on product_category_statistics_stock(category_id);
+
+alter table product_category_statistics enable row level security;
+alter table product_category_statistics force row level security;
+create policy tenant_isolation on product_category_statistics using (tenant_id = current_tenant_id());
+
+alter table product_category_statistics_stock enable row level security;
+alter table product_category_statistics_stock force row level security;
+create policy tenant_isolation on product_category_statistics_stock using (tenant_id = current_tenant_id());
@@ -0,0 +1,26 @@
+-- Names of the categories and descriptions of the products in other locales than the default
+-- one, whose texts are kept in the tables themselves. Names are unique within a locale.
+create table product_category_translation(
+    category_id integer not null references product_category(id) on delete cascade,
+    locale varchar(35) not null,
+    name varchar not null,
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete restrict,
+    primary key (category_id, locale),
+    unique (tenant_id, locale, name)
+);
+
+create table product_translation(
+    product_id integer not null references product(id) on delete cascade,
+    locale varchar(35) not null,
+    description varchar not null,
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete restrict,
+    primary key (product_id, locale)
+);
+
+alter table product_category_translation enable row level security;
+alter table product_category_translation force row level security;
+create policy tenant_isolation on product_category_translation using (tenant_id = current_tenant_id());
+
+alter table product_translation enable row level security;
+alter table product_translation force row level security;
+create policy tenant_isolation on product_translation using (tenant_id = current_tenant_id());
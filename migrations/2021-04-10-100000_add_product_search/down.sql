@@ -0,0 +1,5 @@
+drop index product_category_name_trigrams_idx;
+drop index product_description_trigrams_idx;
+drop index product_description_words_idx;
+
+drop extension if exists pg_trgm;
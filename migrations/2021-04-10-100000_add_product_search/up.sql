@@ -0,0 +1,10 @@
+-- Search of the products by the words of their descriptions and of the names of their
+-- categories. Misspelled words are matched through the trigrams of pg_trgm.
+create extension if not exists pg_trgm;
+
+create index product_description_words_idx
+    on product using gin (to_tsvector('simple', description));
+create index product_description_trigrams_idx
+    on product using gin (description gin_trgm_ops);
+create index product_category_name_trigrams_idx
+    on product_category using gin (name gin_trgm_ops);
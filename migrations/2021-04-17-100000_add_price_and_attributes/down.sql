@@ -0,0 +1,3 @@
+drop table product_attribute;
+
+alter table product drop column price;
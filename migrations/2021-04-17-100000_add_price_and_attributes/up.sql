@@ -0,0 +1,17 @@
+-- Price of the products in the smallest unit of the currency, like cents, and the values of
+-- their attributes, like `color` or `size`, by which product listings are filtered
+alter table product add column price integer check (price >= 0);
+
+create table product_attribute(
+    product_id integer not null references product(id) on delete cascade,
+    name varchar not null,
+    value varchar not null,
+    tenant_id integer not null default current_tenant_id() references tenant(id) on delete restrict,
+    primary key (product_id, name)
+);
+
+create index product_attribute_name_value_idx on product_attribute(name, value);
+
+alter table product_attribute enable row level security;
+alter table product_attribute force row level security;
+create policy tenant_isolation on product_attribute using (tenant_id = current_tenant_id());
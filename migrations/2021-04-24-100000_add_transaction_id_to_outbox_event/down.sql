@@ -0,0 +1,3 @@
+drop index outbox_event_transaction_id_id_idx;
+
+alter table outbox_event drop column transaction_id;
@@ -0,0 +1,6 @@
+-- The transaction which recorded the event. Events are streamed in the order of their
+-- transactions, and only once no older transaction is running, so that an event of a
+-- transaction which commits late is never skipped, whatever its id.
+alter table outbox_event add column transaction_id bigint not null default txid_current();
+
+create index outbox_event_transaction_id_id_idx on outbox_event (transaction_id, id);
@@ -0,0 +1,3 @@
+drop index product_category_classification_product_id_idx;
+drop index product_category_classification_product_category_id_idx;
+drop index product_category_name_words_idx;
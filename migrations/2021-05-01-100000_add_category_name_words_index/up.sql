@@ -0,0 +1,8 @@
+-- Products are searched by the words of their category names through the categories, so that
+-- the names are matched through their own indexes instead of once per product.
+create index product_category_name_words_idx
+    on product_category using gin (to_tsvector('simple', name));
+create index product_category_classification_product_category_id_idx
+    on product_category_classification (product_category_id);
+create index product_category_classification_product_id_idx
+    on product_category_classification (product_id);
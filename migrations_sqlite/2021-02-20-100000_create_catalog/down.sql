@@ -0,0 +1,7 @@
+drop table outbox_event;
+drop table inventory_item;
+drop table warehouse;
+drop table product_category_rollup;
+drop table product_category_classification;
+drop table product;
+drop table product_category;
@@ -0,0 +1,49 @@
+-- Catalog of a single site, which holds the data of one tenant
+create table product_category(
+    id integer primary key,
+    name varchar unique not null
+);
+
+create table product (
+    id integer primary key,
+    description varchar not null
+);
+
+create table product_category_classification (
+    id integer primary key,
+    product_id integer not null,
+    product_category_id integer not null,
+    is_primary_classification boolean not null,
+    foreign key (product_id) references product(id) on delete cascade,
+    foreign key (product_category_id) references product_category(id) on delete restrict
+);
+
+create table product_category_rollup(
+    id integer primary key,
+    upper_category_id integer not null,
+    lower_category_id integer not null,
+    foreign key (upper_category_id) references product_category(id) on delete restrict,
+    foreign key (lower_category_id) references product_category(id) on delete restrict
+);
+
+create table warehouse(
+    id integer primary key,
+    description text not null
+);
+
+create table inventory_item (
+    id integer primary key,
+    product_id integer not null,
+    instance_description text,
+    warehouse_id integer,
+    foreign key (product_id) references product(id) on delete restrict,
+    foreign key (warehouse_id) references warehouse(id) on delete restrict
+);
+
+create table outbox_event(
+    id integer primary key,
+    event_type varchar not null,
+    payload text not null,
+    created_at timestamp not null default current_timestamp,
+    request_id varchar
+);
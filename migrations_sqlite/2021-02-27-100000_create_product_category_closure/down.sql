@@ -0,0 +1,5 @@
+drop trigger product_category_rollup_closure_delete;
+drop trigger product_category_rollup_closure_change;
+drop trigger product_category_rollup_closure_insert;
+drop trigger product_category_closure_insert;
+drop table product_category_closure;
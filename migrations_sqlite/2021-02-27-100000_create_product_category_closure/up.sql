@@ -0,0 +1,65 @@
+-- Every category paired with itself and each of its descendants, like on Postgres. SQLite does
+-- not allow recursive queries in triggers, so the closure can only be extended there and links
+-- of the hierarchy can not be removed.
+create table product_category_closure(
+    ancestor_id integer not null,
+    descendant_id integer not null,
+    depth integer not null,
+    primary key (ancestor_id, descendant_id),
+    foreign key (ancestor_id) references product_category(id) on delete cascade,
+    foreign key (descendant_id) references product_category(id) on delete cascade
+);
+
+create index product_category_closure_descendant_id_idx on product_category_closure(descendant_id);
+
+create trigger product_category_closure_insert
+    after insert on product_category
+begin
+    insert into product_category_closure(ancestor_id, descendant_id, depth)
+    values (new.id, new.id, 0);
+end;
+
+create trigger product_category_rollup_closure_insert
+    after insert on product_category_rollup
+begin
+    insert into product_category_closure(ancestor_id, descendant_id, depth)
+    select upper.ancestor_id, lower.descendant_id, upper.depth + 1 + lower.depth
+      from product_category_closure upper, product_category_closure lower
+     where upper.descendant_id = new.upper_category_id
+       and lower.ancestor_id = new.lower_category_id
+    on conflict (ancestor_id, descendant_id)
+    do update set depth = min(depth, excluded.depth);
+end;
+
+-- Links may be reordered, but not moved to other categories
+create trigger product_category_rollup_closure_change
+    before update of upper_category_id, lower_category_id on product_category_rollup
+begin
+    select raise(abort, 'links of the category hierarchy can not be changed');
+end;
+
+create trigger product_category_rollup_closure_delete
+    before delete on product_category_rollup
+begin
+    select raise(abort, 'links of the category hierarchy can not be removed');
+end;
+
+insert into product_category_closure(ancestor_id, descendant_id, depth)
+select id, id, 0 from product_category;
+
+-- Existing links are expanded level by level. The union keeps each pair once per depth, and no
+-- shortest path has more links than there are categories, which also ends the walk of cycles.
+insert into product_category_closure(ancestor_id, descendant_id, depth)
+with recursive path(ancestor_id, descendant_id, depth) as (
+    select upper_category_id, lower_category_id, 1
+      from product_category_rollup
+    union
+    select path.ancestor_id, rollup.lower_category_id, path.depth + 1
+      from path
+      join product_category_rollup rollup on rollup.upper_category_id = path.descendant_id
+     where path.depth < (select count(*) from product_category)
+)
+select ancestor_id, descendant_id, min(depth)
+  from path
+ where ancestor_id <> descendant_id
+ group by ancestor_id, descendant_id;
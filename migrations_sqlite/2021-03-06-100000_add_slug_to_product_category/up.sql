@@ -0,0 +1,6 @@
+-- Slugs are chosen by the application like on Postgres. SQLite can not match patterns in
+-- updates, so existing categories only get their lowercased name with dashes for spaces, which
+-- the application replaces with the proper slug when the category is saved next.
+alter table product_category add column slug text not null default '';
+
+update product_category set slug = replace(trim(lower(name)), ' ', '-');
@@ -0,0 +1,6 @@
+alter table product_category drop column is_visible;
+alter table product_category drop column image_ref;
+alter table product_category drop column description;
+
+drop index product_category_rollup_upper_category_id_position_idx;
+alter table product_category_rollup drop column position;
@@ -0,0 +1,15 @@
+-- Positions of the links like on Postgres, where existing links keep the order of their creation
+alter table product_category_rollup add column position integer not null default 0;
+
+update product_category_rollup
+   set position = (select count(*)
+                     from product_category_rollup earlier
+                    where earlier.upper_category_id = product_category_rollup.upper_category_id
+                      and earlier.id < product_category_rollup.id);
+
+create index product_category_rollup_upper_category_id_position_idx
+    on product_category_rollup(upper_category_id, position);
+
+alter table product_category add column description text;
+alter table product_category add column image_ref text;
+alter table product_category add column is_visible boolean not null default true;
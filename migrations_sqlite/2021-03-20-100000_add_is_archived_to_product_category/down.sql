@@ -0,0 +1,7 @@
+create trigger product_category_rollup_closure_delete
+    before delete on product_category_rollup
+begin
+    select raise(abort, 'links of the category hierarchy can not be removed');
+end;
+
+alter table product_category drop column is_archived;
@@ -0,0 +1,6 @@
+-- Categories merged into others are archived instead of deleted, like on Postgres
+alter table product_category add column is_archived boolean not null default false;
+
+-- Merging removes the links of the merged category. The repository updates the closure after
+-- removing links, which the triggers can not do on SQLite.
+drop trigger product_category_rollup_closure_delete;
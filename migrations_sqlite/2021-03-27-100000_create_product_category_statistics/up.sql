@@ -0,0 +1,20 @@
+-- Snapshot of the product counts and stock of every category, like on Postgres
+create table product_category_statistics(
+    category_id integer primary key,
+    direct_products bigint not null,
+    products bigint not null,
+    refreshed_at timestamp not null default current_timestamp,
+    foreign key (category_id) references product_category(id) on delete cascade
+);
+
+create table product_category_statistics_stock(
+    id integer primary key,
+    category_id integer not null,
+    warehouse_id integer,
+    quantity bigint not null,
+    foreign key (category_id) references product_category_statistics(category_id) on delete cascade,
+    foreign key (warehouse_id) references warehouse(id) on delete cascade
+);
+
+create index product_category_statistics_stock_category_id_idx
+    on product_category_statistics_stock(category_id);
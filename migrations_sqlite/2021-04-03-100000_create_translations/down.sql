@@ -0,0 +1,2 @@
+drop table product_translation;
+drop table product_category_translation;
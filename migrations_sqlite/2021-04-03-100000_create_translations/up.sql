@@ -0,0 +1,17 @@
+-- Names of the categories and descriptions of the products in other locales, like on Postgres
+create table product_category_translation(
+    category_id integer not null,
+    locale varchar(35) not null,
+    name varchar not null,
+    primary key (category_id, locale),
+    unique (locale, name),
+    foreign key (category_id) references product_category(id) on delete cascade
+);
+
+create table product_translation(
+    product_id integer not null,
+    locale varchar(35) not null,
+    description varchar not null,
+    primary key (product_id, locale),
+    foreign key (product_id) references product(id) on delete cascade
+);
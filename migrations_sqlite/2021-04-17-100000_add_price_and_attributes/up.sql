@@ -0,0 +1,13 @@
+-- Prices in the smallest unit of the currency and attribute values of the products, like on
+-- Postgres
+alter table product add column price integer check (price >= 0);
+
+create table product_attribute(
+    product_id integer not null,
+    name varchar not null,
+    value varchar not null,
+    primary key (product_id, name),
+    foreign key (product_id) references product(id) on delete cascade
+);
+
+create index product_attribute_name_value_idx on product_attribute(name, value);
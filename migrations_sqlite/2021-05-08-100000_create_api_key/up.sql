@@ -0,0 +1,7 @@
+-- Keys of the single site, which unlike on Postgres are not bound to a tenant
+create table api_key(
+    id integer primary key,
+    key_hash varchar(64) unique not null,
+    role varchar not null check (role in ('viewer', 'clerk', 'admin')),
+    description text not null
+);
@@ -0,0 +1,12 @@
+-- Responses of requests made with an Idempotency-Key header, which are replayed when a client
+-- repeats the request. The response columns are null while the first request is in progress.
+create table idempotency_key(
+    key varchar primary key not null,
+    fingerprint varchar not null,
+    response_status smallint,
+    response_content_type varchar,
+    response_body blob,
+    created_at timestamp not null
+);
+
+create index idempotency_key_created_at_idx on idempotency_key(created_at);
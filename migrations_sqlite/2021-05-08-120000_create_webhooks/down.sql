@@ -0,0 +1,5 @@
+drop trigger queue_webhook_deliveries;
+
+drop table webhook_delivery;
+
+drop table webhook_subscription;
@@ -0,0 +1,35 @@
+-- The event types of a subscription are a JSON array of strings
+create table webhook_subscription(
+    id integer primary key,
+    url text not null,
+    event_types text not null,
+    secret text not null,
+    active boolean not null default 1
+);
+
+create table webhook_delivery(
+    id integer primary key,
+    outbox_event_id integer not null,
+    webhook_subscription_id integer not null,
+    status varchar not null default 'pending' check (status in ('pending', 'delivered', 'dead')),
+    attempts integer not null default 0,
+    next_attempt_at timestamp not null default current_timestamp,
+    last_error text,
+    foreign key (outbox_event_id) references outbox_event(id) on delete cascade,
+    foreign key (webhook_subscription_id) references webhook_subscription(id) on delete cascade
+);
+
+create index webhook_delivery_pending_idx on webhook_delivery(next_attempt_at) where status = 'pending';
+
+-- Every event is queued for delivery to the subscriptions interested in it, within the
+-- transaction which records the event
+create trigger queue_webhook_deliveries after insert on outbox_event
+begin
+    insert into webhook_delivery(outbox_event_id, webhook_subscription_id)
+    select new.id, subscription.id
+      from webhook_subscription subscription
+     where subscription.active
+       and exists (
+           select 1 from json_each(subscription.event_types) where value = new.event_type
+       );
+end;
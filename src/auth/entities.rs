@@ -0,0 +1,17 @@
+#[cfg(feature = "postgres")]
+use crate::schema::*;
+
+#[derive(Queryable, Clone, Debug)]
+#[cfg_attr(feature = "postgres", derive(Identifiable), table_name = "api_key")]
+/// API key which can be used as a bearer token
+///
+/// Only the SHA-256 hash of the key is stored, so that the contents of the database can not be
+/// used to authenticate against the API. Every key on Postgres is bound to the tenant it was
+/// created for, while keys of an SQLite database belong to its single site.
+pub struct ApiKey {
+    pub id: i32,
+    pub key_hash: String,
+    pub role: String,
+    pub description: String,
+    pub tenant_id: Option<i32>,
+}
@@ -0,0 +1,239 @@
+use super::{
+    entities::ApiKey,
+    roles::{RequiredRole, Role},
+    tokens,
+};
+use crate::repository::{memory::MemoryDatabase, ApiKeyRepository};
+use rocket::{
+    fairing::AdHoc,
+    http::Status,
+    request::{self, FromRequest},
+    Outcome, Request, State,
+};
+use std::marker::PhantomData;
+
+/// Settings used to verify bearer tokens
+///
+/// Signed JSON web tokens are only accepted if a `jwt_secret` is configured.
+pub struct AuthConfig {
+    pub jwt_secret: Option<String>,
+}
+
+/// Reads the authentication settings from the Rocket configuration and manages them as state
+pub fn fairing() -> AdHoc {
+    AdHoc::on_attach("Authentication", |rocket| {
+        let jwt_secret = rocket.config().get_string("jwt_secret").ok();
+        Ok(rocket.manage(AuthConfig { jwt_secret }))
+    })
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// Caller of the API as identified by the bearer token of the request
+///
+/// Principals which are not bound to a tenant may only act for any tenant if they are global
+/// administrators, see [`Principal::acts_for_any_tenant`].
+pub struct Principal {
+    pub subject: String,
+    pub role: Role,
+    pub tenant_id: Option<i32>,
+    pub global: bool,
+}
+
+impl Principal {
+    /// Whether the principal may name any tenant to act for
+    ///
+    /// This takes an administrator whose token explicitly carries the `global` claim. A token
+    /// which merely leaves out its tenant is not enough.
+    pub fn acts_for_any_tenant(&self) -> bool {
+        self.tenant_id.is_none() && self.global && self.role >= Role::Admin
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+    Forbidden,
+    Unavailable,
+}
+
+impl AuthError {
+    pub(crate) fn status(&self) -> Status {
+        match self {
+            Self::MissingToken | Self::InvalidToken => Status::Unauthorized,
+            Self::Forbidden => Status::Forbidden,
+            Self::Unavailable => Status::ServiceUnavailable,
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Principal {
+    type Error = AuthError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        match request.local_cache(|| authenticate(request)) {
+            Ok(principal) => Outcome::Success(principal.clone()),
+            Err(error) => Outcome::Failure((error.status(), error.clone())),
+        }
+    }
+}
+
+/// Principal which authenticated the request, if its credentials were already checked
+///
+/// Unlike the [`Principal`] guard this never verifies credentials itself, so it is cheap enough
+/// to be called for every response.
+pub fn authenticated_principal(request: &Request) -> Option<Principal> {
+    request
+        .local_cache(|| Err::<Principal, AuthError>(AuthError::MissingToken))
+        .as_ref()
+        .ok()
+        .cloned()
+}
+
+/// Request guard which succeeds only for principals holding at least the role `R`
+///
+/// Routes declare the rights they require through the type of the guard, for example
+/// `_admin: Authorized<Admin>`.
+pub struct Authorized<R: RequiredRole> {
+    pub principal: Principal,
+    required_role: PhantomData<R>,
+}
+
+impl<'a, 'r, R: RequiredRole> FromRequest<'a, 'r> for Authorized<R> {
+    type Error = AuthError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        match request.guard::<Principal>() {
+            Outcome::Success(principal) if principal.role >= R::ROLE => Outcome::Success(Self {
+                principal,
+                required_role: PhantomData,
+            }),
+            Outcome::Success(_) => Outcome::Failure((Status::Forbidden, AuthError::Forbidden)),
+            Outcome::Failure(failure) => Outcome::Failure(failure),
+            Outcome::Forward(()) => Outcome::Forward(()),
+        }
+    }
+}
+
+fn authenticate(request: &Request) -> Result<Principal, AuthError> {
+    let token = request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or(AuthError::MissingToken)?;
+
+    if tokens::is_jwt(token) {
+        authenticate_jwt(request, token)
+    } else {
+        authenticate_api_key(request, token)
+    }
+}
+
+fn authenticate_jwt(request: &Request, token: &str) -> Result<Principal, AuthError> {
+    let auth_config = request
+        .guard::<State<AuthConfig>>()
+        .succeeded()
+        .ok_or(AuthError::Unavailable)?;
+    let jwt_secret = auth_config
+        .jwt_secret
+        .as_ref()
+        .ok_or(AuthError::InvalidToken)?;
+    let claims = tokens::decode_jwt(token, jwt_secret).map_err(|_| AuthError::InvalidToken)?;
+    Ok(Principal {
+        subject: claims.sub,
+        role: claims.role,
+        tenant_id: claims.tenant,
+        global: claims.global,
+    })
+}
+
+fn authenticate_api_key(request: &Request, token: &str) -> Result<Principal, AuthError> {
+    let api_key = match find_api_key(request, &tokens::hash_api_key(token))? {
+        Some(api_key) => api_key,
+        None => return Err(AuthError::InvalidToken),
+    };
+    Ok(Principal {
+        subject: format!("api-key:{}", api_key.id),
+        role: api_key.role.parse().map_err(|_| AuthError::InvalidToken)?,
+        tenant_id: api_key.tenant_id,
+        global: false,
+    })
+}
+
+/// Looks the key up in the database of the configured backend
+fn find_api_key(request: &Request, key_hash: &str) -> Result<Option<ApiKey>, AuthError> {
+    if let Some(database) = request.guard::<State<MemoryDatabase>>().succeeded() {
+        return database
+            .connect()
+            .api_key(key_hash)
+            .map_err(|_| AuthError::Unavailable);
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(db_conn) = request.guard::<crate::SqliteDbConn>().succeeded() {
+        return db_conn
+            .api_key(key_hash)
+            .map_err(|_| AuthError::Unavailable);
+    }
+    #[cfg(feature = "postgres")]
+    if let Some(db_conn) = request.guard::<crate::DbConn>().succeeded() {
+        return db_conn
+            .api_key(key_hash)
+            .map_err(|_| AuthError::Unavailable);
+    }
+    Err(AuthError::Unavailable)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        auth::{tokens::hash_api_key, Role},
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::http::{ContentType, Header, Status};
+
+    backend_test!(
+        request_without_token_is_unauthorized,
+        |client, _repository| {
+            let response = client.get("/productcategory").dispatch();
+
+            assert_eq!(Status::Unauthorized, response.status());
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        viewer_can_not_create_product_category,
+        |client, repository| {
+            let response = client
+                .post("/productcategory")
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Viewer))
+                .body("{\"name\":\"product\"}")
+                .dispatch();
+
+            assert_eq!(Status::Forbidden, response.status());
+            assert!(repository.categories()?.is_empty());
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        stored_api_key_authenticates_request,
+        |client, repository| {
+            repository.insert_api_key(&hash_api_key("viewer-key"), Role::Viewer, "dashboard")?;
+
+            let accepted_response = client
+                .get("/productcategory")
+                .header(Header::new("Authorization", "Bearer viewer-key"))
+                .dispatch();
+            let rejected_response = client
+                .get("/productcategory")
+                .header(Header::new("Authorization", "Bearer unknown-key"))
+                .dispatch();
+
+            assert_eq!(Status::Ok, accepted_response.status());
+            assert_eq!(Status::Unauthorized, rejected_response.status());
+            Ok(())
+        }
+    );
+}
@@ -0,0 +1,18 @@
+use super::{entities::ApiKey, tokens, Role};
+use crate::repository::ApiKeyRepository;
+use diesel::QueryResult;
+
+/// Creates a new API key with the role, bound to the tenant the connection is scoped to
+///
+/// Returns the stored key together with the plain key, which is not stored and can only be
+/// handed out now.
+pub fn create_api_key<R: ApiKeyRepository>(
+    repository: &R,
+    role: Role,
+    description: &str,
+) -> QueryResult<(ApiKey, String)> {
+    let plain_key = tokens::generate_api_key();
+    let api_key =
+        repository.insert_api_key(&tokens::hash_api_key(&plain_key), role, description)?;
+    Ok((api_key, plain_key))
+}
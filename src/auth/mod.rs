@@ -0,0 +1,8 @@
+pub mod entities;
+pub mod guards;
+pub mod keys;
+pub mod roles;
+pub mod tokens;
+
+pub use guards::*;
+pub use roles::*;
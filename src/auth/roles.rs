@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+/// Role of an authenticated principal
+///
+/// Roles are ordered by the rights they grant, so that every role includes the rights of the
+/// roles below it. A viewer can only read the catalog, a clerk can additionally record stock
+/// movements, and an admin can also change the catalog itself.
+pub enum Role {
+    Viewer,
+    Clerk,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Clerk => "clerk",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "viewer" => Ok(Role::Viewer),
+            "clerk" => Ok(Role::Clerk),
+            "admin" => Ok(Role::Admin),
+            unknown => Err(format!("Unknown role: {}", unknown)),
+        }
+    }
+}
+
+/// Marker for the minimal role which a route requires
+pub trait RequiredRole {
+    const ROLE: Role;
+}
+
+/// Requires at least the `viewer` role
+pub struct Viewer;
+
+/// Requires at least the `clerk` role
+pub struct Clerk;
+
+/// Requires the `admin` role
+pub struct Admin;
+
+impl RequiredRole for Viewer {
+    const ROLE: Role = Role::Viewer;
+}
+
+impl RequiredRole for Clerk {
+    const ROLE: Role = Role::Clerk;
+}
+
+impl RequiredRole for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Role;
+
+    #[test]
+    fn admin_includes_rights_of_all_other_roles() {
+        assert!(Role::Admin > Role::Clerk);
+        assert!(Role::Clerk > Role::Viewer);
+    }
+
+    #[test]
+    fn role_parses_from_its_string_representation() {
+        for role in &[Role::Viewer, Role::Clerk, Role::Admin] {
+            assert_eq!(Ok(*role), role.as_str().parse());
+        }
+        assert!("superuser".parse::<Role>().is_err());
+    }
+}
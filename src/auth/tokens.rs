@@ -0,0 +1,115 @@
+use super::roles::Role;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+/// Claims carried by the signed JSON web tokens which the API accepts
+pub struct Claims {
+    pub sub: String,
+    pub role: Role,
+    pub exp: u64,
+    #[serde(default)]
+    pub tenant: Option<i32>,
+    /// Whether an administrator without a tenant may act for any tenant
+    #[serde(default)]
+    pub global: bool,
+}
+
+/// Hashes an API key into the form in which it is stored in the database
+pub fn hash_api_key(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.as_bytes()))
+}
+
+/// Generates a new random API key
+///
+/// The key is hex encoded and so never contains dots, see [`is_jwt`].
+pub fn generate_api_key() -> String {
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    hex::encode(key)
+}
+
+/// Checks whether the token has the `header.payload.signature` shape of a JSON web token
+///
+/// API keys never contain dots, so this is enough to tell the two kinds of tokens apart.
+pub fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+pub fn encode_jwt(claims: &Claims, secret: &str) -> jsonwebtoken::errors::Result<String> {
+    encode(
+        &Header::new(Algorithm::HS256),
+        claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+}
+
+/// Verifies the signature and expiry of the token and returns its claims
+pub fn decode_jwt(token: &str, secret: &str) -> jsonwebtoken::errors::Result<Claims> {
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &Validation::new(Algorithm::HS256),
+    )
+    .map(|token_data| token_data.claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_jwt, encode_jwt, generate_api_key, hash_api_key, is_jwt, Claims};
+    use crate::auth::Role;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn claims_expiring_in(seconds: i64) -> Claims {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        Claims {
+            sub: "tester".to_string(),
+            role: Role::Clerk,
+            exp: (now + seconds) as u64,
+            tenant: Some(1),
+            global: false,
+        }
+    }
+
+    #[test]
+    fn api_key_hash_is_stable_hex_encoded_sha256() {
+        assert_eq!(hash_api_key("secret-key"), hash_api_key("secret-key"));
+        assert_ne!(hash_api_key("secret-key"), hash_api_key("other-key"));
+        assert_eq!(hash_api_key("secret-key").len(), 64);
+    }
+
+    #[test]
+    fn generated_api_keys_are_unique_and_not_mistaken_for_tokens() {
+        let (first_key, second_key) = (generate_api_key(), generate_api_key());
+
+        assert_ne!(first_key, second_key);
+        assert_eq!(64, first_key.len());
+        assert!(!is_jwt(&first_key));
+    }
+
+    #[test]
+    fn signed_token_is_decoded_with_same_secret() {
+        let claims = claims_expiring_in(60);
+        let token = encode_jwt(&claims, "secret").unwrap();
+
+        assert!(is_jwt(&token));
+        assert_eq!(decode_jwt(&token, "secret").unwrap(), claims);
+    }
+
+    #[test]
+    fn token_signed_with_other_secret_is_rejected() {
+        let token = encode_jwt(&claims_expiring_in(60), "other secret").unwrap();
+        assert!(decode_jwt(&token, "secret").is_err());
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let token = encode_jwt(&claims_expiring_in(-3600), "secret").unwrap();
+        assert!(decode_jwt(&token, "secret").is_err());
+    }
+}
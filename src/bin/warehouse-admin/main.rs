@@ -0,0 +1,298 @@
+//! Administration of a warehouse database from the command line
+//!
+//! All commands operate on the database named by `--database-url` or the `database_url` of the
+//! server settings, and commands touching warehouse data act for the tenant given by
+//! `--tenant`.
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use diesel::{Connection, PgConnection};
+use std::{error::Error, fs, io, process};
+use warehouse_rs::{
+    auth::{keys::create_api_key, Role},
+    csv_transfer::{
+        export::CsvExport,
+        import::{import, parse_column_mapping, ImportOptions, ImportReport},
+        CsvResource,
+    },
+    fixtures, migrations, product_category,
+    repository::{CategoryRepository, StockRepository},
+    settings, tenancy,
+};
+
+type CommandResult = Result<(), Box<dyn Error>>;
+
+fn main() {
+    let matches = app().get_matches();
+    if let Err(error) = run(&matches) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    let resource = Arg::with_name("resource").required(true).possible_values(&[
+        "categories",
+        "products",
+        "stock",
+    ]);
+    App::new("warehouse-admin")
+        .about("Administers a warehouse database")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("database-url")
+                .long("database-url")
+                .takes_value(true)
+                .help("Connection URL of the database, defaults to the one of the server settings"),
+        )
+        .arg(
+            Arg::with_name("tenant")
+                .long("tenant")
+                .takes_value(true)
+                .default_value("1")
+                .help("Tenant whose data is seeded, imported, exported or reported"),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate")
+                .about("Applies or reverts schema migrations")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("run").about("Applies all pending migrations"))
+                .subcommand(
+                    SubCommand::with_name("revert").about("Reverts the latest applied migration"),
+                )
+                .subcommand(
+                    SubCommand::with_name("status").about("Lists migrations and their state"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("seed").about("Seeds a sample catalog and stock"))
+        .subcommand(
+            SubCommand::with_name("api-key")
+                .about("Manages API keys")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about("Creates an API key and prints it once")
+                        .arg(
+                            Arg::with_name("role")
+                                .long("role")
+                                .takes_value(true)
+                                .required(true)
+                                .possible_values(&["viewer", "clerk", "admin"]),
+                        )
+                        .arg(
+                            Arg::with_name("description")
+                                .long("description")
+                                .takes_value(true)
+                                .default_value(""),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Imports a CSV file")
+                .arg(resource.clone())
+                .arg(Arg::with_name("file").required(true))
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Reports the changes without committing them"),
+                )
+                .arg(
+                    Arg::with_name("columns")
+                        .long("columns")
+                        .takes_value(true)
+                        .help("Maps headers of the file to columns, as in Header:column,..."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports a resource as CSV")
+                .arg(resource)
+                .arg(Arg::with_name("file").help("Written to standard output if omitted")),
+        )
+        .subcommand(
+            SubCommand::with_name("check-hierarchy")
+                .about("Reports cycles in the category hierarchy"),
+        )
+        .subcommand(
+            SubCommand::with_name("stock-report")
+                .about("Reports the stock of every product per warehouse"),
+        )
+}
+
+fn run(matches: &ArgMatches) -> CommandResult {
+    let database_url = match matches.value_of("database-url") {
+        Some(database_url) => database_url.to_string(),
+        None => settings::load()?.database_url,
+    };
+    let connection = PgConnection::establish(&database_url)?;
+    if let ("migrate", Some(migrate_matches)) = matches.subcommand() {
+        return migrate(&connection, migrate_matches);
+    }
+
+    let tenant_id: i32 = matches
+        .value_of("tenant")
+        .unwrap_or_default()
+        .parse()
+        .map_err(|_| "the tenant has to be a number")?;
+    if !tenancy::scope_session(&connection, tenant_id)? {
+        return Err(format!("tenant {} does not exist", tenant_id).into());
+    }
+    match matches.subcommand() {
+        ("seed", _) => seed(&connection),
+        ("api-key", Some(api_key_matches)) => match api_key_matches.subcommand() {
+            ("create", Some(create_matches)) => create_key(&connection, create_matches),
+            _ => unreachable!("subcommand is required"),
+        },
+        ("import", Some(import_matches)) => import_file(&connection, import_matches),
+        ("export", Some(export_matches)) => export(connection, export_matches),
+        ("check-hierarchy", _) => check_hierarchy(&connection),
+        ("stock-report", _) => stock_report(&connection),
+        _ => unreachable!("subcommand is required"),
+    }
+}
+
+fn migrate(connection: &PgConnection, matches: &ArgMatches) -> CommandResult {
+    match matches.subcommand_name() {
+        Some("run") => {
+            let applied = migrations::migrate(connection, migrations::MigrationMode::Run)?;
+            if applied.is_empty() {
+                println!("The database is up to date");
+            }
+            for migration in applied {
+                println!("Applied {}", migration.name);
+            }
+        }
+        Some("revert") => {
+            match migrations::with_lock(connection, || migrations::revert_latest(connection))? {
+                Some(migration) => println!("Reverted {}", migration.name),
+                None => println!("No migration has been applied"),
+            }
+        }
+        _ => {
+            let applied = migrations::applied_versions(connection)?;
+            for migration in migrations::embedded() {
+                let state = if applied.iter().any(|version| version == migration.version) {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                println!("{:<8} {}", state, migration.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn seed(connection: &PgConnection) -> CommandResult {
+    let summary = fixtures::seed(connection)?;
+    print_report("categories", &summary.categories);
+    match summary.products {
+        Some(report) => {
+            print_report("products", &report);
+            println!(
+                "warehouses: {} created, inventory items: {} created",
+                summary.warehouses, summary.inventory_items
+            );
+        }
+        None => println!("products: skipped, the tenant already has products"),
+    }
+    Ok(())
+}
+
+fn create_key(connection: &PgConnection, matches: &ArgMatches) -> CommandResult {
+    let role: Role = matches.value_of("role").unwrap_or_default().parse()?;
+    let description = matches.value_of("description").unwrap_or_default();
+    let (api_key, plain_key) = create_api_key(connection, role, description)?;
+    eprintln!(
+        "Created API key {} with role {}, it is shown only once:",
+        api_key.id, role
+    );
+    println!("{}", plain_key);
+    Ok(())
+}
+
+fn import_file(connection: &PgConnection, matches: &ArgMatches) -> CommandResult {
+    let resource: CsvResource = matches.value_of("resource").unwrap_or_default().parse()?;
+    let csv_text = fs::read_to_string(matches.value_of("file").unwrap_or_default())?;
+    let options = ImportOptions {
+        dry_run: matches.is_present("dry-run"),
+        column_mapping: parse_column_mapping(matches.value_of("columns").unwrap_or_default())?,
+    };
+    let report = import(connection, resource, &csv_text, &options)?;
+    print_report(resource.as_str(), &report);
+    for error in &report.errors {
+        eprintln!("row {}: {}", error.row, error.message);
+    }
+    if report.errors.is_empty() {
+        Ok(())
+    } else {
+        Err("the file was not imported".into())
+    }
+}
+
+fn export(connection: PgConnection, matches: &ArgMatches) -> CommandResult {
+    let resource: CsvResource = matches.value_of("resource").unwrap_or_default().parse()?;
+    let mut export = CsvExport::new(connection, resource);
+    match matches.value_of("file") {
+        Some(path) => io::copy(&mut export, &mut fs::File::create(path)?)?,
+        None => io::copy(&mut export, &mut io::stdout())?,
+    };
+    Ok(())
+}
+
+fn check_hierarchy(connection: &PgConnection) -> CommandResult {
+    let cycles = product_category::hierarchy::find_cycles(connection)?;
+    if cycles.is_empty() {
+        println!("The category hierarchy has no cycles");
+        return Ok(());
+    }
+    let categories = connection.categories()?;
+    for cycle in &cycles {
+        let path: Vec<String> = cycle
+            .iter()
+            .map(
+                |id| match categories.iter().find(|category| category.id == *id) {
+                    Some(category) => format!("{} ({})", category.name, id),
+                    None => id.to_string(),
+                },
+            )
+            .collect();
+        println!("cycle: {}", path.join(" -> "));
+    }
+    Err(format!("found {} cycles in the category hierarchy", cycles.len()).into())
+}
+
+fn stock_report(connection: &PgConnection) -> CommandResult {
+    println!(
+        "{:<8} {:<32} {:<32} {:>8}",
+        "product", "description", "warehouse", "quantity"
+    );
+    for level in connection.stock_levels()? {
+        println!(
+            "{:<8} {:<32} {:<32} {:>8}",
+            level.product_id,
+            level.product_description,
+            level
+                .warehouse_description
+                .unwrap_or_else(|| "unassigned".to_string()),
+            level.quantity
+        );
+    }
+    Ok(())
+}
+
+fn print_report(resource: &str, report: &ImportReport) {
+    println!(
+        "{}: {} rows, {} created, {} updated, {} linked{}",
+        resource,
+        report.rows,
+        report.created,
+        report.updated,
+        report.linked,
+        if report.committed {
+            ""
+        } else {
+            ", not committed"
+        }
+    );
+}
@@ -0,0 +1,128 @@
+//! Prices and attribute values of the products, by which listings are filtered
+
+use super::{entities::ProductAttribute, products::update_product};
+use crate::{
+    auth::{Admin, Authorized, Viewer},
+    entities::Product,
+    repository::{BackendConn, ProductRepository},
+    utilities::{DbError, GetResponder},
+};
+use rocket::{http::Status, response::status::BadRequest};
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ProductPriceBody {
+    /// Price in the smallest unit of the currency, or none to leave the product unpriced
+    pub price: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ProductAttributeBody {
+    pub value: String,
+}
+
+type AttributeResponse =
+    Result<Result<GetResponder<ProductAttribute>, BadRequest<String>>, DbError>;
+
+#[put("/<product_id>/price", format = "json", data = "<price>")]
+pub fn put_price(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    product_id: i32,
+    price: Json<ProductPriceBody>,
+) -> Result<Result<GetResponder<Product>, BadRequest<String>>, DbError> {
+    if price.price.map_or(false, |price| price < 0) {
+        return Ok(Err(BadRequest(Some(
+            "Price can not be negative".to_string(),
+        ))));
+    }
+    let price = price.price;
+    let priced = update_product(&db_conn, product_id, || {
+        db_conn.set_product_price(product_id, price)?;
+        Ok(true)
+    })?;
+    if priced.is_none() {
+        return Ok(Ok(GetResponder::NotFound(())));
+    }
+    Ok(Ok(match db_conn.product(product_id)? {
+        Some(product) => GetResponder::Found(Json(product)),
+        None => GetResponder::NotFound(()),
+    }))
+}
+
+/// Lists the attribute values of the product, ordered by attribute name
+#[get("/<product_id>/attributes")]
+pub fn get_attributes(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    product_id: i32,
+) -> Result<GetResponder<Vec<ProductAttribute>>, DbError> {
+    if db_conn.product(product_id)?.is_none() {
+        return Ok(GetResponder::NotFound(()));
+    }
+    Ok(GetResponder::Found(Json(
+        db_conn.attributes_of_product(product_id)?,
+    )))
+}
+
+/// Sets the value of the attribute of the product, replacing the one it had
+///
+/// Names can not contain colons, which separate them from the values in listing filters.
+#[put(
+    "/<product_id>/attributes/<name>",
+    format = "json",
+    data = "<attribute>"
+)]
+pub fn put_attribute(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    product_id: i32,
+    name: String,
+    attribute: Json<ProductAttributeBody>,
+) -> AttributeResponse {
+    let name = name.trim();
+    if name.is_empty() || name.contains(':') {
+        return Ok(Err(BadRequest(Some(format!(
+            "Attribute name {} has to be non-empty and without colons",
+            name
+        )))));
+    }
+    let value = attribute.into_inner().value;
+    if value.trim().is_empty() {
+        return Ok(Err(BadRequest(Some(
+            "Attribute value is empty".to_string(),
+        ))));
+    }
+    let attribute = ProductAttribute {
+        product_id,
+        name: name.to_string(),
+        value: value.trim().to_string(),
+    };
+    let saved = update_product(&db_conn, product_id, || {
+        db_conn.save_product_attribute(&attribute)?;
+        Ok(true)
+    })?;
+    Ok(Ok(match saved {
+        Some(_) => GetResponder::Found(Json(attribute)),
+        None => GetResponder::NotFound(()),
+    }))
+}
+
+#[delete("/<product_id>/attributes/<name>")]
+pub fn delete_attribute(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    product_id: i32,
+    name: String,
+) -> Result<Status, DbError> {
+    let deleted = update_product(&db_conn, product_id, || {
+        db_conn.delete_product_attribute(product_id, name.trim())
+    })?;
+    if deleted == Some(true) {
+        Ok(Status::Ok)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
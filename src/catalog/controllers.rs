@@ -0,0 +1,175 @@
+use super::facets::{facets, Listing, ListingFilters};
+use crate::{
+    auth::{Authorized, Viewer},
+    localization::{localize_products, Locale},
+    product_category::localized,
+    repository::{BackendConn, CategoryRepository, FacetRepository, WarehouseRepository},
+    utilities::DbError,
+};
+use rocket::response::status::BadRequest;
+use rocket_contrib::json::Json;
+
+/// Number of products on a page when the listing does not limit them
+pub const DEFAULT_LIMIT: i64 = 20;
+/// Most products on a page of a listing
+pub const MAX_LIMIT: i64 = 100;
+
+/// Lists a page of the products matching the facet filters, with the product counts of every
+/// facet
+///
+/// Filters are repeatable `category`, `warehouse` and `attribute=name:value` parameters and the
+/// price bounds `min_price` and `max_price`.
+#[get("/listing?<limit>&<offset>&<filters..>")]
+pub fn get_listing(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    locale: Locale,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    filters: Result<ListingFilters, String>,
+) -> Result<Result<Json<Listing>, BadRequest<String>>, DbError> {
+    let reject = |message: String| Ok(Err(BadRequest(Some(message))));
+    let filters = match filters {
+        Ok(filters) => filters,
+        Err(message) => return reject(message),
+    };
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return reject(format!("Limit has to be between 1 and {}", MAX_LIMIT));
+    }
+    let offset = offset.unwrap_or(0);
+    if offset < 0 {
+        return reject("Offset can not be negative".to_string());
+    }
+    let counts = db_conn.facet_counts(&filters, limit, offset)?;
+    let categories = localized(&db_conn, &locale, db_conn.categories()?)?;
+    let facets = facets(&counts, &categories, &db_conn.warehouses()?, &filters);
+    Ok(Ok(Json(Listing {
+        total: counts.total,
+        products: localize_products(&db_conn, &locale, counts.products)?,
+        facets,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        auth::Role,
+        catalog::facets::Listing,
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::http::{ContentType, Status};
+
+    backend_test!(products_are_listed_by_facets, |client, repository| {
+        let lighting = repository.insert_category("lighting")?;
+        let lamps = repository.insert_category("lamps")?;
+        repository.insert_rollup(lighting.id, lamps.id)?;
+        let desk_lamp = repository.insert_product("desk lamp")?;
+        let floor_lamp = repository.insert_product("floor lamp")?;
+        let bulb = repository.insert_product("bulb")?;
+        repository.classify(desk_lamp.id, lamps.id, true)?;
+        repository.classify(floor_lamp.id, lamps.id, true)?;
+        repository.classify(bulb.id, lighting.id, true)?;
+        let depot = repository.insert_warehouse("depot")?;
+        repository.insert_inventory_item(desk_lamp.id, Some(depot.id), None)?;
+        let put = |path: String, body: &str| {
+            client
+                .put(path)
+                .header(bearer_token(Role::Admin))
+                .header(ContentType::JSON)
+                .body(body)
+                .dispatch()
+                .status()
+        };
+        for (product_id, price) in &[(desk_lamp.id, 2500), (floor_lamp.id, 9000), (bulb.id, 300)] {
+            assert_eq!(
+                Status::Ok,
+                put(
+                    format!("/product/{}/price", product_id),
+                    &format!(r#"{{"price": {}}}"#, price)
+                )
+            );
+        }
+        for (product_id, color) in &[(desk_lamp.id, "black"), (floor_lamp.id, "white")] {
+            assert_eq!(
+                Status::Ok,
+                put(
+                    format!("/product/{}/attributes/color", product_id),
+                    &format!(r#"{{"value": "{}"}}"#, color)
+                )
+            );
+        }
+        let list = |query: String| {
+            let mut response = client
+                .get(format!("/product/listing?{}", query))
+                .header(bearer_token(Role::Viewer))
+                .dispatch();
+            (
+                response.status(),
+                response.body_string().unwrap_or_default(),
+            )
+        };
+
+        let (status, body) = list(format!(
+            "category={}&attribute=color:black&attribute=color:white&max_price=5000",
+            lighting.id
+        ));
+        let listing: Listing = serde_json::from_str(&body)?;
+        let (warehouse_status, in_depot) = list(format!("warehouse={}&limit=1", depot.id));
+        let in_depot: Listing = serde_json::from_str(&in_depot)?;
+        let negative_status = put(format!("/product/{}/price", bulb.id), r#"{"price": -1}"#);
+        let (invalid_status, invalid) = list("attribute=black".to_string());
+
+        assert_eq!(Status::Ok, status);
+        assert_eq!(1, listing.total);
+        assert_eq!(desk_lamp.id, listing.products[0].id);
+        assert_eq!(Some(2500), listing.products[0].price);
+        assert_eq!(
+            vec![(lighting.id, 1), (lamps.id, 1)],
+            listing
+                .facets
+                .categories
+                .iter()
+                .map(|facet| (facet.category_id, facet.count))
+                .collect::<Vec<_>>()
+        );
+        // Counted without the price filter, which excludes the white lamp
+        assert_eq!(
+            vec![("black".to_string(), 1), ("white".to_string(), 0)],
+            listing.facets.attributes[0]
+                .values
+                .iter()
+                .map(|value| (value.value.clone(), value.count))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (Some(2500), Some(9000), 2),
+            (
+                listing.facets.price.min,
+                listing.facets.price.max,
+                listing.facets.price.count
+            )
+        );
+        assert_eq!(Status::Ok, warehouse_status);
+        assert_eq!(
+            (1, vec![desk_lamp.id]),
+            (
+                in_depot.total,
+                in_depot
+                    .products
+                    .iter()
+                    .map(|product| product.id)
+                    .collect::<Vec<_>>()
+            )
+        );
+        assert_eq!(Status::BadRequest, negative_status);
+        assert_eq!(
+            (
+                Status::BadRequest,
+                "Filter attribute=black has to be like attribute=name:value".to_string()
+            ),
+            (invalid_status, invalid)
+        );
+        Ok(())
+    });
+}
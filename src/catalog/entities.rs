@@ -0,0 +1,16 @@
+use crate::schema::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Queryable, Insertable, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug,
+)]
+#[table_name = "product_attribute"]
+/// Value of an attribute of a product, like `red` for its `color`
+///
+/// A product has at most one value for each attribute.
+pub struct ProductAttribute {
+    pub product_id: i32,
+    pub name: String,
+    pub value: String,
+}
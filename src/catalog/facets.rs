@@ -0,0 +1,573 @@
+use super::entities::ProductAttribute;
+use crate::{
+    entities::{Product, Warehouse},
+    product_category::entities::ProductCategory,
+};
+use rocket::request::{FromQuery, Query};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Everything by which products are filtered and counted, as held by the memory repository
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct CatalogIndex {
+    /// All products, ordered by id
+    pub products: Vec<Product>,
+    /// Products paired with each category they are classified into or below
+    pub placements: Vec<(i32, i32)>,
+    /// Products paired with each warehouse they have items in
+    pub stocked: Vec<(i32, i32)>,
+    pub attributes: Vec<ProductAttribute>,
+}
+
+/// Values of the facets which listed products have to have
+///
+/// Within a facet, products need one of the values, and they have to match every facet.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct ListingFilters {
+    /// Products classified into any of the categories or below them
+    pub categories: Vec<i32>,
+    /// Products with items in any of the warehouses
+    pub warehouses: Vec<i32>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+    /// Values of the named attributes, of which a product has to have one for every name
+    pub attributes: BTreeMap<String, Vec<String>>,
+}
+
+impl<'q> FromQuery<'q> for ListingFilters {
+    type Error = String;
+
+    /// Reads repeatable `category`, `warehouse` and `attribute=name:value` parameters and the
+    /// price bounds `min_price` and `max_price`, ignoring any other parameters
+    fn from_query(query: Query<'q>) -> Result<Self, Self::Error> {
+        let mut filters = ListingFilters::default();
+        for item in query {
+            let key = item.key.url_decode_lossy();
+            let value = item.value.url_decode_lossy();
+            let id = || {
+                value
+                    .parse::<i32>()
+                    .map_err(|_| format!("Filter {}={} is not a number", key, value))
+            };
+            match key.as_str() {
+                "category" => filters.categories.push(id()?),
+                "warehouse" => filters.warehouses.push(id()?),
+                "min_price" => filters.min_price = Some(id()?),
+                "max_price" => filters.max_price = Some(id()?),
+                "attribute" => match value.split_once(':') {
+                    Some((name, attribute_value)) if !name.is_empty() => filters
+                        .attributes
+                        .entry(name.to_string())
+                        .or_default()
+                        .push(attribute_value.to_string()),
+                    _ => {
+                        return Err(format!(
+                            "Filter attribute={} has to be like attribute=name:value",
+                            value
+                        ))
+                    }
+                },
+                _ => {}
+            }
+        }
+        if let (Some(min_price), Some(max_price)) = (filters.min_price, filters.max_price) {
+            if min_price > max_price {
+                return Err(format!(
+                    "Minimum price {} is above the maximum price {}",
+                    min_price, max_price
+                ));
+            }
+        }
+        Ok(filters)
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Number of products in a category and the categories below it
+pub struct CategoryFacet {
+    pub category_id: i32,
+    pub name: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Number of products with items in a warehouse
+pub struct WarehouseFacet {
+    pub warehouse_id: i32,
+    pub description: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Range of the prices of the priced products
+pub struct PriceFacet {
+    pub min: Option<i32>,
+    pub max: Option<i32>,
+    /// Products with a price
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Number of products with a value of an attribute
+pub struct AttributeValueFacet {
+    pub value: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Values of an attribute with their product counts, ordered by value
+pub struct AttributeFacet {
+    pub name: String,
+    pub values: Vec<AttributeValueFacet>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Product counts of the values of every facet
+///
+/// Values without products are left out, unless they are filtered by.
+pub struct Facets {
+    pub categories: Vec<CategoryFacet>,
+    pub warehouses: Vec<WarehouseFacet>,
+    pub price: PriceFacet,
+    pub attributes: Vec<AttributeFacet>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Page of the products matching the filters of a listing, with the counts of the facets
+pub struct Listing {
+    /// Number of products matching the filters, on all pages
+    pub total: i64,
+    pub products: Vec<Product>,
+    pub facets: Facets,
+}
+
+/// Page of the products matching the filters of a listing, with the product counts of the facet
+/// values as counted by the repositories
+///
+/// The values of every facet are counted among the products matching all other filters.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FacetCounts {
+    /// Number of products matching the filters, on all pages
+    pub total: i64,
+    /// Products of the page, ordered by id
+    pub products: Vec<Product>,
+    /// Products in each category or below it
+    pub categories: BTreeMap<i32, i64>,
+    /// Products with items in each warehouse
+    pub warehouses: BTreeMap<i32, i64>,
+    pub price: PriceFacet,
+    /// Products with each value of every attribute
+    pub attributes: BTreeMap<String, BTreeMap<String, i64>>,
+}
+
+/// Facet whose filter is left out when counting the values of it
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Facet<'a> {
+    Category,
+    Warehouse,
+    Price,
+    Attribute(&'a str),
+}
+
+/// Facet values of a product
+#[derive(Default)]
+struct ProductFacts<'a> {
+    categories: BTreeSet<i32>,
+    warehouses: BTreeSet<i32>,
+    attributes: BTreeMap<&'a str, &'a str>,
+}
+
+fn matches(
+    product: &Product,
+    facts: &ProductFacts,
+    filters: &ListingFilters,
+    except: Option<Facet>,
+) -> bool {
+    let any_of = |selected: &[i32], values: &BTreeSet<i32>| {
+        selected.is_empty() || selected.iter().any(|id| values.contains(id))
+    };
+    let in_price_range = match (filters.min_price, filters.max_price, product.price) {
+        (None, None, _) => true,
+        (_, _, None) => false,
+        (min_price, max_price, Some(price)) => {
+            min_price.map_or(true, |min_price| price >= min_price)
+                && max_price.map_or(true, |max_price| price <= max_price)
+        }
+    };
+    (except == Some(Facet::Category) || any_of(&filters.categories, &facts.categories))
+        && (except == Some(Facet::Warehouse) || any_of(&filters.warehouses, &facts.warehouses))
+        && (except == Some(Facet::Price) || in_price_range)
+        && filters.attributes.iter().all(|(name, values)| {
+            except == Some(Facet::Attribute(name))
+                || facts.attributes.get(name.as_str()).map_or(false, |value| {
+                    values.iter().any(|selected| selected == value)
+                })
+        })
+}
+
+/// Page of the products of the index matching all filters, and the counts of the facets
+pub fn count_facets(
+    index: &CatalogIndex,
+    filters: &ListingFilters,
+    limit: i64,
+    offset: i64,
+) -> FacetCounts {
+    let mut facts: BTreeMap<i32, ProductFacts> = BTreeMap::new();
+    for (product_id, category_id) in &index.placements {
+        facts
+            .entry(*product_id)
+            .or_default()
+            .categories
+            .insert(*category_id);
+    }
+    for (product_id, warehouse_id) in &index.stocked {
+        facts
+            .entry(*product_id)
+            .or_default()
+            .warehouses
+            .insert(*warehouse_id);
+    }
+    for attribute in &index.attributes {
+        facts
+            .entry(attribute.product_id)
+            .or_default()
+            .attributes
+            .insert(&attribute.name, &attribute.value);
+    }
+    let no_facts = ProductFacts::default();
+    let (products, facts, no_facts) = (&index.products, &facts, &no_facts);
+    let matching = |except: Option<Facet>| -> Vec<(&Product, &ProductFacts)> {
+        products
+            .iter()
+            .map(|product| (product, facts.get(&product.id).unwrap_or(no_facts)))
+            .filter(|(product, facts)| matches(product, facts, filters, except))
+            .collect()
+    };
+
+    let mut category_counts: BTreeMap<i32, i64> = BTreeMap::new();
+    for (_, facts) in matching(Some(Facet::Category)) {
+        for category_id in &facts.categories {
+            *category_counts.entry(*category_id).or_default() += 1;
+        }
+    }
+    let mut warehouse_counts: BTreeMap<i32, i64> = BTreeMap::new();
+    for (_, facts) in matching(Some(Facet::Warehouse)) {
+        for warehouse_id in &facts.warehouses {
+            *warehouse_counts.entry(*warehouse_id).or_default() += 1;
+        }
+    }
+    let prices: Vec<i32> = matching(Some(Facet::Price))
+        .into_iter()
+        .filter_map(|(product, _)| product.price)
+        .collect();
+    let attribute_names: BTreeSet<&str> = index
+        .attributes
+        .iter()
+        .map(|attribute| attribute.name.as_str())
+        .collect();
+    let attributes = attribute_names
+        .into_iter()
+        .map(|name| {
+            let mut value_counts: BTreeMap<String, i64> = BTreeMap::new();
+            for (_, facts) in matching(Some(Facet::Attribute(name))) {
+                if let Some(value) = facts.attributes.get(name) {
+                    *value_counts.entry(value.to_string()).or_default() += 1;
+                }
+            }
+            (name.to_string(), value_counts)
+        })
+        .filter(|(_, value_counts)| !value_counts.is_empty())
+        .collect();
+    let matching_products = matching(None);
+    FacetCounts {
+        total: matching_products.len() as i64,
+        products: matching_products
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(product, _)| product.clone())
+            .collect(),
+        categories: category_counts,
+        warehouses: warehouse_counts,
+        price: PriceFacet {
+            min: prices.iter().min().copied(),
+            max: prices.iter().max().copied(),
+            count: prices.len() as i64,
+        },
+        attributes,
+    }
+}
+
+/// Facets of the counts, naming the categories and warehouses
+///
+/// Archived categories are not counted, and the values filtered by are kept even without
+/// products.
+pub fn facets(
+    counts: &FacetCounts,
+    categories: &[ProductCategory],
+    warehouses: &[Warehouse],
+    filters: &ListingFilters,
+) -> Facets {
+    let attribute_names: BTreeSet<&str> = counts
+        .attributes
+        .keys()
+        .chain(filters.attributes.keys())
+        .map(String::as_str)
+        .collect();
+    let attributes = attribute_names
+        .into_iter()
+        .map(|name| {
+            let mut value_counts: BTreeMap<&str, i64> = filters
+                .attributes
+                .get(name)
+                .into_iter()
+                .flatten()
+                .map(|value| (value.as_str(), 0))
+                .collect();
+            for (value, count) in counts.attributes.get(name).into_iter().flatten() {
+                value_counts.insert(value, *count);
+            }
+            AttributeFacet {
+                name: name.to_string(),
+                values: value_counts
+                    .into_iter()
+                    .map(|(value, count)| AttributeValueFacet {
+                        value: value.to_string(),
+                        count,
+                    })
+                    .collect(),
+            }
+        })
+        .filter(|facet| !facet.values.is_empty())
+        .collect();
+    Facets {
+        categories: categories
+            .iter()
+            .filter(|category| !category.is_archived)
+            .filter_map(|category| {
+                let count = counts.categories.get(&category.id).copied().unwrap_or(0);
+                Some(CategoryFacet {
+                    category_id: category.id,
+                    name: category.name.clone(),
+                    count,
+                })
+                .filter(|_| count > 0 || filters.categories.contains(&category.id))
+            })
+            .collect(),
+        warehouses: warehouses
+            .iter()
+            .filter_map(|warehouse| {
+                let count = counts.warehouses.get(&warehouse.id).copied().unwrap_or(0);
+                Some(WarehouseFacet {
+                    warehouse_id: warehouse.id,
+                    description: warehouse.description.clone(),
+                    count,
+                })
+                .filter(|_| count > 0 || filters.warehouses.contains(&warehouse.id))
+            })
+            .collect(),
+        price: counts.price.clone(),
+        attributes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_facets, facets, CatalogIndex, ListingFilters};
+    use crate::{
+        catalog::entities::ProductAttribute,
+        entities::{Product, Warehouse},
+        product_category::entities::ProductCategory,
+    };
+    use rocket::{
+        http::RawStr,
+        request::{FormItems, FromQuery, Query},
+    };
+
+    fn category(id: i32, name: &str) -> ProductCategory {
+        ProductCategory {
+            id,
+            name: name.to_string(),
+            slug: name.to_string(),
+            description: None,
+            image_ref: None,
+            is_visible: true,
+            is_archived: false,
+        }
+    }
+
+    fn attribute(product_id: i32, name: &str, value: &str) -> ProductAttribute {
+        ProductAttribute {
+            product_id,
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn index() -> CatalogIndex {
+        let product = |id: i32, description: &str, price: Option<i32>| Product {
+            id,
+            description: description.to_string(),
+            price,
+        };
+        CatalogIndex {
+            products: vec![
+                product(1, "red shirt", Some(1500)),
+                product(2, "blue shirt", Some(2500)),
+                product(3, "red skirt", Some(4000)),
+                product(4, "gift card", None),
+            ],
+            // Clothes (1) holds shirts (2) and skirts (3)
+            placements: vec![(1, 1), (1, 2), (2, 1), (2, 2), (3, 1), (3, 3)],
+            stocked: vec![(1, 1), (2, 1), (2, 2), (3, 2)],
+            attributes: vec![
+                attribute(1, "color", "red"),
+                attribute(2, "color", "blue"),
+                attribute(3, "color", "red"),
+                attribute(1, "size", "M"),
+            ],
+        }
+    }
+
+    fn parse(query: &str) -> Result<ListingFilters, String> {
+        let items: Vec<_> = FormItems::from(RawStr::from_str(query)).collect();
+        ListingFilters::from_query(Query(&items))
+    }
+
+    #[test]
+    fn filters_are_read_from_the_query() {
+        let filters = parse("category=2&category=3&attribute=color:red&attribute=color:dark%3Ablue&min_price=100&locale=de").unwrap();
+
+        assert_eq!(vec![2, 3], filters.categories);
+        assert_eq!(Some(100), filters.min_price);
+        assert_eq!(
+            vec!["red".to_string(), "dark:blue".to_string()],
+            filters.attributes["color"]
+        );
+        assert_eq!(
+            Err("Filter warehouse=north is not a number".to_string()),
+            parse("warehouse=north")
+        );
+        assert_eq!(
+            Err("Filter attribute=red has to be like attribute=name:value".to_string()),
+            parse("attribute=red")
+        );
+        assert_eq!(
+            Err("Minimum price 500 is above the maximum price 100".to_string()),
+            parse("min_price=500&max_price=100")
+        );
+    }
+
+    #[test]
+    fn facets_are_counted_without_their_own_filter() {
+        let categories = vec![category(1, "clothes"), category(2, "shirts"), {
+            let mut skirts = category(3, "skirts");
+            skirts.is_archived = true;
+            skirts
+        }];
+        let warehouses = vec![
+            Warehouse {
+                id: 1,
+                description: "north".to_string(),
+            },
+            Warehouse {
+                id: 2,
+                description: "south".to_string(),
+            },
+        ];
+        let mut filters = parse("category=2&attribute=color:red&attribute=color:green").unwrap();
+        filters.max_price = Some(3000);
+
+        let counts = count_facets(&index(), &filters, 10, 0);
+        let (products, facets) = (
+            &counts.products,
+            facets(&counts, &categories, &warehouses, &filters),
+        );
+
+        assert_eq!(
+            vec![1],
+            products
+                .iter()
+                .map(|product| product.id)
+                .collect::<Vec<_>>()
+        );
+        // Any red product up to 3000, in whichever category
+        assert_eq!(
+            vec![(1, 1)],
+            facets
+                .categories
+                .iter()
+                .map(|facet| (facet.category_id, facet.count))
+                .filter(|(category_id, _)| *category_id == 1)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(2, 1)],
+            facets
+                .categories
+                .iter()
+                .map(|facet| (facet.category_id, facet.count))
+                .filter(|(category_id, _)| *category_id != 1)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(1, 1)],
+            facets
+                .warehouses
+                .iter()
+                .map(|facet| (facet.warehouse_id, facet.count))
+                .collect::<Vec<_>>()
+        );
+        // Red shirts at any price
+        assert_eq!((Some(1500), Some(1500), 1), {
+            let price = &facets.price;
+            (price.min, price.max, price.count)
+        });
+        let values = |name: &str| -> Vec<(String, i64)> {
+            facets
+                .attributes
+                .iter()
+                .find(|facet| facet.name == name)
+                .map(|facet| {
+                    facet
+                        .values
+                        .iter()
+                        .map(|value| (value.value.clone(), value.count))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        assert_eq!(
+            vec![
+                ("blue".to_string(), 1),
+                ("green".to_string(), 0),
+                ("red".to_string(), 1)
+            ],
+            values("color")
+        );
+        assert_eq!(vec![("M".to_string(), 1)], values("size"));
+    }
+
+    #[test]
+    fn everything_is_listed_without_filters() {
+        let filters = ListingFilters::default();
+        let counts = count_facets(&index(), &filters, 3, 1);
+        let facets = facets(&counts, &[category(1, "clothes")], &[], &filters);
+
+        assert_eq!(4, counts.total);
+        assert_eq!(
+            vec![2, 3, 4],
+            counts
+                .products
+                .iter()
+                .map(|product| product.id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(3, facets.categories[0].count);
+        assert_eq!((Some(1500), Some(4000), 3), {
+            let price = &facets.price;
+            (price.min, price.max, price.count)
+        });
+    }
+}
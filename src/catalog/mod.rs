@@ -0,0 +1,13 @@
+//! Listing of the products filtered by facets, like categories, stock, prices and attributes
+//!
+//! Along with the products matching all filters, a listing counts the products for every value
+//! of every facet. Each facet is counted under all filters but its own, so that the counts tell
+//! how many products selecting another value would add.
+pub mod attributes;
+pub mod controllers;
+pub mod entities;
+pub mod facets;
+pub mod products;
+
+pub use attributes::*;
+pub use controllers::*;
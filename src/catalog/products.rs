@@ -0,0 +1,71 @@
+//! Changes to products which are shared by the catalog and localization routes
+//!
+//! Every change runs in its own transaction and records a `product.updated` event.
+
+use crate::{outbox::EventType, repository::ProductRepository};
+use diesel::QueryResult;
+
+/// Applies the change to the product and records its new state as a `product.updated` event
+///
+/// Returns `None` without applying the change if the product does not exist. Otherwise returns
+/// what the change reported, which is whether it changed anything; no event is recorded for
+/// changes which did not.
+pub fn update_product<R, F>(repository: &R, product_id: i32, change: F) -> QueryResult<Option<bool>>
+where
+    R: ProductRepository,
+    F: FnOnce() -> QueryResult<bool>,
+{
+    repository.atomically(|| {
+        if repository.product(product_id)?.is_none() {
+            return Ok(None);
+        }
+        if !change()? {
+            return Ok(Some(false));
+        }
+        let product = repository.product(product_id)?.ok_or(diesel::NotFound)?;
+        repository.record(EventType::ProductUpdated, &product)?;
+        Ok(Some(true))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::update_product;
+    use crate::{
+        outbox::EventType,
+        repository::{MemoryRepository, ProductRepository},
+    };
+
+    #[test]
+    fn changed_product_is_recorded_with_its_new_state() {
+        let repository = MemoryRepository::new();
+        let lamp = repository.insert_product("desk lamp").unwrap();
+
+        let changed = update_product(&repository, lamp.id, || {
+            repository.set_product_price(lamp.id, Some(2500))?;
+            Ok(true)
+        })
+        .unwrap();
+
+        let events = repository.events();
+        assert_eq!(Some(true), changed);
+        assert_eq!(1, events.len());
+        assert_eq!(EventType::ProductUpdated, events[0].0);
+        assert_eq!(2500, events[0].1["price"]);
+    }
+
+    #[test]
+    fn unchanged_or_missing_product_is_not_recorded() {
+        let repository = MemoryRepository::new();
+        let lamp = repository.insert_product("desk lamp").unwrap();
+
+        let unchanged = update_product(&repository, lamp.id, || {
+            repository.delete_product_attribute(lamp.id, "color")
+        })
+        .unwrap();
+        let missing = update_product(&repository, lamp.id + 1, || Ok(true)).unwrap();
+
+        assert_eq!((Some(false), None), (unchanged, missing));
+        assert!(repository.events().is_empty());
+    }
+}
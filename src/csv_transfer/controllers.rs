@@ -0,0 +1,254 @@
+use super::{
+    export::CsvExport,
+    import::{self, parse_column_mapping, ImportOptions, ImportReport},
+    CsvResource,
+};
+use crate::{
+    auth::{Admin, Authorized, Viewer},
+    idempotency::{Idempotency, Idempotent},
+    repository::BackendConn,
+    utilities::{DbError, UploadText},
+};
+use rocket::{
+    http::{ContentType, Status},
+    response::{
+        content::Content,
+        status::{BadRequest, Custom},
+        Stream,
+    },
+};
+use rocket_contrib::json::Json;
+
+/// Streams all rows of the resource as CSV
+#[get("/<resource>")]
+pub fn get(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    resource: CsvResource,
+) -> Content<Stream<CsvExport<BackendConn>>> {
+    Content(
+        ContentType::CSV,
+        Stream::from(CsvExport::new(db_conn, resource)),
+    )
+}
+
+type ImportResponse = Result<Result<Custom<Json<ImportReport>>, BadRequest<String>>, DbError>;
+
+/// Imports a CSV file of the resource
+///
+/// Headers are mapped to columns with the `columns` parameter, e.g. `Category:name`. With
+/// `dry_run` the file is only validated. Responds with 422 and the errors of all rejected rows
+/// if the file can not be imported, in which case nothing is changed, and with 413 if the file
+/// exceeds the upload limit.
+#[post(
+    "/<resource>?<dry_run>&<columns>",
+    format = "text/csv",
+    data = "<csv_text>"
+)]
+pub fn post(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    idempotency: Idempotency,
+    resource: CsvResource,
+    dry_run: Option<bool>,
+    columns: Option<String>,
+    csv_text: UploadText,
+) -> Idempotent<ImportResponse> {
+    let UploadText(csv_text) = csv_text;
+    idempotency.run(&db_conn, &csv_text, || {
+        let column_mapping = match columns.as_deref().map(parse_column_mapping).transpose() {
+            Ok(column_mapping) => column_mapping.unwrap_or_default(),
+            Err(message) => return Ok(Err(BadRequest(Some(message)))),
+        };
+        let options = ImportOptions {
+            dry_run: dry_run.unwrap_or(false),
+            column_mapping,
+        };
+        let report = import::import(&db_conn, resource, &csv_text, &options)?;
+        let status = if report.errors.is_empty() {
+            Status::Ok
+        } else {
+            Status::UnprocessableEntity
+        };
+        Ok(Ok(Custom(status, Json(report))))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        auth::Role,
+        csv_transfer::import::ImportReport,
+        outbox::entities::OutboxEvent,
+        test_utils::{backend_test, bearer_token},
+        utilities::DEFAULT_UPLOAD_LIMIT,
+    };
+    use rocket::http::{ContentType, Status};
+
+    backend_test!(
+        imported_categories_are_linked_to_their_parents,
+        |client, repository| {
+            let mut response = client
+                .post("/csv/categories?columns=Category:name,Belongs%20to:parents")
+                .header(ContentType::CSV)
+                .header(bearer_token(Role::Admin))
+                .body(
+                    "Category,Belongs to\nShirts,Clothes\nClothes,\nSkirts,Clothes;Women\nWomen,\n",
+                )
+                .dispatch();
+            let report: ImportReport = serde_json::from_str(&response.body_string().unwrap())?;
+
+            assert_eq!(Status::Ok, response.status());
+            assert!(report.committed);
+            assert_eq!((4, 3), (report.created, report.linked));
+            assert_eq!(4, repository.categories()?.len());
+            assert_eq!(3, repository.rollups()?.len());
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        file_above_the_upload_limit_is_rejected_instead_of_truncated,
+        |client, repository| {
+            let row = "A shirt,\n";
+            let rows = DEFAULT_UPLOAD_LIMIT as usize / row.len() + 1;
+
+            let response = client
+                .post("/csv/products")
+                .header(ContentType::CSV)
+                .header(bearer_token(Role::Admin))
+                .body(format!("description,categories\n{}", row.repeat(rows)))
+                .dispatch();
+
+            assert_eq!(Status::PayloadTooLarge, response.status());
+            assert!(repository.products()?.is_empty());
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        import_with_invalid_rows_reports_them_and_changes_nothing,
+        |client, repository| {
+            let mut response = client
+                .post("/csv/products")
+                .header(ContentType::CSV)
+                .header(bearer_token(Role::Admin))
+                .body("description,categories\nA book,\nA shirt,Shirts\n,\n")
+                .dispatch();
+            let report: ImportReport = serde_json::from_str(&response.body_string().unwrap())?;
+
+            let rejected_rows: Vec<u64> = report.errors.iter().map(|error| error.row).collect();
+            assert_eq!(Status::UnprocessableEntity, response.status());
+            assert!(!report.committed);
+            assert_eq!(vec![3, 4], rejected_rows);
+            assert!(repository.products()?.is_empty());
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        dry_run_reports_changes_without_committing,
+        |client, repository| {
+            let mut response = client
+                .post("/csv/categories?dry_run=true")
+                .header(ContentType::CSV)
+                .header(bearer_token(Role::Admin))
+                .body("name\nShirts\nSkirts\n")
+                .dispatch();
+            let report: ImportReport = serde_json::from_str(&response.body_string().unwrap())?;
+
+            assert_eq!(Status::Ok, response.status());
+            assert!(!report.committed);
+            assert_eq!(2, report.created);
+            assert!(repository.categories()?.is_empty());
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        exported_products_list_their_categories,
+        |client, _repository| {
+            client
+                .post("/csv/categories")
+                .header(ContentType::CSV)
+                .header(bearer_token(Role::Admin))
+                .body("name\nBooks\nNovels\n")
+                .dispatch();
+            let import_response = client
+                .post("/csv/products")
+                .header(ContentType::CSV)
+                .header(bearer_token(Role::Admin))
+                .body("description,categories,primary_category\nA novel,Books;Novels,Novels\n")
+                .dispatch();
+
+            let mut export_response = client
+                .get("/csv/products")
+                .header(bearer_token(Role::Viewer))
+                .dispatch();
+            let exported = export_response.body_string().unwrap();
+
+            assert_eq!(Status::Ok, import_response.status());
+            assert_eq!(Some(ContentType::CSV), export_response.content_type());
+            let rows: Vec<&str> = exported.lines().collect();
+            assert_eq!("id,description,categories,primary_category", rows[0]);
+            assert!(rows[1].ends_with(",A novel,Books;Novels,Novels"));
+            assert_eq!(2, rows.len());
+            Ok(())
+        }
+    );
+
+    backend_test!(export_is_read_from_one_snapshot, |client, repository| {
+        let names: Vec<String> = (0..600)
+            .map(|index| format!("category {:03}", index))
+            .collect();
+        let name_refs: Vec<&str> = names.iter().map(String::as_str).collect();
+        repository.insert_missing_categories(&name_refs)?;
+
+        let mut export_response = client
+            .get("/csv/categories")
+            .header(bearer_token(Role::Viewer))
+            .dispatch();
+        let body = export_response.body().unwrap().into_inner();
+        let mut first_byte = [0; 1];
+        body.read_exact(&mut first_byte)?;
+        repository.insert_category("added during the export")?;
+        let mut exported = String::from_utf8(first_byte.to_vec())?;
+        body.read_to_string(&mut exported)?;
+
+        assert_eq!(601, exported.lines().count());
+        assert!(!exported.contains("added during the export"));
+        Ok(())
+    });
+
+    backend_test!(
+        imported_products_and_stock_are_published_as_events,
+        |client, repository| {
+            client
+                .post("/csv/products")
+                .header(ContentType::CSV)
+                .header(bearer_token(Role::Admin))
+                .body("description\nA novel\n")
+                .dispatch();
+            let novel_id = repository.products()?[0].id;
+            client
+                .post("/csv/stock")
+                .header(ContentType::CSV)
+                .header(bearer_token(Role::Admin))
+                .body(format!("product_id\n{}\n", novel_id))
+                .dispatch();
+
+            let origin = crate::events::broadcaster::StreamPosition {
+                transaction_id: 0,
+                event_id: 0,
+            };
+            let events: Vec<OutboxEvent> =
+                repository.events_after(origin, repository.stream_horizon()?, 10)?;
+            let event_types: Vec<&str> = events
+                .iter()
+                .map(|event| event.event_type.as_str())
+                .collect();
+            assert_eq!(vec!["product.created", "stock.changed"], event_types);
+            Ok(())
+        }
+    );
+}
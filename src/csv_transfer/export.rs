@@ -0,0 +1,221 @@
+use super::{join_list, CsvResource};
+use crate::repository::{CategoryRepository, ProductRepository, StockRepository, Transactional};
+use diesel::QueryResult;
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+};
+
+/// Number of rows loaded from the database at once while exporting
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+fn database_error(error: diesel::result::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error)
+}
+
+/// Sorted names of the categories with the ids
+fn sorted_names(
+    names: &HashMap<i32, String>,
+    category_ids: impl Iterator<Item = i32>,
+) -> Vec<String> {
+    let mut sorted: Vec<String> = category_ids
+        .filter_map(|id| names.get(&id).cloned())
+        .collect();
+    sorted.sort();
+    sorted
+}
+
+/// Reader which exports a resource as CSV
+///
+/// The rows are loaded page by page as the export is read, so that exports of large catalogs
+/// can be streamed without holding them in memory. Each page loads the names of the categories
+/// its rows refer to. All pages are read in one snapshot, so that changes made during the export
+/// do not mix different states of the data.
+pub struct CsvExport<R: Transactional> {
+    repository: R,
+    resource: CsvResource,
+    after_id: i32,
+    in_snapshot: bool,
+    buffer: Vec<u8>,
+    position: usize,
+    header_written: bool,
+    finished: bool,
+}
+
+impl<R: CategoryRepository + ProductRepository + StockRepository> CsvExport<R> {
+    pub fn new(repository: R, resource: CsvResource) -> Self {
+        CsvExport {
+            repository,
+            resource,
+            after_id: 0,
+            in_snapshot: false,
+            buffer: Vec::new(),
+            position: 0,
+            header_written: false,
+            finished: false,
+        }
+    }
+
+    fn load_page(&mut self) -> io::Result<()> {
+        if !self.in_snapshot {
+            self.repository.begin_snapshot().map_err(database_error)?;
+            self.in_snapshot = true;
+        }
+        let rows = match self.resource {
+            CsvResource::Categories => self.category_rows(),
+            CsvResource::Products => self.product_rows(),
+            CsvResource::Stock => self.stock_rows(),
+        }
+        .map_err(database_error)?;
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        if !self.header_written {
+            writer.write_record(self.resource.columns())?;
+            self.header_written = true;
+        }
+        for (id, row) in &rows {
+            writer.write_record(row)?;
+            self.after_id = *id;
+        }
+        self.finished = (rows.len() as i64) < EXPORT_PAGE_SIZE;
+        if self.finished {
+            self.in_snapshot = false;
+            self.repository.end_snapshot().map_err(database_error)?;
+        }
+        self.buffer = writer.into_inner().map_err(|error| error.into_error())?;
+        self.position = 0;
+        Ok(())
+    }
+
+    /// Names of the categories with the ids
+    fn names_by_id(&self, category_ids: &[i32]) -> QueryResult<HashMap<i32, String>> {
+        Ok(self
+            .repository
+            .categories_with_ids(category_ids)?
+            .into_iter()
+            .map(|category| (category.id, category.name))
+            .collect())
+    }
+
+    fn category_rows(&self) -> QueryResult<Vec<(i32, Vec<String>)>> {
+        let categories = self
+            .repository
+            .categories_after(self.after_id, EXPORT_PAGE_SIZE)?;
+        let category_ids: Vec<i32> = categories.iter().map(|category| category.id).collect();
+        let rollups = self.repository.rollups_of(&category_ids)?;
+        let parent_ids: Vec<i32> = rollups.iter().map(|(upper_id, _)| *upper_id).collect();
+        let category_names = self.names_by_id(&parent_ids)?;
+        Ok(categories
+            .into_iter()
+            .map(|category| {
+                let parent_names = sorted_names(
+                    &category_names,
+                    rollups
+                        .iter()
+                        .filter(|(_, lower_id)| *lower_id == category.id)
+                        .map(|(upper_id, _)| *upper_id),
+                );
+                (
+                    category.id,
+                    vec![
+                        category.id.to_string(),
+                        category.name,
+                        join_list(&parent_names),
+                    ],
+                )
+            })
+            .collect())
+    }
+
+    fn product_rows(&self) -> QueryResult<Vec<(i32, Vec<String>)>> {
+        let products = self
+            .repository
+            .products_after(self.after_id, EXPORT_PAGE_SIZE)?;
+        let product_ids: Vec<i32> = products.iter().map(|product| product.id).collect();
+        let classifications = self.repository.classifications_of(&product_ids)?;
+        let category_ids: Vec<i32> = classifications
+            .iter()
+            .map(|classification| classification.product_category_id)
+            .collect();
+        let names = self.names_by_id(&category_ids)?;
+        Ok(products
+            .into_iter()
+            .map(|product| {
+                let classifications_of_product = classifications
+                    .iter()
+                    .filter(|classification| classification.product_id == product.id);
+                let category_names = sorted_names(
+                    &names,
+                    classifications_of_product
+                        .clone()
+                        .map(|classification| classification.product_category_id),
+                );
+                let primary_category = sorted_names(
+                    &names,
+                    classifications_of_product
+                        .filter(|classification| classification.is_primary_classification)
+                        .map(|classification| classification.product_category_id),
+                )
+                .into_iter()
+                .next()
+                .unwrap_or_default();
+                (
+                    product.id,
+                    vec![
+                        product.id.to_string(),
+                        product.description,
+                        join_list(&category_names),
+                        primary_category,
+                    ],
+                )
+            })
+            .collect())
+    }
+
+    fn stock_rows(&self) -> QueryResult<Vec<(i32, Vec<String>)>> {
+        let items = self
+            .repository
+            .inventory_items_after(self.after_id, EXPORT_PAGE_SIZE)?;
+        Ok(items
+            .into_iter()
+            .map(|item| {
+                (
+                    item.id,
+                    vec![
+                        item.id.to_string(),
+                        item.product_id.to_string(),
+                        item.warehouse_id
+                            .map_or_else(String::new, |value| value.to_string()),
+                        item.instance_description.unwrap_or_default(),
+                    ],
+                )
+            })
+            .collect())
+    }
+}
+
+impl<R: Transactional> Drop for CsvExport<R> {
+    /// Ends the snapshot of an export which was not read to the end, like when the client went away
+    fn drop(&mut self) {
+        if self.in_snapshot {
+            if let Err(error) = self.repository.end_snapshot() {
+                log::error!("Ending the snapshot of a CSV export failed: {}", error);
+            }
+        }
+    }
+}
+
+impl<R: CategoryRepository + ProductRepository + StockRepository> Read for CsvExport<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.load_page()?;
+        }
+        let count = buf.len().min(self.buffer.len() - self.position);
+        buf[..count].copy_from_slice(&self.buffer[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
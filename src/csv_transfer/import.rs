@@ -0,0 +1,577 @@
+use super::{split_list, CsvResource};
+use crate::{
+    entities::InventoryItem,
+    outbox::EventType,
+    product_category::entities::ProductCategory,
+    repository::{
+        CategoryRepository, ProductRepository, StockRepository, Transactional, WarehouseRepository,
+    },
+};
+use csv::{ReaderBuilder, StringRecord, Trim};
+use diesel::{
+    result::Error::{DatabaseError, NotFound, RollbackTransaction},
+    QueryResult,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug)]
+/// Reason for which a row of an imported file was rejected
+///
+/// Rows are numbered by their line in the file, so the header is row 1.
+pub struct RowError {
+    pub row: u64,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Default)]
+/// Outcome of an import
+///
+/// The counts describe the changes the import made, or would have made if it is a dry run or
+/// was rejected.
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub committed: bool,
+    pub rows: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub linked: usize,
+    pub errors: Vec<RowError>,
+}
+
+impl ImportReport {
+    fn reject(&mut self, row: u64, message: String) {
+        self.errors.push(RowError { row, message });
+    }
+
+    fn count(&mut self, change: Change) {
+        match change {
+            Change::Created => self.created += 1,
+            Change::Updated => self.updated += 1,
+            Change::Unchanged => {}
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ImportOptions {
+    /// Validates the file and reports the changes without committing them
+    pub dry_run: bool,
+    /// Maps headers of the file to the columns of the resource
+    pub column_mapping: HashMap<String, String>,
+}
+
+/// Parses a mapping of headers to columns in the form `Header:column,Other header:column`
+pub fn parse_column_mapping(mapping: &str) -> Result<HashMap<String, String>, String> {
+    mapping
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(
+            |pair| match pair.rsplitn(2, ':').collect::<Vec<_>>().as_slice() {
+                [column, header] => Ok((header.trim().to_string(), column.trim().to_string())),
+                _ => Err(format!(
+                    "Column mapping must have the form header:column: {}",
+                    pair
+                )),
+            },
+        )
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+enum Change {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+enum RowFailure {
+    Invalid(String),
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for RowFailure {
+    fn from(error: diesel::result::Error) -> Self {
+        RowFailure::Database(error)
+    }
+}
+
+struct Row {
+    number: u64,
+    values: HashMap<&'static str, String>,
+}
+
+impl Row {
+    fn get(&self, column: &str) -> Option<&str> {
+        self.values
+            .get(column)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn require(&self, column: &str) -> Result<&str, RowFailure> {
+        self.get(column)
+            .ok_or_else(|| RowFailure::Invalid(format!("Missing value for column {}", column)))
+    }
+
+    fn integer(&self, column: &str) -> Result<Option<i32>, RowFailure> {
+        self.get(column)
+            .map(|value| {
+                value.parse().map_err(|_| {
+                    RowFailure::Invalid(format!("Column {} is not a number: {}", column, value))
+                })
+            })
+            .transpose()
+    }
+
+    fn list(&self, column: &str) -> Vec<&str> {
+        self.get(column).map(split_list).unwrap_or_default()
+    }
+}
+
+/// Imports a CSV file of the resource
+///
+/// The file is imported as a whole or not at all: if any row is rejected, none of the changes
+/// are committed and the report lists the errors of all rows.
+pub fn import<R>(
+    repository: &R,
+    resource: CsvResource,
+    csv_text: &str,
+    options: &ImportOptions,
+) -> QueryResult<ImportReport>
+where
+    R: CategoryRepository + ProductRepository + StockRepository + WarehouseRepository,
+{
+    let mut report = ImportReport {
+        dry_run: options.dry_run,
+        ..ImportReport::default()
+    };
+    let outcome = repository.atomically(|| {
+        let rows = read_rows(resource, csv_text, &options.column_mapping, &mut report);
+        match resource {
+            CsvResource::Categories => import_categories(repository, &rows, &mut report)?,
+            CsvResource::Products => import_products(repository, &rows, &mut report)?,
+            CsvResource::Stock => import_stock(repository, &rows, &mut report)?,
+        }
+        if report.errors.is_empty() && !options.dry_run {
+            Ok(())
+        } else {
+            Err(RollbackTransaction)
+        }
+    });
+    match outcome {
+        Ok(()) => report.committed = true,
+        Err(RollbackTransaction) => {}
+        Err(error) => return Err(error),
+    }
+    report.errors.sort_by_key(|error| error.row);
+    Ok(report)
+}
+
+/// Maps the headers of a file to the columns of the resource
+///
+/// Headers which are not mapped explicitly are matched with the columns ignoring case.
+fn map_headers(
+    resource: CsvResource,
+    headers: &StringRecord,
+    column_mapping: &HashMap<String, String>,
+) -> Result<Vec<&'static str>, String> {
+    let mut columns = Vec::new();
+    for header in headers.iter() {
+        let target = column_mapping
+            .get(header)
+            .map(String::as_str)
+            .unwrap_or(header)
+            .to_lowercase();
+        let column = resource
+            .columns()
+            .iter()
+            .find(|column| **column == target)
+            .ok_or_else(|| format!("Unknown column for {}: {}", resource, header))?;
+        if columns.contains(column) {
+            return Err(format!("Column {} appears more than once", column));
+        }
+        columns.push(*column);
+    }
+    for required in resource.required_columns() {
+        if !columns.contains(required) {
+            return Err(format!("Missing required column: {}", required));
+        }
+    }
+    Ok(columns)
+}
+
+fn read_rows(
+    resource: CsvResource,
+    csv_text: &str,
+    column_mapping: &HashMap<String, String>,
+    report: &mut ImportReport,
+) -> Vec<Row> {
+    let mut reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .from_reader(csv_text.as_bytes());
+    let columns = match reader
+        .headers()
+        .map_err(|error| error.to_string())
+        .and_then(|headers| map_headers(resource, headers, column_mapping))
+    {
+        Ok(columns) => columns,
+        Err(message) => {
+            report.reject(1, message);
+            return Vec::new();
+        }
+    };
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        report.rows += 1;
+        match record {
+            Ok(record) => rows.push(Row {
+                number: record.position().map_or(0, |position| position.line()),
+                values: columns
+                    .iter()
+                    .copied()
+                    .zip(record.iter().map(String::from))
+                    .collect(),
+            }),
+            Err(error) => report.reject(
+                error.position().map_or(0, |position| position.line()),
+                error.to_string(),
+            ),
+        }
+    }
+    rows
+}
+
+/// Runs the import of a single row in a savepoint, so that a rejected row does not abort the
+/// transaction of the import and the remaining rows can still be validated
+fn import_row<R: Transactional, T>(
+    repository: &R,
+    import: impl FnOnce() -> Result<T, RowFailure>,
+) -> QueryResult<Result<T, String>> {
+    let mut invalid = None;
+    let imported = repository.atomically(|| {
+        import().map_err(|failure| match failure {
+            RowFailure::Invalid(message) => {
+                invalid = Some(message);
+                RollbackTransaction
+            }
+            RowFailure::Database(error) => error,
+        })
+    });
+    match (imported, invalid) {
+        (Ok(value), _) => Ok(Ok(value)),
+        (Err(RollbackTransaction), Some(message)) => Ok(Err(message)),
+        (Err(DatabaseError(_, information)), _) => Ok(Err(information.message().to_string())),
+        (Err(error), _) => Err(error),
+    }
+}
+
+fn load_category_ids<R: CategoryRepository>(repository: &R) -> QueryResult<HashMap<String, i32>> {
+    Ok(repository
+        .categories()?
+        .into_iter()
+        .map(|category| (category.name, category.id))
+        .collect())
+}
+
+fn resolve_category(category_ids: &HashMap<String, i32>, name: &str) -> Result<i32, RowFailure> {
+    category_ids
+        .get(name)
+        .copied()
+        .ok_or_else(|| RowFailure::Invalid(format!("Unknown category: {}", name)))
+}
+
+fn import_categories<R: CategoryRepository>(
+    repository: &R,
+    rows: &[Row],
+    report: &mut ImportReport,
+) -> QueryResult<()> {
+    let mut imported_categories = Vec::new();
+    for row in rows {
+        let imported = import_row(repository, || {
+            let name = row.require("name")?;
+            match row.integer("id")? {
+                Some(id) => {
+                    let existing = repository.category(id)?.ok_or_else(|| {
+                        RowFailure::Invalid(format!("Unknown category id: {}", id))
+                    })?;
+                    if existing.name == name {
+                        return Ok((id, Change::Unchanged));
+                    }
+                    repository.save_categories(&[ProductCategory {
+                        name: name.to_string(),
+                        ..existing
+                    }])?;
+                    let updated_category = repository.category(id)?.ok_or(NotFound)?;
+                    repository.record(EventType::CategoryUpdated, &updated_category)?;
+                    Ok((id, Change::Updated))
+                }
+                None => {
+                    if let Some(existing) = repository.categories_named(&[name])?.pop() {
+                        return Ok((existing.id, Change::Unchanged));
+                    }
+                    let created_category = repository.insert_category(name)?;
+                    repository.record(EventType::CategoryCreated, &created_category)?;
+                    Ok((created_category.id, Change::Created))
+                }
+            }
+        })?;
+        match imported {
+            Ok((id, change)) => {
+                report.count(change);
+                imported_categories.push((row, id));
+            }
+            Err(message) => report.reject(row.number, message),
+        }
+    }
+
+    // Parents are resolved once all categories of the file exist, so that a category may name a
+    // parent which appears further down in the file
+    let category_ids = load_category_ids(repository)?;
+    for (row, category_id) in imported_categories {
+        for parent in row.list("parents") {
+            let linked = import_row(repository, || {
+                let parent_id = resolve_category(&category_ids, parent)?;
+                if parent_id == category_id {
+                    return Err(RowFailure::Invalid(format!(
+                        "Category can not be its own parent: {}",
+                        parent
+                    )));
+                }
+                Ok(link_categories(repository, parent_id, category_id)?)
+            })?;
+            match linked {
+                Ok(true) => report.linked += 1,
+                Ok(false) => {}
+                Err(message) => report.reject(row.number, message),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rolls the lower category up into the upper category, unless it already is
+///
+/// Returns whether a new link was created.
+fn link_categories<R: CategoryRepository>(
+    repository: &R,
+    upper_id: i32,
+    lower_id: i32,
+) -> QueryResult<bool> {
+    if repository
+        .rollups_of(&[lower_id])?
+        .contains(&(upper_id, lower_id))
+    {
+        return Ok(false);
+    }
+    repository.insert_rollup(upper_id, lower_id)?;
+    Ok(true)
+}
+
+fn import_products<R: CategoryRepository + ProductRepository>(
+    repository: &R,
+    rows: &[Row],
+    report: &mut ImportReport,
+) -> QueryResult<()> {
+    let category_ids = load_category_ids(repository)?;
+    for row in rows {
+        let imported = import_row(repository, || {
+            let description = row.require("description")?;
+            let primary_category = row.get("primary_category");
+            let mut classified_ids = Vec::new();
+            for category in row.list("categories").into_iter().chain(primary_category) {
+                let category_id = resolve_category(&category_ids, category)?;
+                if !classified_ids.contains(&category_id) {
+                    classified_ids.push(category_id);
+                }
+            }
+
+            let (product_id, change) = match row.integer("id")? {
+                Some(id) => {
+                    let existing = repository.product(id)?.ok_or_else(|| {
+                        RowFailure::Invalid(format!("Unknown product id: {}", id))
+                    })?;
+                    if existing.description == description {
+                        (id, Change::Unchanged)
+                    } else {
+                        repository.set_product_description(id, description)?;
+                        (id, Change::Updated)
+                    }
+                }
+                None => (repository.insert_product(description)?.id, Change::Created),
+            };
+
+            let mut linked = 0;
+            for category_id in classified_ids {
+                if classify_product(repository, product_id, category_id)? {
+                    linked += 1;
+                }
+            }
+            if let Some(primary_category) = primary_category {
+                let primary_id = resolve_category(&category_ids, primary_category)?;
+                mark_primary_classification(repository, product_id, primary_id)?;
+            }
+            let event_type = match &change {
+                Change::Created => Some(EventType::ProductCreated),
+                Change::Updated => Some(EventType::ProductUpdated),
+                Change::Unchanged if linked > 0 => Some(EventType::ProductUpdated),
+                Change::Unchanged => None,
+            };
+            if let Some(event_type) = event_type {
+                let product = repository.product(product_id)?.ok_or(NotFound)?;
+                repository.record(event_type, &product)?;
+            }
+            Ok((change, linked))
+        })?;
+        match imported {
+            Ok((change, linked)) => {
+                report.count(change);
+                report.linked += linked;
+            }
+            Err(message) => report.reject(row.number, message),
+        }
+    }
+    Ok(())
+}
+
+/// Classifies the product into the category, unless it already is
+///
+/// Returns whether a new classification was created.
+fn classify_product<R: ProductRepository>(
+    repository: &R,
+    product_id: i32,
+    category_id: i32,
+) -> QueryResult<bool> {
+    if repository
+        .classifications_of(&[product_id])?
+        .iter()
+        .any(|classification| classification.product_category_id == category_id)
+    {
+        return Ok(false);
+    }
+    repository.classify(product_id, category_id, false)?;
+    Ok(true)
+}
+
+fn mark_primary_classification<R: ProductRepository>(
+    repository: &R,
+    product_id: i32,
+    primary_category_id: i32,
+) -> QueryResult<()> {
+    for classification in repository.classifications_of(&[product_id])? {
+        let is_primary = classification.product_category_id == primary_category_id;
+        if classification.is_primary_classification != is_primary {
+            repository.update_classification(
+                classification.id,
+                classification.product_category_id,
+                is_primary,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn import_stock<R: ProductRepository + StockRepository + WarehouseRepository>(
+    repository: &R,
+    rows: &[Row],
+    report: &mut ImportReport,
+) -> QueryResult<()> {
+    for row in rows {
+        let imported = import_row(repository, || {
+            let product_id = row.integer("product_id")?.ok_or_else(|| {
+                RowFailure::Invalid("Missing value for column product_id".to_string())
+            })?;
+            let warehouse_id = row.integer("warehouse_id")?;
+            let instance_description = row.get("instance_description").map(String::from);
+            if repository.product(product_id)?.is_none() {
+                return Err(RowFailure::Invalid(format!(
+                    "Unknown product id: {}",
+                    product_id
+                )));
+            }
+            if let Some(warehouse_id) = warehouse_id {
+                if repository.warehouse(warehouse_id)?.is_none() {
+                    return Err(RowFailure::Invalid(format!(
+                        "Unknown warehouse id: {}",
+                        warehouse_id
+                    )));
+                }
+            }
+
+            match row.integer("id")? {
+                Some(id) => {
+                    let existing = repository.inventory_item(id)?.ok_or_else(|| {
+                        RowFailure::Invalid(format!("Unknown inventory item id: {}", id))
+                    })?;
+                    let item = InventoryItem {
+                        id,
+                        product_id,
+                        instance_description,
+                        warehouse_id,
+                    };
+                    if existing == item {
+                        return Ok(Change::Unchanged);
+                    }
+                    repository.update_inventory_item(&item)?;
+                    repository.record(EventType::StockChanged, &item)?;
+                    Ok(Change::Updated)
+                }
+                None => {
+                    let item = repository.insert_inventory_item(
+                        product_id,
+                        warehouse_id,
+                        instance_description.as_deref(),
+                    )?;
+                    repository.record(EventType::StockChanged, &item)?;
+                    Ok(Change::Created)
+                }
+            }
+        })?;
+        match imported {
+            Ok(change) => report.count(change),
+            Err(message) => report.reject(row.number, message),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{map_headers, parse_column_mapping};
+    use crate::csv_transfer::CsvResource;
+    use csv::StringRecord;
+    use std::collections::HashMap;
+
+    #[test]
+    fn headers_match_columns_ignoring_case() {
+        let headers = StringRecord::from(vec!["Name", "PARENTS"]);
+        assert_eq!(
+            Ok(vec!["name", "parents"]),
+            map_headers(CsvResource::Categories, &headers, &HashMap::new())
+        );
+    }
+
+    #[test]
+    fn mapped_headers_are_renamed_to_columns() {
+        let mapping = parse_column_mapping("Category name:name, Belongs to:parents").unwrap();
+        let headers = StringRecord::from(vec!["Category name", "Belongs to"]);
+        assert_eq!(
+            Ok(vec!["name", "parents"]),
+            map_headers(CsvResource::Categories, &headers, &mapping)
+        );
+    }
+
+    #[test]
+    fn unknown_and_missing_columns_are_rejected() {
+        let unknown = StringRecord::from(vec!["name", "colour"]);
+        let missing = StringRecord::from(vec!["parents"]);
+        assert!(map_headers(CsvResource::Categories, &unknown, &HashMap::new()).is_err());
+        assert!(map_headers(CsvResource::Categories, &missing, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn malformed_column_mapping_is_rejected() {
+        assert!(parse_column_mapping("name").is_err());
+    }
+}
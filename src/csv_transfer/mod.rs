@@ -0,0 +1,103 @@
+//! Import and export of the catalog as CSV files
+//!
+//! Every resource has a fixed set of columns. Columns which hold several values, such as the
+//! parent categories of a category, separate them with [`LIST_SEPARATOR`], and categories are
+//! always referred to by name, so that files can be maintained in a spreadsheet.
+
+pub mod controllers;
+pub mod export;
+pub mod import;
+
+pub use controllers::*;
+
+use rocket::{http::RawStr, request::FromParam};
+use std::{fmt, str::FromStr};
+
+/// Separator of the values in columns which hold several values
+pub const LIST_SEPARATOR: char = ';';
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+/// Resources which can be imported and exported as CSV
+pub enum CsvResource {
+    Categories,
+    Products,
+    Stock,
+}
+
+impl CsvResource {
+    pub const ALL: &'static [CsvResource] = &[
+        CsvResource::Categories,
+        CsvResource::Products,
+        CsvResource::Stock,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CsvResource::Categories => "categories",
+            CsvResource::Products => "products",
+            CsvResource::Stock => "stock",
+        }
+    }
+
+    /// Columns of the resource, in the order in which they are exported
+    pub fn columns(self) -> &'static [&'static str] {
+        match self {
+            CsvResource::Categories => &["id", "name", "parents"],
+            CsvResource::Products => &["id", "description", "categories", "primary_category"],
+            CsvResource::Stock => &["id", "product_id", "warehouse_id", "instance_description"],
+        }
+    }
+
+    /// Columns which every imported file has to contain
+    pub fn required_columns(self) -> &'static [&'static str] {
+        match self {
+            CsvResource::Categories => &["name"],
+            CsvResource::Products => &["description"],
+            CsvResource::Stock => &["product_id"],
+        }
+    }
+}
+
+impl fmt::Display for CsvResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CsvResource {
+    type Err = String;
+
+    fn from_str(resource: &str) -> Result<Self, Self::Err> {
+        CsvResource::ALL
+            .iter()
+            .find(|known| known.as_str() == resource)
+            .copied()
+            .ok_or_else(|| format!("Unknown resource: {}", resource))
+    }
+}
+
+impl<'a> FromParam<'a> for CsvResource {
+    type Error = String;
+
+    fn from_param(param: &'a RawStr) -> Result<Self, Self::Error> {
+        param.as_str().parse()
+    }
+}
+
+/// Splits a column holding several values into its trimmed, non-empty values
+pub fn split_list(value: &str) -> Vec<&str> {
+    value
+        .split(LIST_SEPARATOR)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Joins values into a column holding several values
+pub fn join_list<S: AsRef<str>>(values: &[S]) -> String {
+    values
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>()
+        .join(&LIST_SEPARATOR.to_string())
+}
@@ -1,6 +1,10 @@
 use super::schema::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-#[derive(Queryable, Identifiable)]
+#[derive(
+    Queryable, Identifiable, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug,
+)]
 #[table_name = "product"]
 /// A product which is sold by the company
 ///
@@ -9,11 +13,13 @@ use super::schema::*;
 /// other information, such as how much stock of this product do we have, or which competitor too
 /// sells this product.
 pub struct Product {
-    id: i32,
-    description: String,
+    pub id: i32,
+    pub description: String,
+    /// Price in the smallest unit of the currency, like cents, unless the product is not priced
+    pub price: Option<i32>,
 }
 
-#[derive(Queryable, Identifiable, Associations)]
+#[derive(Queryable, Identifiable, Associations, Clone, PartialEq, Eq, Debug)]
 #[belongs_to(Product, foreign_key = "product_id")]
 #[belongs_to(
     crate::product_category::entities::ProductCategory,
@@ -24,13 +30,13 @@ pub struct Product {
 ///
 /// Specifies to which categories does a product belong.
 pub struct ProductCategoryClassification {
-    id: i32,
-    product_id: i32,
-    product_category_id: i32,
-    is_primary_classification: bool,
+    pub id: i32,
+    pub product_id: i32,
+    pub product_category_id: i32,
+    pub is_primary_classification: bool,
 }
 
-#[derive(Queryable, Identifiable, Associations)]
+#[derive(Queryable, Identifiable, Associations, Clone, PartialEq, Eq, Debug)]
 #[table_name = "product_category_rollup"]
 /// Hierarchy of product categories
 ///
@@ -41,12 +47,14 @@ pub struct ProductCategoryClassification {
 /// Note category should not be related to children of its children. Basically the relationship of
 /// categories should not be transitive.
 pub struct ProductCategoryRollup {
-    id: i32,
-    upper_category_id: i32,
-    lower_category_id: i32,
+    pub id: i32,
+    pub upper_category_id: i32,
+    pub lower_category_id: i32,
+    /// Order of the lower category among the subcategories of the upper category
+    pub position: i32,
 }
 
-#[derive(Queryable, Identifiable)]
+#[derive(Queryable, Identifiable, Serialize, Clone, PartialEq, Eq, Debug)]
 #[table_name = "inventory_item"]
 /// Instance of product
 ///
@@ -57,7 +65,16 @@ pub struct ProductCategoryRollup {
 /// Note currently this type represents a serializable product. This means that each instance of a
 /// product is considered to be unique. There is currently no concept of "lot" or "stack" of product
 pub struct InventoryItem {
-    id: i32,
-    product_id: i32,
-    instance_description: String,
+    pub id: i32,
+    pub product_id: i32,
+    pub instance_description: Option<String>,
+    pub warehouse_id: Option<i32>,
+}
+
+#[derive(Queryable, Identifiable, Clone, PartialEq, Eq, Debug)]
+#[table_name = "warehouse"]
+/// Location at which inventory items are stocked
+pub struct Warehouse {
+    pub id: i32,
+    pub description: String,
 }
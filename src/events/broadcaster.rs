@@ -0,0 +1,387 @@
+#[cfg(feature = "postgres")]
+use crate::tenancy::{clear_session_scope, scope_session};
+use crate::{
+    outbox::entities::OutboxEvent,
+    repository::{memory::MemoryDatabase, OutboxRepository},
+    settings::Backend,
+};
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use diesel::Connection;
+#[cfg(feature = "postgres")]
+use diesel::PgConnection;
+use diesel::QueryResult;
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    Rocket,
+};
+#[cfg(feature = "postgres")]
+use std::collections::BTreeSet;
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Largest number of events loaded per tenant in one poll of the outbox
+const POLL_BATCH_SIZE: i64 = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+/// Place of an event in the stream, which is ordered by transaction and then by event id
+///
+/// Ids are taken from a sequence when events are recorded, so a transaction which commits late
+/// can add an event below the id of events which were already streamed. Ordering by the
+/// transaction instead, and only streaming transactions older than every running one, never
+/// skips such an event.
+pub struct StreamPosition {
+    pub transaction_id: i64,
+    pub event_id: i64,
+}
+
+impl StreamPosition {
+    pub fn of(event: &OutboxEvent) -> Self {
+        StreamPosition {
+            transaction_id: event.transaction_id,
+            event_id: event.id,
+        }
+    }
+
+    /// Position before the events of all transactions which are still running
+    pub fn current<R: OutboxRepository>(repository: &R) -> QueryResult<Self> {
+        Ok(StreamPosition {
+            transaction_id: repository.stream_horizon()?,
+            event_id: 0,
+        })
+    }
+}
+
+struct Subscriber {
+    tenant_id: Option<i32>,
+    topics: Option<Vec<String>>,
+    position: StreamPosition,
+    sender: Sender<Arc<OutboxEvent>>,
+}
+
+impl Subscriber {
+    fn wants(&self, event: &OutboxEvent) -> bool {
+        StreamPosition::of(event) > self.position
+            && self.topics.as_ref().map_or(true, |topics| {
+                topics
+                    .iter()
+                    .any(|topic| topic == topic_of(&event.event_type))
+            })
+    }
+}
+
+/// Topic of an event type, which is the part before the first dot, e.g. `category`
+pub fn topic_of(event_type: &str) -> &str {
+    event_type.split('.').next().unwrap_or(event_type)
+}
+
+#[derive(Clone, Default)]
+/// Forwards the events appearing in the outbox to the subscribed clients of this instance
+pub struct EventBroadcaster {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl EventBroadcaster {
+    /// Subscribes to the events of the tenant which follow the position in the stream
+    ///
+    /// The tenant is the one of [`OutboxRepository::outbox_tenant_id`]. Only events of the given
+    /// topics are received, or all events if no topics are given.
+    pub fn subscribe(
+        &self,
+        tenant_id: Option<i32>,
+        topics: Option<Vec<String>>,
+        position: StreamPosition,
+    ) -> Receiver<Arc<OutboxEvent>> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(Subscriber {
+            tenant_id,
+            topics,
+            position,
+            sender,
+        });
+        receiver
+    }
+
+    /// Loads new events of every subscribed tenant and forwards them to the subscribers
+    #[cfg(feature = "postgres")]
+    pub fn poll_tenants(&self, connection: &PgConnection) -> QueryResult<usize> {
+        let tenant_ids: BTreeSet<i32> = self
+            .subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|subscriber| subscriber.tenant_id)
+            .collect();
+        let mut loaded = 0;
+        for tenant_id in tenant_ids {
+            if scope_session(connection, tenant_id)? {
+                loaded += self.poll(connection)?;
+            }
+        }
+        clear_session_scope(connection)?;
+        Ok(loaded)
+    }
+
+    /// Loads new events from the outbox and forwards them to the subscribers of its tenant
+    ///
+    /// Only events of transactions which are older than every running transaction are loaded,
+    /// so a long running transaction holds back the stream until it finishes. Returns the
+    /// number of events which were loaded.
+    pub fn poll<R: OutboxRepository>(&self, repository: &R) -> QueryResult<usize> {
+        let tenant_id = repository.outbox_tenant_id()?;
+        let cursor = self
+            .subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|subscriber| subscriber.tenant_id == tenant_id)
+            .map(|subscriber| subscriber.position)
+            .min();
+        let cursor = match cursor {
+            Some(cursor) => cursor,
+            None => return Ok(0),
+        };
+
+        let horizon = repository.stream_horizon()?;
+        let events: Vec<Arc<OutboxEvent>> = repository
+            .events_after(cursor, horizon, POLL_BATCH_SIZE)?
+            .into_iter()
+            .map(Arc::new)
+            .collect();
+
+        let caught_up = StreamPosition {
+            transaction_id: horizon,
+            event_id: 0,
+        };
+        self.subscribers.lock().unwrap().retain_mut(|subscriber| {
+            if subscriber.tenant_id != tenant_id {
+                return true;
+            }
+            for event in events.iter().filter(|event| subscriber.wants(event)) {
+                if subscriber.sender.send(event.clone()).is_err() {
+                    return false;
+                }
+            }
+            // A partial batch holds all events of the finished transactions
+            subscriber.position = match events.last() {
+                Some(last_event) if events.len() as i64 == POLL_BATCH_SIZE => {
+                    subscriber.position.max(StreamPosition::of(last_event))
+                }
+                _ => subscriber.position.max(caught_up),
+            };
+            true
+        });
+        Ok(events.len())
+    }
+
+    #[cfg_attr(
+        not(any(feature = "postgres", feature = "sqlite")),
+        allow(unused_variables)
+    )]
+    fn spawn_poller(&self, backend: Backend, database_url: String, poll_interval: Duration) {
+        let broadcaster = self.clone();
+        thread::spawn(move || match backend {
+            #[cfg(feature = "postgres")]
+            Backend::Postgres => poll_on(&database_url, poll_interval, |connection| {
+                broadcaster.poll_tenants(connection)
+            }),
+            #[cfg(not(feature = "postgres"))]
+            Backend::Postgres => {
+                log::error!("Event stream is not started: Postgres is not built in")
+            }
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => poll_on(
+                &database_url,
+                poll_interval,
+                |connection: &diesel::SqliteConnection| {
+                    crate::repository::sqlite::prepare(connection)?;
+                    broadcaster.poll(connection)
+                },
+            ),
+            #[cfg(not(feature = "sqlite"))]
+            Backend::Sqlite => log::error!("Event stream is not started: SQLite is not built in"),
+            Backend::Memory => {
+                log::error!("Event stream is not started: the in-memory database has no URL")
+            }
+        });
+    }
+
+    /// Polls the outbox of the in-memory database, which can not fail to connect
+    fn spawn_memory_poller(&self, database: MemoryDatabase, poll_interval: Duration) {
+        let broadcaster = self.clone();
+        thread::spawn(move || {
+            let repository = database.connect();
+            loop {
+                if let Err(error) = broadcaster.poll(&repository) {
+                    log::error!("Polling of events failed: {}", error);
+                }
+                thread::sleep(poll_interval);
+            }
+        });
+    }
+}
+
+/// Polls on a connection of its own, which is established again after a failure
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+fn poll_on<C, F>(database_url: &str, poll_interval: Duration, poll: F) -> !
+where
+    C: Connection,
+    F: Fn(&C) -> QueryResult<usize>,
+{
+    let mut connection: Option<C> = None;
+    loop {
+        if connection.is_none() {
+            connection = C::establish(database_url)
+                .map_err(|error| log::error!("Event stream can not connect: {}", error))
+                .ok();
+        }
+        if let Some(established) = &connection {
+            if let Err(error) = poll(established) {
+                log::error!("Polling of events failed: {}", error);
+                connection = None;
+            }
+        }
+        thread::sleep(poll_interval);
+    }
+}
+
+/// Manages the [`EventBroadcaster`] and starts polling the outbox when Rocket launches
+///
+/// The polling interval is set in milliseconds with `event_poll_interval_ms`.
+pub fn fairing() -> impl Fairing {
+    EventStreamFairing {
+        broadcaster: EventBroadcaster::default(),
+    }
+}
+
+struct EventStreamFairing {
+    broadcaster: EventBroadcaster,
+}
+
+impl Fairing for EventStreamFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Event stream",
+            kind: Kind::Attach | Kind::Launch,
+        }
+    }
+
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        Ok(rocket.manage(self.broadcaster.clone()))
+    }
+
+    fn on_launch(&self, rocket: &Rocket) {
+        let config = rocket.config();
+        let poll_interval = config
+            .get_int("event_poll_interval_ms")
+            .unwrap_or(500)
+            .max(10);
+        let backend = Backend::from_rocket_config(config);
+        if let Some(database) = rocket.state::<MemoryDatabase>() {
+            self.broadcaster.spawn_memory_poller(
+                database.clone(),
+                Duration::from_millis(poll_interval as u64),
+            );
+            return;
+        }
+        match backend.database_url(config) {
+            Ok(url) => self.broadcaster.spawn_poller(
+                backend,
+                url,
+                Duration::from_millis(poll_interval as u64),
+            ),
+            Err(error) => log::error!("Event stream is not started: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{topic_of, EventBroadcaster, StreamPosition};
+    #[cfg(feature = "postgres")]
+    use crate::outbox;
+    use crate::{outbox::EventType, test_utils::backend_test};
+    #[cfg(feature = "postgres")]
+    use diesel::{connection::TransactionManager, Connection, PgConnection};
+    #[cfg(feature = "postgres")]
+    use testcontainers::clients::Cli;
+
+    #[test]
+    fn topic_is_prefix_of_event_type() {
+        assert_eq!("category", topic_of("category.created"));
+        assert_eq!("stock", topic_of("stock"));
+    }
+
+    backend_test!(
+        subscriber_receives_new_events_of_its_topics_once,
+        |_client, repository| {
+            let broadcaster = EventBroadcaster::default();
+            let start = StreamPosition {
+                transaction_id: 0,
+                event_id: 0,
+            };
+            repository.record(EventType::CategoryCreated, &1)?;
+            let first_event = repository
+                .events_after(start, repository.stream_horizon()?, 1)?
+                .remove(0);
+
+            let tenant_id = repository.outbox_tenant_id()?;
+            let category_events =
+                broadcaster.subscribe(tenant_id, Some(vec!["category".to_string()]), start);
+            let stock_events =
+                broadcaster.subscribe(tenant_id, Some(vec!["stock".to_string()]), start);
+            let resumed_events =
+                broadcaster.subscribe(tenant_id, None, StreamPosition::of(&first_event));
+            repository.record(EventType::CategoryDeleted, &1)?;
+            broadcaster.poll(repository)?;
+            broadcaster.poll(repository)?;
+
+            let second_event = repository
+                .events_after(
+                    StreamPosition::of(&first_event),
+                    repository.stream_horizon()?,
+                    1,
+                )?
+                .remove(0);
+            let received_ids: Vec<i64> = category_events.try_iter().map(|event| event.id).collect();
+            let resumed_ids: Vec<i64> = resumed_events.try_iter().map(|event| event.id).collect();
+            assert_eq!(vec![first_event.id, second_event.id], received_ids);
+            assert_eq!(vec![second_event.id], resumed_ids);
+            assert_eq!(0, stock_events.try_iter().count());
+            Ok(())
+        }
+    );
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn event_of_transaction_committing_late_is_not_skipped(
+    ) -> Result<(), crate::migrations::MigrationError> {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (_, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+        let late_connection = PgConnection::establish(&database_metadata.url).unwrap();
+        crate::tenancy::scope_session(&late_connection, 1)?;
+        let broadcaster = EventBroadcaster::default();
+        let events = broadcaster.subscribe(Some(1), None, StreamPosition::current(&connection)?);
+
+        let late_transaction = late_connection.transaction_manager();
+        late_transaction.begin_transaction(&late_connection)?;
+        let late_event = outbox::record(&late_connection, EventType::CategoryCreated, &1)?;
+        let early_event = outbox::record(&connection, EventType::CategoryCreated, &2)?;
+        broadcaster.poll_tenants(&connection)?;
+        let received_while_running: Vec<i64> = events.try_iter().map(|event| event.id).collect();
+        late_transaction.commit_transaction(&late_connection)?;
+        broadcaster.poll_tenants(&connection)?;
+        let received_after_commit: Vec<i64> = events.try_iter().map(|event| event.id).collect();
+
+        assert!(late_event.id < early_event.id);
+        assert!(received_while_running.is_empty());
+        assert_eq!(vec![late_event.id, early_event.id], received_after_commit);
+        Ok(())
+    }
+}
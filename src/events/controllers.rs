@@ -0,0 +1,132 @@
+use super::{
+    broadcaster::{EventBroadcaster, StreamPosition},
+    stream::EventStream,
+};
+use crate::{
+    auth::{Authorized, Viewer},
+    repository::{BackendConn, OutboxRepository},
+    utilities::DbError,
+};
+use rocket::{
+    request::{self, FromRequest},
+    Outcome, Request, State,
+};
+use std::time::Duration;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Id of the last event a reconnecting client has received, from the `Last-Event-ID` header
+pub struct LastEventId(pub Option<i64>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for LastEventId {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let last_event_id = request
+            .headers()
+            .get_one("Last-Event-ID")
+            .and_then(|value| value.trim().parse().ok());
+        Outcome::Success(LastEventId(last_event_id))
+    }
+}
+
+/// Streams the events of the tenant as server-sent events
+///
+/// Clients which reconnect with a `Last-Event-ID` receive all events they missed, other clients
+/// receive the events recorded after they connected. Clients whose last event is gone receive
+/// all events of the transactions which recorded later events. The `types` parameter is a comma separated
+/// list of topics, such as `category`, which limits the stream to events of those topics.
+#[get("/?<types>")]
+pub fn get(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    last_event_id: LastEventId,
+    types: Option<String>,
+    broadcaster: State<EventBroadcaster>,
+) -> Result<EventStream, DbError> {
+    let position = match last_event_id.0 {
+        Some(last_event_id) => match db_conn.outbox_event(last_event_id)? {
+            Some(last_event) => StreamPosition::of(&last_event),
+            None => match db_conn.first_transaction_after(last_event_id)? {
+                Some(first_transaction_id) => StreamPosition {
+                    transaction_id: first_transaction_id,
+                    event_id: 0,
+                },
+                None => StreamPosition::current(&db_conn)?,
+            },
+        },
+        None => StreamPosition::current(&db_conn)?,
+    };
+    let topics = types.map(|types| {
+        types
+            .split(',')
+            .map(str::trim)
+            .filter(|topic| !topic.is_empty())
+            .map(String::from)
+            .collect()
+    });
+    let receiver = broadcaster.subscribe(db_conn.outbox_tenant_id()?, topics, position);
+    Ok(EventStream::new(receiver, HEARTBEAT_INTERVAL))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        auth::Role,
+        events::EventBroadcaster,
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::http::{ContentType, Header, Status};
+    use std::{
+        io::ErrorKind,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    backend_test!(stream_resumes_after_last_event_id, |client, repository| {
+        client
+            .post("/productcategory")
+            .header(ContentType::JSON)
+            .header(bearer_token(Role::Admin))
+            .body("{\"name\":\"shirts\"}")
+            .dispatch();
+
+        let mut response = client
+            .get("/events?types=category")
+            .header(bearer_token(Role::Viewer))
+            .header(Header::new("Last-Event-ID", "0"))
+            .dispatch();
+        let content_type = response.content_type();
+        client
+            .rocket()
+            .state::<EventBroadcaster>()
+            .unwrap()
+            .poll(repository)?;
+
+        let body = response.body().unwrap().into_inner();
+        let mut received = String::new();
+        let mut buffer = [0; 256];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !received.contains("data: ") || !received.ends_with("\n\n") {
+            assert!(
+                Instant::now() < deadline,
+                "No event was streamed in time, received: {:?}",
+                received
+            );
+            match body.read(&mut buffer) {
+                Ok(0) => panic!("Event stream ended after: {:?}", received),
+                Ok(count) => received.push_str(std::str::from_utf8(&buffer[..count]).unwrap()),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                Err(error) => panic!("Reading the event stream failed: {}", error),
+            }
+        }
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(ContentType::new("text", "event-stream")), content_type);
+        assert!(received.contains("event: category.created\n"));
+        assert!(received.contains("\"name\":\"shirts\""));
+        Ok(())
+    });
+}
@@ -0,0 +1,10 @@
+//! Live stream of the domain events recorded in the outbox
+//!
+//! Every server instance polls the outbox and forwards new events to the clients connected to
+//! it, so clients see the same events no matter which instance serves them.
+pub mod broadcaster;
+pub mod controllers;
+pub mod stream;
+
+pub use broadcaster::{fairing, EventBroadcaster};
+pub use controllers::*;
@@ -0,0 +1,79 @@
+use crate::outbox::entities::OutboxEvent;
+use rocket::{
+    http::ContentType,
+    response::{self, Responder},
+    Request, Response,
+};
+use std::{
+    io::{self, Read},
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Formats an event in the `text/event-stream` format, using the outbox id as event id
+pub fn format_event(event: &OutboxEvent) -> String {
+    format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        event.id, event.event_type, event.payload
+    )
+}
+
+/// Body of a server-sent events response
+///
+/// Every event is followed by a `WouldBlock` error, which makes Rocket flush the event to the
+/// client instead of waiting for its buffer to fill up. A comment is sent whenever no event
+/// arrived within the heartbeat interval, so that disconnected clients are noticed.
+pub struct EventStream {
+    receiver: Receiver<Arc<OutboxEvent>>,
+    heartbeat_interval: Duration,
+    pending: Vec<u8>,
+    position: usize,
+    flush_requested: bool,
+}
+
+impl EventStream {
+    pub fn new(receiver: Receiver<Arc<OutboxEvent>>, heartbeat_interval: Duration) -> Self {
+        EventStream {
+            receiver,
+            heartbeat_interval,
+            pending: b"retry: 3000\n\n".to_vec(),
+            position: 0,
+            flush_requested: false,
+        }
+    }
+}
+
+impl Read for EventStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.pending.len() {
+            if self.flush_requested {
+                self.flush_requested = false;
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "flush event"));
+            }
+            self.pending = match self.receiver.recv_timeout(self.heartbeat_interval) {
+                Ok(event) => format_event(&event).into_bytes(),
+                Err(RecvTimeoutError::Timeout) => b": keep-alive\n\n".to_vec(),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            };
+            self.position = 0;
+        }
+        let count = buf.len().min(self.pending.len() - self.position);
+        buf[..count].copy_from_slice(&self.pending[self.position..self.position + count]);
+        self.position += count;
+        self.flush_requested = self.position == self.pending.len();
+        Ok(count)
+    }
+}
+
+impl<'r> Responder<'r> for EventStream {
+    fn respond_to(self, _: &Request) -> response::Result<'r> {
+        Response::build()
+            .header(ContentType::new("text", "event-stream"))
+            .raw_header("Cache-Control", "no-cache")
+            .streamed_body(self)
+            .ok()
+    }
+}
@@ -0,0 +1,107 @@
+//! Sample catalog for development and demonstration databases
+//!
+//! The categories and products are imported through the CSV import, so that fixtures and
+//! imported catalogs end up in the same shape.
+
+use crate::csv_transfer::{
+    import::{import, ImportOptions, ImportReport},
+    CsvResource,
+};
+use diesel::{
+    dsl::exists, select, ExpressionMethods, PgConnection, QueryDsl, QueryResult, RunQueryDsl,
+};
+
+const CATEGORIES: &str = include_str!("categories.csv");
+const PRODUCTS: &str = include_str!("products.csv");
+const WAREHOUSES: &[&str] = &["Main warehouse", "Outlet"];
+/// Number of items of every product stocked in each warehouse
+const ITEMS_PER_WAREHOUSE: &[i32] = &[3, 1];
+
+/// Changes made by seeding, per imported resource
+pub struct SeedSummary {
+    pub categories: ImportReport,
+    pub products: Option<ImportReport>,
+    pub warehouses: usize,
+    pub inventory_items: usize,
+}
+
+/// Seeds the sample catalog into the tenant the connection is scoped to
+///
+/// Categories are created if they are missing. Products, warehouses and stock are only seeded
+/// into a tenant without products, so that seeding twice does not duplicate them.
+pub fn seed(connection: &PgConnection) -> QueryResult<SeedSummary> {
+    use crate::schema::{inventory_item, product, warehouse};
+    let options = ImportOptions::default();
+    let categories = import(connection, CsvResource::Categories, CATEGORIES, &options)?;
+    let mut summary = SeedSummary {
+        categories,
+        products: None,
+        warehouses: 0,
+        inventory_items: 0,
+    };
+    if select(exists(product::table.select(product::id))).get_result(connection)? {
+        return Ok(summary);
+    }
+    summary.products = Some(import(
+        connection,
+        CsvResource::Products,
+        PRODUCTS,
+        &options,
+    )?);
+
+    let warehouse_ids: Vec<i32> = diesel::insert_into(warehouse::table)
+        .values(
+            WAREHOUSES
+                .iter()
+                .map(|description| warehouse::description.eq(*description))
+                .collect::<Vec<_>>(),
+        )
+        .returning(warehouse::id)
+        .get_results(connection)?;
+    let product_ids: Vec<i32> = product::table.select(product::id).load(connection)?;
+    let mut items = Vec::new();
+    for product_id in product_ids {
+        for (warehouse_id, quantity) in warehouse_ids.iter().zip(ITEMS_PER_WAREHOUSE) {
+            for _ in 0..*quantity {
+                items.push((
+                    inventory_item::product_id.eq(product_id),
+                    inventory_item::warehouse_id.eq(*warehouse_id),
+                ));
+            }
+        }
+    }
+    summary.warehouses = warehouse_ids.len();
+    summary.inventory_items = diesel::insert_into(inventory_item::table)
+        .values(&items)
+        .execute(connection)?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::seed;
+    use diesel::prelude::*;
+    use testcontainers::clients::Cli;
+
+    #[test]
+    fn seeding_twice_does_not_duplicate_the_catalog(
+    ) -> Result<(), crate::migrations::MigrationError> {
+        use crate::schema::{inventory_item, product, product_category};
+
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (_, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+
+        let first_summary = seed(&connection)?;
+        let second_summary = seed(&connection)?;
+
+        let categories: i64 = product_category::table.count().get_result(&connection)?;
+        let products: i64 = product::table.count().get_result(&connection)?;
+        let items: i64 = inventory_item::table.count().get_result(&connection)?;
+        assert!(first_summary.categories.committed);
+        assert!(first_summary.products.unwrap().committed);
+        assert!(second_summary.products.is_none());
+        assert_eq!((8, 5, 20), (categories, products, items));
+        Ok(())
+    }
+}
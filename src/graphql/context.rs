@@ -0,0 +1,316 @@
+use super::loaders::BatchLoader;
+use crate::{
+    auth::Role,
+    entities::{
+        InventoryItem, Product, ProductCategoryClassification, ProductCategoryRollup, Warehouse,
+    },
+    product_category::entities::ProductCategory,
+    repository::{
+        BackendConn, CategoryRepository, ProductRepository, StockRepository, WarehouseRepository,
+    },
+};
+use diesel::QueryResult;
+use juniper::{FieldError, FieldResult};
+use std::{cell::RefCell, collections::HashMap};
+
+#[derive(Default)]
+struct Loaders {
+    categories: BatchLoader<ProductCategory>,
+    products: BatchLoader<Product>,
+    warehouses: BatchLoader<Warehouse>,
+    subcategories: BatchLoader<ProductCategory>,
+    parent_categories: BatchLoader<ProductCategory>,
+    category_classifications: BatchLoader<ProductCategoryClassification>,
+    product_classifications: BatchLoader<ProductCategoryClassification>,
+    product_items: BatchLoader<InventoryItem>,
+    warehouse_items: BatchLoader<InventoryItem>,
+}
+
+/// State of a single GraphQL request
+///
+/// Holds the connection and the loaders which batch the queries of nested fields.
+/// Every query is only cached for the duration of the request.
+pub struct Context {
+    connection: BackendConn,
+    role: Role,
+    loaders: RefCell<Loaders>,
+}
+
+impl juniper::Context for Context {}
+
+/// Pairs every value with a key taken from it, as expected by [`BatchLoader::load`]
+fn keyed<V>(values: Vec<V>, key: impl Fn(&V) -> i32) -> Vec<(i32, V)> {
+    values
+        .into_iter()
+        .map(|value| (key(&value), value))
+        .collect()
+}
+
+/// Replaces the linked id of every `(key, linked id)` pair with the category it refers to
+fn linked_categories(
+    connection: &BackendConn,
+    links: Vec<(i32, i32)>,
+) -> QueryResult<Vec<(i32, ProductCategory)>> {
+    let linked_ids: Vec<i32> = links.iter().map(|(_, linked_id)| *linked_id).collect();
+    let categories: HashMap<i32, ProductCategory> = connection
+        .categories_with_ids(&linked_ids)?
+        .into_iter()
+        .map(|category| (category.id, category))
+        .collect();
+    Ok(links
+        .into_iter()
+        .filter_map(|(key, linked_id)| {
+            categories
+                .get(&linked_id)
+                .map(|category| (key, category.clone()))
+        })
+        .collect())
+}
+
+impl Context {
+    pub fn new(connection: BackendConn, role: Role) -> Self {
+        Context {
+            connection,
+            role,
+            loaders: RefCell::new(Loaders::default()),
+        }
+    }
+
+    pub fn connection(&self) -> &BackendConn {
+        &self.connection
+    }
+
+    /// Fails unless the principal of the request holds at least the role
+    pub fn require_role(&self, role: Role) -> FieldResult<()> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(FieldError::new(
+                format!("The {} role is required", role),
+                juniper::Value::null(),
+            ))
+        }
+    }
+
+    /// Registers loaded categories, so that their nested fields are loaded in batches
+    pub fn categories_loaded(&self, categories: &[ProductCategory]) {
+        let mut loaders = self.loaders.borrow_mut();
+        for category in categories {
+            loaders.categories.prime(category.id, category.clone());
+        }
+        let ids: Vec<i32> = categories.iter().map(|category| category.id).collect();
+        loaders.subcategories.register(ids.iter().copied());
+        loaders.parent_categories.register(ids.iter().copied());
+        loaders.category_classifications.register(ids);
+    }
+
+    /// Registers loaded products, so that their nested fields are loaded in batches
+    pub fn products_loaded(&self, products: &[Product]) {
+        let mut loaders = self.loaders.borrow_mut();
+        for product in products {
+            loaders.products.prime(product.id, product.clone());
+        }
+        let ids: Vec<i32> = products.iter().map(|product| product.id).collect();
+        loaders
+            .product_classifications
+            .register(ids.iter().copied());
+        loaders.product_items.register(ids);
+    }
+
+    /// Registers loaded warehouses, so that their nested fields are loaded in batches
+    pub fn warehouses_loaded(&self, warehouses: &[Warehouse]) {
+        let mut loaders = self.loaders.borrow_mut();
+        for warehouse in warehouses {
+            loaders.warehouses.prime(warehouse.id, warehouse.clone());
+        }
+        loaders
+            .warehouse_items
+            .register(warehouses.iter().map(|warehouse| warehouse.id));
+    }
+
+    /// Registers loaded classifications, so that their products and categories are batched
+    pub fn classifications_loaded(&self, classifications: &[ProductCategoryClassification]) {
+        let mut loaders = self.loaders.borrow_mut();
+        loaders.products.register(
+            classifications
+                .iter()
+                .map(|classification| classification.product_id),
+        );
+        loaders.categories.register(
+            classifications
+                .iter()
+                .map(|classification| classification.product_category_id),
+        );
+    }
+
+    /// Registers loaded rollups, so that their categories are batched
+    pub fn rollups_loaded(&self, rollups: &[ProductCategoryRollup]) {
+        let mut loaders = self.loaders.borrow_mut();
+        for rollup in rollups {
+            loaders
+                .categories
+                .register(vec![rollup.upper_category_id, rollup.lower_category_id]);
+        }
+    }
+
+    /// Registers loaded inventory items, so that their products and warehouses are batched
+    pub fn items_loaded(&self, items: &[InventoryItem]) {
+        let mut loaders = self.loaders.borrow_mut();
+        loaders
+            .products
+            .register(items.iter().map(|item| item.product_id));
+        loaders
+            .warehouses
+            .register(items.iter().filter_map(|item| item.warehouse_id));
+    }
+
+    pub fn category(&self, category_id: i32) -> QueryResult<Option<ProductCategory>> {
+        let connection = &self.connection;
+        let categories = self
+            .loaders
+            .borrow_mut()
+            .categories
+            .load(category_id, |ids| {
+                let categories = connection.categories_with_ids(ids)?;
+                Ok(keyed(categories, |category: &ProductCategory| category.id))
+            })?;
+        self.categories_loaded(&categories);
+        Ok(categories.into_iter().next())
+    }
+
+    pub fn product(&self, product_id: i32) -> QueryResult<Option<Product>> {
+        let connection = &self.connection;
+        let products = self.loaders.borrow_mut().products.load(product_id, |ids| {
+            let products = connection.products_with_ids(ids)?;
+            Ok(keyed(products, |product: &Product| product.id))
+        })?;
+        self.products_loaded(&products);
+        Ok(products.into_iter().next())
+    }
+
+    pub fn warehouse(&self, warehouse_id: i32) -> QueryResult<Option<Warehouse>> {
+        let connection = &self.connection;
+        let warehouses = self
+            .loaders
+            .borrow_mut()
+            .warehouses
+            .load(warehouse_id, |ids| {
+                let warehouses = connection.warehouses_with_ids(ids)?;
+                Ok(keyed(warehouses, |warehouse: &Warehouse| warehouse.id))
+            })?;
+        self.warehouses_loaded(&warehouses);
+        Ok(warehouses.into_iter().next())
+    }
+
+    pub fn subcategories(&self, category_id: i32) -> QueryResult<Vec<ProductCategory>> {
+        let connection = &self.connection;
+        let subcategories = self
+            .loaders
+            .borrow_mut()
+            .subcategories
+            .load(category_id, |ids| {
+                let links = connection
+                    .rollups_of(ids)?
+                    .into_iter()
+                    .filter(|(upper_id, _)| ids.contains(upper_id))
+                    .collect();
+                linked_categories(connection, links)
+            })?;
+        self.categories_loaded(&subcategories);
+        Ok(subcategories)
+    }
+
+    pub fn parent_categories(&self, category_id: i32) -> QueryResult<Vec<ProductCategory>> {
+        let connection = &self.connection;
+        let parent_categories =
+            self.loaders
+                .borrow_mut()
+                .parent_categories
+                .load(category_id, |ids| {
+                    let links = connection
+                        .rollups_of(ids)?
+                        .into_iter()
+                        .filter(|(_, lower_id)| ids.contains(lower_id))
+                        .map(|(upper_id, lower_id)| (lower_id, upper_id))
+                        .collect();
+                    let mut parents = linked_categories(connection, links)?;
+                    parents.sort_by_key(|(_, parent)| parent.id);
+                    Ok(parents)
+                })?;
+        self.categories_loaded(&parent_categories);
+        Ok(parent_categories)
+    }
+
+    pub fn category_classifications(
+        &self,
+        category_id: i32,
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        let connection = &self.connection;
+        let classifications =
+            self.loaders
+                .borrow_mut()
+                .category_classifications
+                .load(category_id, |ids| {
+                    let classifications = connection.classifications_in(ids)?;
+                    Ok(keyed(
+                        classifications,
+                        |classification: &ProductCategoryClassification| {
+                            classification.product_category_id
+                        },
+                    ))
+                })?;
+        self.classifications_loaded(&classifications);
+        Ok(classifications)
+    }
+
+    pub fn product_classifications(
+        &self,
+        product_id: i32,
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        let connection = &self.connection;
+        let classifications =
+            self.loaders
+                .borrow_mut()
+                .product_classifications
+                .load(product_id, |ids| {
+                    let classifications = connection.classifications_of(ids)?;
+                    Ok(keyed(
+                        classifications,
+                        |classification: &ProductCategoryClassification| classification.product_id,
+                    ))
+                })?;
+        self.classifications_loaded(&classifications);
+        Ok(classifications)
+    }
+
+    pub fn product_items(&self, product_id: i32) -> QueryResult<Vec<InventoryItem>> {
+        let connection = &self.connection;
+        let items = self
+            .loaders
+            .borrow_mut()
+            .product_items
+            .load(product_id, |ids| {
+                let items = connection.inventory_items_of(ids)?;
+                Ok(keyed(items, |item: &InventoryItem| item.product_id))
+            })?;
+        self.items_loaded(&items);
+        Ok(items)
+    }
+
+    pub fn warehouse_items(&self, warehouse_id: i32) -> QueryResult<Vec<InventoryItem>> {
+        let connection = &self.connection;
+        let items = self
+            .loaders
+            .borrow_mut()
+            .warehouse_items
+            .load(warehouse_id, |ids| {
+                let items = connection.inventory_items_in(ids)?;
+                Ok(items
+                    .into_iter()
+                    .filter_map(|item| item.warehouse_id.map(|key| (key, item)))
+                    .collect())
+            })?;
+        self.items_loaded(&items);
+        Ok(items)
+    }
+}
@@ -0,0 +1,197 @@
+use super::{
+    context::Context,
+    limits::{check_limits, QueryLimits},
+    schema::Schema,
+};
+use crate::{
+    auth::{Authorized, Viewer},
+    idempotency::{Idempotency, Idempotent},
+    repository::BackendConn,
+};
+use juniper::{
+    http::{GraphQLRequest, GraphQLResponse},
+    FieldError, InputValue,
+};
+use rocket::{http::Status, response::status::Custom, State};
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GraphQLRequestBody {
+    pub query: String,
+    #[serde(rename = "operationName", default)]
+    pub operation_name: Option<String>,
+    #[serde(default)]
+    #[schemars(with = "Option<serde_json::Map<String, serde_json::Value>>")]
+    pub variables: Option<InputValue>,
+}
+
+type GraphQLResult = Result<Custom<Json<serde_json::Value>>, serde_json::Error>;
+
+/// Executes a GraphQL query or mutation
+///
+/// Responds with 400 if the query is invalid or exceeds the configured limits. Like the
+/// mutations of the REST API, mutations are applied once per `Idempotency-Key`.
+#[post("/", format = "json", data = "<request>")]
+pub fn post(
+    db_conn: BackendConn,
+    viewer: Authorized<Viewer>,
+    idempotency: Idempotency,
+    schema: State<Schema>,
+    limits: State<QueryLimits>,
+    request: Json<GraphQLRequestBody>,
+) -> Idempotent<GraphQLResult> {
+    let request = request.into_inner();
+    let context = Context::new(db_conn, viewer.principal.role);
+    idempotency.run(context.connection(), &request, || {
+        if let Err(message) = check_limits(&request.query, &limits) {
+            let response: GraphQLResponse =
+                GraphQLResponse::error(FieldError::new(message, juniper::Value::null()));
+            return Ok(Custom(
+                Status::BadRequest,
+                Json(serde_json::to_value(response)?),
+            ));
+        }
+        let graphql_request = GraphQLRequest::new(
+            request.query.clone(),
+            request.operation_name.clone(),
+            request.variables.clone(),
+        );
+        let response = graphql_request.execute(&schema, &context);
+        let status = if response.is_ok() {
+            Status::Ok
+        } else {
+            Status::BadRequest
+        };
+        Ok(Custom(status, Json(serde_json::to_value(response)?)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        auth::Role,
+        idempotency::{IDEMPOTENCY_KEY_HEADER, REPLAYED_HEADER},
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::{
+        http::{ContentType, Header, Status},
+        local::{Client, LocalResponse},
+    };
+    use serde_json::Value;
+
+    fn graphql_query<'c>(client: &'c Client, role: Role, query: &str) -> LocalResponse<'c> {
+        client
+            .post("/graphql")
+            .header(ContentType::JSON)
+            .header(bearer_token(role))
+            .body(serde_json::json!({ "query": query }).to_string())
+            .dispatch()
+    }
+
+    backend_test!(
+        category_is_fetched_with_subcategories_products_and_stock,
+        |client, repository| {
+            let clothes = repository.insert_category("clothes")?;
+            let shirts = repository.insert_category("shirts")?;
+            repository.insert_rollup(clothes.id, shirts.id)?;
+            let white_shirt = repository.insert_product("white shirt")?;
+            repository.classify(white_shirt.id, shirts.id, true)?;
+            let north = repository.insert_warehouse("north")?;
+            repository.insert_inventory_item(white_shirt.id, Some(north.id), None)?;
+            repository.insert_inventory_item(white_shirt.id, Some(north.id), None)?;
+
+            let mut response = graphql_query(
+                client,
+                Role::Viewer,
+                &format!(
+                    "{{ productCategory(id: {}) {{ name subcategories {{ name products {{ \
+                     description stock {{ warehouse {{ description }} quantity }} }} }} }} }}",
+                    clothes.id
+                ),
+            );
+            let body: Value = serde_json::from_str(&response.body_string().unwrap())?;
+
+            assert_eq!(Status::Ok, response.status());
+            assert_eq!(
+                serde_json::json!({ "productCategory": { "name": "clothes", "subcategories": [{
+                    "name": "shirts",
+                    "products": [{
+                        "description": "white shirt",
+                        "stock": [{ "warehouse": { "description": "north" }, "quantity": 2 }]
+                    }]
+                }]}}),
+                body["data"]
+            );
+            Ok(())
+        }
+    );
+
+    backend_test!(mutations_require_the_admin_role, |client, repository| {
+        let mutation = "mutation { createProductCategory(name: \"shirts\") { id name } }";
+
+        let mut viewer_response = graphql_query(client, Role::Viewer, mutation);
+        let viewer_body: Value = serde_json::from_str(&viewer_response.body_string().unwrap())?;
+        let mut admin_response = graphql_query(client, Role::Admin, mutation);
+        let admin_body: Value = serde_json::from_str(&admin_response.body_string().unwrap())?;
+
+        let names: Vec<String> = repository
+            .categories()?
+            .into_iter()
+            .map(|category| category.name)
+            .collect();
+        assert!(viewer_body["errors"].is_array());
+        assert_eq!(
+            serde_json::json!("shirts"),
+            admin_body["data"]["createProductCategory"]["name"]
+        );
+        assert_eq!(vec!["shirts"], names);
+        Ok(())
+    });
+
+    backend_test!(retried_mutation_is_applied_once, |client, repository| {
+        let shirts = repository.insert_category("shirts")?;
+        let mutation = format!("mutation {{ deleteProductCategory(id: {}) }}", shirts.id);
+        let retry = || {
+            client
+                .post("/graphql")
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Admin))
+                .header(Header::new(IDEMPOTENCY_KEY_HEADER, "mutation-1"))
+                .body(serde_json::json!({ "query": mutation }).to_string())
+                .dispatch()
+        };
+
+        let mut first_response = retry();
+        let mut repeated_response = retry();
+
+        assert_eq!(Status::Ok, first_response.status());
+        assert!(repository.categories()?.is_empty());
+        assert_eq!(
+            Some("true"),
+            repeated_response.headers().get_one(REPLAYED_HEADER)
+        );
+        assert_eq!(
+            first_response.body_string(),
+            repeated_response.body_string()
+        );
+        Ok(())
+    });
+
+    backend_test!(
+        query_exceeding_depth_limit_is_rejected,
+        |client, _repository| {
+            let nested_subcategories = "subcategories { ".repeat(20) + "id" + &" }".repeat(20);
+
+            let response = graphql_query(
+                client,
+                Role::Viewer,
+                &format!("{{ productCategories {{ {} }} }}", nested_subcategories),
+            );
+
+            assert_eq!(Status::BadRequest, response.status());
+            Ok(())
+        }
+    );
+}
@@ -0,0 +1,185 @@
+use graphql_parser::query::{
+    parse_query, Definition, FragmentDefinition, OperationDefinition, Selection, SelectionSet,
+};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// Bounds on the size of the queries which are executed
+///
+/// The depth is the deepest nesting of fields, the complexity is the number of fields which are
+/// resolved for each object, counting the fields of fragments wherever they are spread.
+pub struct QueryLimits {
+    pub max_depth: usize,
+    pub max_complexity: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        QueryLimits {
+            max_depth: 15,
+            max_complexity: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+struct Measure {
+    depth: usize,
+    complexity: usize,
+}
+
+type Fragments<'a> = HashMap<&'a str, &'a FragmentDefinition<'a, &'a str>>;
+
+/// Measures selection sets, measuring every fragment only once
+///
+/// Fragments may spread other fragments several times, so measuring them anew at every spread
+/// takes time exponential in the length of such a chain.
+struct Measurer<'a> {
+    fragments: Fragments<'a>,
+    fragment_measures: HashMap<&'a str, Measure>,
+    spread_fragments: Vec<&'a str>,
+}
+
+impl<'a> Measurer<'a> {
+    fn measure(&mut self, selection_set: &'a SelectionSet<'a, &'a str>) -> Measure {
+        let mut total = Measure::default();
+        for selection in &selection_set.items {
+            let nested = match selection {
+                Selection::Field(field) => {
+                    let fields = self.measure(&field.selection_set);
+                    Measure {
+                        depth: fields.depth + 1,
+                        complexity: fields.complexity.saturating_add(1),
+                    }
+                }
+                Selection::InlineFragment(fragment) => self.measure(&fragment.selection_set),
+                Selection::FragmentSpread(spread) => self.measure_fragment(spread.fragment_name),
+            };
+            total.depth = total.depth.max(nested.depth);
+            total.complexity = total.complexity.saturating_add(nested.complexity);
+        }
+        total
+    }
+
+    fn measure_fragment(&mut self, name: &'a str) -> Measure {
+        if let Some(measure) = self.fragment_measures.get(name) {
+            return *measure;
+        }
+        // Cyclic spreads are rejected by the validation of the query later on
+        let fragment = match self.fragments.get(name) {
+            Some(fragment) if !self.spread_fragments.contains(&name) => *fragment,
+            _ => return Measure::default(),
+        };
+        self.spread_fragments.push(name);
+        let measure = self.measure(&fragment.selection_set);
+        self.spread_fragments.pop();
+        self.fragment_measures.insert(name, measure);
+        measure
+    }
+}
+
+/// Rejects queries which exceed the limits
+///
+/// Queries which can not be parsed are let through, so that the executor reports the syntax
+/// error in its usual format.
+pub fn check_limits(query: &str, limits: &QueryLimits) -> Result<(), String> {
+    let document = match parse_query::<&str>(query) {
+        Ok(document) => document,
+        Err(_) => return Ok(()),
+    };
+    let fragments = document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::Fragment(fragment) => Some((fragment.name, fragment)),
+            Definition::Operation(_) => None,
+        })
+        .collect();
+    let mut measurer = Measurer {
+        fragments,
+        fragment_measures: HashMap::new(),
+        spread_fragments: Vec::new(),
+    };
+    for definition in &document.definitions {
+        let selection_set = match definition {
+            Definition::Operation(OperationDefinition::SelectionSet(selection_set)) => {
+                selection_set
+            }
+            Definition::Operation(OperationDefinition::Query(query)) => &query.selection_set,
+            Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+                &mutation.selection_set
+            }
+            Definition::Operation(OperationDefinition::Subscription(subscription)) => {
+                &subscription.selection_set
+            }
+            Definition::Fragment(_) => continue,
+        };
+        let measure = measurer.measure(selection_set);
+        if measure.depth > limits.max_depth {
+            return Err(format!(
+                "Query depth {} exceeds the limit of {}",
+                measure.depth, limits.max_depth
+            ));
+        }
+        if measure.complexity > limits.max_complexity {
+            return Err(format!(
+                "Query complexity {} exceeds the limit of {}",
+                measure.complexity, limits.max_complexity
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_limits, QueryLimits};
+
+    const LIMITS: QueryLimits = QueryLimits {
+        max_depth: 3,
+        max_complexity: 5,
+    };
+
+    #[test]
+    fn query_within_limits_is_accepted() {
+        assert_eq!(
+            Ok(()),
+            check_limits("{ productCategories { id name } }", &LIMITS)
+        );
+    }
+
+    #[test]
+    fn too_deep_query_is_rejected() {
+        let query = "{ productCategories { subcategories { subcategories { id } } } }";
+        assert!(check_limits(query, &LIMITS).is_err());
+    }
+
+    #[test]
+    fn fragments_count_where_they_are_spread() {
+        let query = "query { a: productCategories { ...names } b: productCategories { ...names } }
+            fragment names on ProductCategory { id name }";
+        assert!(check_limits(query, &LIMITS).is_err());
+    }
+
+    #[test]
+    fn fragments_spreading_each_other_repeatedly_are_measured_once() {
+        let mut query = "{ productCategories { ...f0 } }".to_string();
+        for level in 0..64 {
+            query.push_str(&format!(
+                " fragment f{} on ProductCategory {{ a: subcategories {{ ...f{} }} b: subcategories {{ ...f{} }} }}",
+                level,
+                level + 1,
+                level + 1
+            ));
+        }
+        query.push_str(" fragment f64 on ProductCategory { id }");
+        let limits = QueryLimits {
+            max_depth: 100,
+            max_complexity: 1000,
+        };
+
+        let result = check_limits(&query, &limits);
+
+        assert!(result.unwrap_err().contains("complexity"));
+    }
+}
@@ -0,0 +1,96 @@
+use diesel::QueryResult;
+use std::collections::{HashMap, HashSet};
+
+/// Loads the values related to many keys with a single query
+///
+/// Resolvers register the keys of all objects they return. When a nested field of one of those
+/// objects is resolved, the values of all registered keys are fetched at once, so that the
+/// remaining objects of the list are answered from the cache instead of one query each.
+pub struct BatchLoader<V> {
+    pending: HashSet<i32>,
+    loaded: HashMap<i32, Vec<V>>,
+}
+
+impl<V> Default for BatchLoader<V> {
+    fn default() -> Self {
+        BatchLoader {
+            pending: HashSet::new(),
+            loaded: HashMap::new(),
+        }
+    }
+}
+
+impl<V: Clone> BatchLoader<V> {
+    /// Registers keys whose values are likely to be requested
+    pub fn register<I: IntoIterator<Item = i32>>(&mut self, keys: I) {
+        for key in keys {
+            if !self.loaded.contains_key(&key) {
+                self.pending.insert(key);
+            }
+        }
+    }
+
+    /// Stores an already known value of a key
+    pub fn prime(&mut self, key: i32, value: V) {
+        self.pending.remove(&key);
+        self.loaded.insert(key, vec![value]);
+    }
+
+    /// Returns the values of the key, fetching them together with all registered keys
+    ///
+    /// `fetch` receives the keys to load and returns the values paired with their keys.
+    pub fn load<F>(&mut self, key: i32, fetch: F) -> QueryResult<Vec<V>>
+    where
+        F: FnOnce(&[i32]) -> QueryResult<Vec<(i32, V)>>,
+    {
+        if !self.loaded.contains_key(&key) {
+            self.pending.insert(key);
+            let mut keys: Vec<i32> = self.pending.drain().collect();
+            keys.sort_unstable();
+            let values = fetch(&keys)?;
+            for key in keys {
+                self.loaded.entry(key).or_default();
+            }
+            for (key, value) in values {
+                self.loaded.entry(key).or_default().push(value);
+            }
+        }
+        Ok(self.loaded[&key].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BatchLoader;
+    use std::cell::Cell;
+
+    #[test]
+    fn registered_keys_are_fetched_in_one_batch() {
+        let fetches = Cell::new(0);
+        let fetch = |keys: &[i32]| {
+            fetches.set(fetches.get() + 1);
+            Ok(keys.iter().map(|key| (*key, key * 10)).collect())
+        };
+        let mut loader = BatchLoader::default();
+        loader.register(vec![1, 2, 3]);
+
+        assert_eq!(vec![20], loader.load(2, fetch).unwrap());
+        assert_eq!(vec![10], loader.load(1, fetch).unwrap());
+        assert_eq!(vec![30], loader.load(3, fetch).unwrap());
+        assert_eq!(1, fetches.get());
+    }
+
+    #[test]
+    fn keys_without_values_are_cached_as_empty() {
+        let fetches = Cell::new(0);
+        let fetch = |_: &[i32]| {
+            fetches.set(fetches.get() + 1);
+            Ok(Vec::<(i32, i32)>::new())
+        };
+        let mut loader = BatchLoader::default();
+
+        assert!(loader.load(7, fetch).unwrap().is_empty());
+        assert!(loader.load(7, fetch).unwrap().is_empty());
+        assert_eq!(1, fetches.get());
+    }
+}
@@ -0,0 +1,37 @@
+//! GraphQL API over the warehouse domain
+//!
+//! The schema exposes the same data as the REST API, but lets clients fetch nested objects,
+//! such as a category with its subcategories, products and stock, in a single request. Nested
+//! fields are loaded in batches per request, and queries are bounded by [`QueryLimits`].
+
+pub mod context;
+pub mod controllers;
+pub mod limits;
+pub mod loaders;
+pub mod schema;
+
+pub use controllers::*;
+pub use limits::QueryLimits;
+
+use rocket::fairing::AdHoc;
+
+/// Manages the GraphQL schema and the query limits as state
+///
+/// The limits are read from `graphql_max_depth` and `graphql_max_complexity`.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_attach("GraphQL", |rocket| {
+        let defaults = QueryLimits::default();
+        let config = rocket.config();
+        let limits = QueryLimits {
+            max_depth: config
+                .get_int("graphql_max_depth")
+                .map_or(defaults.max_depth, |depth| depth.max(1) as usize),
+            max_complexity: config
+                .get_int("graphql_max_complexity")
+                .map_or(defaults.max_complexity, |complexity| {
+                    complexity.max(1) as usize
+                }),
+        };
+        Ok(rocket.manage(schema::schema()).manage(limits))
+    })
+}
@@ -0,0 +1,327 @@
+use super::context::Context;
+use crate::{
+    auth::Role,
+    entities::{
+        InventoryItem, Product, ProductCategoryClassification, ProductCategoryRollup, Warehouse,
+    },
+    product_category::{
+        entities::{visible_by_default, ProductCategory},
+        hierarchy, operations,
+    },
+    repository::{CategoryRepository, ProductRepository, StockRepository, WarehouseRepository},
+};
+use juniper::{FieldResult, RootNode};
+use std::collections::BTreeMap;
+
+pub type Schema = RootNode<'static, Query, Mutation>;
+
+/// Creates the GraphQL schema of the warehouse
+pub fn schema() -> Schema {
+    Schema::new(Query, Mutation)
+}
+
+pub struct Query;
+
+#[juniper::object(Context = Context)]
+impl Query {
+    fn product_category(context: &Context, id: i32) -> FieldResult<Option<ProductCategory>> {
+        Ok(context.category(id)?)
+    }
+
+    fn product_categories(context: &Context) -> FieldResult<Vec<ProductCategory>> {
+        let categories = context.connection().categories()?;
+        context.categories_loaded(&categories);
+        Ok(categories)
+    }
+
+    /// The category reached by following slugs like `clothes/swimwear` from a root category
+    fn product_category_at_path(
+        context: &Context,
+        path: String,
+    ) -> FieldResult<Option<ProductCategory>> {
+        let slugs: Vec<&str> = path.split('/').filter(|slug| !slug.is_empty()).collect();
+        Ok(hierarchy::category_at_path(context.connection(), &slugs)?)
+    }
+
+    fn product(context: &Context, id: i32) -> FieldResult<Option<Product>> {
+        Ok(context.product(id)?)
+    }
+
+    fn products(context: &Context) -> FieldResult<Vec<Product>> {
+        let products = context.connection().products()?;
+        context.products_loaded(&products);
+        Ok(products)
+    }
+
+    fn product_category_classifications(
+        context: &Context,
+    ) -> FieldResult<Vec<ProductCategoryClassification>> {
+        let classifications = context.connection().classifications()?;
+        context.classifications_loaded(&classifications);
+        Ok(classifications)
+    }
+
+    fn product_category_rollups(context: &Context) -> FieldResult<Vec<ProductCategoryRollup>> {
+        let rollups = context.connection().rollup_links()?;
+        context.rollups_loaded(&rollups);
+        Ok(rollups)
+    }
+
+    fn inventory_items(context: &Context) -> FieldResult<Vec<InventoryItem>> {
+        let items = context.connection().inventory_items()?;
+        context.items_loaded(&items);
+        Ok(items)
+    }
+
+    fn warehouse(context: &Context, id: i32) -> FieldResult<Option<Warehouse>> {
+        Ok(context.warehouse(id)?)
+    }
+
+    fn warehouses(context: &Context) -> FieldResult<Vec<Warehouse>> {
+        let warehouses = context.connection().warehouses()?;
+        context.warehouses_loaded(&warehouses);
+        Ok(warehouses)
+    }
+}
+
+pub struct Mutation;
+
+#[juniper::object(Context = Context)]
+impl Mutation {
+    /// Returns the category with the name, creating it if it does not exist yet
+    fn create_product_category(context: &Context, name: String) -> FieldResult<ProductCategory> {
+        context.require_role(Role::Admin)?;
+        let (category, _) = operations::create_or_get(context.connection(), &name)?;
+        Ok(category)
+    }
+
+    /// Creates the category with the id, or replaces its name and display metadata if it exists
+    fn upsert_product_category(
+        context: &Context,
+        id: i32,
+        name: String,
+        description: Option<String>,
+        image_ref: Option<String>,
+        is_visible: Option<bool>,
+    ) -> FieldResult<ProductCategory> {
+        context.require_role(Role::Admin)?;
+        let category = ProductCategory {
+            id,
+            name,
+            slug: String::new(),
+            description,
+            image_ref,
+            is_visible: is_visible.unwrap_or_else(visible_by_default),
+            is_archived: false,
+        };
+        let (saved_category, _) = operations::upsert(context.connection(), &category)?;
+        Ok(saved_category)
+    }
+
+    /// Deletes the category and returns whether it existed
+    fn delete_product_category(context: &Context, id: i32) -> FieldResult<bool> {
+        context.require_role(Role::Admin)?;
+        Ok(operations::delete(context.connection(), id)?)
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl ProductCategory {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names the category in URLs, unique among the categories with the same parent
+    fn slug(&self) -> &str {
+        &self.slug
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    fn image_ref(&self) -> Option<&str> {
+        self.image_ref.as_deref()
+    }
+
+    /// Hidden categories are left out of the navigation of the shop
+    fn is_visible(&self) -> bool {
+        self.is_visible
+    }
+
+    /// Categories which roll up into this category
+    fn subcategories(&self, context: &Context) -> FieldResult<Vec<ProductCategory>> {
+        Ok(context.subcategories(self.id)?)
+    }
+
+    /// Categories into which this category rolls up
+    fn parent_categories(&self, context: &Context) -> FieldResult<Vec<ProductCategory>> {
+        Ok(context.parent_categories(self.id)?)
+    }
+
+    fn classifications(
+        &self,
+        context: &Context,
+    ) -> FieldResult<Vec<ProductCategoryClassification>> {
+        Ok(context.category_classifications(self.id)?)
+    }
+
+    /// Products which are classified directly into this category
+    fn products(&self, context: &Context) -> FieldResult<Vec<Product>> {
+        let mut products = Vec::new();
+        for classification in context.category_classifications(self.id)? {
+            products.extend(context.product(classification.product_id)?);
+        }
+        Ok(products)
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl Product {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Price in the smallest unit of the currency, like cents
+    fn price(&self) -> Option<i32> {
+        self.price
+    }
+
+    fn classifications(
+        &self,
+        context: &Context,
+    ) -> FieldResult<Vec<ProductCategoryClassification>> {
+        Ok(context.product_classifications(self.id)?)
+    }
+
+    /// Categories into which the product is classified
+    fn categories(&self, context: &Context) -> FieldResult<Vec<ProductCategory>> {
+        let mut categories = Vec::new();
+        for classification in context.product_classifications(self.id)? {
+            categories.extend(context.category(classification.product_category_id)?);
+        }
+        Ok(categories)
+    }
+
+    fn inventory_items(&self, context: &Context) -> FieldResult<Vec<InventoryItem>> {
+        Ok(context.product_items(self.id)?)
+    }
+
+    /// Number of inventory items of the product in each warehouse
+    fn stock(&self, context: &Context) -> FieldResult<Vec<WarehouseStock>> {
+        let mut quantities: BTreeMap<i32, i32> = BTreeMap::new();
+        for item in context.product_items(self.id)? {
+            if let Some(warehouse_id) = item.warehouse_id {
+                *quantities.entry(warehouse_id).or_default() += 1;
+            }
+        }
+        Ok(quantities
+            .into_iter()
+            .map(|(warehouse_id, quantity)| WarehouseStock {
+                warehouse_id,
+                quantity,
+            })
+            .collect())
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl ProductCategoryClassification {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn is_primary_classification(&self) -> bool {
+        self.is_primary_classification
+    }
+
+    fn product(&self, context: &Context) -> FieldResult<Option<Product>> {
+        Ok(context.product(self.product_id)?)
+    }
+
+    fn product_category(&self, context: &Context) -> FieldResult<Option<ProductCategory>> {
+        Ok(context.category(self.product_category_id)?)
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl ProductCategoryRollup {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn upper_category(&self, context: &Context) -> FieldResult<Option<ProductCategory>> {
+        Ok(context.category(self.upper_category_id)?)
+    }
+
+    fn lower_category(&self, context: &Context) -> FieldResult<Option<ProductCategory>> {
+        Ok(context.category(self.lower_category_id)?)
+    }
+
+    /// Order of the lower category among the subcategories of the upper category
+    fn position(&self) -> i32 {
+        self.position
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl InventoryItem {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn instance_description(&self) -> Option<&str> {
+        self.instance_description.as_deref()
+    }
+
+    fn product(&self, context: &Context) -> FieldResult<Option<Product>> {
+        Ok(context.product(self.product_id)?)
+    }
+
+    fn warehouse(&self, context: &Context) -> FieldResult<Option<Warehouse>> {
+        match self.warehouse_id {
+            Some(warehouse_id) => Ok(context.warehouse(warehouse_id)?),
+            None => Ok(None),
+        }
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl Warehouse {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn inventory_items(&self, context: &Context) -> FieldResult<Vec<InventoryItem>> {
+        Ok(context.warehouse_items(self.id)?)
+    }
+}
+
+/// Number of inventory items of a product which are stocked in a warehouse
+pub struct WarehouseStock {
+    warehouse_id: i32,
+    quantity: i32,
+}
+
+#[juniper::object(Context = Context)]
+impl WarehouseStock {
+    fn warehouse(&self, context: &Context) -> FieldResult<Option<Warehouse>> {
+        Ok(context.warehouse(self.warehouse_id)?)
+    }
+
+    fn quantity(&self) -> i32 {
+        self.quantity
+    }
+}
@@ -0,0 +1,141 @@
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use crate::migrations;
+#[cfg(feature = "postgres")]
+use crate::DbConn;
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use diesel::{sql_query, RunQueryDsl};
+use rocket::{http::Status, response::status::Custom};
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+#[derive(Serialize, JsonSchema, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+}
+
+#[derive(Serialize, JsonSchema, PartialEq, Eq, Debug)]
+/// Outcome of a single readiness check, with the reason of a failure
+pub struct HealthCheck {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub message: Option<String>,
+}
+
+impl HealthCheck {
+    fn from_result<E: ToString>(name: &'static str, result: Result<(), E>) -> Self {
+        match result {
+            Ok(()) => HealthCheck {
+                name,
+                status: CheckStatus::Pass,
+                message: None,
+            },
+            Err(error) => HealthCheck {
+                name,
+                status: CheckStatus::Fail,
+                message: Some(error.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema, PartialEq, Eq, Debug)]
+pub struct HealthReport {
+    pub status: CheckStatus,
+    pub checks: Vec<HealthCheck>,
+}
+
+/// Reports that the process is running and able to answer requests
+#[get("/live")]
+pub fn live() -> Json<HealthReport> {
+    Json(HealthReport {
+        status: CheckStatus::Pass,
+        checks: Vec::new(),
+    })
+}
+
+/// Reports whether the instance can serve requests
+///
+/// The instance is ready if a pooled connection answers a query and every migration known to
+/// the binary, and no other, is applied. Without a pooled connection the guard already answers
+/// with `503 Service Unavailable`.
+#[cfg(feature = "postgres")]
+#[get("/ready")]
+pub fn ready(db_conn: DbConn) -> Custom<Json<HealthReport>> {
+    let database_check = HealthCheck::from_result(
+        "database",
+        sql_query("select 1").execute(&*db_conn).map(|_| ()),
+    );
+    let migrations_check = HealthCheck::from_result("migrations", migrations::verify(&db_conn));
+    readiness(vec![database_check, migrations_check])
+}
+
+/// Reports whether the instance can serve requests from its SQLite database, like [`ready`]
+#[cfg(feature = "sqlite")]
+#[get("/ready")]
+pub fn ready_sqlite(db_conn: crate::SqliteDbConn) -> Custom<Json<HealthReport>> {
+    let database_check = HealthCheck::from_result(
+        "database",
+        sql_query("select 1").execute(&*db_conn).map(|_| ()),
+    );
+    let migrations_check =
+        HealthCheck::from_result("migrations", migrations::sqlite::verify(&db_conn));
+    readiness(vec![database_check, migrations_check])
+}
+
+/// Reports that an instance serving the in-memory database can serve requests, which it can as
+/// soon as it is launched
+#[get("/ready")]
+pub fn ready_memory() -> Custom<Json<HealthReport>> {
+    readiness(vec![HealthCheck::from_result(
+        "database",
+        Ok::<(), String>(()),
+    )])
+}
+
+fn readiness(checks: Vec<HealthCheck>) -> Custom<Json<HealthReport>> {
+    if checks.iter().all(|check| check.status == CheckStatus::Pass) {
+        Custom(
+            Status::Ok,
+            Json(HealthReport {
+                status: CheckStatus::Pass,
+                checks,
+            }),
+        )
+    } else {
+        Custom(
+            Status::ServiceUnavailable,
+            Json(HealthReport {
+                status: CheckStatus::Fail,
+                checks,
+            }),
+        )
+    }
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use rocket::{http::Status, local::Client};
+    use testcontainers::clients::Cli;
+
+    #[test]
+    fn migrated_instance_is_live_and_ready() -> Result<(), crate::migrations::MigrationError> {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (rocket, _) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+        let client = Client::new(rocket).expect("valid rocket instance");
+
+        let live_response = client.get("/health/live").dispatch();
+        let mut ready_response = client.get("/health/ready").dispatch();
+
+        let report: serde_json::Value =
+            serde_json::from_str(&ready_response.body_string().unwrap()).unwrap();
+        assert_eq!(Status::Ok, live_response.status());
+        assert_eq!(Status::Ok, ready_response.status());
+        assert_eq!("pass", report["status"]);
+        assert_eq!(2, report["checks"].as_array().unwrap().len());
+        Ok(())
+    }
+}
@@ -0,0 +1,4 @@
+//! Probes telling a load balancer or orchestrator whether an instance can serve requests
+pub mod controllers;
+
+pub use controllers::*;
@@ -0,0 +1,15 @@
+use crate::schema::*;
+use chrono::{DateTime, Utc};
+
+#[derive(Identifiable, Queryable, PartialEq, Debug)]
+#[table_name = "idempotency_key"]
+#[primary_key(key)]
+/// Key of a request together with the response it produced, once it has completed
+pub struct IdempotencyRecord {
+    pub key: String,
+    pub fingerprint: String,
+    pub response_status: Option<i16>,
+    pub response_content_type: Option<String>,
+    pub response_body: Option<Vec<u8>>,
+    pub created_at: DateTime<Utc>,
+}
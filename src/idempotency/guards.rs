@@ -0,0 +1,171 @@
+use super::{
+    responder::Idempotent,
+    store::{self, Claim},
+    IdempotencyConfig, IDEMPOTENCY_KEY_HEADER,
+};
+use crate::{localization::Locale, repository::IdempotencyRepository, utilities::DbError};
+use rocket::{
+    http::Status,
+    request::{self, FromRequest},
+    Outcome, Request, State,
+};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Longest key which is accepted
+const MAX_KEY_LENGTH: usize = 255;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum IdempotencyError {
+    InvalidKey,
+    Unavailable,
+}
+
+/// Request guard reading the `Idempotency-Key` header of a request
+///
+/// Routes pass their body and handler to [`Idempotency::run`], which only runs the handler if
+/// the request was not made before. Requests without the header are always run.
+pub struct Idempotency {
+    key: Option<String>,
+    method: String,
+    uri: String,
+    /// Locale the request is answered in, as localized routes answer differently per locale
+    locale: String,
+    retention: Duration,
+    claim_timeout: Duration,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Idempotency {
+    type Error = IdempotencyError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let key = match request.headers().get_one(IDEMPOTENCY_KEY_HEADER) {
+            Some(key) if key.trim().is_empty() || key.len() > MAX_KEY_LENGTH => {
+                return Outcome::Failure((Status::BadRequest, IdempotencyError::InvalidKey))
+            }
+            key => key.map(|key| key.trim().to_string()),
+        };
+        let config = match request.guard::<State<IdempotencyConfig>>() {
+            Outcome::Success(config) => config,
+            _ => {
+                return Outcome::Failure((
+                    Status::ServiceUnavailable,
+                    IdempotencyError::Unavailable,
+                ))
+            }
+        };
+        let locale = request
+            .guard::<Locale>()
+            .succeeded()
+            .map(|locale| locale.as_str().to_string())
+            .unwrap_or_default();
+        Outcome::Success(Idempotency {
+            key,
+            method: request.method().as_str().to_string(),
+            uri: request.uri().to_string(),
+            locale,
+            retention: config.retention,
+            claim_timeout: config.claim_timeout,
+        })
+    }
+}
+
+impl Idempotency {
+    /// Runs the handler unless the request was made before with the same key
+    ///
+    /// The request is identified by its method, URI, locale and body. Repeated requests get the
+    /// stored response, and requests reusing the key with a different fingerprint are rejected.
+    pub fn run<C, B, R, F>(self, connection: &C, body: &B, handler: F) -> Idempotent<R>
+    where
+        C: IdempotencyRepository,
+        B: Serialize,
+        F: FnOnce() -> R,
+    {
+        let key = match self.key {
+            Some(key) => key,
+            None => return Idempotent::Unkeyed(handler()),
+        };
+        let body = match serde_json::to_vec(body) {
+            Ok(body) => body,
+            Err(error) => {
+                return Idempotent::Failed(DbError(diesel::result::Error::SerializationError(
+                    Box::new(error),
+                )))
+            }
+        };
+        let request_fingerprint = store::fingerprint(&self.method, &self.uri, &self.locale, &body);
+        match connection.claim_idempotency_key(
+            &key,
+            &request_fingerprint,
+            self.retention,
+            self.claim_timeout,
+        ) {
+            Ok(Claim::Claimed(claimed_at)) => Idempotent::Fresh {
+                response: handler(),
+                key,
+                claimed_at,
+            },
+            Ok(Claim::Completed(stored_response)) => Idempotent::Replayed(stored_response),
+            Ok(Claim::InProgress) => Idempotent::InProgress,
+            Ok(Claim::Mismatch) => Idempotent::Mismatch,
+            Err(error) => Idempotent::Failed(DbError(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        auth::Role,
+        idempotency::{IDEMPOTENCY_KEY_HEADER, REPLAYED_HEADER},
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::{
+        http::{ContentType, Header, Status},
+        local::{Client, LocalResponse},
+    };
+
+    fn post_category<'c>(client: &'c Client, key: &str, name: &str) -> LocalResponse<'c> {
+        client
+            .post("/productcategory")
+            .header(ContentType::JSON)
+            .header(bearer_token(Role::Admin))
+            .header(Header::new(IDEMPOTENCY_KEY_HEADER, key.to_string()))
+            .body(format!("{{\"name\":\"{}\"}}", name))
+            .dispatch()
+    }
+
+    backend_test!(
+        repeated_request_replays_the_stored_response,
+        |client, repository| {
+            let mut first_response = post_category(client, "receipt-1", "Shirts");
+            let mut repeated_response = post_category(client, "receipt-1", "Shirts");
+            let mismatched_response = post_category(client, "receipt-1", "Skirts");
+            let other_locale_response = client
+                .post("/productcategory")
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Admin))
+                .header(Header::new(IDEMPOTENCY_KEY_HEADER, "receipt-1"))
+                .header(Header::new("Accept-Language", "de"))
+                .body("{\"name\":\"Shirts\"}")
+                .dispatch();
+
+            assert_eq!(Status::Created, first_response.status());
+            assert_eq!(None, first_response.headers().get_one(REPLAYED_HEADER));
+            assert_eq!(Status::Created, repeated_response.status());
+            assert_eq!(
+                Some("true"),
+                repeated_response.headers().get_one(REPLAYED_HEADER)
+            );
+            assert_eq!(Some(ContentType::JSON), repeated_response.content_type());
+            assert_eq!(
+                first_response.body_string(),
+                repeated_response.body_string()
+            );
+            assert_eq!(Status::UnprocessableEntity, mismatched_response.status());
+            assert_eq!(Status::UnprocessableEntity, other_locale_response.status());
+            assert_eq!(1, repository.categories()?.len());
+            Ok(())
+        }
+    );
+}
@@ -0,0 +1,52 @@
+//! Safe retries of non-idempotent requests through the `Idempotency-Key` header
+//!
+//! The first request with a key claims it together with a fingerprint of the request, and the
+//! response it produced is stored under the key. Repeating the request replays the stored
+//! response without running it again, while reusing the key for a different request is
+//! rejected. Keys expire after the retention period and can be used again afterwards, and keys
+//! whose request did not complete within the claim timeout can be claimed again right away.
+#[cfg(feature = "postgres")]
+pub mod entities;
+pub mod guards;
+pub mod responder;
+pub mod store;
+
+pub use guards::Idempotency;
+pub use responder::Idempotent;
+
+use rocket::fairing::AdHoc;
+use std::time::Duration;
+
+/// Header in which clients send the key of a request
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Header marking responses which were replayed from an earlier request
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// Settings of the idempotency keys
+pub struct IdempotencyConfig {
+    /// Time after which stored keys and responses are discarded
+    pub retention: Duration,
+    /// Time after which a key whose request has not completed is free again
+    pub claim_timeout: Duration,
+}
+
+/// Reads the retention period from `idempotency_retention_hours` and the claim timeout from
+/// `idempotency_claim_timeout_seconds`, and manages them as state
+pub fn fairing() -> AdHoc {
+    AdHoc::on_attach("Idempotency keys", |rocket| {
+        let retention_hours = rocket
+            .config()
+            .get_int("idempotency_retention_hours")
+            .unwrap_or(24)
+            .max(1) as u64;
+        let claim_timeout_seconds = rocket
+            .config()
+            .get_int("idempotency_claim_timeout_seconds")
+            .unwrap_or(120)
+            .max(1) as u64;
+        Ok(rocket.manage(IdempotencyConfig {
+            retention: Duration::from_secs(retention_hours * 3600),
+            claim_timeout: Duration::from_secs(claim_timeout_seconds),
+        }))
+    })
+}
@@ -0,0 +1,156 @@
+use super::{store::StoredResponse, REPLAYED_HEADER};
+use crate::{
+    repository::{BackendConn, IdempotencyRepository},
+    request_log::RequestId,
+    utilities::DbError,
+};
+use chrono::{DateTime, Utc};
+use rocket::{
+    http::{ContentType, Header, Status},
+    response::{self, Responder},
+    Request, Response,
+};
+use rocket_contrib::json::Json;
+use serde_json::json;
+use std::io::Cursor;
+
+/// Response of a route guarded by [`super::Idempotency`]
+pub enum Idempotent<R> {
+    /// Response of a request without a key
+    Unkeyed(R),
+    /// Response of the first request with the key, which is stored when it is sent
+    Fresh {
+        response: R,
+        key: String,
+        claimed_at: DateTime<Utc>,
+    },
+    /// Stored response of an earlier request with the key
+    Replayed(StoredResponse),
+    /// The earlier request with the key has not completed yet
+    InProgress,
+    /// The key was used for a different request
+    Mismatch,
+    /// The key could not be claimed
+    Failed(DbError),
+}
+
+impl<R> Idempotent<R> {
+    /// Statuses the responder answers with in addition to the ones of the route
+    pub const STATUSES: &'static [(Status, &'static str)] = &[
+        (
+            Status::Conflict,
+            "A request with the same idempotency key is still in progress",
+        ),
+        (
+            Status::UnprocessableEntity,
+            "The idempotency key was used for a different request",
+        ),
+    ];
+}
+
+impl<'r, R: Responder<'r>> Responder<'r> for Idempotent<R> {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        match self {
+            Idempotent::Unkeyed(response) => response.respond_to(request),
+            Idempotent::Fresh {
+                response,
+                key,
+                claimed_at,
+            } => {
+                let response = response.respond_to(request);
+                store_response(request, &key, claimed_at, response)
+            }
+            Idempotent::Replayed(stored_response) => {
+                let mut response = Response::build()
+                    .status(Status::from_code(stored_response.status).unwrap_or(Status::Ok))
+                    .header(Header::new(REPLAYED_HEADER, "true"))
+                    .sized_body(Cursor::new(stored_response.body))
+                    .finalize();
+                if let Some(content_type) = stored_response
+                    .content_type
+                    .as_ref()
+                    .and_then(|content_type| ContentType::parse_flexible(content_type))
+                {
+                    response.set_header(content_type);
+                }
+                Ok(response)
+            }
+            Idempotent::InProgress => error_response(
+                request,
+                Status::Conflict,
+                "A request with this idempotency key is still in progress",
+            ),
+            Idempotent::Mismatch => error_response(
+                request,
+                Status::UnprocessableEntity,
+                "This idempotency key was already used for a different request",
+            ),
+            Idempotent::Failed(db_error) => db_error.respond_to(request),
+        }
+    }
+}
+
+fn error_response<'r>(request: &Request, status: Status, message: &str) -> response::Result<'r> {
+    let mut response = Json(json!({ "error": message })).respond_to(request)?;
+    response.set_status(status);
+    Ok(response)
+}
+
+/// Stores the response under the key, or frees the key if the request failed on the server
+///
+/// Responses are stored on a connection of their own, since the connection of the route is
+/// released before the response is built.
+fn store_response<'r>(
+    request: &Request,
+    key: &str,
+    claimed_at: DateTime<Utc>,
+    response: response::Result<'r>,
+) -> response::Result<'r> {
+    let mut response = match response {
+        Ok(response) if response.status().class().is_server_error() => {
+            update_key(request, |connection| {
+                connection.release_idempotency_key(key, claimed_at)
+            });
+            return Ok(response);
+        }
+        Ok(response) => response,
+        Err(status) => {
+            update_key(request, |connection| {
+                connection.release_idempotency_key(key, claimed_at)
+            });
+            return Err(status);
+        }
+    };
+    let stored_response = StoredResponse {
+        status: response.status().code,
+        content_type: response
+            .content_type()
+            .map(|content_type| content_type.to_string()),
+        body: response.body_bytes().unwrap_or_default(),
+    };
+    update_key(request, |connection| {
+        connection.complete_idempotency_key(key, claimed_at, &stored_response)
+    });
+    response.set_sized_body(Cursor::new(stored_response.body));
+    Ok(response)
+}
+
+/// Runs the update on a connection of the request's tenant, like the one of the route
+fn update_key<F>(request: &Request, update: F)
+where
+    F: FnOnce(&BackendConn) -> diesel::QueryResult<()>,
+{
+    let result = match request.guard::<BackendConn>().succeeded() {
+        Some(db_conn) => update(&db_conn).map_err(|error| error.to_string()),
+        None => Err("no database connection is available".to_string()),
+    };
+    if let Err(error) = result {
+        log::error!(
+            "{}",
+            json!({
+                "request_id": RequestId::of(request).0,
+                "idempotency_error": error,
+            })
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+/// Response stored for a key, as it is replayed
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Claim {
+    /// The key was free and now belongs to the request, which claimed it at the time
+    Claimed(DateTime<Utc>),
+    /// The same request completed before with the stored response
+    Completed(StoredResponse),
+    /// The same request is still being served
+    InProgress,
+    /// The key was used for a different request
+    Mismatch,
+}
+
+/// Fingerprint of a request, from its method, URI, resolved locale and body
+pub fn fingerprint(method: &str, uri: &str, locale: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(uri.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(locale.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// Time the duration ago, before which keys and claims are discarded
+pub(crate) fn before_now(duration: Duration) -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::seconds(duration.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fingerprint;
+
+    #[test]
+    fn fingerprint_covers_method_uri_locale_and_body() {
+        let original = fingerprint("POST", "/productcategory", "en", b"{\"name\":\"a\"}");
+
+        assert_eq!(64, original.len());
+        assert_eq!(
+            original,
+            fingerprint("POST", "/productcategory", "en", b"{\"name\":\"a\"}")
+        );
+        assert_ne!(
+            original,
+            fingerprint("POST", "/productcategory", "en", b"{\"name\":\"b\"}")
+        );
+        assert_ne!(
+            original,
+            fingerprint("POST", "/webhooks", "en", b"{\"name\":\"a\"}")
+        );
+        assert_ne!(
+            original,
+            fingerprint("POST", "/productcategory", "de", b"{\"name\":\"a\"}")
+        );
+    }
+}
@@ -0,0 +1,3 @@
+//! Stock of products, represented by the inventory items stocked in the warehouses
+
+pub mod reports;
@@ -0,0 +1,82 @@
+use diesel::sql_types::{BigInt, Integer, Nullable, Text};
+use serde::Serialize;
+
+#[derive(QueryableByName, Serialize, Clone, PartialEq, Eq, Debug)]
+/// Number of inventory items of a product in a warehouse
+///
+/// Items which are not assigned to any warehouse are reported without a warehouse.
+pub struct StockLevel {
+    #[sql_type = "Integer"]
+    pub product_id: i32,
+    #[sql_type = "Text"]
+    pub product_description: String,
+    #[sql_type = "Nullable<Integer>"]
+    pub warehouse_id: Option<i32>,
+    #[sql_type = "Nullable<Text>"]
+    pub warehouse_description: Option<String>,
+    #[sql_type = "BigInt"]
+    pub quantity: i64,
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use super::StockLevel;
+    use crate::repository::StockRepository;
+    use diesel::prelude::*;
+    use testcontainers::clients::Cli;
+
+    #[test]
+    fn stock_is_counted_per_product_and_warehouse() -> Result<(), crate::migrations::MigrationError>
+    {
+        use crate::schema::{inventory_item, product, warehouse};
+
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (_, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+        diesel::insert_into(product::table)
+            .values((product::id.eq(1), product::description.eq("white shirt")))
+            .execute(&connection)?;
+        diesel::insert_into(warehouse::table)
+            .values((warehouse::id.eq(1), warehouse::description.eq("north")))
+            .execute(&connection)?;
+        diesel::insert_into(inventory_item::table)
+            .values(&vec![
+                (
+                    inventory_item::product_id.eq(1),
+                    inventory_item::warehouse_id.eq(Some(1)),
+                ),
+                (
+                    inventory_item::product_id.eq(1),
+                    inventory_item::warehouse_id.eq(Some(1)),
+                ),
+                (
+                    inventory_item::product_id.eq(1),
+                    inventory_item::warehouse_id.eq(None),
+                ),
+            ])
+            .execute(&connection)?;
+
+        let levels = connection.stock_levels()?;
+
+        assert_eq!(
+            vec![
+                StockLevel {
+                    product_id: 1,
+                    product_description: "white shirt".to_string(),
+                    warehouse_id: Some(1),
+                    warehouse_description: Some("north".to_string()),
+                    quantity: 2,
+                },
+                StockLevel {
+                    product_id: 1,
+                    product_description: "white shirt".to_string(),
+                    warehouse_id: None,
+                    warehouse_description: None,
+                    quantity: 1,
+                },
+            ],
+            levels
+        );
+        Ok(())
+    }
+}
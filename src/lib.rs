@@ -4,20 +4,179 @@
 extern crate diesel;
 #[macro_use]
 extern crate rocket;
-#[macro_use]
+#[cfg_attr(any(feature = "postgres", feature = "sqlite"), macro_use)]
 extern crate rocket_contrib;
-#[macro_use]
-extern crate diesel_migrations;
 
-mod entities;
+pub mod auth;
+pub mod catalog;
+pub mod csv_transfer;
+pub mod entities;
+pub mod events;
+#[cfg(feature = "postgres")]
+pub mod fixtures;
+pub mod graphql;
+pub mod health;
+pub mod idempotency;
+pub mod inventory;
+pub mod localization;
+pub mod metrics;
+pub mod migrations;
+pub mod openapi;
+pub mod outbox;
 pub mod product_category;
+pub mod repository;
+pub mod request_log;
 pub mod schema;
+pub mod search;
+pub mod settings;
+pub mod tenancy;
 mod test_utils;
 pub mod utilities;
+pub mod webhooks;
 
+/// Pooled connection to the Postgres database of all tenants
+#[cfg(feature = "postgres")]
 #[database("pgdatabase")]
 pub struct DbConn(diesel::PgConnection);
 
+/// Pooled connection to the SQLite database of a single site
+#[cfg(feature = "sqlite")]
+#[database("sqlitedatabase")]
+pub struct SqliteDbConn(diesel::SqliteConnection);
+
+/// Attaches the fairings and mounts the routes of the warehouse API
+///
+/// Parts of the API which are disabled through the [`settings::Features`] in the configuration
+/// are left out. The database pool is the one of the configured [`settings::Backend`].
+pub fn configure(rocket: rocket::Rocket) -> rocket::Rocket {
+    let features = settings::Features::from_rocket_config(rocket.config());
+    let mut rocket = rocket.attach(request_log::fairing());
+    rocket = match settings::Backend::from_rocket_config(rocket.config()) {
+        #[cfg(feature = "postgres")]
+        settings::Backend::Postgres => rocket.attach(DbConn::fairing()),
+        #[cfg(feature = "sqlite")]
+        settings::Backend::Sqlite => rocket.attach(SqliteDbConn::fairing()),
+        // Only configured in builds with the feature
+        #[cfg(not(feature = "postgres"))]
+        settings::Backend::Postgres => rocket,
+        #[cfg(not(feature = "sqlite"))]
+        settings::Backend::Sqlite => rocket,
+        settings::Backend::Memory => rocket.manage(repository::memory::MemoryDatabase::default()),
+    };
+    rocket = rocket
+        .attach(migrations::fairing())
+        .attach(auth::fairing())
+        .attach(idempotency::fairing())
+        .attach(localization::fairing());
+    if features.metrics {
+        rocket = rocket.attach(metrics::fairing());
+    }
+    if features.webhooks {
+        rocket = rocket.attach(webhooks::fairing());
+    }
+    if features.events {
+        rocket = rocket.attach(events::fairing());
+    }
+    if features.graphql {
+        rocket = rocket.attach(graphql::fairing());
+    }
+    mount_routes(rocket)
+}
+
+/// Mounts the routes of the enabled parts of the warehouse API
+pub fn mount_routes(rocket: rocket::Rocket) -> rocket::Rocket {
+    let features = settings::Features::from_rocket_config(rocket.config());
+    let backend = settings::Backend::from_rocket_config(rocket.config());
+    let mut rocket = rocket
+        .mount(
+            "/productcategory",
+            routes![
+                product_category::get,
+                product_category::get_all,
+                product_category::post,
+                product_category::delete,
+                product_category::put,
+                product_category::bulk_post,
+                product_category::bulk_put,
+                product_category::bulk_delete,
+                product_category::get_descendants,
+                product_category::get_ancestors,
+                product_category::get_subtree,
+                product_category::get_products,
+                product_category::get_by_path,
+                product_category::put_subcategory_order,
+                product_category::post_merge,
+                product_category::get_all_statistics,
+                product_category::get_statistics,
+                product_category::post_statistics_refresh,
+                product_category::get_taxonomy,
+                product_category::post_taxonomy_lines,
+                product_category::post_taxonomy_tree,
+                product_category::get_translations,
+                product_category::put_translation,
+                product_category::delete_translation
+            ],
+        )
+        .mount(
+            "/product",
+            routes![
+                localization::get_product_translations,
+                localization::put_product_translation,
+                localization::delete_product_translation,
+                search::get_search,
+                catalog::get_listing,
+                catalog::put_price,
+                catalog::get_attributes,
+                catalog::put_attribute,
+                catalog::delete_attribute
+            ],
+        )
+        .mount("/health", routes![health::live]);
+    rocket = match backend {
+        #[cfg(feature = "postgres")]
+        settings::Backend::Postgres => rocket.mount("/health", routes![health::ready]),
+        #[cfg(not(feature = "postgres"))]
+        settings::Backend::Postgres => rocket,
+        #[cfg(feature = "sqlite")]
+        settings::Backend::Sqlite => rocket.mount("/health", routes![health::ready_sqlite]),
+        #[cfg(not(feature = "sqlite"))]
+        settings::Backend::Sqlite => rocket,
+        settings::Backend::Memory => rocket.mount("/health", routes![health::ready_memory]),
+    };
+    if features.csv {
+        rocket = rocket.mount("/csv", routes![csv_transfer::get, csv_transfer::post]);
+    }
+    if features.events {
+        rocket = rocket.mount("/events", routes![events::get]);
+    }
+    if features.graphql {
+        rocket = rocket.mount("/graphql", routes![graphql::post]);
+    }
+    if features.docs {
+        rocket = rocket.mount("/", routes![openapi::get_specification, openapi::get_docs]);
+    }
+    if features.metrics {
+        rocket = match backend {
+            #[cfg(feature = "postgres")]
+            settings::Backend::Postgres => rocket.mount("/metrics", routes![metrics::get]),
+            #[cfg(not(feature = "postgres"))]
+            settings::Backend::Postgres => rocket,
+            #[cfg(feature = "sqlite")]
+            settings::Backend::Sqlite => rocket.mount("/metrics", routes![metrics::get_sqlite]),
+            #[cfg(not(feature = "sqlite"))]
+            settings::Backend::Sqlite => rocket,
+            settings::Backend::Memory => rocket.mount("/metrics", routes![metrics::get_memory]),
+        };
+    }
+    if features.webhooks {
+        rocket = rocket.mount(
+            "/webhooks",
+            routes![webhooks::get_all, webhooks::post, webhooks::delete],
+        );
+    }
+    rocket
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,28 @@
+use crate::schema::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Queryable, Insertable, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug,
+)]
+#[table_name = "product_category_translation"]
+/// Name of a product category in a locale other than the default one
+///
+/// No two categories have the same name in a locale, counting the names which fall back to the
+/// default locale.
+pub struct CategoryTranslation {
+    pub category_id: i32,
+    pub locale: String,
+    pub name: String,
+}
+
+#[derive(
+    Queryable, Insertable, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug,
+)]
+#[table_name = "product_translation"]
+/// Description of a product in a locale other than the default one
+pub struct ProductTranslation {
+    pub product_id: i32,
+    pub locale: String,
+    pub description: String,
+}
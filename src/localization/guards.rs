@@ -0,0 +1,88 @@
+use super::{Locales, ACCEPT_LANGUAGE_HEADER};
+use rocket::{
+    http::{RawStr, Status},
+    request::{self, FromRequest},
+    Outcome, Request, State,
+};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LocaleError {
+    /// The `locale` query parameter names a locale which is not supported
+    Unsupported(String),
+    Unavailable,
+}
+
+/// Request guard resolving the locale in which a request is served
+///
+/// The `locale` query parameter has to name a supported locale. Without it, the locale is
+/// negotiated from the `Accept-Language` header, falling back to the default locale.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Locale {
+    tag: String,
+    is_default: bool,
+}
+
+impl Locale {
+    pub fn new(tag: &str, locales: &Locales) -> Self {
+        Locale {
+            tag: tag.to_string(),
+            is_default: tag == locales.default,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.tag
+    }
+
+    /// Whether texts are served as they are, without looking up translations
+    pub fn is_default(&self) -> bool {
+        self.is_default
+    }
+}
+
+/// Locale which the [`Locale`] guard resolved for a request, if it ran, so that the response
+/// can name it
+#[derive(Default)]
+pub(super) struct ServedLocale {
+    pub(super) tag: Option<String>,
+    /// Whether the locale was negotiated from the `Accept-Language` header rather than queried
+    pub(super) negotiated: bool,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Locale {
+    type Error = LocaleError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let locales = match request.guard::<State<Locales>>() {
+            Outcome::Success(locales) => locales,
+            _ => return Outcome::Failure((Status::ServiceUnavailable, LocaleError::Unavailable)),
+        };
+        let requested = request
+            .get_query_value::<&RawStr>("locale")
+            .and_then(Result::ok)
+            .map(|value| value.percent_decode_lossy().into_owned());
+        let negotiated = requested.is_none();
+        let tag = match requested {
+            Some(requested) => match locales.find(&requested) {
+                Some(tag) => tag,
+                None => {
+                    return Outcome::Failure((
+                        Status::BadRequest,
+                        LocaleError::Unsupported(requested),
+                    ))
+                }
+            },
+            None => locales.negotiate(
+                request
+                    .headers()
+                    .get_one(ACCEPT_LANGUAGE_HEADER)
+                    .unwrap_or_default(),
+            ),
+        };
+        request.local_cache(|| ServedLocale {
+            tag: Some(tag.to_string()),
+            negotiated,
+        });
+        Outcome::Success(Locale::new(tag, &locales))
+    }
+}
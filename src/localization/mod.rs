@@ -0,0 +1,194 @@
+//! Category names and product descriptions in the locales of the shops
+//!
+//! The texts in the default locale are those of the categories and products themselves, and
+//! translations add them in the other supported locales. A text which is not translated into a
+//! locale falls back to the default one. Requests choose their locale with the `locale` query
+//! parameter or, without it, through the `Accept-Language` header.
+pub mod entities;
+pub mod guards;
+pub mod products;
+
+pub use guards::Locale;
+pub use products::*;
+
+use guards::ServedLocale;
+use rocket::{
+    config::Config,
+    fairing::{Fairing, Info, Kind},
+    Request, Response, Rocket,
+};
+
+/// Header through which clients list the locales they prefer
+pub const ACCEPT_LANGUAGE_HEADER: &str = "Accept-Language";
+pub const CONTENT_LANGUAGE_HEADER: &str = "Content-Language";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// Locales in which texts are served
+pub struct Locales {
+    /// Locale of the texts of the categories and products themselves
+    pub default: String,
+    /// All locales which can be requested, including the default one
+    pub supported: Vec<String>,
+}
+
+impl Default for Locales {
+    fn default() -> Self {
+        Locales {
+            default: "en".to_string(),
+            supported: vec!["en".to_string(), "de".to_string(), "hr".to_string()],
+        }
+    }
+}
+
+impl Locales {
+    /// Reads `default_locale` and the comma separated `locales` of a Rocket configuration
+    pub fn from_rocket_config(config: &Config) -> Self {
+        let defaults = Locales::default();
+        let default = config
+            .get_str("default_locale")
+            .map_or(defaults.default, normalize);
+        let mut supported: Vec<String> = config
+            .get_str("locales")
+            .map(|locales| locales.split(',').map(normalize).collect())
+            .unwrap_or(defaults.supported);
+        if !supported.contains(&default) {
+            supported.insert(0, default.clone());
+        }
+        Locales { default, supported }
+    }
+
+    /// Supported locale which the tag names, either exactly or by its language
+    ///
+    /// `de-AT` is served in `de` unless `de-at` is supported itself.
+    pub fn find(&self, tag: &str) -> Option<&str> {
+        let tag = normalize(tag);
+        let language = tag.split('-').next().unwrap_or_default();
+        self.supported
+            .iter()
+            .find(|locale| **locale == tag)
+            .or_else(|| self.supported.iter().find(|locale| *locale == language))
+            .map(String::as_str)
+    }
+
+    /// Supported locale with exactly the tag, which is not the default one
+    ///
+    /// Only these locales have translations, the texts in the default locale are kept with the
+    /// categories and products themselves.
+    pub fn translatable(&self, tag: &str) -> Result<&str, String> {
+        let tag = normalize(tag);
+        match self.supported.iter().find(|locale| **locale == tag) {
+            Some(locale) if *locale == self.default => Err(format!(
+                "Texts in the default locale {} are not translations",
+                locale
+            )),
+            Some(locale) => Ok(locale),
+            None => Err(format!(
+                "Locale {} is not supported, expected one of {}",
+                tag,
+                self.supported.join(", ")
+            )),
+        }
+    }
+
+    /// Supported locale preferred most by the value of an `Accept-Language` header
+    ///
+    /// Falls back to the default locale if the header names none of the supported ones.
+    pub fn negotiate(&self, accept_language: &str) -> &str {
+        let mut preferences: Vec<(&str, f32)> = accept_language
+            .split(',')
+            .filter_map(|preference| {
+                let mut parts = preference.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+                    .find_map(|quality| quality.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((tag, quality)).filter(|(tag, quality)| !tag.is_empty() && *quality > 0.0)
+            })
+            .collect();
+        // Stable, so that equally preferred locales keep the order of the header
+        preferences.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        preferences
+            .into_iter()
+            .find_map(|(tag, _)| self.find(tag))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Lowercases a locale tag and separates its parts with dashes, like `de-at`
+pub fn normalize(tag: &str) -> String {
+    tag.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+/// Whether the tag is a locale like `en`, `hr` or `de-AT`
+pub fn is_valid_tag(tag: &str) -> bool {
+    let mut parts = tag.split(|c| c == '-' || c == '_');
+    let language = parts.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|part| {
+            (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Reads the locales from the configuration and manages them as state, and names the locale of
+/// localized responses in their headers
+pub fn fairing() -> impl Fairing {
+    LocalizationFairing
+}
+
+struct LocalizationFairing;
+
+impl Fairing for LocalizationFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Locales",
+            kind: Kind::Attach | Kind::Response,
+        }
+    }
+
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        let locales = Locales::from_rocket_config(rocket.config());
+        Ok(rocket.manage(locales))
+    }
+
+    /// Sets `Content-Language` on responses served in a locale, and `Vary: Accept-Language` on
+    /// those whose locale was negotiated, so that shared caches keep one response per locale
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let served = request.local_cache(ServedLocale::default);
+        if let Some(tag) = &served.tag {
+            response.set_raw_header(CONTENT_LANGUAGE_HEADER, tag.clone());
+            if served.negotiated {
+                response.adjoin_raw_header("Vary", ACCEPT_LANGUAGE_HEADER);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_tag, Locales};
+
+    #[test]
+    fn preferred_supported_locale_is_negotiated() {
+        let locales = Locales::default();
+
+        assert_eq!("de", locales.negotiate("de-AT, en;q=0.8"));
+        assert_eq!("hr", locales.negotiate("fr;q=0.9, en;q=0.5, hr;q=0.7"));
+        assert_eq!("en", locales.negotiate("fr, it"));
+        assert_eq!("en", locales.negotiate("hr;q=0, *"));
+        assert_eq!("en", locales.negotiate(""));
+        assert_eq!(Some("hr"), locales.find("HR_hr"));
+        assert_eq!(None, locales.find("fr"));
+    }
+
+    #[test]
+    fn locale_tags_are_validated() {
+        assert!(is_valid_tag("en"));
+        assert!(is_valid_tag("de-AT"));
+        assert!(is_valid_tag("zh_Hant_TW"));
+        assert!(!is_valid_tag("english"));
+        assert!(!is_valid_tag("de-"));
+        assert!(!is_valid_tag(""));
+    }
+}
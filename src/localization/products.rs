@@ -0,0 +1,109 @@
+//! Descriptions of the products in the locales of the shops
+
+use super::{entities::ProductTranslation, normalize, Locale, Locales};
+use crate::{
+    auth::{Admin, Authorized, Viewer},
+    catalog::products::update_product,
+    entities::Product,
+    repository::{BackendConn, ProductRepository},
+    utilities::{DbError, GetResponder},
+};
+use diesel::QueryResult;
+use rocket::{http::Status, response::status::BadRequest, State};
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ProductTranslationBody {
+    pub description: String,
+}
+
+/// The products with their descriptions in the locale, where they are translated into it
+pub fn localize_products<R: ProductRepository>(
+    repository: &R,
+    locale: &Locale,
+    mut products: Vec<Product>,
+) -> QueryResult<Vec<Product>> {
+    if locale.is_default() {
+        return Ok(products);
+    }
+    let descriptions: HashMap<i32, String> = repository
+        .product_translations(locale.as_str())?
+        .into_iter()
+        .map(|translation| (translation.product_id, translation.description))
+        .collect();
+    for product in &mut products {
+        if let Some(description) = descriptions.get(&product.id) {
+            product.description.clone_from(description);
+        }
+    }
+    Ok(products)
+}
+
+/// Lists the descriptions of the product in the locales it is translated into
+#[get("/<product_id>/translations")]
+pub fn get_product_translations(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    product_id: i32,
+) -> Result<GetResponder<Vec<ProductTranslation>>, DbError> {
+    if db_conn.product(product_id)?.is_none() {
+        return Ok(GetResponder::NotFound(()));
+    }
+    Ok(GetResponder::Found(Json(
+        db_conn.translations_of_product(product_id)?,
+    )))
+}
+
+#[put(
+    "/<product_id>/translations/<locale>",
+    format = "json",
+    data = "<translation>"
+)]
+pub fn put_product_translation(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    locales: State<Locales>,
+    product_id: i32,
+    locale: String,
+    translation: Json<ProductTranslationBody>,
+) -> Result<Result<GetResponder<ProductTranslation>, BadRequest<String>>, DbError> {
+    let locale = match locales.translatable(&locale) {
+        Ok(locale) => locale,
+        Err(message) => return Ok(Err(BadRequest(Some(message)))),
+    };
+    let translation = ProductTranslation {
+        product_id,
+        locale: locale.to_string(),
+        description: translation.into_inner().description,
+    };
+    let saved = update_product(&db_conn, product_id, || {
+        db_conn.save_product_translation(&translation)?;
+        Ok(true)
+    })?;
+    Ok(Ok(match saved {
+        Some(_) => GetResponder::Found(Json(translation)),
+        None => GetResponder::NotFound(()),
+    }))
+}
+
+/// Removes the description of the product in the locale, so that it falls back to the default
+/// one
+#[delete("/<product_id>/translations/<locale>")]
+pub fn delete_product_translation(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    product_id: i32,
+    locale: String,
+) -> Result<Status, DbError> {
+    let deleted = update_product(&db_conn, product_id, || {
+        db_conn.delete_product_translation(product_id, &normalize(&locale))
+    })?;
+    if deleted == Some(true) {
+        Ok(Status::Ok)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
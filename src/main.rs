@@ -0,0 +1,26 @@
+//! Warehouse API server, configured through [`warehouse_rs::settings`]
+
+use rocket::config::Environment;
+use std::process;
+use warehouse_rs::{request_log::JsonLogger, settings};
+
+fn main() {
+    let settings = settings::load().and_then(|settings| {
+        let environment = Environment::active().unwrap_or(Environment::Production);
+        let rocket_config = settings.rocket_config(environment)?;
+        Ok((settings, rocket_config))
+    });
+    let (settings, rocket_config) = match settings {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprint!("{}", error);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = JsonLogger::init(settings.log_level) {
+        eprintln!("The logger could not be installed: {}", error);
+    }
+    let error = warehouse_rs::configure(rocket::custom(rocket_config)).launch();
+    eprintln!("The server could not be launched: {}", error);
+    process::exit(1);
+}
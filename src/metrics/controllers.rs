@@ -0,0 +1,216 @@
+use super::{
+    exposition,
+    gauges::{self, GaugeCache, TenantCounts},
+    RequestMetrics,
+};
+use crate::repository::{memory::MemoryDatabase, StatisticsRepository};
+#[cfg(feature = "postgres")]
+use crate::{auth::Principal, DbConnPool};
+#[cfg(feature = "postgres")]
+use rocket::http::Status;
+use rocket::{http::ContentType, response::content::Content, State};
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use rocket_contrib::databases::r2d2;
+use std::time::Instant;
+
+/// Returns the metrics of the instance in the Prometheus text format
+///
+/// Only global administrators may scrape the metrics, since the domain gauges of every tenant
+/// are included. The gauges are counted at most once per refresh interval, and left out if the
+/// database can not be queried, so that request metrics are still scraped while the database is
+/// unavailable.
+#[cfg(feature = "postgres")]
+#[get("/")]
+pub fn get(
+    principal: Principal,
+    request_metrics: State<RequestMetrics>,
+    gauge_cache: State<GaugeCache>,
+    pool: State<DbConnPool>,
+) -> Result<Content<String>, Status> {
+    if !principal.acts_for_any_tenant() {
+        return Err(Status::Forbidden);
+    }
+    Ok(render(
+        &request_metrics,
+        &gauge_cache,
+        Some(pool_gauges(&pool.0)),
+        || {
+            pool.0
+                .get()
+                .map_err(|error| error.to_string())
+                .and_then(|connection| {
+                    gauges::tenant_counts(&connection).map_err(|error| error.to_string())
+                })
+        },
+    ))
+}
+
+/// Returns the metrics of an instance serving an SQLite database, like [`get`]
+///
+/// Any administrator may scrape them, since the database holds the data of a single site.
+#[cfg(feature = "sqlite")]
+#[get("/")]
+pub fn get_sqlite(
+    _admin: crate::auth::Authorized<crate::auth::Admin>,
+    request_metrics: State<RequestMetrics>,
+    gauge_cache: State<GaugeCache>,
+    pool: State<crate::SqliteDbConnPool>,
+) -> Content<String> {
+    render(
+        &request_metrics,
+        &gauge_cache,
+        Some(pool_gauges(&pool.0)),
+        || {
+            pool.0
+                .get()
+                .map_err(|error| error.to_string())
+                .and_then(|connection| site_counts(&*connection))
+        },
+    )
+}
+
+/// Returns the metrics of an instance serving the in-memory database, like [`get_sqlite`]
+///
+/// The pool gauges are left out, since no connections are pooled.
+#[get("/")]
+pub fn get_memory(
+    _admin: crate::auth::Authorized<crate::auth::Admin>,
+    request_metrics: State<RequestMetrics>,
+    gauge_cache: State<GaugeCache>,
+    database: State<MemoryDatabase>,
+) -> Content<String> {
+    render(&request_metrics, &gauge_cache, None, || {
+        site_counts(&database.connect())
+    })
+}
+
+/// Counts of a database which holds the data of a single site
+fn site_counts<R: StatisticsRepository>(repository: &R) -> Result<Vec<TenantCounts>, String> {
+    repository
+        .catalog_counts()
+        .map(|catalog| {
+            vec![TenantCounts {
+                tenant_id: None,
+                catalog,
+            }]
+        })
+        .map_err(|error| error.to_string())
+}
+
+/// Connections of a database pool
+struct PoolGauges {
+    connections: u32,
+    idle_connections: u32,
+    max_size: u32,
+}
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+fn pool_gauges<M: r2d2::ManageConnection>(pool: &r2d2::Pool<M>) -> PoolGauges {
+    let state = pool.state();
+    PoolGauges {
+        connections: state.connections,
+        idle_connections: state.idle_connections,
+        max_size: pool.max_size(),
+    }
+}
+
+fn render<F>(
+    request_metrics: &RequestMetrics,
+    gauge_cache: &GaugeCache,
+    pool: Option<PoolGauges>,
+    count_tenants: F,
+) -> Content<String>
+where
+    F: FnOnce() -> Result<Vec<TenantCounts>, String>,
+{
+    let mut output = String::new();
+    request_metrics.render(&mut output);
+    if let Some(pool) = pool {
+        gauges::render_pool(
+            &mut output,
+            pool.connections,
+            pool.idle_connections,
+            pool.max_size,
+        );
+    }
+    match gauge_cache.tenant_counts(Instant::now(), count_tenants) {
+        Ok(tenant_counts) => gauges::render_tenant_counts(&mut output, &tenant_counts),
+        Err(error) => log::error!("Domain gauges are not available: {}", error),
+    }
+    let (top, sub) = exposition::CONTENT_TYPE;
+    Content(ContentType::new(top, sub), output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        auth::Role,
+        repository::memory::MemoryDatabase,
+        test_utils::{backend_test, bearer_token, global_bearer_token},
+    };
+    use rocket::http::{ContentType, Status};
+    #[cfg(feature = "postgres")]
+    use rocket::local::Client;
+    #[cfg(feature = "postgres")]
+    use testcontainers::clients::Cli;
+
+    backend_test!(metrics_count_requests_and_catalog, |client, repository| {
+        client
+            .post("/productcategory")
+            .header(ContentType::JSON)
+            .header(bearer_token(Role::Admin))
+            .body("{\"name\":\"Shirts\"}")
+            .dispatch();
+        client.get("/productcategory/1").dispatch();
+        client.get("/unknown/path").dispatch();
+        let unauthenticated_response = client.get("/metrics").dispatch();
+        let mut response = client
+            .get("/metrics")
+            .header(global_bearer_token(Role::Admin))
+            .dispatch();
+
+        let body = response.body_string().unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        let categories_sample = match repository.outbox_tenant_id()? {
+            Some(tenant_id) => format!("warehouse_categories{{tenant=\"{}\"}} 1", tenant_id),
+            None => "warehouse_categories 1".to_string(),
+        };
+        assert_eq!(Status::Unauthorized, unauthenticated_response.status());
+        assert_eq!(Status::Ok, response.status());
+        assert!(lines.contains(
+            &"http_requests_total{method=\"POST\",route=\"/productcategory\",status=\"201\"} 1"
+        ));
+        assert!(lines.contains(
+            &"http_requests_total{method=\"GET\",route=\"/productcategory/<product_category_id>\",status=\"401\"} 1"
+        ));
+        assert!(lines
+            .contains(&"http_requests_total{method=\"GET\",route=\"unmatched\",status=\"404\"} 1"));
+        assert!(lines.contains(&categories_sample.as_str()));
+        let pools_connections = client.rocket().state::<MemoryDatabase>().is_none();
+        assert_eq!(
+            pools_connections,
+            lines
+                .iter()
+                .any(|line| line.starts_with("db_pool_max_connections "))
+        );
+        Ok(())
+    });
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn administrators_of_a_single_tenant_may_not_scrape_the_metrics(
+    ) -> Result<(), crate::migrations::MigrationError> {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (rocket, _) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+        let client = Client::new(rocket).expect("valid rocket instance");
+
+        let response = client
+            .get("/metrics")
+            .header(bearer_token(Role::Admin))
+            .dispatch();
+
+        assert_eq!(Status::Forbidden, response.status());
+        Ok(())
+    }
+}
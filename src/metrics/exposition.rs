@@ -0,0 +1,62 @@
+//! Writing of metric families in the Prometheus text exposition format
+use std::fmt::Write;
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: (&str, &str) = ("text", "plain; version=0.0.4");
+
+/// Writes the help and type lines which introduce a metric family
+pub fn family(output: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, metric_type);
+}
+
+/// Writes a sample of a metric with its labels
+pub fn sample<V: std::fmt::Display>(
+    output: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    value: V,
+) {
+    output.push_str(name);
+    if !labels.is_empty() {
+        output.push('{');
+        for (index, (label, label_value)) in labels.iter().enumerate() {
+            if index > 0 {
+                output.push(',');
+            }
+            let _ = write!(output, "{}=\"{}\"", label, escape(label_value));
+        }
+        output.push('}');
+    }
+    let _ = writeln!(output, " {}", value);
+}
+
+fn escape(label_value: &str) -> String {
+    label_value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sample;
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut output = String::new();
+
+        sample(&mut output, "up", &[], 1);
+        sample(
+            &mut output,
+            "items",
+            &[("name", "a \"b\"\\c"), ("id", "1")],
+            2.5,
+        );
+
+        assert_eq!(
+            "up 1\nitems{name=\"a \\\"b\\\"\\\\c\",id=\"1\"} 2.5\n",
+            output
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use super::exposition::{family, sample};
+#[cfg(feature = "postgres")]
+use crate::{
+    repository::StatisticsRepository,
+    tenancy::{clear_session_scope, scope_session},
+};
+#[cfg(feature = "postgres")]
+use diesel::{PgConnection, QueryDsl, QueryResult, RunQueryDsl};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Age after which the domain gauges are counted again when the metrics are scraped
+pub const GAUGE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// Size of the catalog and stock
+pub struct CatalogCounts {
+    pub categories: i64,
+    pub products: i64,
+    /// Inventory items per warehouse, with items not assigned to any warehouse under `None`
+    pub warehouse_items: Vec<(Option<i32>, i64)>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// Size of the catalog and stock of a tenant, or of an SQLite database under `None`
+pub struct TenantCounts {
+    pub tenant_id: Option<i32>,
+    pub catalog: CatalogCounts,
+}
+
+/// Counts the catalog and stock of every tenant
+///
+/// Scopes the session of the connection to each tenant in turn, and clears the scope
+/// afterwards.
+#[cfg(feature = "postgres")]
+pub fn tenant_counts(connection: &PgConnection) -> QueryResult<Vec<TenantCounts>> {
+    use crate::schema::tenant;
+    let tenant_ids: Vec<i32> = tenant::table
+        .select(tenant::id)
+        .order(tenant::id)
+        .load(connection)?;
+    let counts = tenant_ids
+        .into_iter()
+        .map(|tenant_id| {
+            scope_session(connection, tenant_id)?;
+            Ok(TenantCounts {
+                tenant_id: Some(tenant_id),
+                catalog: connection.catalog_counts()?,
+            })
+        })
+        .collect();
+    clear_session_scope(connection)?;
+    counts
+}
+
+/// Counts of every tenant with the time they were counted at
+type RefreshedCounts = Option<(Instant, Vec<TenantCounts>)>;
+
+#[derive(Clone, Default)]
+/// Counts of the last refresh of the domain gauges
+///
+/// Counting runs three queries per tenant, so scrapes within [`GAUGE_REFRESH_INTERVAL`] of the
+/// last refresh are answered with the same counts. Clones share the counts.
+pub struct GaugeCache {
+    refreshed: Arc<Mutex<RefreshedCounts>>,
+}
+
+impl GaugeCache {
+    /// Returns the cached counts, or refreshes them if they are missing or older than the interval
+    ///
+    /// Concurrent scrapes wait for a running refresh instead of counting as well. A failed
+    /// refresh keeps the previous counts for the next attempt.
+    pub fn tenant_counts<F, E>(&self, now: Instant, refresh: F) -> Result<Vec<TenantCounts>, E>
+    where
+        F: FnOnce() -> Result<Vec<TenantCounts>, E>,
+    {
+        let mut refreshed = self.refreshed.lock().unwrap();
+        match &*refreshed {
+            Some((refreshed_at, counts))
+                if now.saturating_duration_since(*refreshed_at) < GAUGE_REFRESH_INTERVAL =>
+            {
+                Ok(counts.clone())
+            }
+            _ => {
+                let counts = refresh()?;
+                *refreshed = Some((now, counts.clone()));
+                Ok(counts)
+            }
+        }
+    }
+}
+
+/// Writes the gauges of the catalog and stock of every tenant
+///
+/// Gauges of an SQLite database carry no tenant label.
+pub fn render_tenant_counts(output: &mut String, counts: &[TenantCounts]) {
+    let tenants: Vec<Option<String>> = counts
+        .iter()
+        .map(|tenant_counts| tenant_counts.tenant_id.map(|id| id.to_string()))
+        .collect();
+    family(
+        output,
+        "warehouse_categories",
+        "gauge",
+        "Number of product categories",
+    );
+    for (tenant_counts, tenant) in counts.iter().zip(&tenants) {
+        sample(
+            output,
+            "warehouse_categories",
+            &tenant_labels(tenant),
+            tenant_counts.catalog.categories,
+        );
+    }
+    family(output, "warehouse_products", "gauge", "Number of products");
+    for (tenant_counts, tenant) in counts.iter().zip(&tenants) {
+        sample(
+            output,
+            "warehouse_products",
+            &tenant_labels(tenant),
+            tenant_counts.catalog.products,
+        );
+    }
+    family(
+        output,
+        "warehouse_inventory_items",
+        "gauge",
+        "Number of inventory items per warehouse",
+    );
+    for (tenant_counts, tenant) in counts.iter().zip(&tenants) {
+        for (warehouse_id, items) in &tenant_counts.catalog.warehouse_items {
+            let warehouse = warehouse_id.map_or_else(|| "none".to_string(), |id| id.to_string());
+            let mut labels = tenant_labels(tenant);
+            labels.push(("warehouse", &warehouse));
+            sample(output, "warehouse_inventory_items", &labels, items);
+        }
+    }
+}
+
+fn tenant_labels(tenant: &Option<String>) -> Vec<(&'static str, &str)> {
+    tenant
+        .iter()
+        .map(|tenant| ("tenant", tenant.as_str()))
+        .collect()
+}
+
+/// Writes the utilisation of the database connection pool
+pub fn render_pool(output: &mut String, connections: u32, idle_connections: u32, max_size: u32) {
+    family(
+        output,
+        "db_pool_connections",
+        "gauge",
+        "Number of open database connections",
+    );
+    sample(output, "db_pool_connections", &[], connections);
+    family(
+        output,
+        "db_pool_idle_connections",
+        "gauge",
+        "Number of open database connections which are not in use",
+    );
+    sample(output, "db_pool_idle_connections", &[], idle_connections);
+    family(
+        output,
+        "db_pool_max_connections",
+        "gauge",
+        "Maximum number of database connections",
+    );
+    sample(output, "db_pool_max_connections", &[], max_size);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CatalogCounts, GaugeCache, TenantCounts, GAUGE_REFRESH_INTERVAL};
+    use std::time::Instant;
+
+    fn counts(categories: i64) -> Vec<TenantCounts> {
+        vec![TenantCounts {
+            tenant_id: Some(1),
+            catalog: CatalogCounts {
+                categories,
+                products: 0,
+                warehouse_items: Vec::new(),
+            },
+        }]
+    }
+
+    #[test]
+    fn counts_are_refreshed_only_after_the_interval() {
+        let cache = GaugeCache::default();
+        let start = Instant::now();
+
+        let first = cache.tenant_counts(start, || Ok::<_, ()>(counts(1)));
+        let cached = cache.tenant_counts(start + GAUGE_REFRESH_INTERVAL / 2, || Err(()));
+        let refreshed =
+            cache.tenant_counts(start + GAUGE_REFRESH_INTERVAL, || Ok::<_, ()>(counts(2)));
+
+        assert_eq!(Ok(counts(1)), first);
+        assert_eq!(Ok(counts(1)), cached);
+        assert_eq!(Ok(counts(2)), refreshed);
+    }
+}
@@ -0,0 +1,12 @@
+//! Metrics of the service in the Prometheus text exposition format
+//!
+//! Request counts and latencies are recorded by the [`fairing`] for every response, while pool
+//! utilisation and domain gauges are read when the metrics are scraped. The domain gauges are
+//! labelled by tenant, so only global administrators may scrape the metrics.
+pub mod controllers;
+pub mod exposition;
+pub mod gauges;
+pub mod requests;
+
+pub use controllers::*;
+pub use requests::{fairing, RequestMetrics};
@@ -0,0 +1,232 @@
+use super::{
+    exposition::{family, sample},
+    gauges::GaugeCache,
+};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    Data, Request, Response, Rocket,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Upper bounds of the latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Route label of requests which did not match any route, so that arbitrary paths do not
+/// create new series
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct SeriesKey {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+#[derive(Default)]
+struct Series {
+    count: u64,
+    latency_sum: f64,
+    /// Number of requests per bucket, not cumulative
+    bucket_counts: Vec<u64>,
+}
+
+#[derive(Clone, Default)]
+/// Counts and latencies of the responses, per method, route and status
+///
+/// Clones share the recorded metrics.
+pub struct RequestMetrics {
+    series: Arc<Mutex<BTreeMap<SeriesKey, Series>>>,
+}
+
+impl RequestMetrics {
+    /// Records a response to a request of the route
+    pub fn observe(&self, method: &str, route: &str, status: u16, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        let mut series = self.series.lock().unwrap();
+        let series = series
+            .entry(SeriesKey {
+                method: method.to_string(),
+                route: route.to_string(),
+                status,
+            })
+            .or_insert_with(|| Series {
+                bucket_counts: vec![0; LATENCY_BUCKETS.len()],
+                ..Series::default()
+            });
+        series.count += 1;
+        series.latency_sum += seconds;
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            series.bucket_counts[bucket] += 1;
+        }
+    }
+
+    /// Writes the request counter and latency histogram
+    pub fn render(&self, output: &mut String) {
+        let series = self.series.lock().unwrap();
+        family(
+            output,
+            "http_requests_total",
+            "counter",
+            "Number of answered requests",
+        );
+        for (key, series) in series.iter() {
+            let status = key.status.to_string();
+            let labels = [
+                ("method", key.method.as_str()),
+                ("route", key.route.as_str()),
+                ("status", status.as_str()),
+            ];
+            sample(output, "http_requests_total", &labels, series.count);
+        }
+        family(
+            output,
+            "http_request_duration_seconds",
+            "histogram",
+            "Time taken to answer requests",
+        );
+        for (key, series) in series.iter() {
+            let status = key.status.to_string();
+            let mut cumulative_count = 0;
+            for (bound, bucket_count) in LATENCY_BUCKETS.iter().zip(&series.bucket_counts) {
+                cumulative_count += bucket_count;
+                let bound = bound.to_string();
+                let labels = [
+                    ("method", key.method.as_str()),
+                    ("route", key.route.as_str()),
+                    ("status", status.as_str()),
+                    ("le", bound.as_str()),
+                ];
+                sample(
+                    output,
+                    "http_request_duration_seconds_bucket",
+                    &labels,
+                    cumulative_count,
+                );
+            }
+            let labels = [
+                ("method", key.method.as_str()),
+                ("route", key.route.as_str()),
+                ("status", status.as_str()),
+            ];
+            let infinite_labels = [labels[0], labels[1], labels[2], ("le", "+Inf")];
+            sample(
+                output,
+                "http_request_duration_seconds_bucket",
+                &infinite_labels,
+                series.count,
+            );
+            sample(
+                output,
+                "http_request_duration_seconds_sum",
+                &labels,
+                series.latency_sum,
+            );
+            sample(
+                output,
+                "http_request_duration_seconds_count",
+                &labels,
+                series.count,
+            );
+        }
+    }
+}
+
+/// Manages the [`RequestMetrics`] and the [`GaugeCache`], and records every response
+pub fn fairing() -> impl Fairing {
+    RequestMetricsFairing {
+        metrics: RequestMetrics::default(),
+    }
+}
+
+struct RequestMetricsFairing {
+    metrics: RequestMetrics,
+}
+
+/// Time at which Rocket started to handle a request
+struct RequestStart(Instant);
+
+impl Fairing for RequestMetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Attach | Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        Ok(rocket
+            .manage(self.metrics.clone())
+            .manage(GaugeCache::default()))
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+        let route = request.route().map_or_else(
+            || UNMATCHED_ROUTE.to_string(),
+            |route| route.uri.path().to_string(),
+        );
+        self.metrics.observe(
+            request.method().as_str(),
+            &route,
+            response.status().code,
+            start.0.elapsed(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestMetrics;
+    use std::time::Duration;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let metrics = RequestMetrics::default();
+        metrics.observe("GET", "/productcategory", 200, Duration::from_millis(3));
+        metrics.observe("GET", "/productcategory", 200, Duration::from_millis(30));
+        metrics.observe("GET", "/productcategory", 200, Duration::from_secs(20));
+        metrics.observe("POST", "/productcategory", 201, Duration::from_millis(3));
+
+        let mut output = String::new();
+        metrics.render(&mut output);
+
+        let series = "method=\"GET\",route=\"/productcategory\",status=\"200\"";
+        for expected_line in &[
+            format!("http_requests_total{{{}}} 3", series),
+            format!(
+                "http_request_duration_seconds_bucket{{{},le=\"0.005\"}} 1",
+                series
+            ),
+            format!(
+                "http_request_duration_seconds_bucket{{{},le=\"0.05\"}} 2",
+                series
+            ),
+            format!(
+                "http_request_duration_seconds_bucket{{{},le=\"10\"}} 2",
+                series
+            ),
+            format!(
+                "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 3",
+                series
+            ),
+            format!("http_request_duration_seconds_count{{{}}} 3", series),
+        ] {
+            assert!(
+                output.lines().any(|line| line == expected_line),
+                "{}",
+                expected_line
+            );
+        }
+        assert!(output.contains("method=\"POST\",route=\"/productcategory\",status=\"201\""));
+    }
+}
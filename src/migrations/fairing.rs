@@ -0,0 +1,118 @@
+#[cfg(feature = "postgres")]
+use super::migrate;
+use super::{Migration, MigrationError, MigrationMode};
+use crate::settings::Backend;
+#[cfg(feature = "postgres")]
+use crate::DbConn;
+use rocket::{config::ConfigError, fairing::AdHoc, Rocket};
+
+/// Applies or verifies the embedded migrations when Rocket is ignited
+///
+/// The mode is read from `migrations` and is one of `run`, the default, `verify` or `skip`.
+/// Rocket refuses to start if the migrations fail, if migrations are pending in `verify` mode or
+/// if the database schema is newer than the binary. Has to be attached after the database pool.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_attach("Database migrations", |rocket| {
+        let mode = match rocket.config().get_str("migrations") {
+            Ok(mode) => mode.parse(),
+            Err(ConfigError::Missing(_)) => Ok(MigrationMode::default()),
+            Err(error) => Err(error.to_string()),
+        };
+        let mode = match mode {
+            Ok(mode) => mode,
+            Err(error) => {
+                log::error!("{}", error);
+                return Err(rocket);
+            }
+        };
+        if mode == MigrationMode::Skip {
+            return Ok(rocket);
+        }
+        match apply(&rocket, mode) {
+            Some(Ok(applied_migrations)) => {
+                for migration in applied_migrations {
+                    log::info!("Applied migration {}", migration.name);
+                }
+                Ok(rocket)
+            }
+            Some(Err(error)) => {
+                log::error!("{}", error);
+                Err(rocket)
+            }
+            None => {
+                log::error!("Migrations can not be checked without a database connection");
+                Err(rocket)
+            }
+        }
+    })
+}
+
+/// Migrates the database of the configured backend, or returns `None` without a connection
+///
+/// The in-memory database has no schema, so nothing is migrated.
+#[cfg_attr(
+    not(any(feature = "postgres", feature = "sqlite")),
+    allow(unused_variables)
+)]
+fn apply(
+    rocket: &Rocket,
+    mode: MigrationMode,
+) -> Option<Result<Vec<&'static Migration>, MigrationError>> {
+    match Backend::from_rocket_config(rocket.config()) {
+        #[cfg(feature = "postgres")]
+        Backend::Postgres => DbConn::get_one(rocket).map(|connection| migrate(&connection, mode)),
+        #[cfg(not(feature = "postgres"))]
+        Backend::Postgres => None,
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => crate::SqliteDbConn::get_one(rocket)
+            .map(|connection| super::sqlite::migrate(&connection, mode)),
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => None,
+        Backend::Memory => Some(Ok(Vec::new())),
+    }
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use crate::migrations::{applied_versions, embedded};
+    use rocket::{config::Environment, error::LaunchErrorKind, local::Client, Config};
+    use std::collections::HashMap;
+    use testcontainers::clients::Cli;
+
+    fn rocket_with_migrations(url: &str, mode: &str) -> rocket::Rocket {
+        let mut pg_database_config = HashMap::new();
+        pg_database_config.insert("url", url.to_string());
+        let mut database_config = HashMap::new();
+        database_config.insert("pgdatabase", pg_database_config);
+        let rocket_config = Config::build(Environment::Development)
+            .extra("databases", database_config)
+            .extra("migrations", mode)
+            .finalize()
+            .unwrap();
+        rocket::custom(rocket_config)
+            .attach(crate::DbConn::fairing())
+            .attach(super::fairing())
+    }
+
+    #[test]
+    fn rocket_applies_migrations_on_startup() -> Result<(), Box<dyn std::error::Error>> {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+
+        let verifying_client =
+            Client::new(rocket_with_migrations(&database_metadata.url, "verify"));
+        let running_client = Client::new(rocket_with_migrations(&database_metadata.url, "run"));
+
+        let connection: diesel::PgConnection =
+            diesel::Connection::establish(&database_metadata.url)?;
+        assert_eq!(
+            Some(true),
+            verifying_client
+                .err()
+                .map(|error| matches!(error.kind(), LaunchErrorKind::FailedFairings(_)))
+        );
+        assert!(running_client.is_ok());
+        assert_eq!(embedded().len(), applied_versions(&connection)?.len());
+        Ok(())
+    }
+}
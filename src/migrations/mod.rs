@@ -0,0 +1,367 @@
+//! Migrations of the database schema, embedded into the binaries at build time
+//!
+//! Applied migrations are tracked in the same table as the Diesel CLI uses, so that databases
+//! can be migrated with either tool. Servers apply or verify the migrations on startup through
+//! the [`fairing`].
+
+pub mod fairing;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use fairing::fairing;
+
+#[cfg(feature = "postgres")]
+use diesel::{
+    connection::SimpleConnection, sql_query, sql_types::BigInt, Connection, ExpressionMethods,
+    PgConnection, QueryDsl, QueryResult, RunQueryDsl,
+};
+use std::{fmt, str::FromStr};
+
+table! {
+    __diesel_schema_migrations (version) {
+        version -> VarChar,
+        run_on -> Timestamp,
+    }
+}
+
+/// Migration as written in the `migrations` directory
+pub struct Migration {
+    pub version: &'static str,
+    pub name: &'static str,
+    pub up_sql: &'static str,
+    pub down_sql: &'static str,
+}
+
+include!(concat!(env!("OUT_DIR"), "/embedded_migrations.rs"));
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database has applied a migration which this binary does not know
+    UnknownMigration(String),
+    /// Migrations known to this binary are not applied, although they were required to be
+    Pending(Vec<&'static str>),
+    Query(diesel::result::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MigrationError::UnknownMigration(version) => write!(
+                f,
+                "The database has applied migration {}, which this binary does not know",
+                version
+            ),
+            MigrationError::Pending(names) => {
+                write!(f, "Migrations are not applied: {}", names.join(", "))
+            }
+            MigrationError::Query(error) => write!(f, "Migration failed: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<diesel::result::Error> for MigrationError {
+    fn from(error: diesel::result::Error) -> Self {
+        MigrationError::Query(error)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+/// How migrations are handled when a server starts
+pub enum MigrationMode {
+    /// Applies pending migrations
+    #[default]
+    Run,
+    /// Refuses to start if migrations are pending
+    Verify,
+    /// Leaves the schema alone
+    Skip,
+}
+
+impl MigrationMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MigrationMode::Run => "run",
+            MigrationMode::Verify => "verify",
+            MigrationMode::Skip => "skip",
+        }
+    }
+}
+
+impl FromStr for MigrationMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "run" => Ok(MigrationMode::Run),
+            "verify" => Ok(MigrationMode::Verify),
+            "skip" => Ok(MigrationMode::Skip),
+            _ => Err(format!(
+                "unknown migration mode {}, expected run, verify or skip",
+                mode
+            )),
+        }
+    }
+}
+
+/// Key of the advisory lock held while migrations are checked or applied
+#[cfg(feature = "postgres")]
+const MIGRATION_LOCK_KEY: i64 = 0x7761_7265_686f_7573;
+
+/// All migrations known to this binary, ordered by version
+pub fn embedded() -> &'static [Migration] {
+    EMBEDDED_MIGRATIONS
+}
+
+#[cfg(feature = "postgres")]
+fn find(version: &str) -> Option<&'static Migration> {
+    embedded()
+        .iter()
+        .find(|migration| migration.version == version)
+}
+
+#[cfg(feature = "postgres")]
+fn ensure_migrations_table(connection: &PgConnection) -> QueryResult<()> {
+    sql_query(
+        "create table if not exists __diesel_schema_migrations (
+            version varchar(50) primary key not null,
+            run_on timestamp not null default current_timestamp
+        )",
+    )
+    .execute(connection)?;
+    Ok(())
+}
+
+/// Versions of the migrations which are applied to the database, in ascending order
+#[cfg(feature = "postgres")]
+pub fn applied_versions(connection: &PgConnection) -> QueryResult<Vec<String>> {
+    use self::__diesel_schema_migrations::dsl::*;
+    let tables = sql_query(
+        "select from pg_tables
+          where schemaname = current_schema() and tablename = '__diesel_schema_migrations'",
+    )
+    .execute(connection)?;
+    if tables == 0 {
+        return Ok(Vec::new());
+    }
+    __diesel_schema_migrations
+        .select(version)
+        .order(version)
+        .load(connection)
+}
+
+/// Migrations which are known to this binary but not applied to the database
+#[cfg(feature = "postgres")]
+pub fn pending(connection: &PgConnection) -> QueryResult<Vec<&'static Migration>> {
+    let applied = applied_versions(connection)?;
+    Ok(embedded()
+        .iter()
+        .filter(|migration| !applied.iter().any(|version| version == migration.version))
+        .collect())
+}
+
+/// Applies all pending migrations, each in its own transaction
+///
+/// Returns the migrations which were applied.
+#[cfg(feature = "postgres")]
+pub fn run_pending(connection: &PgConnection) -> QueryResult<Vec<&'static Migration>> {
+    use self::__diesel_schema_migrations::dsl::*;
+    ensure_migrations_table(connection)?;
+    let pending_migrations = pending(connection)?;
+    for migration in &pending_migrations {
+        connection.transaction(|| {
+            connection.batch_execute(migration.up_sql)?;
+            diesel::insert_into(__diesel_schema_migrations)
+                .values(version.eq(migration.version))
+                .execute(connection)
+        })?;
+    }
+    Ok(pending_migrations)
+}
+
+/// Reverts the latest applied migration
+///
+/// Returns the reverted migration, or `None` if no migration is applied.
+#[cfg(feature = "postgres")]
+pub fn revert_latest(
+    connection: &PgConnection,
+) -> Result<Option<&'static Migration>, MigrationError> {
+    use self::__diesel_schema_migrations::dsl::*;
+    let latest_version = match applied_versions(connection)?.pop() {
+        Some(latest_version) => latest_version,
+        None => return Ok(None),
+    };
+    let migration =
+        find(&latest_version).ok_or(MigrationError::UnknownMigration(latest_version))?;
+    connection.transaction::<_, diesel::result::Error, _>(|| {
+        connection.batch_execute(migration.down_sql)?;
+        diesel::delete(__diesel_schema_migrations.filter(version.eq(migration.version)))
+            .execute(connection)?;
+        Ok(())
+    })?;
+    Ok(Some(migration))
+}
+
+/// Fails if the database has applied migrations which this binary does not know
+///
+/// This is the case if the schema was migrated by a newer version of the application.
+#[cfg(feature = "postgres")]
+pub fn check_known(connection: &PgConnection) -> Result<(), MigrationError> {
+    match applied_versions(connection)?
+        .into_iter()
+        .find(|applied| find(applied).is_none())
+    {
+        Some(unknown_version) => Err(MigrationError::UnknownMigration(unknown_version)),
+        None => Ok(()),
+    }
+}
+
+/// Runs the operation while holding the migration lock of the database
+///
+/// Concurrently starting instances wait for each other instead of applying the same
+/// migrations twice. The lock is held by the session, so it is released even if the operation
+/// fails.
+#[cfg(feature = "postgres")]
+pub fn with_lock<T, E, F>(connection: &PgConnection, operation: F) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E>,
+    E: From<diesel::result::Error>,
+{
+    sql_query("select pg_advisory_lock($1)")
+        .bind::<BigInt, _>(MIGRATION_LOCK_KEY)
+        .execute(connection)?;
+    let result = operation();
+    sql_query("select pg_advisory_unlock($1)")
+        .bind::<BigInt, _>(MIGRATION_LOCK_KEY)
+        .execute(connection)?;
+    result
+}
+
+/// Brings the schema of the database in line with the mode
+///
+/// In every mode but [`MigrationMode::Skip`] this fails if the database has applied migrations
+/// which this binary does not know. Returns the migrations which were applied.
+#[cfg(feature = "postgres")]
+pub fn migrate(
+    connection: &PgConnection,
+    mode: MigrationMode,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    if mode == MigrationMode::Skip {
+        return Ok(Vec::new());
+    }
+    with_lock(connection, || {
+        if mode == MigrationMode::Run {
+            check_known(connection)?;
+            Ok(run_pending(connection)?)
+        } else {
+            verify(connection)?;
+            Ok(Vec::new())
+        }
+    })
+}
+
+/// Fails unless exactly the migrations known to this binary are applied
+#[cfg(feature = "postgres")]
+pub fn verify(connection: &PgConnection) -> Result<(), MigrationError> {
+    check_known(connection)?;
+    let pending_migrations = pending(connection)?;
+    if pending_migrations.is_empty() {
+        Ok(())
+    } else {
+        Err(MigrationError::Pending(
+            pending_migrations
+                .iter()
+                .map(|migration| migration.name)
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use super::{
+        applied_versions, embedded, migrate, pending, revert_latest, run_pending, MigrationError,
+        MigrationMode,
+    };
+    use diesel::RunQueryDsl;
+    use testcontainers::clients::Cli;
+
+    #[test]
+    fn embedded_migrations_are_ordered_by_version() {
+        let versions: Vec<&str> = embedded()
+            .iter()
+            .map(|migration| migration.version)
+            .collect();
+        let mut sorted_versions = versions.clone();
+        sorted_versions.sort_unstable();
+
+        assert!(versions.len() > 1);
+        assert_eq!(sorted_versions, versions);
+        assert!(versions.iter().all(|version| version.len() == 14));
+    }
+
+    #[test]
+    fn migrations_are_applied_and_reverted() -> Result<(), Box<dyn std::error::Error>> {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let connection: diesel::PgConnection =
+            diesel::Connection::establish(&database_metadata.url)?;
+
+        let applied_migrations = run_pending(&connection)?;
+        let reverted_migration = revert_latest(&connection)?.unwrap();
+        let pending_migrations = pending(&connection)?;
+        run_pending(&connection)?;
+
+        assert_eq!(embedded().len(), applied_migrations.len());
+        assert_eq!(
+            embedded().last().unwrap().version,
+            reverted_migration.version
+        );
+        assert_eq!(1, pending_migrations.len());
+        assert_eq!(embedded().len(), applied_versions(&connection)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn newer_schema_is_refused() -> Result<(), Box<dyn std::error::Error>> {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let connection: diesel::PgConnection =
+            diesel::Connection::establish(&database_metadata.url)?;
+
+        run_pending(&connection)?;
+        let verified_migrations = migrate(&connection, MigrationMode::Verify)?;
+        diesel::sql_query(
+            "insert into __diesel_schema_migrations (version) values ('99991231235959')",
+        )
+        .execute(&connection)?;
+        let newer_schema_result = migrate(&connection, MigrationMode::Run);
+
+        assert!(verified_migrations.is_empty());
+        assert!(matches!(
+            newer_schema_result,
+            Err(MigrationError::UnknownMigration(version)) if version == "99991231235959"
+        ));
+        assert!(migrate(&connection, MigrationMode::Skip)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn pending_migrations_fail_verification() -> Result<(), Box<dyn std::error::Error>> {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let connection: diesel::PgConnection =
+            diesel::Connection::establish(&database_metadata.url)?;
+
+        let verification_result = migrate(&connection, MigrationMode::Verify);
+        let applied_migrations = migrate(&connection, MigrationMode::Run)?;
+
+        assert!(matches!(
+            verification_result,
+            Err(MigrationError::Pending(names)) if names.len() == embedded().len()
+        ));
+        assert_eq!(embedded().len(), applied_migrations.len());
+        Ok(())
+    }
+}
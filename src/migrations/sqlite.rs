@@ -0,0 +1,139 @@
+//! Migrations of the SQLite backend, which are kept in `migrations_sqlite`
+//!
+//! SQLite databases are used by a single server, so unlike on Postgres no lock is taken while
+//! migrating.
+
+use super::{Migration, MigrationError, MigrationMode};
+use diesel::{
+    connection::SimpleConnection, sql_query, sql_types::BigInt, Connection, ExpressionMethods,
+    QueryDsl, QueryResult, RunQueryDsl, SqliteConnection,
+};
+
+include!(concat!(env!("OUT_DIR"), "/embedded_sqlite_migrations.rs"));
+
+/// All migrations of the SQLite backend known to this binary, ordered by version
+pub fn embedded() -> &'static [Migration] {
+    EMBEDDED_SQLITE_MIGRATIONS
+}
+
+#[derive(QueryableByName)]
+struct TableCount {
+    #[sql_type = "BigInt"]
+    tables: i64,
+}
+
+/// Versions of the migrations which are applied to the database, in ascending order
+pub fn applied_versions(connection: &SqliteConnection) -> QueryResult<Vec<String>> {
+    use super::__diesel_schema_migrations::dsl::*;
+    let migration_tables: TableCount = sql_query(
+        "select count(*) as tables from sqlite_master
+          where type = 'table' and name = '__diesel_schema_migrations'",
+    )
+    .get_result(connection)?;
+    if migration_tables.tables == 0 {
+        return Ok(Vec::new());
+    }
+    __diesel_schema_migrations
+        .select(version)
+        .order(version)
+        .load(connection)
+}
+
+/// Migrations which are known to this binary but not applied to the database
+pub fn pending(connection: &SqliteConnection) -> QueryResult<Vec<&'static Migration>> {
+    let applied = applied_versions(connection)?;
+    Ok(embedded()
+        .iter()
+        .filter(|migration| !applied.iter().any(|version| version == migration.version))
+        .collect())
+}
+
+/// Applies all pending migrations, each in its own transaction
+///
+/// Returns the migrations which were applied.
+pub fn run_pending(connection: &SqliteConnection) -> QueryResult<Vec<&'static Migration>> {
+    use super::__diesel_schema_migrations::dsl::*;
+    connection.batch_execute(
+        "create table if not exists __diesel_schema_migrations (
+            version varchar(50) primary key not null,
+            run_on timestamp not null default current_timestamp
+        )",
+    )?;
+    let pending_migrations = pending(connection)?;
+    for migration in &pending_migrations {
+        connection.transaction(|| {
+            connection.batch_execute(migration.up_sql)?;
+            diesel::insert_into(__diesel_schema_migrations)
+                .values(version.eq(migration.version))
+                .execute(connection)
+        })?;
+    }
+    Ok(pending_migrations)
+}
+
+/// Fails if the database has applied migrations which this binary does not know
+pub fn check_known(connection: &SqliteConnection) -> Result<(), MigrationError> {
+    match applied_versions(connection)?.into_iter().find(|applied| {
+        !embedded()
+            .iter()
+            .any(|migration| migration.version == applied)
+    }) {
+        Some(unknown_version) => Err(MigrationError::UnknownMigration(unknown_version)),
+        None => Ok(()),
+    }
+}
+
+/// Fails unless exactly the migrations known to this binary are applied
+pub fn verify(connection: &SqliteConnection) -> Result<(), MigrationError> {
+    check_known(connection)?;
+    let pending_migrations = pending(connection)?;
+    if pending_migrations.is_empty() {
+        Ok(())
+    } else {
+        Err(MigrationError::Pending(
+            pending_migrations
+                .iter()
+                .map(|migration| migration.name)
+                .collect(),
+        ))
+    }
+}
+
+/// Brings the schema of the database in line with the mode, like [`super::migrate`]
+pub fn migrate(
+    connection: &SqliteConnection,
+    mode: MigrationMode,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    match mode {
+        MigrationMode::Skip => Ok(Vec::new()),
+        MigrationMode::Verify => verify(connection).map(|_| Vec::new()),
+        MigrationMode::Run => {
+            check_known(connection)?;
+            Ok(run_pending(connection)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{embedded, migrate, MigrationError, MigrationMode};
+    use diesel::{Connection, SqliteConnection};
+
+    #[test]
+    fn migrations_are_applied_once() -> Result<(), Box<dyn std::error::Error>> {
+        let connection = SqliteConnection::establish(":memory:")?;
+
+        let verification_result = migrate(&connection, MigrationMode::Verify);
+        let applied_migrations = migrate(&connection, MigrationMode::Run)?;
+        let reapplied_migrations = migrate(&connection, MigrationMode::Run)?;
+
+        assert!(matches!(
+            verification_result,
+            Err(MigrationError::Pending(_))
+        ));
+        assert_eq!(embedded().len(), applied_migrations.len());
+        assert!(reapplied_migrations.is_empty());
+        assert!(migrate(&connection, MigrationMode::Verify)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use super::spec::specification;
+use rocket::response::content::Html;
+use rocket_contrib::json::Json;
+
+/// Page which renders the specification, bundled into the binary so that it works offline
+const DOCS_PAGE: &str = include_str!("docs.html");
+
+/// Returns the OpenAPI specification of the API
+#[get("/openapi.json")]
+pub fn get_specification() -> Json<serde_json::Value> {
+    Json(specification())
+}
+
+/// Returns a page for browsing the specification
+#[get("/docs")]
+pub fn get_docs() -> Html<&'static str> {
+    Html(DOCS_PAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::{ContentType, Status},
+        local::Client,
+    };
+
+    #[test]
+    fn specification_and_docs_are_served_without_authentication() {
+        let rocket =
+            rocket::ignite().mount("/", routes![super::get_specification, super::get_docs]);
+        let client = Client::new(rocket).expect("valid rocket instance");
+
+        let mut specification_response = client.get("/openapi.json").dispatch();
+        let docs_response = client.get("/docs").dispatch();
+
+        let specification: serde_json::Value =
+            serde_json::from_str(&specification_response.body_string().unwrap()).unwrap();
+        assert_eq!(Status::Ok, specification_response.status());
+        assert_eq!("3.0.3", specification["openapi"]);
+        assert_eq!(Status::Ok, docs_response.status());
+        assert_eq!(Some(ContentType::HTML), docs_response.content_type());
+    }
+}
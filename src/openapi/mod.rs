@@ -0,0 +1,9 @@
+//! OpenAPI specification of the REST API
+//!
+//! The schemas are derived from the serde types of the requests and responses. The operations
+//! are listed by hand next to each other, and a test checks that they match the mounted routes.
+
+pub mod controllers;
+pub mod spec;
+
+pub use controllers::*;
@@ -0,0 +1,898 @@
+use crate::{
+    catalog::{
+        self, entities::ProductAttribute, facets::Listing, ProductAttributeBody, ProductPriceBody,
+    },
+    csv_transfer::{import::ImportReport, CsvResource},
+    entities::Product,
+    graphql::GraphQLRequestBody,
+    health::HealthReport,
+    idempotency::{Idempotent, IDEMPOTENCY_KEY_HEADER},
+    localization::{
+        entities::{CategoryTranslation, ProductTranslation},
+        ProductTranslationBody, ACCEPT_LANGUAGE_HEADER, CONTENT_LANGUAGE_HEADER,
+    },
+    product_category::{
+        entities::{CategoryTree, CategoryWithBreadcrumbs, ProductCategory, RelatedCategory},
+        BulkItemResult, CategoryStatistics, CategoryTranslationBody, MergeReport, MergeRequestBody,
+        ProductCategoryPutBody, ProductCategoryRequestBody, TaxonomyImportReport, TaxonomyNode,
+    },
+    search::{self, SearchHit},
+    tenancy::TENANT_HEADER,
+    utilities::{GetResponder, PostResponder},
+    webhooks::{entities::WebhookSubscription, WebhookSubscriptionRequestBody},
+};
+use rocket::http::Status;
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+/// Operation of the API as described in the specification
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    authenticated: bool,
+    /// Whether the responses are served in a locale, see [`Operation::localized`]
+    localized: bool,
+    parameters: Vec<Value>,
+    request_body: Option<Value>,
+    responses: Map<String, Value>,
+}
+
+fn content(media_type: &str, schema: Value) -> Value {
+    json!({ media_type: { "schema": schema } })
+}
+
+fn schema<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    serde_json::to_value(generator.subschema_for::<T>()).unwrap_or(Value::Null)
+}
+
+impl Operation {
+    fn new(method: &'static str, path: &'static str, summary: &'static str) -> Self {
+        Operation {
+            method,
+            path,
+            summary,
+            authenticated: false,
+            localized: false,
+            parameters: Vec::new(),
+            request_body: None,
+            responses: Map::new(),
+        }
+    }
+
+    /// Requires a bearer token holding at least the role
+    fn requires(mut self, role: &str) -> Self {
+        self.authenticated = true;
+        self.parameters.push(json!({
+            "name": TENANT_HEADER,
+            "in": "header",
+            "required": false,
+            "description": "Tenant to act for, for global administrators without a tenant",
+            "schema": { "type": "integer" },
+        }));
+        let description = format!("Requires the {} role", role);
+        self.responses.insert(
+            "401".to_string(),
+            json!({ "description": "Missing or invalid token" }),
+        );
+        self.responses
+            .insert("403".to_string(), json!({ "description": description }));
+        self
+    }
+
+    /// Requires a bearer token of a global administrator, who acts for no tenant in particular
+    fn requires_global_admin(mut self) -> Self {
+        self.authenticated = true;
+        self.responses.insert(
+            "401".to_string(),
+            json!({ "description": "Missing or invalid token" }),
+        );
+        self.responses.insert(
+            "403".to_string(),
+            json!({ "description": "Requires a global administrator" }),
+        );
+        self
+    }
+
+    fn parameter(mut self, location: &str, name: &str, required: bool, schema: Value) -> Self {
+        self.parameters.push(json!({
+            "name": name,
+            "in": location,
+            "required": required,
+            "schema": schema,
+        }));
+        self
+    }
+
+    fn json_body(mut self, schema: Value) -> Self {
+        self.request_body = Some(json!({
+            "required": true,
+            "content": content("application/json", schema),
+        }));
+        self
+    }
+
+    fn body(mut self, media_type: &str, schema: Value) -> Self {
+        self.request_body = Some(json!({
+            "required": true,
+            "content": content(media_type, schema),
+        }));
+        self
+    }
+
+    /// Accepts the body in another media type as well, for routes split by their format
+    fn alternative_body(mut self, media_type: &str, schema: Value) -> Self {
+        if let Some(request_body) = &mut self.request_body {
+            request_body["content"][media_type] = json!({ "schema": schema });
+        }
+        self
+    }
+
+    fn response(mut self, status: Status, description: &str, body: Option<Value>) -> Self {
+        let mut response = json!({ "description": description });
+        if let Some(body) = body {
+            response["content"] = body;
+        }
+        self.responses.insert(status.code.to_string(), response);
+        self
+    }
+
+    fn json_response(self, status: Status, description: &str, schema: Value) -> Self {
+        self.response(
+            status,
+            description,
+            Some(content("application/json", schema)),
+        )
+    }
+
+    /// Documents the statuses of a route answering with a [`GetResponder`]
+    fn get_responder(self, schema: Value) -> Self {
+        GetResponder::<()>::STATUSES
+            .iter()
+            .fold(self, |operation, (status, description)| {
+                let body = if *status == Status::Ok {
+                    Some(content("application/json", schema.clone()))
+                } else {
+                    None
+                };
+                operation.response(*status, description, body)
+            })
+    }
+
+    /// Documents the statuses of a route answering with a [`PostResponder`]
+    fn post_responder(self, schema: Value) -> Self {
+        PostResponder::<()>::STATUSES
+            .iter()
+            .fold(self, |operation, (status, description)| {
+                operation.json_response(*status, description, schema.clone())
+            })
+    }
+
+    /// Documents how the locale of the texts in the response is chosen
+    fn localized(mut self) -> Self {
+        self.localized = true;
+        self.parameters.push(json!({
+            "name": "locale",
+            "in": "query",
+            "required": false,
+            "description": "Supported locale of the texts, which takes precedence over the header",
+            "schema": { "type": "string" },
+        }));
+        self.parameters.push(json!({
+            "name": ACCEPT_LANGUAGE_HEADER,
+            "in": "header",
+            "required": false,
+            "description": "Preferred locales, falling back to the default locale",
+            "schema": { "type": "string" },
+        }));
+        self.response(Status::BadRequest, "The locale is not supported", None)
+    }
+
+    /// Documents the `Idempotency-Key` header of a route answering with an [`Idempotent`]
+    fn idempotent(mut self) -> Self {
+        self.parameters.push(json!({
+            "name": IDEMPOTENCY_KEY_HEADER,
+            "in": "header",
+            "required": false,
+            "description": "Key under which the response is stored and replayed for retries",
+            "schema": { "type": "string", "maxLength": 255 },
+        }));
+        Idempotent::<()>::STATUSES
+            .iter()
+            .fold(self, |operation, (status, description)| {
+                operation.json_response(*status, description, json!({ "type": "object" }))
+            })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut operation = json!({
+            "summary": self.summary,
+            "operationId": format!("{}{}", self.method, self.path.replace(|c: char| !c.is_alphanumeric(), "_")),
+            "tags": [self.path.trim_start_matches('/').split('/').next().unwrap_or_default()],
+            "parameters": self.parameters,
+            "responses": self.responses,
+        });
+        if let Some(request_body) = &self.request_body {
+            operation["requestBody"] = request_body.clone();
+        }
+        if self.localized {
+            let served = self
+                .responses
+                .keys()
+                .filter(|status| status.starts_with('2'))
+                .cloned()
+                .collect::<Vec<_>>();
+            for status in served {
+                operation["responses"][&status]["headers"] = json!({
+                    CONTENT_LANGUAGE_HEADER: {
+                        "description": "Locale of the texts",
+                        "schema": { "type": "string" },
+                    },
+                    "Vary": {
+                        "description": "Accept-Language, unless the locale is queried",
+                        "schema": { "type": "string" },
+                    },
+                });
+            }
+        }
+        if self.authenticated {
+            operation["responses"]["500"] = json!({ "description": "The database failed" });
+        } else {
+            operation["security"] = json!([]);
+        }
+        operation
+    }
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer", "format": "int32" })
+}
+
+fn operations(generator: &mut SchemaGenerator) -> Vec<Operation> {
+    let category = schema::<ProductCategory>(generator);
+    let category_with_breadcrumbs = schema::<CategoryWithBreadcrumbs>(generator);
+    let category_body = schema::<ProductCategoryRequestBody>(generator);
+    let bulk_results = schema::<Vec<BulkItemResult>>(generator);
+    let related_categories = schema::<Vec<RelatedCategory>>(generator);
+    let category_tree = schema::<CategoryTree>(generator);
+    let import_report = schema::<ImportReport>(generator);
+    let all_statistics = schema::<Vec<CategoryStatistics>>(generator);
+    let taxonomy_tree = schema::<Vec<TaxonomyNode>>(generator);
+    let subscription = schema::<WebhookSubscription>(generator);
+    let health_report = schema::<HealthReport>(generator);
+    let csv_resource = json!({
+        "type": "string",
+        "enum": CsvResource::ALL.iter().map(|resource| resource.as_str()).collect::<Vec<_>>(),
+    });
+    vec![
+        Operation::new("get", "/productcategory", "Lists all product categories")
+            .requires("viewer")
+            .localized()
+            .json_response(
+                Status::Ok,
+                "All product categories",
+                schema::<Vec<CategoryWithBreadcrumbs>>(generator),
+            ),
+        Operation::new(
+            "get",
+            "/productcategory/{product_category_id}",
+            "Returns a product category",
+        )
+        .requires("viewer")
+        .localized()
+        .parameter("path", "product_category_id", true, integer())
+        .get_responder(category_with_breadcrumbs.clone()),
+        Operation::new(
+            "get",
+            "/productcategory/path/{slugs}",
+            "Returns the product category reached by slugs like clothes/swimwear from a root category",
+        )
+        .requires("viewer")
+        .localized()
+        .parameter("path", "slugs", true, json!({ "type": "string" }))
+        .get_responder(category_with_breadcrumbs),
+        Operation::new(
+            "post",
+            "/productcategory",
+            "Returns the product category with the name, creating it if it does not exist",
+        )
+        .requires("admin")
+        .localized()
+        .idempotent()
+        .json_body(category_body.clone())
+        .post_responder(category.clone())
+        .response(
+            Status::Conflict,
+            "A request with the same idempotency key is still in progress, or the default name \
+             of a category created in another locale is taken",
+            None,
+        ),
+        Operation::new(
+            "put",
+            "/productcategory/{id}",
+            "Creates the product category with the id, or replaces its name and display metadata",
+        )
+        .requires("admin")
+        .parameter("path", "id", true, integer())
+        .json_body(schema::<ProductCategoryPutBody>(generator))
+        .json_response(Status::Ok, "The stored product category", category),
+        Operation::new(
+            "delete",
+            "/productcategory/{id}",
+            "Deletes a product category",
+        )
+        .requires("admin")
+        .parameter("path", "id", true, integer())
+        .response(Status::Ok, "The product category no longer exists", None),
+        Operation::new(
+            "post",
+            "/productcategory/bulk",
+            "Creates the missing product categories and returns the existing ones",
+        )
+        .requires("admin")
+        .idempotent()
+        .json_body(schema::<Vec<ProductCategoryRequestBody>>(generator))
+        .json_response(Status::Ok, "Result of every item", bulk_results.clone())
+        .response(Status::BadRequest, "Too many items", None),
+        Operation::new(
+            "put",
+            "/productcategory/bulk",
+            "Creates or replaces the product categories with the given ids",
+        )
+        .requires("admin")
+        .json_body(schema::<Vec<ProductCategory>>(generator))
+        .json_response(Status::Ok, "Result of every item", bulk_results.clone())
+        .response(Status::BadRequest, "Too many items", None),
+        Operation::new(
+            "delete",
+            "/productcategory/bulk",
+            "Deletes the product categories with the given ids",
+        )
+        .requires("admin")
+        .json_body(schema::<Vec<i32>>(generator))
+        .json_response(Status::Ok, "Result of every item", bulk_results)
+        .response(Status::BadRequest, "Too many items", None),
+        Operation::new(
+            "get",
+            "/productcategory/{product_category_id}/descendants",
+            "Lists the categories rolled up into a product category, directly or indirectly",
+        )
+        .requires("viewer")
+        .localized()
+        .parameter("path", "product_category_id", true, integer())
+        .parameter("query", "depth", false, integer())
+        .get_responder(related_categories.clone()),
+        Operation::new(
+            "get",
+            "/productcategory/{product_category_id}/ancestors",
+            "Lists the categories a product category is rolled up into, directly or indirectly",
+        )
+        .requires("viewer")
+        .localized()
+        .parameter("path", "product_category_id", true, integer())
+        .parameter("query", "depth", false, integer())
+        .get_responder(related_categories),
+        Operation::new(
+            "get",
+            "/productcategory/{product_category_id}/subtree",
+            "Returns the hierarchy below a product category as a tree",
+        )
+        .requires("viewer")
+        .localized()
+        .parameter("path", "product_category_id", true, integer())
+        .parameter("query", "depth", false, integer())
+        .get_responder(category_tree.clone()),
+        Operation::new(
+            "put",
+            "/productcategory/{product_category_id}/subcategories",
+            "Orders the subcategories of a product category like the listed ids",
+        )
+        .requires("admin")
+        .parameter("path", "product_category_id", true, integer())
+        .json_body(schema::<Vec<i32>>(generator))
+        .get_responder(category_tree)
+        .response(
+            Status::BadRequest,
+            "The ids are not exactly those of the subcategories",
+            None,
+        ),
+        Operation::new(
+            "post",
+            "/productcategory/{product_category_id}/merge",
+            "Moves the products and links of a product category to another one and archives it",
+        )
+        .requires("admin")
+        .idempotent()
+        .parameter("path", "product_category_id", true, integer())
+        .parameter("query", "dry_run", false, json!({ "type": "boolean" }))
+        .json_body(schema::<MergeRequestBody>(generator))
+        .get_responder(schema::<MergeReport>(generator))
+        .response(
+            Status::BadRequest,
+            "The target does not exist, is archived or would roll up into itself",
+            None,
+        ),
+        Operation::new(
+            "get",
+            "/productcategory/{product_category_id}/products",
+            "Lists the products classified into a product category or any category below it",
+        )
+        .requires("viewer")
+        .localized()
+        .parameter("path", "product_category_id", true, integer())
+        .get_responder(schema::<Vec<Product>>(generator)),
+        Operation::new(
+            "get",
+            "/productcategory/statistics",
+            "Lists the product counts and units in stock of every product category",
+        )
+        .requires("viewer")
+        .parameter("query", "cached", false, json!({ "type": "boolean" }))
+        .json_response(
+            Status::Ok,
+            "Statistics of all product categories",
+            all_statistics.clone(),
+        ),
+        Operation::new(
+            "get",
+            "/productcategory/{product_category_id}/statistics",
+            "Returns the product counts and units in stock of a product category",
+        )
+        .requires("viewer")
+        .parameter("path", "product_category_id", true, integer())
+        .parameter("query", "cached", false, json!({ "type": "boolean" }))
+        .get_responder(schema::<CategoryStatistics>(generator)),
+        Operation::new(
+            "post",
+            "/productcategory/statistics/refresh",
+            "Takes a new snapshot of the statistics returned in the cached mode",
+        )
+        .requires("admin")
+        .json_response(Status::Ok, "The new snapshot", all_statistics),
+        Operation::new(
+            "get",
+            "/productcategory/taxonomy",
+            "Exports the product categories as a taxonomy",
+        )
+        .requires("viewer")
+        .parameter(
+            "query",
+            "format",
+            false,
+            json!({ "type": "string", "enum": ["text", "json"] }),
+        )
+        .response(
+            Status::Ok,
+            "One path per line like `A > B > C`, or the tree with `format=json`",
+            Some({
+                let mut formats = content("text/plain", json!({ "type": "string" }));
+                formats["application/json"] = json!({ "schema": taxonomy_tree.clone() });
+                formats
+            }),
+        )
+        .response(Status::BadRequest, "The format is unknown", None),
+        Operation::new(
+            "post",
+            "/productcategory/taxonomy",
+            "Creates and links the product categories of a taxonomy which do not exist yet",
+        )
+        .requires("admin")
+        .idempotent()
+        .parameter("query", "dry_run", false, json!({ "type": "boolean" }))
+        .body("text/plain", json!({ "type": "string" }))
+        .alternative_body("application/json", taxonomy_tree)
+        .get_responder(schema::<TaxonomyImportReport>(generator))
+        .response(
+            Status::BadRequest,
+            "The taxonomy can not be read, names an archived category or would roll a category up into itself",
+            None,
+        )
+        .response(Status::PayloadTooLarge, "The taxonomy exceeds the upload limit", None),
+        Operation::new(
+            "get",
+            "/productcategory/{product_category_id}/translations",
+            "Lists the names of a product category in the locales it is translated into",
+        )
+        .requires("viewer")
+        .parameter("path", "product_category_id", true, integer())
+        .get_responder(schema::<Vec<CategoryTranslation>>(generator)),
+        Operation::new(
+            "put",
+            "/productcategory/{product_category_id}/translations/{locale}",
+            "Names a product category in a locale other than the default one",
+        )
+        .requires("admin")
+        .parameter("path", "product_category_id", true, integer())
+        .parameter("path", "locale", true, json!({ "type": "string" }))
+        .json_body(schema::<CategoryTranslationBody>(generator))
+        .get_responder(schema::<CategoryTranslation>(generator))
+        .response(Status::BadRequest, "The locale is not translatable", None)
+        .response(
+            Status::Conflict,
+            "Another product category has the name in the locale",
+            None,
+        ),
+        Operation::new(
+            "delete",
+            "/productcategory/{product_category_id}/translations/{locale}",
+            "Removes the name of a product category in a locale",
+        )
+        .requires("admin")
+        .parameter("path", "product_category_id", true, integer())
+        .parameter("path", "locale", true, json!({ "type": "string" }))
+        .response(Status::Ok, "The name falls back to the default locale", None)
+        .response(Status::NotFound, "The category is not translated into the locale", None),
+        Operation::new(
+            "get",
+            "/product/{product_id}/translations",
+            "Lists the descriptions of a product in the locales it is translated into",
+        )
+        .requires("viewer")
+        .parameter("path", "product_id", true, integer())
+        .get_responder(schema::<Vec<ProductTranslation>>(generator)),
+        Operation::new(
+            "put",
+            "/product/{product_id}/translations/{locale}",
+            "Describes a product in a locale other than the default one",
+        )
+        .requires("admin")
+        .parameter("path", "product_id", true, integer())
+        .parameter("path", "locale", true, json!({ "type": "string" }))
+        .json_body(schema::<ProductTranslationBody>(generator))
+        .get_responder(schema::<ProductTranslation>(generator))
+        .response(Status::BadRequest, "The locale is not translatable", None),
+        Operation::new(
+            "delete",
+            "/product/{product_id}/translations/{locale}",
+            "Removes the description of a product in a locale",
+        )
+        .requires("admin")
+        .parameter("path", "product_id", true, integer())
+        .parameter("path", "locale", true, json!({ "type": "string" }))
+        .response(Status::Ok, "The description falls back to the default locale", None)
+        .response(Status::NotFound, "The product is not translated into the locale", None),
+        Operation::new(
+            "get",
+            "/product/listing",
+            "Lists the products matching facet filters with the product counts of every facet",
+        )
+        .requires("viewer")
+        .localized()
+        .parameter(
+            "query",
+            "category",
+            false,
+            json!({ "type": "array", "items": integer() }),
+        )
+        .parameter(
+            "query",
+            "warehouse",
+            false,
+            json!({ "type": "array", "items": integer() }),
+        )
+        .parameter("query", "min_price", false, integer())
+        .parameter("query", "max_price", false, integer())
+        .parameter(
+            "query",
+            "attribute",
+            false,
+            json!({ "type": "array", "items": { "type": "string", "pattern": "^[^:]+:" } }),
+        )
+        .parameter(
+            "query",
+            "limit",
+            false,
+            json!({ "type": "integer", "minimum": 1, "maximum": catalog::MAX_LIMIT, "default": catalog::DEFAULT_LIMIT }),
+        )
+        .parameter("query", "offset", false, json!({ "type": "integer", "minimum": 0 }))
+        .json_response(
+            Status::Ok,
+            "A page of the matching products and the facet counts",
+            schema::<Listing>(generator),
+        )
+        .response(
+            Status::BadRequest,
+            "A filter can not be read, the price range is empty or the page is out of range",
+            None,
+        ),
+        Operation::new(
+            "put",
+            "/product/{product_id}/price",
+            "Sets or removes the price of a product",
+        )
+        .requires("admin")
+        .parameter("path", "product_id", true, integer())
+        .json_body(schema::<ProductPriceBody>(generator))
+        .get_responder(schema::<Product>(generator))
+        .response(Status::BadRequest, "The price is negative", None),
+        Operation::new(
+            "get",
+            "/product/{product_id}/attributes",
+            "Lists the attribute values of a product",
+        )
+        .requires("viewer")
+        .parameter("path", "product_id", true, integer())
+        .get_responder(schema::<Vec<ProductAttribute>>(generator)),
+        Operation::new(
+            "put",
+            "/product/{product_id}/attributes/{name}",
+            "Sets the value of an attribute of a product",
+        )
+        .requires("admin")
+        .parameter("path", "product_id", true, integer())
+        .parameter("path", "name", true, json!({ "type": "string" }))
+        .json_body(schema::<ProductAttributeBody>(generator))
+        .get_responder(schema::<ProductAttribute>(generator))
+        .response(
+            Status::BadRequest,
+            "The name is empty or contains a colon, or the value is empty",
+            None,
+        ),
+        Operation::new(
+            "delete",
+            "/product/{product_id}/attributes/{name}",
+            "Removes the value of an attribute of a product",
+        )
+        .requires("admin")
+        .parameter("path", "product_id", true, integer())
+        .parameter("path", "name", true, json!({ "type": "string" }))
+        .response(Status::Ok, "The product no longer has the attribute", None)
+        .response(Status::NotFound, "The product does not have the attribute", None),
+        Operation::new(
+            "get",
+            "/product/search",
+            "Searches the products by words of their descriptions, identifiers and category names",
+        )
+        .requires("viewer")
+        .parameter("query", "q", true, json!({ "type": "string" }))
+        .parameter("query", "category", false, integer())
+        .parameter("query", "warehouse", false, integer())
+        .parameter("query", "in_stock", false, json!({ "type": "boolean" }))
+        .parameter(
+            "query",
+            "limit",
+            false,
+            json!({ "type": "integer", "minimum": 1, "maximum": search::MAX_LIMIT, "default": search::DEFAULT_LIMIT }),
+        )
+        .json_response(
+            Status::Ok,
+            "The matching products, the most relevant first",
+            schema::<Vec<SearchHit>>(generator),
+        )
+        .response(
+            Status::BadRequest,
+            "The text has no words, the limit is out of range or a filter names an unknown category or warehouse",
+            None,
+        ),
+        Operation::new("get", "/csv/{resource}", "Exports a resource as CSV")
+            .requires("viewer")
+            .parameter("path", "resource", true, csv_resource.clone())
+            .response(
+                Status::Ok,
+                "All rows of the resource",
+                Some(content("text/csv", json!({ "type": "string" }))),
+            ),
+        Operation::new(
+            "post",
+            "/csv/{resource}",
+            "Imports a CSV file of a resource",
+        )
+        .requires("admin")
+        .idempotent()
+        .parameter("path", "resource", true, csv_resource)
+        .parameter("query", "dry_run", false, json!({ "type": "boolean" }))
+        .parameter("query", "columns", false, json!({ "type": "string" }))
+        .body("text/csv", json!({ "type": "string" }))
+        .json_response(Status::Ok, "The file was imported", import_report.clone())
+        .json_response(
+            Status::UnprocessableEntity,
+            "Rows were rejected and nothing was imported",
+            import_report,
+        )
+        .response(Status::BadRequest, "Invalid column mapping or file", None)
+        .response(Status::PayloadTooLarge, "The file exceeds the upload limit", None),
+        Operation::new(
+            "get",
+            "/events",
+            "Streams domain events as server-sent events",
+        )
+        .requires("viewer")
+        .parameter("query", "types", false, json!({ "type": "string" }))
+        .parameter(
+            "header",
+            "Last-Event-ID",
+            false,
+            json!({ "type": "integer" }),
+        )
+        .response(
+            Status::Ok,
+            "Stream of events",
+            Some(content("text/event-stream", json!({ "type": "string" }))),
+        ),
+        Operation::new("post", "/graphql", "Executes a GraphQL query or mutation")
+            .requires("viewer")
+            .idempotent()
+            .json_body(schema::<GraphQLRequestBody>(generator))
+            .json_response(
+                Status::Ok,
+                "Result of the query",
+                json!({ "type": "object" }),
+            )
+            .json_response(
+                Status::BadRequest,
+                "The query is invalid or exceeds the limits",
+                json!({ "type": "object" }),
+            ),
+        Operation::new("get", "/webhooks", "Lists the webhook subscriptions")
+            .requires("admin")
+            .json_response(
+                Status::Ok,
+                "All webhook subscriptions",
+                schema::<Vec<WebhookSubscription>>(generator),
+            ),
+        Operation::new("post", "/webhooks", "Subscribes a webhook to domain events")
+            .requires("admin")
+            .idempotent()
+            .json_body(schema::<WebhookSubscriptionRequestBody>(generator))
+            .json_response(
+                Status::Created,
+                "The subscription was created",
+                subscription,
+            )
+            .response(Status::BadRequest, "The subscription is invalid", None),
+        Operation::new("delete", "/webhooks/{id}", "Deletes a webhook subscription")
+            .requires("admin")
+            .parameter("path", "id", true, integer())
+            .response(Status::Ok, "The subscription no longer exists", None),
+        Operation::new(
+            "get",
+            "/health/live",
+            "Reports that the instance is running",
+        )
+        .json_response(Status::Ok, "The instance is live", health_report.clone()),
+        Operation::new(
+            "get",
+            "/health/ready",
+            "Reports whether the instance can serve requests",
+        )
+        .json_response(Status::Ok, "The instance is ready", health_report.clone())
+        .json_response(
+            Status::ServiceUnavailable,
+            "The database or its schema is not usable",
+            health_report,
+        ),
+        Operation::new(
+            "get",
+            "/metrics",
+            "Returns metrics in the Prometheus text format",
+        )
+        .requires_global_admin()
+        .response(
+            Status::Ok,
+            "Metrics of the instance",
+            Some(content("text/plain", json!({ "type": "string" }))),
+        ),
+        Operation::new("get", "/openapi.json", "Returns this specification").json_response(
+            Status::Ok,
+            "OpenAPI document",
+            json!({ "type": "object" }),
+        ),
+        Operation::new("get", "/docs", "Browses this specification").response(
+            Status::Ok,
+            "Documentation page",
+            Some(content("text/html", json!({ "type": "string" }))),
+        ),
+    ]
+}
+
+/// Builds the OpenAPI 3 document of the API
+pub fn specification() -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let mut paths = Map::new();
+    for operation in operations(&mut generator) {
+        let path = paths
+            .entry(operation.path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        path[operation.method] = operation.to_json();
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Warehouse API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": generator.definitions(),
+            "securitySchemes": {
+                "bearer": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "Signed JSON web token or API key",
+                },
+            },
+        },
+        "security": [{ "bearer": [] }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::specification;
+    #[cfg(feature = "postgres")]
+    use std::collections::BTreeSet;
+
+    /// Converts a Rocket path such as `/productcategory/<id>` into an OpenAPI path template
+    #[cfg(feature = "postgres")]
+    fn path_template(path: &str) -> String {
+        path.split('/')
+            .map(|segment| {
+                if segment.starts_with('<') && segment.ends_with('>') {
+                    format!(
+                        "{{{}}}",
+                        segment.trim_matches(|c| c == '<' || c == '>' || c == '.')
+                    )
+                } else {
+                    segment.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // Without a configured database the routes of Postgres are mounted, which need its pool
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn specification_documents_exactly_the_mounted_routes() {
+        let rocket = crate::mount_routes(rocket::ignite());
+        let mounted: BTreeSet<(String, String)> = rocket
+            .routes()
+            .map(|route| {
+                let path = path_template(route.uri.path())
+                    .trim_end_matches('/')
+                    .to_string();
+                (route.method.as_str().to_lowercase(), path)
+            })
+            .collect();
+
+        let specification = specification();
+        let documented: BTreeSet<(String, String)> = specification["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, operations)| {
+                operations
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect();
+
+        assert_eq!(mounted, documented);
+    }
+
+    #[test]
+    fn responder_statuses_are_documented() {
+        let specification = specification();
+        let get = &specification["paths"]["/productcategory/{product_category_id}"]["get"];
+        let post = &specification["paths"]["/productcategory"]["post"];
+
+        assert!(get["responses"]["200"].is_object());
+        assert!(get["responses"]["404"].is_object());
+        assert!(post["responses"]["200"].is_object());
+        assert!(post["responses"]["201"].is_object());
+    }
+
+    #[test]
+    fn referenced_schemas_are_defined() {
+        let specification = specification();
+        let schemas = &specification["components"]["schemas"];
+
+        assert!(schemas["ProductCategory"].is_object());
+        assert!(schemas["ProductCategoryRequestBody"].is_object());
+    }
+}
@@ -0,0 +1,26 @@
+#[cfg(feature = "postgres")]
+use crate::schema::*;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Queryable, Serialize, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(Identifiable),
+    table_name = "outbox_event"
+)]
+/// Domain event recorded together with the change it describes
+///
+/// The outbox is the log of changes from which webhook deliveries are made, so that an event is
+/// published if and only if the transaction which caused it was committed.
+pub struct OutboxEvent {
+    pub id: i64,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    /// Id of the request during which the event was recorded, if it was caused by a request
+    pub request_id: Option<String>,
+    /// Id of the transaction which recorded the event, by which events are streamed in order
+    #[serde(skip)]
+    pub transaction_id: i64,
+}
@@ -0,0 +1,127 @@
+pub mod entities;
+
+#[cfg(feature = "postgres")]
+use diesel::{ExpressionMethods, PgConnection, QueryResult, RunQueryDsl};
+#[cfg(feature = "postgres")]
+use entities::OutboxEvent;
+#[cfg(feature = "postgres")]
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+/// Kinds of domain events which are written to the outbox
+pub enum EventType {
+    CategoryCreated,
+    CategoryUpdated,
+    CategoryDeleted,
+    CategoryMerged,
+    ProductCreated,
+    ProductUpdated,
+    StockChanged,
+}
+
+impl EventType {
+    pub const ALL: &'static [EventType] = &[
+        EventType::CategoryCreated,
+        EventType::CategoryUpdated,
+        EventType::CategoryDeleted,
+        EventType::CategoryMerged,
+        EventType::ProductCreated,
+        EventType::ProductUpdated,
+        EventType::StockChanged,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventType::CategoryCreated => "category.created",
+            EventType::CategoryUpdated => "category.updated",
+            EventType::CategoryDeleted => "category.deleted",
+            EventType::CategoryMerged => "category.merged",
+            EventType::ProductCreated => "product.created",
+            EventType::ProductUpdated => "product.updated",
+            EventType::StockChanged => "stock.changed",
+        }
+    }
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventType {
+    type Err = String;
+
+    fn from_str(event_type: &str) -> Result<Self, Self::Err> {
+        EventType::ALL
+            .iter()
+            .find(|known| known.as_str() == event_type)
+            .copied()
+            .ok_or_else(|| format!("Unknown event type: {}", event_type))
+    }
+}
+
+/// Writes a domain event to the outbox
+///
+/// Has to be called within the transaction which makes the change described by the event.
+#[cfg(feature = "postgres")]
+pub fn record<T: Serialize>(
+    connection: &PgConnection,
+    event_type: EventType,
+    payload: &T,
+) -> QueryResult<OutboxEvent> {
+    use crate::schema::outbox_event::dsl;
+    let payload = serde_json::to_value(payload)
+        .map_err(|error| diesel::result::Error::SerializationError(Box::new(error)))?;
+    diesel::insert_into(dsl::outbox_event)
+        .values((
+            dsl::event_type.eq(event_type.as_str()),
+            dsl::payload.eq(payload),
+        ))
+        .get_result(connection)
+}
+
+/// Writes one domain event of the same type per payload to the outbox in a single statement
+///
+/// Has to be called within the transaction which makes the changes described by the events.
+#[cfg(feature = "postgres")]
+pub fn record_all<T: Serialize>(
+    connection: &PgConnection,
+    event_type: EventType,
+    payloads: &[T],
+) -> QueryResult<usize> {
+    use crate::schema::outbox_event::dsl;
+    if payloads.is_empty() {
+        return Ok(0);
+    }
+    let rows = payloads
+        .iter()
+        .map(|payload| {
+            serde_json::to_value(payload)
+                .map(|payload| {
+                    (
+                        dsl::event_type.eq(event_type.as_str()),
+                        dsl::payload.eq(payload),
+                    )
+                })
+                .map_err(|error| diesel::result::Error::SerializationError(Box::new(error)))
+        })
+        .collect::<QueryResult<Vec<_>>>()?;
+    diesel::insert_into(dsl::outbox_event)
+        .values(&rows)
+        .execute(connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventType;
+
+    #[test]
+    fn event_type_parses_from_its_string_representation() {
+        for event_type in EventType::ALL {
+            assert_eq!(Ok(*event_type), event_type.as_str().parse());
+        }
+        assert!("category.renamed".parse::<EventType>().is_err());
+    }
+}
@@ -0,0 +1,449 @@
+use super::{controllers::ProductCategoryRequestBody, entities::ProductCategory};
+use crate::{
+    auth::{Admin, Authorized},
+    idempotency::{Idempotency, Idempotent},
+    outbox::EventType,
+    repository::{BackendConn, CategoryRepository, ProductRepository},
+    utilities::DbError,
+};
+use diesel::QueryResult;
+use rocket::response::status::BadRequest;
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Largest number of items accepted in a single bulk request
+pub const MAX_BULK_ITEMS: usize = 5000;
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+/// Outcome of a single item of a bulk request
+pub enum BulkStatus {
+    Created,
+    Existed,
+    Updated,
+    Deleted,
+    NotFound,
+    Conflict,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug)]
+/// Result of a single item of a bulk request
+///
+/// Results are returned in the order of the items in the request.
+pub struct BulkItemResult {
+    pub status: BulkStatus,
+    pub id: Option<i32>,
+    pub name: Option<String>,
+}
+
+impl BulkItemResult {
+    fn new(status: BulkStatus, id: Option<i32>, name: Option<&str>) -> Self {
+        BulkItemResult {
+            status,
+            id,
+            name: name.map(String::from),
+        }
+    }
+}
+
+type BulkResponse = Result<Result<Json<Vec<BulkItemResult>>, BadRequest<String>>, DbError>;
+
+fn check_batch_size(items: usize) -> Result<(), BadRequest<String>> {
+    if items > MAX_BULK_ITEMS {
+        return Err(BadRequest(Some(format!(
+            "Bulk requests accept at most {} items",
+            MAX_BULK_ITEMS
+        ))));
+    }
+    Ok(())
+}
+
+/// Creates the categories which do not exist yet and returns the existing ones otherwise
+#[post("/bulk", format = "json", data = "<new_categories>")]
+pub fn bulk_post(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    idempotency: Idempotency,
+    new_categories: Json<Vec<ProductCategoryRequestBody>>,
+) -> Idempotent<BulkResponse> {
+    idempotency.run(&db_conn, &*new_categories, || {
+        if let Err(bad_request) = check_batch_size(new_categories.len()) {
+            return Ok(Err(bad_request));
+        }
+        Ok(Ok(Json(create_all(&db_conn, &new_categories)?)))
+    })
+}
+
+/// Creates the categories with the given ids, or renames them and replaces their display metadata
+///
+/// An item conflicts if its name belongs to another category, or if its id or name already
+/// appeared earlier in the request.
+#[put("/bulk", format = "json", data = "<categories>")]
+pub fn bulk_put(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    categories: Json<Vec<ProductCategory>>,
+) -> BulkResponse {
+    if let Err(bad_request) = check_batch_size(categories.len()) {
+        return Ok(Err(bad_request));
+    }
+    Ok(Ok(Json(save_all(&db_conn, &categories)?)))
+}
+
+/// Deletes the categories with the given ids
+///
+/// Categories which still have subcategories, parents or classified products conflict and are
+/// kept.
+#[delete("/bulk", format = "json", data = "<ids>")]
+pub fn bulk_delete(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    ids: Json<Vec<i32>>,
+) -> BulkResponse {
+    if let Err(bad_request) = check_batch_size(ids.len()) {
+        return Ok(Err(bad_request));
+    }
+    Ok(Ok(Json(delete_all(&db_conn, &ids)?)))
+}
+
+/// Creates the categories of a bulk POST in one transaction
+pub fn create_all<R: CategoryRepository>(
+    repository: &R,
+    new_categories: &[ProductCategoryRequestBody],
+) -> QueryResult<Vec<BulkItemResult>> {
+    let mut names: Vec<&str> = Vec::new();
+    for new_category in new_categories.iter() {
+        if !names.contains(&new_category.name.as_str()) {
+            names.push(&new_category.name);
+        }
+    }
+    repository.atomically(|| {
+        let created_categories = repository.insert_missing_categories(&names)?;
+        repository.record_all(EventType::CategoryCreated, &created_categories)?;
+
+        let categories = repository.categories_named(&names)?;
+        let ids_by_name: HashMap<&str, i32> = categories
+            .iter()
+            .map(|category| (category.name.as_str(), category.id))
+            .collect();
+        let mut newly_created: HashSet<&str> = created_categories
+            .iter()
+            .map(|category| category.name.as_str())
+            .collect();
+        Ok(new_categories
+            .iter()
+            .map(|new_category| {
+                let name = new_category.name.as_str();
+                match ids_by_name.get(name) {
+                    Some(id) if newly_created.remove(name) => {
+                        BulkItemResult::new(BulkStatus::Created, Some(*id), Some(name))
+                    }
+                    Some(id) => BulkItemResult::new(BulkStatus::Existed, Some(*id), Some(name)),
+                    None => BulkItemResult::new(BulkStatus::Conflict, None, Some(name)),
+                }
+            })
+            .collect())
+    })
+}
+
+/// Creates or replaces the categories of a bulk PUT in one transaction
+pub fn save_all<R: CategoryRepository>(
+    repository: &R,
+    categories: &[ProductCategory],
+) -> QueryResult<Vec<BulkItemResult>> {
+    let ids: Vec<i32> = categories.iter().map(|category| category.id).collect();
+    let names: Vec<&str> = categories
+        .iter()
+        .map(|category| category.name.as_str())
+        .collect();
+    repository.atomically(|| {
+        let existing_categories = repository.lock_categories(&ids, &names)?;
+        let existing_by_id: HashMap<i32, &ProductCategory> = existing_categories
+            .iter()
+            .map(|category| (category.id, category))
+            .collect();
+        let ids_by_name: HashMap<&str, i32> = existing_categories
+            .iter()
+            .map(|category| (category.name.as_str(), category.id))
+            .collect();
+
+        let mut seen_ids = HashSet::new();
+        let mut seen_names = HashSet::new();
+        let mut created_categories = Vec::new();
+        let mut updated_categories = Vec::new();
+        let mut results = Vec::with_capacity(categories.len());
+        for category in categories.iter() {
+            let (id, name) = (category.id, category.name.as_str());
+            let first_occurrence = seen_ids.insert(id) & seen_names.insert(name);
+            let name_taken = ids_by_name.get(name).map_or(false, |owner| *owner != id);
+            let status = if !first_occurrence || name_taken {
+                BulkStatus::Conflict
+            } else {
+                match existing_by_id.get(&id) {
+                    Some(existing) if existing.has_same_content(category) => BulkStatus::Existed,
+                    Some(_) => BulkStatus::Updated,
+                    None => BulkStatus::Created,
+                }
+            };
+            match status {
+                BulkStatus::Created => created_categories.push(category.clone()),
+                BulkStatus::Updated => updated_categories.push(category.clone()),
+                _ => {}
+            }
+            results.push(BulkItemResult::new(status, Some(id), Some(name)));
+        }
+
+        let changed_categories: Vec<ProductCategory> = created_categories
+            .iter()
+            .chain(updated_categories.iter())
+            .cloned()
+            .collect();
+        repository.save_categories(&changed_categories)?;
+        // The events carry the slugs which the categories got when they were saved
+        let saved = |categories: &[ProductCategory]| -> QueryResult<Vec<ProductCategory>> {
+            categories
+                .iter()
+                .map(|category| repository.category(category.id)?.ok_or(diesel::NotFound))
+                .collect()
+        };
+        repository.record_all(EventType::CategoryCreated, &saved(&created_categories)?)?;
+        repository.record_all(EventType::CategoryUpdated, &saved(&updated_categories)?)?;
+        Ok(results)
+    })
+}
+
+/// Deletes the categories of a bulk DELETE in one transaction
+pub fn delete_all<R: CategoryRepository + ProductRepository>(
+    repository: &R,
+    ids: &[i32],
+) -> QueryResult<Vec<BulkItemResult>> {
+    repository.atomically(|| {
+        let mut referenced_ids: HashSet<i32> = HashSet::new();
+        for (upper_id, lower_id) in repository.rollups_of(ids)? {
+            referenced_ids.insert(upper_id);
+            referenced_ids.insert(lower_id);
+        }
+        referenced_ids.extend(
+            repository
+                .classifications_in(ids)?
+                .into_iter()
+                .map(|classification| classification.product_category_id),
+        );
+        let deletable_ids: Vec<i32> = ids
+            .iter()
+            .copied()
+            .filter(|id| !referenced_ids.contains(id))
+            .collect();
+        let deleted_ids = repository.delete_categories(&deletable_ids)?;
+        let deleted_events: Vec<serde_json::Value> = deleted_ids
+            .iter()
+            .map(|id| serde_json::json!({ "id": id }))
+            .collect();
+        repository.record_all(EventType::CategoryDeleted, &deleted_events)?;
+
+        let mut newly_deleted: HashSet<i32> = deleted_ids.into_iter().collect();
+        Ok(ids
+            .iter()
+            .map(|id| {
+                let status = if referenced_ids.contains(id) {
+                    BulkStatus::Conflict
+                } else if newly_deleted.remove(id) {
+                    BulkStatus::Deleted
+                } else {
+                    BulkStatus::NotFound
+                };
+                BulkItemResult::new(status, Some(*id), None)
+            })
+            .collect())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delete_all, save_all, BulkItemResult, BulkStatus};
+    use crate::{
+        auth::Role,
+        outbox::EventType,
+        product_category::entities::ProductCategory,
+        repository::{CategoryRepository, MemoryRepository, ProductRepository},
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::http::{ContentType, Status};
+
+    fn statuses(results: &[BulkItemResult]) -> Vec<BulkStatus> {
+        results.iter().map(|result| result.status).collect()
+    }
+
+    fn category(id: i32, name: &str) -> ProductCategory {
+        ProductCategory {
+            id,
+            name: name.to_string(),
+            slug: name.to_string(),
+            description: None,
+            image_ref: None,
+            is_visible: true,
+            is_archived: false,
+        }
+    }
+
+    #[test]
+    fn save_all_rejects_repeated_ids_and_names() {
+        let repository = MemoryRepository::new();
+        repository
+            .save_categories(&[category(1, "shirts")])
+            .unwrap();
+
+        let results = save_all(
+            &repository,
+            &[
+                category(2, "shirts"),
+                category(1, "tops"),
+                category(3, "tops"),
+                category(1, "skirts"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                BulkStatus::Conflict,
+                BulkStatus::Updated,
+                BulkStatus::Conflict,
+                BulkStatus::Conflict
+            ],
+            statuses(&results)
+        );
+        assert_eq!(vec![category(1, "tops")], repository.categories().unwrap());
+        assert_eq!(EventType::CategoryUpdated, repository.events()[0].0);
+    }
+
+    #[test]
+    fn delete_all_keeps_categories_which_classify_products() {
+        let repository = MemoryRepository::new();
+        repository
+            .save_categories(&[category(1, "shirts"), category(2, "books")])
+            .unwrap();
+        let shirt = repository.insert_product("white shirt").unwrap();
+        repository.classify(shirt.id, 1, true).unwrap();
+
+        let results = delete_all(&repository, &[1, 2]).unwrap();
+
+        assert_eq!(
+            vec![BulkStatus::Conflict, BulkStatus::Deleted],
+            statuses(&results)
+        );
+        assert_eq!(
+            vec![category(1, "shirts")],
+            repository.categories().unwrap()
+        );
+        assert_eq!(1, repository.events().len());
+    }
+
+    backend_test!(
+        bulk_post_creates_missing_categories_and_returns_existing_ones,
+        |client, repository| {
+            let existing_id = repository.insert_category("shirts")?.id;
+
+            let mut response = client
+                .post("/productcategory/bulk")
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Admin))
+                .body("[{\"name\":\"skirts\"},{\"name\":\"shirts\"},{\"name\":\"skirts\"}]")
+                .dispatch();
+            let results: Vec<BulkItemResult> =
+                serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+            assert_eq!(Status::Ok, response.status());
+            assert_eq!(
+                vec![
+                    BulkStatus::Created,
+                    BulkStatus::Existed,
+                    BulkStatus::Existed
+                ],
+                statuses(&results)
+            );
+            assert_eq!(Some(existing_id), results[1].id);
+            assert_eq!(results[0].id, results[2].id);
+            assert_eq!(2, repository.categories()?.len());
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        bulk_put_reports_created_updated_and_conflicting_categories,
+        |client, repository| {
+            repository.save_categories(&[category(1, "shirts"), category(2, "skirts")])?;
+
+            let mut response = client
+                .put("/productcategory/bulk")
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Admin))
+                .body(
+                    "[{\"id\":1,\"name\":\"tops\"},{\"id\":2,\"name\":\"skirts\"},\
+                     {\"id\":3,\"name\":\"dresses\"},{\"id\":4,\"name\":\"skirts\"}]",
+                )
+                .dispatch();
+            let results: Vec<BulkItemResult> =
+                serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+            let mut names: Vec<String> = repository
+                .categories()?
+                .into_iter()
+                .map(|category| category.name)
+                .collect();
+            names.sort();
+            assert_eq!(Status::Ok, response.status());
+            assert_eq!(
+                vec![
+                    BulkStatus::Updated,
+                    BulkStatus::Existed,
+                    BulkStatus::Created,
+                    BulkStatus::Conflict
+                ],
+                statuses(&results)
+            );
+            assert_eq!(vec!["dresses", "skirts", "tops"], names);
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        bulk_delete_keeps_categories_which_are_still_referenced,
+        |client, repository| {
+            repository.save_categories(&[
+                category(1, "clothes"),
+                category(2, "shirts"),
+                category(3, "books"),
+            ])?;
+            repository.insert_rollup(1, 2)?;
+
+            let mut response = client
+                .delete("/productcategory/bulk")
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Admin))
+                .body("[3,2,4]")
+                .dispatch();
+            let results: Vec<BulkItemResult> =
+                serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+            let remaining_ids: Vec<i32> = repository
+                .categories()?
+                .into_iter()
+                .map(|category| category.id)
+                .collect();
+            assert_eq!(Status::Ok, response.status());
+            assert_eq!(
+                vec![
+                    BulkStatus::Deleted,
+                    BulkStatus::Conflict,
+                    BulkStatus::NotFound
+                ],
+                statuses(&results)
+            );
+            assert_eq!(vec![1, 2], remaining_ids);
+            Ok(())
+        }
+    );
+}
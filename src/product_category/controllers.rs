@@ -1,115 +1,146 @@
-use super::entities::ProductCategory;
+use super::{
+    entities::{visible_by_default, CategoryWithBreadcrumbs, ProductCategory},
+    hierarchy, operations,
+    translations::{create_or_get_in, localized, Creating},
+};
 use crate::{
-    utilities::{GetResponder, PostResponder},
-    DbConn,
+    auth::{Admin, Authorized, Viewer},
+    idempotency::{Idempotency, Idempotent},
+    localization::Locale,
+    repository::{BackendConn, CategoryRepository},
+    utilities::{DbError, GetResponder, PostResponder},
 };
-use diesel::{insert_into, ExpressionMethods, QueryDsl, RunQueryDsl};
-use rocket::http::Status;
+use rocket::{http::Status, response::status::Conflict};
 use rocket_contrib::json::Json;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ProductCategoryRequestBody {
     pub name: String,
+    /// Name of a category created in another locale than the default one, in the default locale
+    ///
+    /// The name in the locale of the request is used if it is left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+/// Replacement of a product category, whose display metadata is reset if it is left out
+pub struct ProductCategoryPutBody {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub image_ref: Option<String>,
+    #[serde(default = "visible_by_default")]
+    pub is_visible: bool,
 }
 
 #[get("/")]
-pub fn get_all(db_conn: DbConn) -> Result<Json<Vec<ProductCategory>>, diesel::result::Error> {
-    use crate::schema::product_category::dsl::*;
-    let product_categories = product_category.load(&db_conn.0)?;
-    Ok(Json(product_categories))
+pub fn get_all(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    locale: Locale,
+) -> Result<Json<Vec<CategoryWithBreadcrumbs>>, DbError> {
+    let categories = hierarchy::all_with_breadcrumbs(&db_conn)?;
+    Ok(Json(localized(&db_conn, &locale, categories)?))
 }
 
 #[get("/<product_category_id>")]
 pub fn get(
-    db_conn: DbConn,
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    locale: Locale,
     product_category_id: i32,
-) -> Result<GetResponder<ProductCategory>, diesel::result::Error> {
-    use crate::schema::product_category::dsl::*;
-    return match product_category.find(product_category_id).first(&db_conn.0) {
-        Ok(category_by_id) => Ok(GetResponder::Found(Json(category_by_id))),
-        Err(diesel::NotFound) => Ok(GetResponder::NotFound(())),
-        Err(e) => Err(e),
-    };
+) -> Result<GetResponder<CategoryWithBreadcrumbs>, DbError> {
+    match db_conn.category(product_category_id)? {
+        Some(category_by_id) => {
+            let category = hierarchy::with_breadcrumbs(&db_conn, category_by_id)?;
+            Ok(GetResponder::Found(Json(localized(
+                &db_conn, &locale, category,
+            )?)))
+        }
+        None => Ok(GetResponder::NotFound(())),
+    }
 }
 
+type PostResponse = Result<Result<PostResponder<ProductCategory>, Conflict<String>>, DbError>;
+
+/// Returns the category with the name in the locale of the request, creating it if there is
+/// none
 #[post("/", format = "json", data = "<new_product_category>")]
 pub fn post(
-    db_conn: DbConn,
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    idempotency: Idempotency,
+    locale: Locale,
     new_product_category: Json<ProductCategoryRequestBody>,
-) -> Result<PostResponder<ProductCategory>, diesel::result::Error> {
-    use crate::schema::product_category::dsl;
-
-    let product_categories_with_name: Vec<ProductCategory> = dsl::product_category
-        .filter(dsl::name.eq(&new_product_category.name))
-        .load(&db_conn.0)?;
-
-    match product_categories_with_name.into_iter().nth(0) {
-        Some(first_category) => return Ok(PostResponder::Existed(Json(first_category))),
-        None => {
-            let (id, name) = insert_into(dsl::product_category)
-                .values(dsl::name.eq(&new_product_category.name))
-                .returning((dsl::id, dsl::name))
-                .get_result(&db_conn.0)?;
-            Ok(PostResponder::Created(Json(ProductCategory { id, name })))
+) -> Idempotent<PostResponse> {
+    idempotency.run(&db_conn, &*new_product_category, || {
+        let name = &new_product_category.name;
+        let outcome = if locale.is_default() {
+            match operations::create_or_get(&db_conn, name)? {
+                (created_category, true) => Creating::Created(created_category),
+                (existing_category, false) => Creating::Existed(existing_category),
+            }
+        } else {
+            let default_name = new_product_category.default_name.as_deref();
+            create_or_get_in(&db_conn, &locale, name, default_name)?
+        };
+        match outcome {
+            Creating::Created(category) => Ok(Ok(PostResponder::Created(Json(category)))),
+            Creating::Existed(category) => Ok(Ok(PostResponder::Existed(Json(category)))),
+            Creating::Rejected(message) => Ok(Err(Conflict(Some(message)))),
         }
-    }
+    })
 }
 
 #[delete("/<id>")]
-pub fn delete(conn: DbConn, id: i32) -> Result<Status, diesel::result::Error> {
-    use crate::schema::product_category::dsl;
-    let connection = conn.0;
-    diesel::delete(dsl::product_category)
-        .filter(dsl::id.eq(id))
-        .execute(&connection)?;
+pub fn delete(conn: BackendConn, _admin: Authorized<Admin>, id: i32) -> Result<Status, DbError> {
+    operations::delete(&conn, id)?;
     Ok(Status::Ok)
 }
 
 #[put("/<id>", format = "json", data = "<put_category>")]
 pub fn put(
-    conn: DbConn,
+    conn: BackendConn,
+    _admin: Authorized<Admin>,
     id: i32,
-    put_category: Json<ProductCategoryRequestBody>,
-) -> Result<Json<ProductCategory>, diesel::result::Error> {
-    use crate::schema::product_category::dsl;
-    let connection = conn.0;
+    put_category: Json<ProductCategoryPutBody>,
+) -> Result<Json<ProductCategory>, DbError> {
+    let put_category = put_category.into_inner();
     let new_product_category = ProductCategory {
         id,
-        name: put_category.into_inner().name,
+        name: put_category.name,
+        slug: String::new(),
+        description: put_category.description,
+        image_ref: put_category.image_ref,
+        is_visible: put_category.is_visible,
+        is_archived: false,
     };
-    diesel::insert_into(dsl::product_category)
-        .values(&new_product_category)
-        .on_conflict(dsl::id)
-        .do_update()
-        .set(dsl::name.eq(&new_product_category.name))
-        .execute(&connection)?;
-    Ok(Json(new_product_category))
+    let (saved_category, _) = operations::upsert(&conn, &new_product_category)?;
+    Ok(Json(saved_category))
 }
 
 #[cfg(test)]
+#[allow(clippy::get_first, clippy::unnecessary_to_owned)]
 mod tests {
+    //! Run against every database backend through [`backend_test`]
+
     use super::{super::entities::ProductCategory, ProductCategoryRequestBody};
-    use crate::schema::product_category::dsl::product_category;
-    use diesel::prelude::*;
-    use rocket::{
-        http::{ContentType, Header, Status},
-        local::Client,
+    use crate::{
+        auth::Role,
+        test_utils::{backend_test, bearer_token},
     };
+    use rocket::http::{ContentType, Header, Status};
     use std::cmp::Ordering;
-    use testcontainers::clients::Cli;
-
-    #[test]
-    fn test_saves_product_category_into_db() -> Result<(), diesel_migrations::RunMigrationsError> {
-        use crate::schema::product_category::dsl::*;
 
-        let docker_cli = Cli::default();
-        let database_metadata = crate::test_utils::start_database(&docker_cli);
-        let (rocket, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
-        let client = Client::new(rocket).expect("valid rocket instance");
+    backend_test!(test_saves_product_category_into_db, |client, repository| {
         let mut req = client.post("/productcategory");
         req.set_body("{\"name\":\"product\"}".to_string());
         req.add_header(Header::new("Content-Type", "application/json"));
+        req.add_header(bearer_token(Role::Admin));
 
         let mut response = req.dispatch();
         let response_body = response.body_string().unwrap();
@@ -117,193 +148,150 @@ mod tests {
         let response_product_category: ProductCategory =
             serde_json::from_str(&response_body).unwrap();
 
-        let categories_in_db: Vec<ProductCategory> = product_category
-            .filter(name.eq("product"))
-            .load::<ProductCategory>(&connection)
-            .unwrap();
+        let categories_in_db = repository.categories_named(&["product"])?;
 
         assert_eq!(categories_in_db.len(), 1);
         assert_eq!(categories_in_db.get(0).unwrap(), &response_product_category);
         assert_eq!(response_status, Status::Created);
         Ok(())
-    }
-
-    #[test]
-    fn test_if_product_category_exists_in_db_then_post_returns_it(
-    ) -> Result<(), diesel_migrations::RunMigrationsError> {
-        let docker_cli = Cli::default();
-        let database_metadata = crate::test_utils::start_database(&docker_cli);
-        let (rocket, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
-        let client = Client::new(rocket).expect("valid rocket instance");
-        let mut req = client.post("/productcategory");
-        req.set_body("{\"name\":\"product\"}".to_string());
-        req.add_header(Header::new("Content-Type", "application/json"));
-
-        use crate::schema::product_category::dsl::*;
-        let inserted_id: i32 = diesel::insert_into(product_category)
-            .values(name.eq("product"))
-            .returning(id)
-            .get_result(&connection)
-            .unwrap();
-
-        let mut response = req.dispatch();
-        let response_body = response.body_string().unwrap();
-        let response_status = response.status();
-        let response_product_category =
-            serde_json::from_str::<ProductCategory>(&response_body).unwrap();
-        assert_eq!(inserted_id, response_product_category.id);
-        assert_eq!("product".to_string(), response_product_category.name);
-        assert_eq!(response_status, Status::Ok);
-        Ok(())
-    }
-
-    #[test]
-    fn get_product_categories_returns_all_categories(
-    ) -> Result<(), diesel_migrations::RunMigrationsError> {
-        let docker_cli = Cli::default();
-        let database_metadata = crate::test_utils::start_database(&docker_cli);
-        let (rocket, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
-        let client = Client::new(rocket).expect("valid rocket instance");
-        let get_product_categories_request = client.get("/productcategory");
-
-        use crate::schema::product_category::dsl::*;
-        diesel::insert_into(product_category)
-            .values(&vec![
-                name.eq("first_category".to_string()),
-                name.eq("second_category".to_string()),
-            ])
-            .execute(&connection)?;
-
-        let mut response = get_product_categories_request.dispatch();
-        let response_body = response.body_string().unwrap();
-        let response_status = response.status();
-
-        let mut returned_product_categories: Vec<ProductCategory> =
-            serde_json::from_str(&response_body).unwrap();
-        returned_product_categories.sort_by(
-            |a: &ProductCategory, b: &ProductCategory| -> Ordering { a.name.cmp(&b.name) },
-        );
-        assert_eq!(2, returned_product_categories.len());
-        assert_eq!(
-            "first_category".to_string(),
-            returned_product_categories.get(0).unwrap().name
-        );
-        assert_eq!(
-            "second_category".to_string(),
-            returned_product_categories.get(1).unwrap().name
-        );
-        assert_eq!(Status::Ok, response_status);
-        Ok(())
-    }
-
-    #[test]
-    fn get_product_category_by_id_returns_correct_product_category(
-    ) -> Result<(), diesel_migrations::RunMigrationsError> {
-        let docker_cli = Cli::default();
-        let database_metadata = crate::test_utils::start_database(&docker_cli);
-        let (rocket, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
-        let client = Client::new(rocket).expect("valid rocket instance");
-
-        use crate::schema::product_category::dsl::*;
-        let inserted_product_categories: Vec<(i32, String)> = diesel::insert_into(product_category)
-            .values(vec![name.eq("first"), name.eq("second")])
-            .returning(product_category::all_columns())
-            .get_results(&connection)
-            .unwrap();
-
-        let get_request = client.get(format!(
-            "/productcategory/{}",
-            inserted_product_categories.get(0).unwrap().0
-        ));
-        let mut response = get_request.dispatch();
-        let response_body = response.body_string().unwrap();
-        let response_status = response.status();
-        let returned_product_category: ProductCategory =
-            serde_json::from_str(&response_body).unwrap();
-
-        let expected_product_category = ProductCategory {
-            id: inserted_product_categories.get(0).unwrap().0,
-            name: inserted_product_categories.get(0).unwrap().1.clone(),
-        };
-        assert_eq!(returned_product_category, expected_product_category);
-        assert_eq!(Status::Ok, response_status);
-        Ok(())
-    }
-
-    #[test]
-    fn when_getting_missing_product_category_status_is_404(
-    ) -> Result<(), diesel_migrations::RunMigrationsError> {
-        let docker_cli = Cli::default();
-        let database_metadata = crate::test_utils::start_database(&docker_cli);
-        let (rocket, _) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
-        let client = Client::new(rocket).expect("valid rocket instance");
-
-        let get_request = client.get(format!("/productcategory/{}", 999));
-        let response = get_request.dispatch();
-        let response_status = response.status();
-
-        assert_eq!(Status::NotFound, response_status);
-        Ok(())
-    }
-
-    #[test]
-    fn delete_call_removes_product_category_from_db(
-    ) -> Result<(), diesel_migrations::RunMigrationsError> {
-        use crate::schema::product_category::dsl::*;
-
-        let docker_cli = Cli::default();
-        let database_metadata = crate::test_utils::start_database(&docker_cli);
-        let (rocket, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
-        let client = Client::new(rocket).expect("Valid rocket instance");
-
-        let inserted_product_category_id: i32 = diesel::insert_into(product_category)
-            .values(name.eq("testcat"))
-            .returning(id)
-            .get_result(&connection)
-            .unwrap();
-
-        let saved_product_categories: Vec<ProductCategory> =
-            product_category.load(&connection).unwrap();
-        assert_eq!(saved_product_categories.len(), 1);
-
-        let delete_request =
-            client.delete(format!("/productcategory/{}", inserted_product_category_id));
-        let response = delete_request.dispatch();
-        let response_status = response.status();
-
-        let remaining_product_cateogries: Vec<ProductCategory> =
-            product_category.load(&connection).unwrap();
-        assert_eq!(response_status, Status::Ok);
-        assert_eq!(remaining_product_cateogries.len(), 0);
-        Ok(())
-    }
-
-    #[test]
-    fn put_overwrites_product_category() -> Result<(), diesel_migrations::RunMigrationsError> {
-        use crate::schema::product_category::dsl::*;
-
-        let docker_cli = Cli::default();
-        let database_metadata = crate::test_utils::start_database(&docker_cli);
-        let (rocket, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
-        let client = Client::new(rocket).expect("Valid Rocket instance");
+    });
+
+    backend_test!(
+        test_if_product_category_exists_in_db_then_post_returns_it,
+        |client, repository| {
+            let mut req = client.post("/productcategory");
+            req.set_body("{\"name\":\"product\"}".to_string());
+            req.add_header(Header::new("Content-Type", "application/json"));
+            req.add_header(bearer_token(Role::Admin));
+
+            let inserted_id = repository.insert_category("product")?.id;
+
+            let mut response = req.dispatch();
+            let response_body = response.body_string().unwrap();
+            let response_status = response.status();
+            let response_product_category =
+                serde_json::from_str::<ProductCategory>(&response_body).unwrap();
+            assert_eq!(inserted_id, response_product_category.id);
+            assert_eq!("product".to_string(), response_product_category.name);
+            assert_eq!(response_status, Status::Ok);
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        get_product_categories_returns_all_categories,
+        |client, repository| {
+            let get_product_categories_request = client
+                .get("/productcategory")
+                .header(bearer_token(Role::Viewer));
+
+            repository.insert_category("first_category")?;
+            repository.insert_category("second_category")?;
+
+            let mut response = get_product_categories_request.dispatch();
+            let response_body = response.body_string().unwrap();
+            let response_status = response.status();
+
+            let mut returned_product_categories: Vec<ProductCategory> =
+                serde_json::from_str(&response_body).unwrap();
+            returned_product_categories.sort_by(
+                |a: &ProductCategory, b: &ProductCategory| -> Ordering { a.name.cmp(&b.name) },
+            );
+            assert_eq!(2, returned_product_categories.len());
+            assert_eq!(
+                "first_category".to_string(),
+                returned_product_categories.get(0).unwrap().name
+            );
+            assert_eq!(
+                "second_category".to_string(),
+                returned_product_categories.get(1).unwrap().name
+            );
+            assert_eq!(Status::Ok, response_status);
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        get_product_category_by_id_returns_correct_product_category,
+        |client, repository| {
+            let inserted_product_categories = vec![
+                repository.insert_category("first")?,
+                repository.insert_category("second")?,
+            ];
+
+            let get_request = client
+                .get(format!(
+                    "/productcategory/{}",
+                    inserted_product_categories.get(0).unwrap().id
+                ))
+                .header(bearer_token(Role::Viewer));
+            let mut response = get_request.dispatch();
+            let response_body = response.body_string().unwrap();
+            let response_status = response.status();
+            let returned_product_category: ProductCategory =
+                serde_json::from_str(&response_body).unwrap();
+
+            assert_eq!(
+                &returned_product_category,
+                inserted_product_categories.get(0).unwrap()
+            );
+            assert_eq!(Status::Ok, response_status);
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        when_getting_missing_product_category_status_is_404,
+        |client, _repository| {
+            let get_request = client
+                .get(format!("/productcategory/{}", 999))
+                .header(bearer_token(Role::Viewer));
+            let response = get_request.dispatch();
+            let response_status = response.status();
+
+            assert_eq!(Status::NotFound, response_status);
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        delete_call_removes_product_category_from_db,
+        |client, repository| {
+            let inserted_product_category_id = repository.insert_category("testcat")?.id;
+
+            let saved_product_categories = repository.categories()?;
+            assert_eq!(saved_product_categories.len(), 1);
+
+            let delete_request = client
+                .delete(format!("/productcategory/{}", inserted_product_category_id))
+                .header(bearer_token(Role::Admin));
+            let response = delete_request.dispatch();
+            let response_status = response.status();
+
+            let remaining_product_cateogries = repository.categories()?;
+            assert_eq!(response_status, Status::Ok);
+            assert_eq!(remaining_product_cateogries.len(), 0);
+            Ok(())
+        }
+    );
 
-        diesel::insert_into(product_category)
-            .values(name.eq("testcategory"))
-            .execute(&connection)?;
+    backend_test!(put_overwrites_product_category, |client, repository| {
+        repository.insert_category("testcategory")?;
 
-        let inserted_product_categories: Vec<ProductCategory> =
-            product_category.load(&connection).unwrap();
+        let inserted_product_categories = repository.categories()?;
         assert_eq!(inserted_product_categories.len(), 1);
         let inserted_product_category = inserted_product_categories.get(0).unwrap();
 
         let replacement_product_category = ProductCategoryRequestBody {
             name: "putcategory".to_string(),
+            default_name: None,
         };
 
         let mut put_request =
             client.put(format!("/productcategory/{}", inserted_product_category.id));
         put_request.set_body(serde_json::to_string(&replacement_product_category).unwrap());
         put_request.add_header(ContentType::JSON);
+        put_request.add_header(bearer_token(Role::Admin));
 
         let mut response = put_request.dispatch();
         let response_status = response.status();
@@ -314,31 +302,29 @@ mod tests {
         let expected_product_category = ProductCategory {
             id: inserted_product_category.id,
             name: returned_product_category.name,
+            slug: "putcategory".to_string(),
+            description: None,
+            image_ref: None,
+            is_visible: true,
+            is_archived: false,
         };
 
-        if let Ok(in_db_category) = product_category
-            .find(expected_product_category.id)
-            .first::<ProductCategory>(&connection)
-        {
-            assert_eq!(in_db_category, expected_product_category)
-        }
-
+        assert_eq!(
+            Some(expected_product_category.clone()),
+            repository.category(expected_product_category.id)?
+        );
         assert_eq!(response_status, Status::Ok);
         Ok(())
-    }
-
-    #[test]
-    fn put_creates_new_product_category() -> Result<(), diesel_migrations::RunMigrationsError> {
-        let docker_cli = Cli::default();
-        let database_metadata = crate::test_utils::start_database(&docker_cli);
-        let (rocket, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
-        let client = Client::new(rocket).expect("Expected valid rocket instance");
+    });
 
+    backend_test!(put_creates_new_product_category, |client, repository| {
         let put_category = ProductCategoryRequestBody {
             name: "putcategory".to_string(),
+            default_name: None,
         };
         let mut put_request = client.put("/productcategory/5");
         put_request.add_header(ContentType::JSON);
+        put_request.add_header(bearer_token(Role::Admin));
         put_request.set_body(serde_json::to_string(&put_category).unwrap());
 
         let mut put_response = put_request.dispatch();
@@ -346,15 +332,20 @@ mod tests {
 
         let expected_product_category = ProductCategory {
             id: 5,
+            slug: put_category.name.clone(),
             name: put_category.name,
+            description: None,
+            image_ref: None,
+            is_visible: true,
+            is_archived: false,
         };
         let response_product_category: ProductCategory =
             serde_json::from_str(&response_body).unwrap();
 
-        let categories_in_db: Vec<ProductCategory> = product_category.load(&connection)?;
+        let categories_in_db = repository.categories()?;
         assert_eq!(categories_in_db.len(), 1);
         assert_eq!(response_product_category, expected_product_category);
         assert_eq!(categories_in_db.get(0).unwrap(), &expected_product_category);
         Ok(())
-    }
+    });
 }
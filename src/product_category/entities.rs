@@ -1,7 +1,19 @@
 use crate::schema::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Identifiable, Insertable, Queryable, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[derive(
+    Identifiable,
+    Insertable,
+    Queryable,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Clone,
+    PartialEq,
+    Eq,
+    Debug,
+)]
 #[table_name = "product_category"]
 /// Product category
 ///
@@ -10,4 +22,130 @@ use serde::{Deserialize, Serialize};
 pub struct ProductCategory {
     pub id: i32,
     pub name: String,
+    /// Derived from the name and unique among the categories with the same parent, see
+    /// [`super::slug`]. Slugs given when saving categories are ignored.
+    #[serde(default)]
+    pub slug: String,
+    /// Text shown with the category in the shop
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Reference to the image shown with the category, like a URL or a key of an asset store
+    #[serde(default)]
+    pub image_ref: Option<String>,
+    /// Hidden categories are kept, but left out of the navigation of the shop
+    #[serde(default = "visible_by_default")]
+    pub is_visible: bool,
+    /// Set once the category was merged into another one. Archived categories have neither
+    /// subcategories, parents nor products, and their slugs are free for other categories.
+    /// The flag is ignored when saving categories.
+    #[serde(default)]
+    pub is_archived: bool,
+}
+
+/// Categories are visible unless they are hidden explicitly
+pub fn visible_by_default() -> bool {
+    true
+}
+
+impl ProductCategory {
+    /// Whether the categories have the same name and display metadata, regardless of their ids
+    /// and slugs
+    pub fn has_same_content(&self, other: &ProductCategory) -> bool {
+        self.name == other.name
+            && self.description == other.description
+            && self.image_ref == other.image_ref
+            && self.is_visible == other.is_visible
+    }
+}
+
+#[derive(Queryable, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Product category found through the hierarchy of another category
+pub struct RelatedCategory {
+    pub id: i32,
+    pub name: String,
+    pub slug: String,
+    pub description: Option<String>,
+    pub image_ref: Option<String>,
+    pub is_visible: bool,
+    /// Number of rollup links on the shortest path between the two categories
+    pub depth: i32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Product category together with the categories rolled up into it
+pub struct CategoryTree {
+    pub id: i32,
+    pub name: String,
+    pub slug: String,
+    pub description: Option<String>,
+    pub image_ref: Option<String>,
+    pub is_visible: bool,
+    /// Ordered by the positions of the links to the subcategories
+    pub subcategories: Vec<CategoryTree>,
+}
+
+impl From<ProductCategory> for CategoryTree {
+    fn from(category: ProductCategory) -> Self {
+        CategoryTree {
+            id: category.id,
+            name: category.name,
+            slug: category.slug,
+            description: category.description,
+            image_ref: category.image_ref,
+            is_visible: category.is_visible,
+            subcategories: Vec::new(),
+        }
+    }
+}
+
+impl From<RelatedCategory> for CategoryTree {
+    fn from(category: RelatedCategory) -> Self {
+        CategoryTree {
+            id: category.id,
+            name: category.name,
+            slug: category.slug,
+            description: category.description,
+            image_ref: category.image_ref,
+            is_visible: category.is_visible,
+            subcategories: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Category on the way from a root category down to another category
+pub struct Breadcrumb {
+    pub id: i32,
+    pub name: String,
+    pub slug: String,
+}
+
+impl From<&ProductCategory> for Breadcrumb {
+    fn from(category: &ProductCategory) -> Self {
+        Breadcrumb {
+            id: category.id,
+            name: category.name.clone(),
+            slug: category.slug.clone(),
+        }
+    }
+}
+
+impl From<RelatedCategory> for Breadcrumb {
+    fn from(category: RelatedCategory) -> Self {
+        Breadcrumb {
+            id: category.id,
+            name: category.name,
+            slug: category.slug,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Product category together with the ways to reach it from the root categories
+pub struct CategoryWithBreadcrumbs {
+    #[serde(flatten)]
+    pub category: ProductCategory,
+    /// Every path from a root category down to the category, which is the last breadcrumb of
+    /// each path. A category rolled up into several categories can be reached on several paths.
+    pub breadcrumbs: Vec<Vec<Breadcrumb>>,
 }
@@ -0,0 +1,728 @@
+use super::entities::{
+    Breadcrumb, CategoryTree, CategoryWithBreadcrumbs, ProductCategory, RelatedCategory,
+};
+use super::translations::localized;
+use crate::{
+    auth::{Admin, Authorized, Viewer},
+    entities::Product,
+    localization::{localize_products, Locale},
+    repository::{BackendConn, CategoryRepository, ProductRepository},
+    utilities::{DbError, GetResponder},
+};
+use diesel::QueryResult;
+use rocket::response::status::BadRequest;
+use rocket_contrib::json::Json;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+/// Finds the cycles in a hierarchy given as `(upper, lower)` links
+///
+/// Every returned cycle lists the categories along the links, starting and ending with the same
+/// category, for example `[1, 2, 1]`. Each cycle is reported once.
+pub fn cycles_in(links: &[(i32, i32)]) -> Vec<Vec<i32>> {
+    let mut lower_categories: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for (upper, lower) in links {
+        lower_categories.entry(*upper).or_default().push(*lower);
+    }
+    for lowers in lower_categories.values_mut() {
+        lowers.sort_unstable();
+    }
+
+    let mut visits: HashMap<i32, Visit> = HashMap::new();
+    let mut cycles = Vec::new();
+    for start in lower_categories.keys().copied() {
+        if visits.contains_key(&start) {
+            continue;
+        }
+        // Depth first search with an explicit stack of (category, index of next lower category)
+        let mut path: Vec<(i32, usize)> = vec![(start, 0)];
+        visits.insert(start, Visit::InProgress);
+        while let Some((category, next)) = path.last().copied() {
+            let lowers = lower_categories
+                .get(&category)
+                .map_or(&[][..], Vec::as_slice);
+            match lowers.get(next) {
+                Some(lower) => {
+                    path.last_mut().unwrap().1 += 1;
+                    match visits.get(lower) {
+                        None => {
+                            visits.insert(*lower, Visit::InProgress);
+                            path.push((*lower, 0));
+                        }
+                        Some(Visit::InProgress) => {
+                            let cycle_start = path
+                                .iter()
+                                .position(|(on_path, _)| on_path == lower)
+                                .unwrap();
+                            let mut cycle: Vec<i32> =
+                                path[cycle_start..].iter().map(|(id, _)| *id).collect();
+                            cycle.push(*lower);
+                            cycles.push(cycle);
+                        }
+                        Some(Visit::Done) => {}
+                    }
+                }
+                None => {
+                    visits.insert(category, Visit::Done);
+                    path.pop();
+                }
+            }
+        }
+    }
+    cycles
+}
+
+/// Finds the cycles in the category hierarchy of the tenant
+///
+/// Categories must not roll up into themselves, neither directly nor through subcategories.
+pub fn find_cycles<R: CategoryRepository>(repository: &R) -> QueryResult<Vec<Vec<i32>>> {
+    Ok(cycles_in(&repository.rollups()?))
+}
+
+/// Unfolds the hierarchy below the category into a tree, or returns `None` if it does not exist
+///
+/// Categories which are rolled up into several categories of the subtree appear below each of
+/// them. A path ends before it would return to a category on it, and after `max_depth` links if
+/// that is given. Subcategories are ordered by the positions of their links.
+pub fn subtree<R: CategoryRepository>(
+    repository: &R,
+    category_id: i32,
+    max_depth: Option<i32>,
+) -> QueryResult<Option<CategoryTree>> {
+    let root = match repository.category(category_id)? {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+    let mut nodes: HashMap<i32, CategoryTree> = repository
+        .descendants(category_id, max_depth)?
+        .into_iter()
+        .map(|category| (category.id, CategoryTree::from(category)))
+        .collect();
+    nodes.insert(root.id, CategoryTree::from(root));
+    let ids: Vec<i32> = nodes.keys().copied().collect();
+    let mut lower_categories: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for (upper, lower) in repository.rollups_of(&ids)? {
+        if nodes.contains_key(&upper) && nodes.contains_key(&lower) {
+            let lowers = lower_categories.entry(upper).or_default();
+            if !lowers.contains(&lower) {
+                lowers.push(lower);
+            }
+        }
+    }
+    Ok(Some(unfold(
+        category_id,
+        &nodes,
+        &lower_categories,
+        max_depth,
+        &mut HashSet::new(),
+    )))
+}
+
+fn unfold(
+    category_id: i32,
+    nodes: &HashMap<i32, CategoryTree>,
+    lower_categories: &BTreeMap<i32, Vec<i32>>,
+    remaining_depth: Option<i32>,
+    path: &mut HashSet<i32>,
+) -> CategoryTree {
+    path.insert(category_id);
+    let lowers: Vec<i32> = match remaining_depth {
+        Some(remaining_depth) if remaining_depth <= 0 => Vec::new(),
+        _ => lower_categories
+            .get(&category_id)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter(|lower| !path.contains(lower))
+            .copied()
+            .collect(),
+    };
+    let subcategories = lowers
+        .into_iter()
+        .map(|lower| {
+            let remaining_depth = remaining_depth.map(|depth| depth - 1);
+            unfold(lower, nodes, lower_categories, remaining_depth, path)
+        })
+        .collect();
+    path.remove(&category_id);
+    CategoryTree {
+        subcategories,
+        ..nodes[&category_id].clone()
+    }
+}
+
+/// Outcome of reordering the subcategories of a category
+#[derive(PartialEq, Eq, Debug)]
+pub enum Reordering {
+    Reordered,
+    UnknownCategory,
+    /// The ids are not exactly those of the subcategories, which the message explains
+    Mismatch(String),
+}
+
+/// Positions the subcategories of the category in the order of the ids
+///
+/// Every subcategory has to be listed exactly once, so that an order which was computed from an
+/// outdated list of subcategories is rejected instead of applied partially.
+pub fn reorder_subcategories<R: CategoryRepository>(
+    repository: &R,
+    category_id: i32,
+    lower_category_ids: &[i32],
+) -> QueryResult<Reordering> {
+    repository.atomically(|| {
+        if repository.lock_categories(&[category_id], &[])?.is_empty() {
+            return Ok(Reordering::UnknownCategory);
+        }
+        let mut current_ids: Vec<i32> = repository
+            .rollups_of(&[category_id])?
+            .into_iter()
+            .filter(|(upper_id, _)| *upper_id == category_id)
+            .map(|(_, lower_id)| lower_id)
+            .collect();
+        current_ids.sort_unstable();
+        current_ids.dedup();
+        let mut requested_ids = lower_category_ids.to_vec();
+        requested_ids.sort_unstable();
+        if let Some(repeated) = requested_ids.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Ok(Reordering::Mismatch(format!(
+                "Subcategory {} is listed more than once",
+                repeated[0]
+            )));
+        }
+        if requested_ids != current_ids {
+            let missing: Vec<String> = current_ids
+                .iter()
+                .filter(|id| !requested_ids.contains(id))
+                .map(i32::to_string)
+                .collect();
+            let unknown: Vec<String> = requested_ids
+                .iter()
+                .filter(|id| !current_ids.contains(id))
+                .map(i32::to_string)
+                .collect();
+            return Ok(Reordering::Mismatch(format!(
+                "The order has to list every subcategory exactly once, missing: [{}], not \
+                 subcategories: [{}]",
+                missing.join(", "),
+                unknown.join(", ")
+            )));
+        }
+        repository.set_rollup_positions(category_id, lower_category_ids)?;
+        Ok(Reordering::Reordered)
+    })
+}
+
+/// Adds the breadcrumbs to the category, see [`CategoryWithBreadcrumbs::breadcrumbs`]
+pub fn with_breadcrumbs<R: CategoryRepository>(
+    repository: &R,
+    category: ProductCategory,
+) -> QueryResult<CategoryWithBreadcrumbs> {
+    let mut crumbs: HashMap<i32, Breadcrumb> = repository
+        .ancestors(category.id, None)?
+        .into_iter()
+        .map(|ancestor| (ancestor.id, Breadcrumb::from(ancestor)))
+        .collect();
+    crumbs.insert(category.id, Breadcrumb::from(&category));
+    let ids: Vec<i32> = crumbs.keys().copied().collect();
+    let links = repository.rollups_of(&ids)?;
+    let breadcrumbs = breadcrumbs_along(category.id, &crumbs, &links);
+    Ok(CategoryWithBreadcrumbs {
+        category,
+        breadcrumbs,
+    })
+}
+
+/// All categories with their breadcrumbs, ordered by id
+pub fn all_with_breadcrumbs<R: CategoryRepository>(
+    repository: &R,
+) -> QueryResult<Vec<CategoryWithBreadcrumbs>> {
+    let categories = repository.categories()?;
+    let links = repository.rollups()?;
+    let crumbs: HashMap<i32, Breadcrumb> = categories
+        .iter()
+        .map(|category| (category.id, Breadcrumb::from(category)))
+        .collect();
+    Ok(categories
+        .into_iter()
+        .map(|category| CategoryWithBreadcrumbs {
+            breadcrumbs: breadcrumbs_along(category.id, &crumbs, &links),
+            category,
+        })
+        .collect())
+}
+
+/// Walks the `(upper, lower)` links up from the category to every root category
+///
+/// A path also ends where going further up would return to a category on it.
+fn breadcrumbs_along(
+    category_id: i32,
+    crumbs: &HashMap<i32, Breadcrumb>,
+    links: &[(i32, i32)],
+) -> Vec<Vec<Breadcrumb>> {
+    let mut upper_categories: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for (upper, lower) in links {
+        if crumbs.contains_key(upper) && crumbs.contains_key(lower) {
+            upper_categories.entry(*lower).or_default().push(*upper);
+        }
+    }
+    for uppers in upper_categories.values_mut() {
+        uppers.sort_unstable();
+        uppers.dedup();
+    }
+    let mut paths = Vec::new();
+    climb(&mut vec![category_id], &upper_categories, &mut paths);
+    paths
+        .into_iter()
+        .map(|path| path.iter().map(|id| crumbs[id].clone()).collect())
+        .collect()
+}
+
+fn climb(
+    path: &mut Vec<i32>,
+    upper_categories: &BTreeMap<i32, Vec<i32>>,
+    paths: &mut Vec<Vec<i32>>,
+) {
+    let category_id = *path.last().unwrap();
+    let uppers: Vec<i32> = upper_categories
+        .get(&category_id)
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter(|upper| !path.contains(upper))
+        .copied()
+        .collect();
+    if uppers.is_empty() {
+        paths.push(path.iter().rev().copied().collect());
+    }
+    for upper in uppers {
+        path.push(upper);
+        climb(path, upper_categories, paths);
+        path.pop();
+    }
+}
+
+/// Follows the slugs from a root category down through the subcategories
+///
+/// Returns `None` if a slug matches none of the categories at its level, or if there is no slug.
+pub fn category_at_path<R: CategoryRepository>(
+    repository: &R,
+    slugs: &[&str],
+) -> QueryResult<Option<ProductCategory>> {
+    let mut found: Option<i32> = None;
+    for slug in slugs {
+        let parent_ids: Vec<i32> = found.into_iter().collect();
+        found = repository
+            .slugs_below(&parent_ids)?
+            .into_iter()
+            .find(|(_, below)| below == slug)
+            .map(|(id, _)| id);
+        if found.is_none() {
+            return Ok(None);
+        }
+    }
+    match found {
+        Some(id) => repository.category(id),
+        None => Ok(None),
+    }
+}
+
+#[get("/path/<slugs..>", rank = 2)]
+pub fn get_by_path(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    locale: Locale,
+    slugs: PathBuf,
+) -> Result<GetResponder<CategoryWithBreadcrumbs>, DbError> {
+    let slugs: Vec<&str> = slugs.iter().filter_map(|slug| slug.to_str()).collect();
+    match category_at_path(&db_conn, &slugs)? {
+        Some(category) => {
+            let category = with_breadcrumbs(&db_conn, category)?;
+            Ok(GetResponder::Found(Json(localized(
+                &db_conn, &locale, category,
+            )?)))
+        }
+        None => Ok(GetResponder::NotFound(())),
+    }
+}
+
+/// Orders the subcategories like the ids in the body and returns them
+#[put(
+    "/<product_category_id>/subcategories",
+    format = "json",
+    data = "<lower_category_ids>"
+)]
+pub fn put_subcategory_order(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    product_category_id: i32,
+    lower_category_ids: Json<Vec<i32>>,
+) -> Result<Result<GetResponder<CategoryTree>, BadRequest<String>>, DbError> {
+    match reorder_subcategories(&db_conn, product_category_id, &lower_category_ids)? {
+        Reordering::Reordered => {}
+        Reordering::UnknownCategory => return Ok(Ok(GetResponder::NotFound(()))),
+        Reordering::Mismatch(message) => return Ok(Err(BadRequest(Some(message)))),
+    }
+    match subtree(&db_conn, product_category_id, Some(1))? {
+        Some(tree) => Ok(Ok(GetResponder::Found(Json(tree)))),
+        None => Ok(Ok(GetResponder::NotFound(()))),
+    }
+}
+
+#[get("/<product_category_id>/descendants?<depth>")]
+pub fn get_descendants(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    locale: Locale,
+    product_category_id: i32,
+    depth: Option<i32>,
+) -> Result<GetResponder<Vec<RelatedCategory>>, DbError> {
+    if db_conn.category(product_category_id)?.is_none() {
+        return Ok(GetResponder::NotFound(()));
+    }
+    let descendants = db_conn.descendants(product_category_id, depth)?;
+    Ok(GetResponder::Found(Json(localized(
+        &db_conn,
+        &locale,
+        descendants,
+    )?)))
+}
+
+#[get("/<product_category_id>/ancestors?<depth>")]
+pub fn get_ancestors(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    locale: Locale,
+    product_category_id: i32,
+    depth: Option<i32>,
+) -> Result<GetResponder<Vec<RelatedCategory>>, DbError> {
+    if db_conn.category(product_category_id)?.is_none() {
+        return Ok(GetResponder::NotFound(()));
+    }
+    let ancestors = db_conn.ancestors(product_category_id, depth)?;
+    Ok(GetResponder::Found(Json(localized(
+        &db_conn, &locale, ancestors,
+    )?)))
+}
+
+#[get("/<product_category_id>/subtree?<depth>")]
+pub fn get_subtree(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    locale: Locale,
+    product_category_id: i32,
+    depth: Option<i32>,
+) -> Result<GetResponder<CategoryTree>, DbError> {
+    match subtree(&db_conn, product_category_id, depth)? {
+        Some(tree) => Ok(GetResponder::Found(Json(localized(
+            &db_conn, &locale, tree,
+        )?))),
+        None => Ok(GetResponder::NotFound(())),
+    }
+}
+
+#[get("/<product_category_id>/products")]
+pub fn get_products(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    locale: Locale,
+    product_category_id: i32,
+) -> Result<GetResponder<Vec<Product>>, DbError> {
+    if db_conn.category(product_category_id)?.is_none() {
+        return Ok(GetResponder::NotFound(()));
+    }
+    let products = db_conn.products_in_subtree(product_category_id)?;
+    Ok(GetResponder::Found(Json(localize_products(
+        &db_conn, &locale, products,
+    )?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cycles_in, reorder_subcategories, subtree, Reordering};
+    use crate::{
+        auth::Role,
+        entities::Product,
+        product_category::entities::{CategoryTree, CategoryWithBreadcrumbs, RelatedCategory},
+        repository::{CategoryRepository, MemoryRepository},
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::http::{ContentType, Status};
+
+    fn leaf(id: i32, name: &str) -> CategoryTree {
+        CategoryTree {
+            id,
+            name: name.to_string(),
+            slug: name.to_string(),
+            description: None,
+            image_ref: None,
+            is_visible: true,
+            subcategories: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn tree_has_no_cycles() {
+        assert!(cycles_in(&[(1, 2), (1, 3), (2, 4), (3, 4)]).is_empty());
+    }
+
+    #[test]
+    fn cycles_are_reported_along_their_links() {
+        assert_eq!(vec![vec![1, 2, 3, 1]], cycles_in(&[(1, 2), (2, 3), (3, 1)]));
+        assert_eq!(vec![vec![5, 5]], cycles_in(&[(5, 5)]));
+    }
+
+    #[test]
+    fn subtree_repeats_shared_categories_and_stops_at_cycles() {
+        let repository = MemoryRepository::new();
+        for name in &["clothes", "tops", "sale", "shirts"] {
+            repository.insert_category(name).unwrap();
+        }
+        for (upper, lower) in &[(1, 2), (1, 3), (2, 4), (3, 4), (4, 1)] {
+            repository.insert_rollup(*upper, *lower).unwrap();
+        }
+
+        let tree = subtree(&repository, 1, None).unwrap().unwrap();
+        let shallow_tree = subtree(&repository, 1, Some(1)).unwrap().unwrap();
+
+        let shirts = leaf(4, "shirts");
+        let branch = |id, name: &str| CategoryTree {
+            subcategories: vec![shirts.clone()],
+            ..leaf(id, name)
+        };
+        assert_eq!(
+            CategoryTree {
+                subcategories: vec![branch(2, "tops"), branch(3, "sale")],
+                ..leaf(1, "clothes")
+            },
+            tree
+        );
+        assert_eq!(
+            CategoryTree {
+                subcategories: vec![leaf(2, "tops"), leaf(3, "sale")],
+                ..leaf(1, "clothes")
+            },
+            shallow_tree
+        );
+        assert_eq!(None, subtree(&repository, 5, None).unwrap());
+    }
+
+    #[test]
+    fn reordering_has_to_list_every_subcategory_once() {
+        let repository = MemoryRepository::new();
+        for name in &["clothes", "tops", "skirts", "shoes"] {
+            repository.insert_category(name).unwrap();
+        }
+        for lower in 2..=4 {
+            repository.insert_rollup(1, lower).unwrap();
+        }
+
+        let repeated = reorder_subcategories(&repository, 1, &[4, 2, 4, 3]).unwrap();
+        let incomplete = reorder_subcategories(&repository, 1, &[4, 1]).unwrap();
+        let reordered = reorder_subcategories(&repository, 1, &[4, 2, 3]).unwrap();
+
+        assert_eq!(
+            Reordering::Mismatch("Subcategory 4 is listed more than once".to_string()),
+            repeated
+        );
+        assert_eq!(
+            Reordering::Mismatch(
+                "The order has to list every subcategory exactly once, missing: [2, 3], not \
+                 subcategories: [1]"
+                    .to_string()
+            ),
+            incomplete
+        );
+        assert_eq!(Reordering::Reordered, reordered);
+        assert_eq!(
+            Reordering::UnknownCategory,
+            reorder_subcategories(&repository, 5, &[]).unwrap()
+        );
+        assert_eq!(
+            CategoryTree {
+                subcategories: vec![leaf(4, "shoes"), leaf(2, "tops"), leaf(3, "skirts")],
+                ..leaf(1, "clothes")
+            },
+            subtree(&repository, 1, None).unwrap().unwrap()
+        );
+    }
+
+    backend_test!(
+        subcategories_are_reordered_and_shown_with_their_metadata,
+        |client, repository| {
+            let clothes = repository.insert_category("clothes")?;
+            let tops = repository.insert_category("tops")?;
+            let skirts = repository.insert_category("skirts")?;
+            repository.insert_rollup(clothes.id, tops.id)?;
+            repository.insert_rollup(clothes.id, skirts.id)?;
+            let put = |path: String, body: String| {
+                let mut response = client
+                    .put(path)
+                    .header(ContentType::JSON)
+                    .header(bearer_token(Role::Admin))
+                    .body(body)
+                    .dispatch();
+                (
+                    response.status(),
+                    response.body_string().unwrap_or_default(),
+                )
+            };
+
+            let (metadata_status, _) = put(
+                format!("/productcategory/{}", skirts.id),
+                r#"{"name":"skirts","description":"Minis and maxis","image_ref":"skirts.png","is_visible":false}"#
+                    .to_string(),
+            );
+            let (mismatch_status, _) = put(
+                format!("/productcategory/{}/subcategories", clothes.id),
+                format!("[{}]", skirts.id),
+            );
+            let (reorder_status, tree) = put(
+                format!("/productcategory/{}/subcategories", clothes.id),
+                format!("[{}, {}]", skirts.id, tops.id),
+            );
+            let (missing_status, _) = put(
+                format!("/productcategory/{}/subcategories", skirts.id + 1),
+                "[]".to_string(),
+            );
+
+            assert_eq!(Status::Ok, metadata_status);
+            assert_eq!(Status::BadRequest, mismatch_status);
+            assert_eq!(Status::Ok, reorder_status);
+            assert_eq!(Status::NotFound, missing_status);
+            assert_eq!(
+                CategoryTree {
+                    subcategories: vec![
+                        CategoryTree {
+                            description: Some("Minis and maxis".to_string()),
+                            image_ref: Some("skirts.png".to_string()),
+                            is_visible: false,
+                            ..leaf(skirts.id, "skirts")
+                        },
+                        leaf(tops.id, "tops")
+                    ],
+                    ..leaf(clothes.id, "clothes")
+                },
+                serde_json::from_str::<CategoryTree>(&tree)?
+            );
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        hierarchy_endpoints_look_through_all_levels,
+        |client, repository| {
+            let clothes = repository.insert_category("clothes")?;
+            let tops = repository.insert_category("tops")?;
+            let shirts = repository.insert_category("shirts")?;
+            repository.insert_rollup(clothes.id, tops.id)?;
+            repository.insert_rollup(tops.id, shirts.id)?;
+            let shirt = repository.insert_product("white shirt")?;
+            repository.classify(shirt.id, shirts.id, true)?;
+            let get = |path: String| {
+                let mut response = client
+                    .get(path)
+                    .header(bearer_token(Role::Viewer))
+                    .dispatch();
+                (
+                    response.status(),
+                    response.body_string().unwrap_or_default(),
+                )
+            };
+
+            let (descendants_status, descendants) =
+                get(format!("/productcategory/{}/descendants", clothes.id));
+            let (_, near_descendants) = get(format!(
+                "/productcategory/{}/descendants?depth=1",
+                clothes.id
+            ));
+            let (_, ancestors) = get(format!("/productcategory/{}/ancestors", shirts.id));
+            let (_, tree) = get(format!("/productcategory/{}/subtree?depth=1", clothes.id));
+            let (_, products) = get(format!("/productcategory/{}/products", clothes.id));
+            let (missing_status, _) = get(format!("/productcategory/{}/products", shirts.id + 1));
+
+            let related = |body: &str| -> Vec<(String, i32)> {
+                serde_json::from_str::<Vec<RelatedCategory>>(body)
+                    .unwrap()
+                    .into_iter()
+                    .map(|category| (category.name, category.depth))
+                    .collect()
+            };
+            assert_eq!(Status::Ok, descendants_status);
+            assert_eq!(
+                vec![("tops".to_string(), 1), ("shirts".to_string(), 2)],
+                related(&descendants)
+            );
+            assert_eq!(vec![("tops".to_string(), 1)], related(&near_descendants));
+            assert_eq!(
+                vec![("tops".to_string(), 1), ("clothes".to_string(), 2)],
+                related(&ancestors)
+            );
+            assert_eq!(
+                CategoryTree {
+                    subcategories: vec![leaf(tops.id, "tops")],
+                    ..leaf(clothes.id, "clothes")
+                },
+                serde_json::from_str::<CategoryTree>(&tree)?
+            );
+            assert_eq!(
+                vec![shirt],
+                serde_json::from_str::<Vec<Product>>(&products)?
+            );
+            assert_eq!(Status::NotFound, missing_status);
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        categories_are_found_by_their_slug_path,
+        |client, repository| {
+            let clothes = repository.insert_category("Clothes")?;
+            let swimwear = repository.insert_category("Swimwear")?;
+            let bikinis = repository.insert_category("Bikinis")?;
+            let sale = repository.insert_category("Summer Sale")?;
+            repository.insert_rollup(clothes.id, swimwear.id)?;
+            repository.insert_rollup(swimwear.id, bikinis.id)?;
+            repository.insert_rollup(sale.id, bikinis.id)?;
+            let get = |path: String| {
+                let mut response = client
+                    .get(path)
+                    .header(bearer_token(Role::Viewer))
+                    .dispatch();
+                let category = response
+                    .body_string()
+                    .and_then(|body| serde_json::from_str::<CategoryWithBreadcrumbs>(&body).ok());
+                (response.status(), category)
+            };
+
+            let (_, by_path) = get("/productcategory/path/clothes/swimwear/bikinis".to_string());
+            let (_, by_other_path) = get("/productcategory/path/summer-sale/bikinis".to_string());
+            let (_, by_id) = get(format!("/productcategory/{}", bikinis.id));
+            let (not_a_root_status, _) = get("/productcategory/path/swimwear/bikinis".to_string());
+
+            let by_path = by_path.unwrap();
+            let paths: Vec<Vec<String>> = by_path
+                .breadcrumbs
+                .iter()
+                .map(|path| path.iter().map(|crumb| crumb.slug.clone()).collect())
+                .collect();
+            assert_eq!("bikinis", by_path.category.slug);
+            assert_eq!(
+                vec![
+                    vec!["clothes", "swimwear", "bikinis"],
+                    vec!["summer-sale", "bikinis"]
+                ],
+                paths
+            );
+            assert_eq!(Some(&by_path), by_other_path.as_ref());
+            assert_eq!(Some(&by_path), by_id.as_ref());
+            assert_eq!(Status::NotFound, not_a_root_status);
+            Ok(())
+        }
+    );
+}
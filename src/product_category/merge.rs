@@ -0,0 +1,527 @@
+//! Merging duplicate categories
+//!
+//! A merge runs in a single transaction and can be previewed: with `dry_run` it is made and
+//! reported, but rolled back instead of committed.
+
+use super::hierarchy::cycles_in;
+use crate::{
+    auth::{Admin, Authorized},
+    entities::ProductCategoryClassification,
+    idempotency::{Idempotency, Idempotent},
+    outbox::EventType,
+    repository::{BackendConn, CategoryRepository, ProductRepository, Transactional},
+    utilities::{DbError, GetResponder},
+};
+use diesel::{result::Error::RollbackTransaction, QueryResult};
+use rocket::response::status::BadRequest;
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+/// Category into which another one is merged
+pub struct MergeRequestBody {
+    pub target_id: i32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Default)]
+/// Changes made by merging a category into another, or which a dry run would have made
+pub struct MergeReport {
+    pub source_id: i32,
+    pub target_id: i32,
+    pub dry_run: bool,
+    pub committed: bool,
+    /// Classifications moved from the source to the target
+    pub moved_classifications: usize,
+    /// Classifications removed because the product is classified into the target anyway
+    pub merged_classifications: usize,
+    /// Links of the source which now roll the target up or down
+    pub moved_rollups: usize,
+    /// Links of the source which the target had already, or which would link it to itself
+    pub dropped_rollups: usize,
+}
+
+/// Outcome of restructuring the categories, such as a merge
+#[derive(PartialEq, Eq, Debug)]
+pub enum Restructuring<T> {
+    Done(T),
+    UnknownCategory,
+    /// The change can not be made, which the message explains
+    Rejected(String),
+}
+
+/// Runs the change in a transaction, which is rolled back if it is a dry run or not done
+///
+/// Returns the outcome and whether it was committed.
+pub(super) fn commit_unless_dry_run<R, T, F>(
+    repository: &R,
+    dry_run: bool,
+    change: F,
+) -> QueryResult<(Restructuring<T>, bool)>
+where
+    R: Transactional,
+    F: FnOnce() -> QueryResult<Restructuring<T>>,
+{
+    let mut outcome = None;
+    let transaction = repository.atomically(|| {
+        let restructuring = change()?;
+        let keep = !dry_run && matches!(restructuring, Restructuring::Done(_));
+        outcome = Some(restructuring);
+        if keep {
+            Ok(())
+        } else {
+            Err(RollbackTransaction)
+        }
+    });
+    match (transaction, outcome) {
+        (Ok(()), Some(outcome)) => Ok((outcome, true)),
+        (Err(RollbackTransaction), Some(outcome)) => Ok((outcome, false)),
+        (Err(error), _) => Err(error),
+        (Ok(()), None) => unreachable!("the change succeeded without an outcome"),
+    }
+}
+
+/// Merges the source category into the target and archives the source
+///
+/// The classifications and links of the source are moved to the target. A product ends up with
+/// one classification into the target, which is primary if any of the merged ones was. Links
+/// the target has already, and links between the source and the target, are dropped.
+pub fn merge_categories<R>(
+    repository: &R,
+    source_id: i32,
+    target_id: i32,
+    dry_run: bool,
+) -> QueryResult<Restructuring<MergeReport>>
+where
+    R: CategoryRepository + ProductRepository,
+{
+    let (mut outcome, committed) = commit_unless_dry_run(repository, dry_run, || {
+        merge(repository, source_id, target_id)
+    })?;
+    if let Restructuring::Done(report) = &mut outcome {
+        report.dry_run = dry_run;
+        report.committed = committed;
+    }
+    Ok(outcome)
+}
+
+fn merge<R>(
+    repository: &R,
+    source_id: i32,
+    target_id: i32,
+) -> QueryResult<Restructuring<MergeReport>>
+where
+    R: CategoryRepository + ProductRepository,
+{
+    let locked = repository.lock_categories(&[source_id, target_id], &[])?;
+    let find = |id: i32| locked.iter().find(|category| category.id == id);
+    let source = match find(source_id) {
+        Some(source) => source,
+        None => return Ok(Restructuring::UnknownCategory),
+    };
+    if source_id == target_id {
+        return Ok(Restructuring::Rejected(
+            "A category can not be merged into itself".to_string(),
+        ));
+    }
+    let target = match find(target_id) {
+        Some(target) => target,
+        None => {
+            return Ok(Restructuring::Rejected(format!(
+                "Category {} does not exist",
+                target_id
+            )))
+        }
+    };
+    if let Some(archived) = [source, target]
+        .iter()
+        .find(|category| category.is_archived)
+    {
+        return Ok(Restructuring::Rejected(format!(
+            "Category {} is archived",
+            archived.id
+        )));
+    }
+    let mut report = MergeReport {
+        source_id,
+        target_id,
+        ..MergeReport::default()
+    };
+
+    let (source_links, mut links): (Vec<_>, Vec<_>) = repository
+        .rollups()?
+        .into_iter()
+        .partition(|(upper, lower)| *upper == source_id || *lower == source_id);
+    let retarget = |id: i32| if id == source_id { target_id } else { id };
+    let mut moved_links = Vec::new();
+    for (upper, lower) in source_links {
+        let link = (retarget(upper), retarget(lower));
+        if link.0 == link.1 || links.contains(&link) {
+            report.dropped_rollups += 1;
+        } else {
+            links.push(link);
+            moved_links.push(link);
+            report.moved_rollups += 1;
+        }
+    }
+    let new_cycle = cycles_in(&links).into_iter().find(|cycle| {
+        cycle
+            .windows(2)
+            .any(|link| moved_links.contains(&(link[0], link[1])))
+    });
+    if let Some(cycle) = new_cycle {
+        let path: Vec<String> = cycle.iter().map(i32::to_string).collect();
+        return Ok(Restructuring::Rejected(format!(
+            "Merging would roll category {} up into itself: {}",
+            target_id,
+            path.join(" > ")
+        )));
+    }
+    repository.delete_rollups_of(source_id)?;
+    for (upper, lower) in moved_links {
+        repository.insert_rollup(upper, lower)?;
+    }
+
+    let mut classifications: BTreeMap<i32, Vec<ProductCategoryClassification>> = BTreeMap::new();
+    for classification in repository.classifications_in(&[source_id, target_id])? {
+        classifications
+            .entry(classification.product_id)
+            .or_default()
+            .push(classification);
+    }
+    let mut merged_ids = Vec::new();
+    for of_product in classifications.values() {
+        let is_primary = of_product
+            .iter()
+            .any(|classification| classification.is_primary_classification);
+        let kept = of_product
+            .iter()
+            .find(|classification| classification.product_category_id == target_id)
+            .unwrap_or(&of_product[0]);
+        if kept.product_category_id != target_id {
+            report.moved_classifications += 1;
+        }
+        if kept.product_category_id != target_id || kept.is_primary_classification != is_primary {
+            repository.update_classification(kept.id, target_id, is_primary)?;
+        }
+        merged_ids.extend(
+            of_product
+                .iter()
+                .filter(|classification| classification.id != kept.id)
+                .map(|classification| classification.id),
+        );
+    }
+    report.merged_classifications = repository.delete_classifications(&merged_ids)?;
+
+    repository.archive_category(source_id)?;
+    repository.record(
+        EventType::CategoryMerged,
+        &serde_json::json!({ "source_id": source_id, "target_id": target_id }),
+    )?;
+    Ok(Restructuring::Done(report))
+}
+
+pub(super) type RestructuringResponse<T> =
+    Result<Result<GetResponder<T>, BadRequest<String>>, DbError>;
+
+pub(super) fn respond<T>(outcome: Restructuring<T>) -> RestructuringResponse<T> {
+    match outcome {
+        Restructuring::Done(report) => Ok(Ok(GetResponder::Found(Json(report)))),
+        Restructuring::UnknownCategory => Ok(Ok(GetResponder::NotFound(()))),
+        Restructuring::Rejected(message) => Ok(Err(BadRequest(Some(message)))),
+    }
+}
+
+/// Merges the category into the one in the body and archives it
+#[post(
+    "/<product_category_id>/merge?<dry_run>",
+    format = "json",
+    data = "<merge>"
+)]
+pub fn post_merge(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    idempotency: Idempotency,
+    product_category_id: i32,
+    dry_run: Option<bool>,
+    merge: Json<MergeRequestBody>,
+) -> Idempotent<RestructuringResponse<MergeReport>> {
+    idempotency.run(&db_conn, &*merge, || {
+        respond(merge_categories(
+            &db_conn,
+            product_category_id,
+            merge.target_id,
+            dry_run.unwrap_or(false),
+        )?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_categories, MergeReport, Restructuring};
+    use crate::{
+        auth::Role,
+        outbox::EventType,
+        product_category::statistics::CategoryStatistics,
+        repository::{CategoryRepository, MemoryRepository, ProductRepository},
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::http::{ContentType, Status};
+
+    fn classifications_of(repository: &MemoryRepository, category_id: i32) -> Vec<(i32, bool)> {
+        repository
+            .classifications_in(&[category_id])
+            .unwrap()
+            .into_iter()
+            .map(|classification| {
+                (
+                    classification.product_id,
+                    classification.is_primary_classification,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merging_moves_classifications_and_links_without_duplicates() {
+        let repository = MemoryRepository::new();
+        for name in &["clothes", "sale", "T-Shirts", "Tee Shirts", "v-necks"] {
+            repository.insert_category(name).unwrap();
+        }
+        for (upper, lower) in &[(1, 3), (1, 4), (2, 3), (3, 5), (3, 4)] {
+            repository.insert_rollup(*upper, *lower).unwrap();
+        }
+        for (product, category, is_primary) in &[
+            ("red shirt", 3, true),
+            ("blue shirt", 3, false),
+            ("green shirt", 4, true),
+            ("white shirt", 3, false),
+        ] {
+            let product = repository.insert_product(product).unwrap();
+            repository
+                .classify(product.id, *category, *is_primary)
+                .unwrap();
+        }
+        repository.classify(1, 4, false).unwrap();
+        repository.classify(4, 3, true).unwrap();
+
+        let outcome = merge_categories(&repository, 3, 4, false).unwrap();
+
+        assert_eq!(
+            Restructuring::Done(MergeReport {
+                source_id: 3,
+                target_id: 4,
+                dry_run: false,
+                committed: true,
+                moved_classifications: 2,
+                merged_classifications: 2,
+                moved_rollups: 2,
+                dropped_rollups: 2,
+            }),
+            outcome
+        );
+        assert_eq!(vec![(1, true), (2, false), (3, true), (4, true)], {
+            let mut classifications = classifications_of(&repository, 4);
+            classifications.sort_unstable();
+            classifications
+        });
+        assert!(classifications_of(&repository, 3).is_empty());
+        assert_eq!(vec![(1, 4), (2, 4), (4, 5)], repository.rollups().unwrap());
+        assert!(repository.category(3).unwrap().unwrap().is_archived);
+        assert_eq!(
+            Some(EventType::CategoryMerged),
+            repository
+                .events()
+                .last()
+                .map(|(event_type, _)| *event_type)
+        );
+    }
+
+    #[test]
+    fn merging_is_rejected_unless_both_categories_can_be_merged() {
+        let repository = MemoryRepository::new();
+        for name in &["tops", "shirts", "blouses", "basics"] {
+            repository.insert_category(name).unwrap();
+        }
+        repository.insert_rollup(1, 4).unwrap();
+        repository.insert_rollup(4, 2).unwrap();
+        let rejected =
+            |source_id, target_id| match merge_categories(&repository, source_id, target_id, false)
+                .unwrap()
+            {
+                Restructuring::Rejected(message) => message,
+                outcome => panic!("Merge was not rejected: {:?}", outcome),
+            };
+
+        assert_eq!("A category can not be merged into itself", rejected(2, 2));
+        assert_eq!("Category 5 does not exist", rejected(2, 5));
+        assert_eq!(
+            "Merging would roll category 1 up into itself: 1 > 4 > 1",
+            rejected(2, 1)
+        );
+        assert_eq!(
+            Restructuring::UnknownCategory,
+            merge_categories(&repository, 5, 1, false).unwrap()
+        );
+        merge_categories(&repository, 3, 2, false).unwrap();
+        assert_eq!("Category 3 is archived", rejected(2, 3));
+        assert_eq!(vec![(1, 4), (4, 2)], repository.rollups().unwrap());
+    }
+
+    #[test]
+    fn dry_run_reports_the_merge_without_keeping_it() {
+        let repository = MemoryRepository::new();
+        repository.insert_category("T-Shirts").unwrap();
+        repository.insert_category("Tee Shirts").unwrap();
+        let shirt = repository.insert_product("red shirt").unwrap();
+        repository.classify(shirt.id, 1, true).unwrap();
+
+        let outcome = merge_categories(&repository, 1, 2, true).unwrap();
+
+        match outcome {
+            Restructuring::Done(report) => {
+                assert!(report.dry_run);
+                assert!(!report.committed);
+                assert_eq!(1, report.moved_classifications);
+            }
+            outcome => panic!("Merge was not previewed: {:?}", outcome),
+        }
+        assert_eq!(vec![(shirt.id, true)], classifications_of(&repository, 1));
+        assert!(!repository.category(1).unwrap().unwrap().is_archived);
+        assert!(repository.events().is_empty());
+    }
+
+    backend_test!(
+        categories_are_merged_after_a_preview,
+        |client, repository| {
+            let clothes = repository.insert_category("clothes")?;
+            let source = repository.insert_category("T-Shirts")?;
+            let target = repository.insert_category("Tee Shirts")?;
+            repository.insert_rollup(clothes.id, source.id)?;
+            let shirt = repository.insert_product("red shirt")?;
+            repository.classify(shirt.id, source.id, true)?;
+            repository.classify(shirt.id, target.id, false)?;
+            let post = |path: String, body: String| {
+                let mut response = client
+                    .post(path)
+                    .header(ContentType::JSON)
+                    .header(bearer_token(Role::Admin))
+                    .body(body)
+                    .dispatch();
+                (
+                    response.status(),
+                    response.body_string().unwrap_or_default(),
+                )
+            };
+            let merge_body = format!(r#"{{"target_id":{}}}"#, target.id);
+
+            let (preview_status, preview) = post(
+                format!("/productcategory/{}/merge?dry_run=true", source.id),
+                merge_body.clone(),
+            );
+            let descendants_after_preview = repository.descendants(clothes.id, None)?;
+            let (merge_status, merge) = post(
+                format!("/productcategory/{}/merge", source.id),
+                merge_body.clone(),
+            );
+            let (repeated_status, _) =
+                post(format!("/productcategory/{}/merge", source.id), merge_body);
+            let (missing_status, _) = post(
+                format!("/productcategory/{}/merge", target.id + 1),
+                format!(r#"{{"target_id":{}}}"#, target.id),
+            );
+
+            let preview: MergeReport = serde_json::from_str(&preview)?;
+            let merge: MergeReport = serde_json::from_str(&merge)?;
+            assert_eq!(Status::Ok, preview_status);
+            assert!(preview.dry_run && !preview.committed);
+            assert_eq!(1, descendants_after_preview.len());
+            assert_eq!(source.id, descendants_after_preview[0].id);
+            assert_eq!(Status::Ok, merge_status);
+            assert_eq!(
+                MergeReport {
+                    dry_run: false,
+                    committed: true,
+                    ..preview
+                },
+                merge
+            );
+            assert_eq!(
+                (0, 1, 1, 0),
+                (
+                    merge.moved_classifications,
+                    merge.merged_classifications,
+                    merge.moved_rollups,
+                    merge.dropped_rollups
+                )
+            );
+            assert_eq!(Status::BadRequest, repeated_status);
+            assert_eq!(Status::NotFound, missing_status);
+            let classifications = repository.classifications_in(&[source.id, target.id])?;
+            assert_eq!(1, classifications.len());
+            assert_eq!(target.id, classifications[0].product_category_id);
+            assert!(classifications[0].is_primary_classification);
+            let descendants = repository.descendants(clothes.id, None)?;
+            assert_eq!(
+                vec![target.id],
+                descendants
+                    .iter()
+                    .map(|category| category.id)
+                    .collect::<Vec<_>>()
+            );
+            assert!(repository.ancestors(source.id, None)?.is_empty());
+            assert!(repository
+                .category(source.id)?
+                .map_or(false, |source| source.is_archived));
+            Ok(())
+        }
+    );
+
+    backend_test!(merged_categories_are_counted_once, |client, repository| {
+        let clothes = repository.insert_category("clothes")?;
+        let source = repository.insert_category("knitwear")?;
+        let target = repository.insert_category("sweaters")?;
+        repository.insert_rollup(clothes.id, source.id)?;
+        repository.insert_rollup(clothes.id, target.id)?;
+        let cardigan = repository.insert_product("cardigan")?;
+        let pullover = repository.insert_product("pullover")?;
+        let vest = repository.insert_product("knitted vest")?;
+        repository.classify(cardigan.id, source.id, true)?;
+        repository.classify(vest.id, source.id, true)?;
+        repository.classify(vest.id, target.id, false)?;
+        repository.classify(pullover.id, target.id, true)?;
+        for product_id in &[cardigan.id, pullover.id, vest.id] {
+            repository.insert_inventory_item(*product_id, None, None)?;
+        }
+        let totals = |category_id: i32| -> Result<(i64, i64, i64), serde_json::Error> {
+            let mut response = client
+                .get(format!("/productcategory/{}/statistics", category_id))
+                .header(bearer_token(Role::Viewer))
+                .dispatch();
+            let statistics: CategoryStatistics =
+                serde_json::from_str(&response.body_string().unwrap_or_default())?;
+            Ok((
+                statistics.direct_products,
+                statistics.products,
+                statistics.units_in_stock,
+            ))
+        };
+        let before_merge = totals(clothes.id)?;
+
+        let merge_status = client
+            .post(format!("/productcategory/{}/merge", source.id))
+            .header(ContentType::JSON)
+            .header(bearer_token(Role::Admin))
+            .body(format!(r#"{{"target_id":{}}}"#, target.id))
+            .dispatch()
+            .status();
+
+        assert_eq!(Status::Ok, merge_status);
+        assert_eq!((0, 3, 3), before_merge);
+        assert_eq!(before_merge, totals(clothes.id)?);
+        assert_eq!((3, 3, 3), totals(target.id)?);
+        assert_eq!((0, 0, 0), totals(source.id)?);
+        Ok(())
+    });
+}
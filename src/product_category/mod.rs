@@ -1,4 +1,18 @@
+pub mod bulk;
 pub mod controllers;
 pub mod entities;
+pub mod hierarchy;
+pub mod merge;
+pub mod operations;
+pub mod slug;
+pub mod statistics;
+pub mod taxonomy;
+pub mod translations;
 
+pub use bulk::*;
 pub use controllers::*;
+pub use hierarchy::*;
+pub use merge::*;
+pub use statistics::*;
+pub use taxonomy::*;
+pub use translations::*;
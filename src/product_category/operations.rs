@@ -0,0 +1,131 @@
+//! Changes to product categories which are shared by the REST and GraphQL APIs
+//!
+//! Every operation runs in its own transaction and records the matching domain event.
+
+use super::entities::ProductCategory;
+use crate::{outbox::EventType, repository::CategoryRepository};
+use diesel::QueryResult;
+
+/// Returns the category with the name, creating it if it does not exist yet
+///
+/// The flag tells whether the category was created.
+pub fn create_or_get<R: CategoryRepository>(
+    repository: &R,
+    name: &str,
+) -> QueryResult<(ProductCategory, bool)> {
+    repository.atomically(
+        || match repository.categories_named(&[name])?.into_iter().next() {
+            Some(first_category) => Ok((first_category, false)),
+            None => {
+                let created_category = repository.insert_category(name)?;
+                repository.record(EventType::CategoryCreated, &created_category)?;
+                Ok((created_category, true))
+            }
+        },
+    )
+}
+
+/// Creates the category with its id, or renames it if it exists
+///
+/// Returns the saved category with its slug, and whether the category existed before.
+pub fn upsert<R: CategoryRepository>(
+    repository: &R,
+    category: &ProductCategory,
+) -> QueryResult<(ProductCategory, bool)> {
+    repository.atomically(|| {
+        let existed = repository.category(category.id)?.is_some();
+        repository.save_categories(std::slice::from_ref(category))?;
+        let saved_category = repository.category(category.id)?.ok_or(diesel::NotFound)?;
+        let event_type = if existed {
+            EventType::CategoryUpdated
+        } else {
+            EventType::CategoryCreated
+        };
+        repository.record(event_type, &saved_category)?;
+        Ok((saved_category, existed))
+    })
+}
+
+/// Deletes the category and returns whether it existed
+pub fn delete<R: CategoryRepository>(repository: &R, id: i32) -> QueryResult<bool> {
+    repository.atomically(|| {
+        let existed = !repository.delete_categories(&[id])?.is_empty();
+        if existed {
+            repository.record(EventType::CategoryDeleted, &serde_json::json!({ "id": id }))?;
+        }
+        Ok(existed)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_or_get, delete, upsert};
+    use crate::{
+        outbox::EventType,
+        product_category::entities::ProductCategory,
+        repository::{CategoryRepository, MemoryRepository},
+    };
+
+    fn event_types(repository: &MemoryRepository) -> Vec<EventType> {
+        repository
+            .events()
+            .into_iter()
+            .map(|(event_type, _)| event_type)
+            .collect()
+    }
+
+    #[test]
+    fn existing_category_is_returned_without_an_event() {
+        let repository = MemoryRepository::new();
+
+        let (created, was_created) = create_or_get(&repository, "shirts").unwrap();
+        let (existing, was_created_again) = create_or_get(&repository, "shirts").unwrap();
+
+        assert!(was_created && !was_created_again);
+        assert_eq!(created, existing);
+        assert_eq!(vec![EventType::CategoryCreated], event_types(&repository));
+    }
+
+    #[test]
+    fn upsert_renames_existing_categories() {
+        let repository = MemoryRepository::new();
+        let category = ProductCategory {
+            id: 7,
+            name: "Shirts".to_string(),
+            slug: String::new(),
+            description: None,
+            image_ref: None,
+            is_visible: true,
+            is_archived: false,
+        };
+
+        let (created, existed_before) = upsert(&repository, &category).unwrap();
+        let renamed = ProductCategory {
+            name: "Tops & Tees".to_string(),
+            ..category
+        };
+        let (saved, existed_after) = upsert(&repository, &renamed).unwrap();
+
+        assert!(!existed_before && existed_after);
+        assert_eq!("shirts", created.slug);
+        assert_eq!("tops-tees", saved.slug);
+        assert_eq!(vec![saved], repository.categories().unwrap());
+        assert_eq!(
+            vec![EventType::CategoryCreated, EventType::CategoryUpdated],
+            event_types(&repository)
+        );
+    }
+
+    #[test]
+    fn deleting_a_referenced_category_changes_nothing() {
+        let repository = MemoryRepository::new();
+        let (clothes, _) = create_or_get(&repository, "clothes").unwrap();
+        let (shirts, _) = create_or_get(&repository, "shirts").unwrap();
+        repository.insert_rollup(clothes.id, shirts.id).unwrap();
+
+        assert!(delete(&repository, shirts.id).is_err());
+        assert!(!delete(&repository, 99).unwrap());
+        assert_eq!(2, repository.categories().unwrap().len());
+        assert_eq!(2, repository.events().len());
+    }
+}
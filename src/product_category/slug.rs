@@ -0,0 +1,82 @@
+//! Slugs which name categories in URLs like `/clothes/swimwear/bikinis`
+//!
+//! A slug is derived from the name of its category, and categories rolled up into the same
+//! category, or root categories among themselves, never share a slug. Names which only differ in
+//! punctuation or case are told apart by a number, so "T-Shirts" and "t shirts" become
+//! `t-shirts` and `t-shirts-2`.
+
+/// Slug used for names without any letter or digit
+const FALLBACK: &str = "category";
+
+/// Lowercases the name and joins its words with dashes
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for word in name
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.extend(word.chars().flat_map(char::to_lowercase));
+    }
+    if slug.is_empty() {
+        slug.push_str(FALLBACK);
+    }
+    slug
+}
+
+/// Chooses the slug of a category with the name, which none of its siblings has taken
+///
+/// The current slug is kept as long as it still belongs to the name, so that links stay valid
+/// when a sibling with a similar name disappears.
+pub fn choose(name: &str, current: &str, taken: &[String]) -> String {
+    let base = slugify(name);
+    let is_free = |slug: &str| !taken.iter().any(|taken| taken == slug);
+    if belongs_to(current, &base) && is_free(current) {
+        return current.to_string();
+    }
+    if is_free(&base) {
+        return base;
+    }
+    (2..)
+        .map(|number| format!("{}-{}", base, number))
+        .find(|slug| is_free(slug))
+        .unwrap()
+}
+
+/// Whether the slug is the base slug of a name or the base slug with a number
+fn belongs_to(slug: &str, base: &str) -> bool {
+    match slug.strip_prefix(base) {
+        Some("") => true,
+        Some(suffix) => suffix
+            .strip_prefix('-')
+            .filter(|number| !number.starts_with('0'))
+            .and_then(|number| number.parse::<u32>().ok())
+            .map_or(false, |number| number >= 2),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{choose, slugify};
+
+    #[test]
+    fn names_are_turned_into_lowercase_words() {
+        assert_eq!("swimwear", slugify("Swimwear"));
+        assert_eq!("t-shirts-tops", slugify("  T-Shirts & Tops!"));
+        assert_eq!("röcke", slugify("Röcke"));
+        assert_eq!("category", slugify("***"));
+    }
+
+    #[test]
+    fn taken_slugs_are_numbered() {
+        let taken = vec!["t-shirts".to_string(), "t-shirts-2".to_string()];
+
+        assert_eq!("t-shirts-3", choose("T Shirts", "", &taken));
+        assert_eq!("t-shirts-2", choose("T Shirts", "t-shirts-2", &taken[..1]));
+        assert_eq!("tops", choose("Tops", "t-shirts-2", &taken[..1]));
+        assert_eq!("t-shirts", choose("T Shirts", "t-shirts-07", &[]));
+    }
+}
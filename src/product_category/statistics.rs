@@ -0,0 +1,291 @@
+//! Product counts and stock of the categories, as shown on category pages
+//!
+//! The numbers are aggregated over the whole hierarchy below a category, which gets expensive
+//! for large catalogs. They can therefore be read from a snapshot instead, which is taken when
+//! it is first needed and replaced whenever it is refreshed.
+
+use crate::{
+    auth::{Admin, Authorized, Viewer},
+    repository::{BackendConn, StatisticsRepository},
+    utilities::{DbError, GetResponder},
+};
+use chrono::{DateTime, Utc};
+use diesel::{
+    sql_types::{BigInt, Integer, Nullable, Text},
+    QueryResult,
+};
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Units in stock in a warehouse
+///
+/// Items which are not assigned to any warehouse are reported without a warehouse.
+pub struct WarehouseStock {
+    pub warehouse_id: Option<i32>,
+    pub warehouse_description: Option<String>,
+    pub quantity: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Number of products in a category and the units of them in stock
+pub struct CategoryStatistics {
+    pub category_id: i32,
+    /// Products classified into the category itself
+    pub direct_products: i64,
+    /// Products classified into the category or any category below it which is not archived,
+    /// each counted once
+    pub products: i64,
+    /// Units of those products in stock in all warehouses
+    pub units_in_stock: i64,
+    /// Units of those products in stock per warehouse, ordered by warehouse
+    pub stock: Vec<WarehouseStock>,
+    /// Time at which the snapshot was taken, if the numbers were read from it
+    #[schemars(with = "Option<String>")]
+    pub refreshed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, QueryableByName, Debug)]
+/// Product counts of a category, as loaded by the database backends
+pub struct CategoryCounts {
+    #[sql_type = "Integer"]
+    pub category_id: i32,
+    #[sql_type = "BigInt"]
+    pub direct_products: i64,
+    #[sql_type = "BigInt"]
+    pub products: i64,
+}
+
+#[derive(Queryable, QueryableByName, Debug)]
+/// Stock of a category in a warehouse, as loaded by the database backends
+pub struct CategoryStock {
+    #[sql_type = "Integer"]
+    pub category_id: i32,
+    #[sql_type = "Nullable<Integer>"]
+    pub warehouse_id: Option<i32>,
+    #[sql_type = "Nullable<Text>"]
+    pub warehouse_description: Option<String>,
+    #[sql_type = "BigInt"]
+    pub quantity: i64,
+}
+
+/// Combines the counts of the categories with their stock, keeping the order of both
+///
+/// The time the snapshot was taken is given for numbers read from it.
+pub fn assemble(
+    counts: Vec<CategoryCounts>,
+    stock: Vec<CategoryStock>,
+    refreshed_at: Option<DateTime<Utc>>,
+) -> Vec<CategoryStatistics> {
+    let mut stock_of_category: BTreeMap<i32, Vec<WarehouseStock>> = BTreeMap::new();
+    for row in stock {
+        stock_of_category
+            .entry(row.category_id)
+            .or_default()
+            .push(WarehouseStock {
+                warehouse_id: row.warehouse_id,
+                warehouse_description: row.warehouse_description,
+                quantity: row.quantity,
+            });
+    }
+    counts
+        .into_iter()
+        .map(|counts| {
+            let stock = stock_of_category
+                .remove(&counts.category_id)
+                .unwrap_or_default();
+            CategoryStatistics {
+                category_id: counts.category_id,
+                direct_products: counts.direct_products,
+                products: counts.products,
+                units_in_stock: stock.iter().map(|stock| stock.quantity).sum(),
+                stock,
+                refreshed_at,
+            }
+        })
+        .collect()
+}
+
+/// Statistics of all categories, ordered by category id
+///
+/// With `cached`, they are read from the snapshot, which is taken first if there is none.
+pub fn all_statistics<R: StatisticsRepository>(
+    repository: &R,
+    cached: bool,
+) -> QueryResult<Vec<CategoryStatistics>> {
+    if !cached {
+        return repository.category_statistics();
+    }
+    let stored = repository.stored_category_statistics()?;
+    if stored.is_empty() {
+        return refresh(repository);
+    }
+    Ok(stored)
+}
+
+/// Statistics of the category, or `None` if it does not exist
+///
+/// Only the subtree of the category is counted. In the cached mode, categories which are not
+/// part of the snapshot, because it was not taken yet or they were created after it, are counted
+/// live.
+pub fn statistics_of<R: StatisticsRepository>(
+    repository: &R,
+    category_id: i32,
+    cached: bool,
+) -> QueryResult<Option<CategoryStatistics>> {
+    if cached {
+        if let Some(stored) = repository.stored_category_statistics_of(category_id)? {
+            return Ok(Some(stored));
+        }
+    }
+    repository.category_statistics_of(category_id)
+}
+
+/// Replaces the snapshot with the current statistics and returns them
+pub fn refresh<R: StatisticsRepository>(repository: &R) -> QueryResult<Vec<CategoryStatistics>> {
+    repository.atomically(|| {
+        let statistics = repository.category_statistics()?;
+        repository.store_category_statistics(&statistics)?;
+        repository.stored_category_statistics()
+    })
+}
+
+/// Lists the product counts and stock of every category
+#[get("/statistics?<cached>")]
+pub fn get_all_statistics(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    cached: Option<bool>,
+) -> Result<Json<Vec<CategoryStatistics>>, DbError> {
+    Ok(Json(all_statistics(&db_conn, cached.unwrap_or(false))?))
+}
+
+#[get("/<product_category_id>/statistics?<cached>")]
+pub fn get_statistics(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    product_category_id: i32,
+    cached: Option<bool>,
+) -> Result<GetResponder<CategoryStatistics>, DbError> {
+    match statistics_of(&db_conn, product_category_id, cached.unwrap_or(false))? {
+        Some(statistics) => Ok(GetResponder::Found(Json(statistics))),
+        None => Ok(GetResponder::NotFound(())),
+    }
+}
+
+/// Takes a new snapshot of the statistics for the cached mode
+#[post("/statistics/refresh")]
+pub fn post_statistics_refresh(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+) -> Result<Json<Vec<CategoryStatistics>>, DbError> {
+    Ok(Json(refresh(&db_conn)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CategoryStatistics;
+    use crate::{
+        auth::Role,
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::http::Status;
+
+    backend_test!(
+        cached_statistics_are_kept_until_refreshed,
+        |client, repository| {
+            let clothes = repository.insert_category("clothes")?;
+            let shirts = repository.insert_category("shirts")?;
+            repository.insert_rollup(clothes.id, shirts.id)?;
+            let shirt = repository.insert_product("white shirt")?;
+            repository.classify(shirt.id, shirts.id, true)?;
+            let north = repository.insert_warehouse("north")?;
+            repository.insert_inventory_item(shirt.id, Some(north.id), None)?;
+            let request = |post: bool, path: String, role: Role| {
+                let request = if post {
+                    client.post(path)
+                } else {
+                    client.get(path)
+                };
+                let mut response = request.header(bearer_token(role)).dispatch();
+                (
+                    response.status(),
+                    response.body_string().unwrap_or_default(),
+                )
+            };
+            let units_of_clothes = |body: &str| -> (i64, i64, bool) {
+                let statistics: Vec<CategoryStatistics> = serde_json::from_str(body).unwrap();
+                statistics
+                    .into_iter()
+                    .find(|statistics| statistics.category_id == clothes.id)
+                    .map(|statistics| {
+                        (
+                            statistics.products,
+                            statistics.units_in_stock,
+                            statistics.refreshed_at.is_some(),
+                        )
+                    })
+                    .unwrap()
+            };
+
+            let (_, first_cached) = request(
+                false,
+                "/productcategory/statistics?cached=true".to_string(),
+                Role::Viewer,
+            );
+            repository.insert_inventory_item(shirt.id, None, None)?;
+            let (_, second_cached) = request(
+                false,
+                "/productcategory/statistics?cached=true".to_string(),
+                Role::Viewer,
+            );
+            let (live_status, live) = request(
+                false,
+                "/productcategory/statistics".to_string(),
+                Role::Viewer,
+            );
+            let (forbidden_status, _) = request(
+                true,
+                "/productcategory/statistics/refresh".to_string(),
+                Role::Viewer,
+            );
+            let (refresh_status, refreshed) = request(
+                true,
+                "/productcategory/statistics/refresh".to_string(),
+                Role::Admin,
+            );
+            let (_, shirts_statistics) = request(
+                false,
+                format!("/productcategory/{}/statistics?cached=true", shirts.id),
+                Role::Viewer,
+            );
+            let (missing_status, _) = request(
+                false,
+                format!("/productcategory/{}/statistics", shirts.id + 1),
+                Role::Viewer,
+            );
+
+            assert_eq!((1, 1, true), units_of_clothes(&first_cached));
+            assert_eq!((1, 1, true), units_of_clothes(&second_cached));
+            assert_eq!(Status::Ok, live_status);
+            assert_eq!((1, 2, false), units_of_clothes(&live));
+            assert_eq!(Status::Forbidden, forbidden_status);
+            assert_eq!(Status::Ok, refresh_status);
+            assert_eq!((1, 2, true), units_of_clothes(&refreshed));
+            let shirts_statistics: CategoryStatistics = serde_json::from_str(&shirts_statistics)?;
+            assert_eq!(
+                (1, 1, 2),
+                (
+                    shirts_statistics.direct_products,
+                    shirts_statistics.products,
+                    shirts_statistics.units_in_stock
+                )
+            );
+            assert_eq!(2, shirts_statistics.stock.len());
+            assert_eq!(Status::NotFound, missing_status);
+            Ok(())
+        }
+    );
+}
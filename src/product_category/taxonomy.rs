@@ -0,0 +1,629 @@
+//! Import and export of the category tree as a taxonomy
+//!
+//! Two formats are understood. The line format of the Google Product Taxonomy lists the path of
+//! every category, like `Apparel & Accessories > Clothing > Shirts & Tops`, optionally preceded
+//! by an id as in `212 - Apparel & Accessories > Clothing > Shirts & Tops`. Names which would be
+//! misread in a line are escaped with backslashes, like `Shoes \> 40 EUR`. The JSON format nests
+//! the categories as `{"name": ..., "children": [...]}` nodes.
+//!
+//! Both formats list the paths in pre-order, roots by id and subcategories by their position,
+//! so that importing an export into an empty catalog and exporting again gives the same tree.
+//! Archived categories are not exported.
+
+use super::{
+    hierarchy::cycles_in,
+    merge::{commit_unless_dry_run, respond, Restructuring, RestructuringResponse},
+    slug::slugify,
+};
+use crate::{
+    auth::{Admin, Authorized, Viewer},
+    idempotency::{Idempotency, Idempotent},
+    outbox::EventType,
+    repository::{BackendConn, CategoryRepository},
+    utilities::{DbError, UploadText},
+};
+use diesel::QueryResult;
+use rocket::{
+    http::ContentType,
+    response::{content::Content, status::BadRequest},
+};
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Separator of the categories in a path of the line format
+const SEPARATOR: &str = " > ";
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+/// Category of a taxonomy with the categories rolled up into it
+pub struct TaxonomyNode {
+    pub name: String,
+    #[serde(default)]
+    pub children: Vec<TaxonomyNode>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Default)]
+/// Changes made by importing a taxonomy, or which a dry run would have made
+pub struct TaxonomyImportReport {
+    pub dry_run: bool,
+    pub committed: bool,
+    /// Paths listed by the taxonomy
+    pub paths: usize,
+    /// Categories which did not exist yet
+    pub created: usize,
+    /// Existing categories found by their name or by their path
+    pub matched: usize,
+    /// Links added between a category and the category above it
+    pub linked: usize,
+}
+
+/// Paths of the categories in a file of the line format
+///
+/// Blank lines and comments starting with `#` are skipped. A backslash makes the next character
+/// part of the name, so that names may hold `>`, start with `#` or a digit, or keep whitespace
+/// at either end; `\n` and `\r` stand for line breaks.
+pub fn parse_lines(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut paths = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim_start();
+        if line.trim_end().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = match line.split_once(" - ") {
+            Some((id, path)) if id.chars().all(|c| c.is_ascii_digit()) => path,
+            _ => line,
+        };
+        let path = split_path(line);
+        if path.iter().any(String::is_empty) {
+            return Err(format!("Line {}: category name is empty", index + 1));
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Names of a path in the line format, with their escapes resolved and unescaped whitespace at
+/// either end removed
+fn split_path(line: &str) -> Vec<String> {
+    fn trimmed(name: &[(char, bool)]) -> String {
+        let is_padding = |(c, escaped): &(char, bool)| !escaped && c.is_whitespace();
+        let start = name
+            .iter()
+            .position(|c| !is_padding(c))
+            .unwrap_or(name.len());
+        let end = name
+            .iter()
+            .rposition(|c| !is_padding(c))
+            .map_or(start, |end| end + 1);
+        name[start..end].iter().map(|(c, _)| c).collect()
+    }
+
+    let mut names = Vec::new();
+    // Characters of the current name, and whether they were escaped
+    let mut name: Vec<(char, bool)> = Vec::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some(escaped) => escaped,
+                    None => '\\',
+                };
+                name.push((escaped, true));
+            }
+            '>' => {
+                names.push(trimmed(&name));
+                name.clear();
+            }
+            c => name.push((c, false)),
+        }
+    }
+    names.push(trimmed(&name));
+    names
+}
+
+/// The name as it is written in the line format, so that [`parse_lines`] reads it back unchanged
+///
+/// The first name of a line also has a leading `#` or digit escaped, which would otherwise start
+/// a comment or an id.
+fn escape_name(name: &str, first: bool) -> String {
+    let last = name.chars().count().saturating_sub(1);
+    let mut escaped = String::with_capacity(name.len());
+    for (index, c) in name.chars().enumerate() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' | '>' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if (c.is_whitespace() && (index == 0 || index == last))
+                || (first && index == 0 && (c == '#' || c.is_ascii_digit())) =>
+            {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Paths of all categories of the tree, in pre-order
+pub fn paths_of(tree: &[TaxonomyNode]) -> Vec<Vec<String>> {
+    fn visit(node: &TaxonomyNode, path: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+        path.push(node.name.clone());
+        paths.push(path.clone());
+        for child in &node.children {
+            visit(child, path, paths);
+        }
+        path.pop();
+    }
+
+    let mut paths = Vec::new();
+    for root in tree {
+        visit(root, &mut Vec::new(), &mut paths);
+    }
+    paths
+}
+
+/// The tree in the line format, one path per line
+pub fn to_lines(tree: &[TaxonomyNode]) -> String {
+    let mut lines = String::new();
+    for path in paths_of(tree) {
+        let names: Vec<String> = path
+            .iter()
+            .enumerate()
+            .map(|(index, name)| escape_name(name, index == 0))
+            .collect();
+        lines.push_str(&names.join(SEPARATOR));
+        lines.push('\n');
+    }
+    lines
+}
+
+/// The categories which are not archived as a tree
+///
+/// A category with several parents appears below each of them. Links which would close a cycle
+/// are not followed.
+pub fn export_tree<R: CategoryRepository>(repository: &R) -> QueryResult<Vec<TaxonomyNode>> {
+    let names: BTreeMap<i32, String> = repository
+        .categories()?
+        .into_iter()
+        .filter(|category| !category.is_archived)
+        .map(|category| (category.id, category.name))
+        .collect();
+    let mut lower_categories: HashMap<i32, Vec<i32>> = HashMap::new();
+    let mut rolled_up = HashSet::new();
+    for (upper, lower) in repository.rollups()? {
+        if names.contains_key(&upper) && names.contains_key(&lower) {
+            lower_categories.entry(upper).or_default().push(lower);
+            rolled_up.insert(lower);
+        }
+    }
+
+    fn node(
+        id: i32,
+        names: &BTreeMap<i32, String>,
+        lower_categories: &HashMap<i32, Vec<i32>>,
+        path: &mut Vec<i32>,
+    ) -> TaxonomyNode {
+        path.push(id);
+        let mut children = Vec::new();
+        for lower in lower_categories.get(&id).map_or(&[][..], Vec::as_slice) {
+            if !path.contains(lower) {
+                children.push(node(*lower, names, lower_categories, path));
+            }
+        }
+        path.pop();
+        TaxonomyNode {
+            name: names[&id].clone(),
+            children,
+        }
+    }
+
+    Ok(names
+        .keys()
+        .filter(|id| !rolled_up.contains(id))
+        .map(|id| node(*id, &names, &lower_categories, &mut Vec::new()))
+        .collect())
+}
+
+/// Creates the categories of the paths which do not exist yet and links every category to the
+/// one above it in the path
+///
+/// A category of a path is the category with the same name, or else the category below the
+/// previous one in the path, or the root category, with the slug of the name. Linking a
+/// category found by its name may give it another parent.
+pub fn import_taxonomy<R: CategoryRepository>(
+    repository: &R,
+    paths: &[Vec<String>],
+    dry_run: bool,
+) -> QueryResult<Restructuring<TaxonomyImportReport>> {
+    let (mut outcome, committed) =
+        commit_unless_dry_run(repository, dry_run, || import(repository, paths))?;
+    if let Restructuring::Done(report) = &mut outcome {
+        report.dry_run = dry_run;
+        report.committed = committed;
+    }
+    Ok(outcome)
+}
+
+fn import<R: CategoryRepository>(
+    repository: &R,
+    paths: &[Vec<String>],
+) -> QueryResult<Restructuring<TaxonomyImportReport>> {
+    let mut report = TaxonomyImportReport {
+        paths: paths.len(),
+        ..TaxonomyImportReport::default()
+    };
+    let mut resolved: HashMap<&[String], i32> = HashMap::new();
+    let mut found = HashSet::new();
+    let mut new_links = Vec::new();
+    for path in paths {
+        let mut upper = None;
+        for depth in 0..path.len() {
+            let prefix = &path[..=depth];
+            if let Some(id) = resolved.get(prefix) {
+                upper = Some(*id);
+                continue;
+            }
+            let name = &path[depth];
+            let id = match find_category(repository, name, upper)? {
+                Some(category) if category.is_archived => {
+                    return Ok(Restructuring::Rejected(format!(
+                        "Category {} is archived",
+                        category.id
+                    )))
+                }
+                Some(category) => {
+                    if found.insert(category.id) {
+                        report.matched += 1;
+                    }
+                    category.id
+                }
+                None => {
+                    let category = repository.insert_category(name)?;
+                    repository.record(EventType::CategoryCreated, &category)?;
+                    found.insert(category.id);
+                    report.created += 1;
+                    category.id
+                }
+            };
+            if let Some(upper) = upper {
+                if !repository.rollups_of(&[id])?.contains(&(upper, id)) {
+                    repository.insert_rollup(upper, id)?;
+                    new_links.push((upper, id));
+                    report.linked += 1;
+                }
+            }
+            resolved.insert(prefix, id);
+            upper = Some(id);
+        }
+    }
+
+    let new_cycle = cycles_in(&repository.rollups()?).into_iter().find(|cycle| {
+        cycle
+            .windows(2)
+            .any(|link| new_links.contains(&(link[0], link[1])))
+    });
+    if let Some(cycle) = new_cycle {
+        let path: Vec<String> = cycle.iter().map(i32::to_string).collect();
+        return Ok(Restructuring::Rejected(format!(
+            "Importing would roll category {} up into itself: {}",
+            cycle[0],
+            path.join(" > ")
+        )));
+    }
+    Ok(Restructuring::Done(report))
+}
+
+fn find_category<R: CategoryRepository>(
+    repository: &R,
+    name: &str,
+    upper: Option<i32>,
+) -> QueryResult<Option<super::entities::ProductCategory>> {
+    if let Some(category) = repository.categories_named(&[name])?.into_iter().next() {
+        return Ok(Some(category));
+    }
+    let slug = slugify(name);
+    let uppers: Vec<i32> = upper.into_iter().collect();
+    match repository
+        .slugs_below(&uppers)?
+        .into_iter()
+        .find(|(_, taken)| *taken == slug)
+    {
+        Some((id, _)) => repository.category(id),
+        None => Ok(None),
+    }
+}
+
+/// Exports the category tree in the line format, or as JSON with `format=json`
+#[get("/taxonomy?<format>")]
+pub fn get_taxonomy(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    format: Option<String>,
+) -> Result<Result<Content<String>, BadRequest<String>>, DbError> {
+    let tree = export_tree(&db_conn)?;
+    match format.as_deref().unwrap_or("text") {
+        "text" => Ok(Ok(Content(ContentType::Plain, to_lines(&tree)))),
+        "json" => Ok(Ok(Content(
+            ContentType::JSON,
+            serde_json::to_string(&tree)
+                .map_err(|error| diesel::result::Error::SerializationError(Box::new(error)))?,
+        ))),
+        format => Ok(Err(BadRequest(Some(format!(
+            "Unknown taxonomy format {}, expected text or json",
+            format
+        ))))),
+    }
+}
+
+/// Imports a taxonomy in the line format
+#[post("/taxonomy?<dry_run>", format = "text/plain", data = "<text>")]
+pub fn post_taxonomy_lines(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    idempotency: Idempotency,
+    dry_run: Option<bool>,
+    text: UploadText,
+) -> Idempotent<RestructuringResponse<TaxonomyImportReport>> {
+    let UploadText(text) = text;
+    idempotency.run(&db_conn, &text, || {
+        let paths = match parse_lines(&text) {
+            Ok(paths) => paths,
+            Err(message) => return Ok(Err(BadRequest(Some(message)))),
+        };
+        respond(import_taxonomy(&db_conn, &paths, dry_run.unwrap_or(false))?)
+    })
+}
+
+/// Imports a taxonomy in the JSON format
+#[post("/taxonomy?<dry_run>", format = "json", data = "<tree>")]
+pub fn post_taxonomy_tree(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    idempotency: Idempotency,
+    dry_run: Option<bool>,
+    tree: Json<Vec<TaxonomyNode>>,
+) -> Idempotent<RestructuringResponse<TaxonomyImportReport>> {
+    idempotency.run(&db_conn, &*tree, || {
+        let paths = paths_of(&tree);
+        if paths.iter().flatten().any(|name| name.trim().is_empty()) {
+            return Ok(Err(BadRequest(Some("Category name is empty".to_string()))));
+        }
+        respond(import_taxonomy(&db_conn, &paths, dry_run.unwrap_or(false))?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        export_tree, import_taxonomy, parse_lines, paths_of, to_lines, TaxonomyImportReport,
+        TaxonomyNode,
+    };
+    use crate::{
+        auth::Role,
+        product_category::Restructuring,
+        repository::{CategoryRepository, MemoryRepository},
+        test_utils::{backend_test, bearer_token},
+        utilities::DEFAULT_UPLOAD_LIMIT,
+    };
+    use rocket::http::{ContentType, Status};
+
+    fn import(repository: &MemoryRepository, text: &str, dry_run: bool) -> TaxonomyImportReport {
+        match import_taxonomy(repository, &parse_lines(text).unwrap(), dry_run).unwrap() {
+            Restructuring::Done(report) => report,
+            outcome => panic!("Taxonomy was not imported: {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn google_taxonomy_lines_are_parsed() {
+        let text = "# Google_Product_Taxonomy_Version: 2021-09-21\n\
+                    166 - Apparel & Accessories\n\
+                    \n\
+                    1604 - Apparel & Accessories > Clothing\n\
+                    Apparel & Accessories>Clothing > Shirts & Tops\n";
+
+        assert_eq!(
+            vec![
+                vec!["Apparel & Accessories"],
+                vec!["Apparel & Accessories", "Clothing"],
+                vec!["Apparel & Accessories", "Clothing", "Shirts & Tops"],
+            ],
+            parse_lines(text).unwrap()
+        );
+        assert_eq!(
+            Err("Line 2: category name is empty".to_string()),
+            parse_lines("Apparel\nApparel >  > Shirts")
+        );
+    }
+
+    #[test]
+    fn names_which_look_like_syntax_round_trip_through_lines() {
+        let leaf = |name: &str| TaxonomyNode {
+            name: name.to_string(),
+            children: Vec::new(),
+        };
+        let tree = vec![
+            TaxonomyNode {
+                name: "212 - Apparel".to_string(),
+                children: vec![leaf("Shoes > 40 EUR"), leaf("#1 hits"), leaf(" padded ")],
+            },
+            leaf("# not a comment"),
+            leaf("back\\slash\\"),
+            leaf("two\nlines"),
+        ];
+
+        let lines = to_lines(&tree);
+
+        assert_eq!(7, lines.lines().count());
+        assert_eq!(Ok(paths_of(&tree)), parse_lines(&lines));
+    }
+
+    #[test]
+    fn exported_tree_is_imported_into_the_same_tree() {
+        let repository = MemoryRepository::new();
+        for name in &["clothes", "sale", "shirts", "hats", "v-necks", "old"] {
+            repository.insert_category(name).unwrap();
+        }
+        for (upper, lower) in &[(1, 4), (1, 3), (3, 5), (2, 3), (1, 6)] {
+            repository.insert_rollup(*upper, *lower).unwrap();
+        }
+        repository.archive_category(6).unwrap();
+        let tree = export_tree(&repository).unwrap();
+
+        let lines = MemoryRepository::new();
+        import(&lines, &to_lines(&tree), false);
+        let json = MemoryRepository::new();
+        import_taxonomy(&json, &paths_of(&tree), false).unwrap();
+
+        assert_eq!(
+            "clothes\nclothes > hats\nclothes > shirts\nclothes > shirts > v-necks\n\
+             sale\nsale > shirts\nsale > shirts > v-necks\n",
+            to_lines(&tree)
+        );
+        assert_eq!(tree, export_tree(&lines).unwrap());
+        assert_eq!(tree, export_tree(&json).unwrap());
+    }
+
+    #[test]
+    fn existing_categories_are_matched_by_name_or_path() {
+        let repository = MemoryRepository::new();
+        let apparel = repository.insert_category("Apparel").unwrap();
+        let shirts = repository.insert_category("T-Shirts").unwrap();
+        repository.insert_rollup(apparel.id, shirts.id).unwrap();
+
+        let preview = import(&repository, "Apparel > T Shirts > V-Necks", true);
+        let report = import(
+            &repository,
+            "Apparel > T Shirts > V-Necks\nSale > T-Shirts",
+            false,
+        );
+
+        assert_eq!(
+            TaxonomyImportReport {
+                dry_run: true,
+                committed: false,
+                paths: 1,
+                created: 1,
+                matched: 2,
+                linked: 1,
+            },
+            preview
+        );
+        assert_eq!(
+            TaxonomyImportReport {
+                dry_run: false,
+                committed: true,
+                paths: 2,
+                created: 2,
+                matched: 2,
+                linked: 2,
+            },
+            report
+        );
+        assert_eq!(4, repository.categories().unwrap().len());
+        assert_eq!(vec![(1, 2), (2, 3), (4, 2)], repository.rollups().unwrap());
+    }
+
+    #[test]
+    fn imports_which_close_a_cycle_are_rejected() {
+        let repository = MemoryRepository::new();
+        import(&repository, "clothes > shirts", false);
+
+        let outcome = import_taxonomy(
+            &repository,
+            &parse_lines("shirts > clothes").unwrap(),
+            false,
+        );
+
+        assert_eq!(
+            Restructuring::Rejected(
+                "Importing would roll category 1 up into itself: 1 > 2 > 1".to_string()
+            ),
+            outcome.unwrap()
+        );
+        assert_eq!(vec![(1, 2)], repository.rollups().unwrap());
+    }
+
+    backend_test!(
+        taxonomy_is_exchanged_as_lines_and_json,
+        |client, repository| {
+            repository.insert_category("Apparel")?;
+            let post = |content_type: ContentType, body: &str, role: Role| {
+                let mut response = client
+                    .post("/productcategory/taxonomy")
+                    .header(content_type)
+                    .header(bearer_token(role))
+                    .body(body)
+                    .dispatch();
+                (
+                    response.status(),
+                    response.body_string().unwrap_or_default(),
+                )
+            };
+            let get = |query: &str| {
+                let mut response = client
+                    .get(format!("/productcategory/taxonomy{}", query))
+                    .header(bearer_token(Role::Viewer))
+                    .dispatch();
+                (
+                    response.status(),
+                    response.content_type(),
+                    response.body_string().unwrap_or_default(),
+                )
+            };
+
+            let (forbidden_status, _) = post(ContentType::Plain, "Apparel > Shirts", Role::Viewer);
+            let (lines_status, lines_report) = post(
+                ContentType::Plain,
+                "1 - Apparel\n2 - Apparel > Shirts\n",
+                Role::Admin,
+            );
+            let (tree_status, _) = post(
+                ContentType::JSON,
+                r#"[{"name": "Apparel", "children": [{"name": "Hats"}]}]"#,
+                Role::Admin,
+            );
+            let (empty_status, _) = post(ContentType::Plain, "Apparel > ", Role::Admin);
+            let (oversized_status, _) = post(
+                ContentType::Plain,
+                &"Apparel > Socks\n".repeat(DEFAULT_UPLOAD_LIMIT as usize / 16 + 1),
+                Role::Admin,
+            );
+            let (text_status, text_type, text) = get("");
+            let (json_status, json_type, json) = get("?format=json");
+            let (unknown_status, _, _) = get("?format=xml");
+
+            assert_eq!(Status::Forbidden, forbidden_status);
+            assert_eq!(Status::Ok, lines_status);
+            let lines_report: TaxonomyImportReport = serde_json::from_str(&lines_report)?;
+            assert_eq!(
+                (1, 1, 1),
+                (
+                    lines_report.created,
+                    lines_report.matched,
+                    lines_report.linked
+                )
+            );
+            assert_eq!(Status::Ok, tree_status);
+            assert_eq!(Status::BadRequest, empty_status);
+            assert_eq!(Status::PayloadTooLarge, oversized_status);
+            assert_eq!(Status::Ok, text_status);
+            assert_eq!(Some(ContentType::Plain), text_type);
+            assert_eq!("Apparel\nApparel > Shirts\nApparel > Hats\n", text);
+            assert_eq!(Status::Ok, json_status);
+            assert_eq!(Some(ContentType::JSON), json_type);
+            let tree: Vec<TaxonomyNode> = serde_json::from_str(&json)?;
+            assert_eq!(text, to_lines(&tree));
+            assert_eq!(Status::BadRequest, unknown_status);
+            Ok(())
+        }
+    );
+}
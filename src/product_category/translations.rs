@@ -0,0 +1,588 @@
+//! Names of the categories in the locales of the shops
+//!
+//! Names are unique within every locale, counting the categories whose name is not translated
+//! into the locale by their name in the default locale, as that is the name they are shown with.
+
+use super::entities::{
+    Breadcrumb, CategoryTree, CategoryWithBreadcrumbs, ProductCategory, RelatedCategory,
+};
+use crate::{
+    auth::{Admin, Authorized, Viewer},
+    localization::{entities::CategoryTranslation, normalize, Locale, Locales},
+    outbox::EventType,
+    repository::{BackendConn, CategoryRepository},
+    utilities::{DbError, GetResponder},
+};
+use diesel::{
+    result::{DatabaseErrorKind, Error},
+    QueryResult,
+};
+use rocket::{
+    http::Status,
+    response::status::{BadRequest, Conflict},
+    State,
+};
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct CategoryTranslationBody {
+    pub name: String,
+}
+
+/// Names of the categories translated into a locale
+///
+/// Empty for the default locale, whose names are those of the categories themselves.
+pub struct CategoryNames(HashMap<i32, String>);
+
+impl CategoryNames {
+    pub fn load<R: CategoryRepository>(repository: &R, locale: &Locale) -> QueryResult<Self> {
+        if locale.is_default() {
+            return Ok(CategoryNames(HashMap::new()));
+        }
+        Ok(CategoryNames(
+            repository
+                .category_translations(locale.as_str())?
+                .into_iter()
+                .map(|translation| (translation.category_id, translation.name))
+                .collect(),
+        ))
+    }
+
+    fn translate(&self, category_id: i32, name: &mut String) {
+        if let Some(translated) = self.0.get(&category_id) {
+            name.clone_from(translated);
+        }
+    }
+}
+
+/// Values holding names of categories, which are replaced by the names in a locale
+pub trait Localize {
+    fn localize(&mut self, names: &CategoryNames);
+}
+
+impl Localize for ProductCategory {
+    fn localize(&mut self, names: &CategoryNames) {
+        names.translate(self.id, &mut self.name);
+    }
+}
+
+impl Localize for RelatedCategory {
+    fn localize(&mut self, names: &CategoryNames) {
+        names.translate(self.id, &mut self.name);
+    }
+}
+
+impl Localize for Breadcrumb {
+    fn localize(&mut self, names: &CategoryNames) {
+        names.translate(self.id, &mut self.name);
+    }
+}
+
+impl Localize for CategoryWithBreadcrumbs {
+    fn localize(&mut self, names: &CategoryNames) {
+        self.category.localize(names);
+        self.breadcrumbs.localize(names);
+    }
+}
+
+impl Localize for CategoryTree {
+    fn localize(&mut self, names: &CategoryNames) {
+        names.translate(self.id, &mut self.name);
+        self.subcategories.localize(names);
+    }
+}
+
+impl<T: Localize> Localize for Vec<T> {
+    fn localize(&mut self, names: &CategoryNames) {
+        for value in self {
+            value.localize(names);
+        }
+    }
+}
+
+/// The value with the names of its categories in the locale
+pub fn localized<R, T>(repository: &R, locale: &Locale, mut value: T) -> QueryResult<T>
+where
+    R: CategoryRepository,
+    T: Localize,
+{
+    if !locale.is_default() {
+        value.localize(&CategoryNames::load(repository, locale)?);
+    }
+    Ok(value)
+}
+
+/// Category which is shown with the name in the locale
+pub fn category_named_in<R: CategoryRepository>(
+    repository: &R,
+    locale: &Locale,
+    name: &str,
+) -> QueryResult<Option<ProductCategory>> {
+    let translations = if locale.is_default() {
+        Vec::new()
+    } else {
+        repository.category_translations(locale.as_str())?
+    };
+    if let Some(translation) = translations
+        .iter()
+        .find(|translation| translation.name == name)
+    {
+        return repository.category(translation.category_id);
+    }
+    Ok(repository
+        .categories_named(&[name])?
+        .into_iter()
+        .find(|category| {
+            !translations
+                .iter()
+                .any(|translation| translation.category_id == category.id)
+        }))
+}
+
+/// Answers a unique violation with the outcome of finding the name taken
+///
+/// The checks before saving a name can not see a concurrent request saving the same one, whose
+/// name the constraints of the tables then reject.
+fn or_taken<T>(outcome: QueryResult<T>, taken: impl FnOnce() -> T) -> QueryResult<T> {
+    match outcome {
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(taken()),
+        outcome => outcome,
+    }
+}
+
+/// Outcome of looking up a category by its name in a locale
+#[derive(PartialEq, Eq, Debug)]
+pub enum Creating {
+    Created(ProductCategory),
+    Existed(ProductCategory),
+    /// No category was created, which the message explains
+    Rejected(String),
+}
+
+/// Returns the category with the name in the locale, creating it if no category has the name
+/// in the locale
+///
+/// A new category is named the default name in the default locale, or the same if none is given.
+/// It is rejected if a category whose name is translated differently into the locale already has
+/// that name in the default locale.
+pub fn create_or_get_in<R: CategoryRepository>(
+    repository: &R,
+    locale: &Locale,
+    name: &str,
+    default_name: Option<&str>,
+) -> QueryResult<Creating> {
+    let outcome = repository.atomically(|| {
+        if let Some(existing) = category_named_in(repository, locale, name)? {
+            return Ok(Creating::Existed(localized(repository, locale, existing)?));
+        }
+        let default_name = default_name.unwrap_or(name);
+        if default_name.trim().is_empty() {
+            return Ok(Creating::Rejected(
+                "Default category name is empty".to_string(),
+            ));
+        }
+        if let Some(taken) = repository.categories_named(&[default_name])?.first() {
+            return Ok(Creating::Rejected(format!(
+                "Category name {} is taken in the default locale by category {}, \
+                 give another default_name for the new category",
+                default_name, taken.id
+            )));
+        }
+        let mut created_category = repository.insert_category(default_name)?;
+        repository.record(EventType::CategoryCreated, &created_category)?;
+        if !locale.is_default() {
+            repository.save_category_translation(&CategoryTranslation {
+                category_id: created_category.id,
+                locale: locale.as_str().to_string(),
+                name: name.to_string(),
+            })?;
+            created_category.name = name.to_string();
+        }
+        Ok(Creating::Created(created_category))
+    });
+    or_taken(outcome, || {
+        Creating::Rejected(format!(
+            "Category name {} was taken by a concurrent request in locale {}",
+            name,
+            locale.as_str()
+        ))
+    })
+}
+
+/// Outcome of translating the name of a category
+#[derive(PartialEq, Eq, Debug)]
+pub enum Translating {
+    Translated(CategoryTranslation),
+    UnknownCategory,
+    /// The name can not be given, which the message explains
+    Rejected(String),
+    /// Another category has the name in the locale
+    Taken(String),
+}
+
+/// Names the category in the locale, which has to be one of the translatable locales
+pub fn translate_category<R: CategoryRepository>(
+    repository: &R,
+    locales: &Locales,
+    category_id: i32,
+    locale: &str,
+    name: &str,
+) -> QueryResult<Translating> {
+    let locale = match locales.translatable(locale) {
+        Ok(locale) => Locale::new(locale, locales),
+        Err(message) => return Ok(Translating::Rejected(message)),
+    };
+    if name.trim().is_empty() {
+        return Ok(Translating::Rejected("Category name is empty".to_string()));
+    }
+    let outcome = repository.atomically(|| {
+        if repository.category(category_id)?.is_none() {
+            return Ok(Translating::UnknownCategory);
+        }
+        if let Some(named) = category_named_in(repository, &locale, name)? {
+            if named.id != category_id {
+                return Ok(Translating::Taken(format!(
+                    "Category name {} is taken in locale {} by category {}",
+                    name,
+                    locale.as_str(),
+                    named.id
+                )));
+            }
+        }
+        let translation = CategoryTranslation {
+            category_id,
+            locale: locale.as_str().to_string(),
+            name: name.to_string(),
+        };
+        repository.save_category_translation(&translation)?;
+        Ok(Translating::Translated(translation))
+    });
+    or_taken(outcome, || {
+        Translating::Taken(format!(
+            "Category name {} was taken by a concurrent request in locale {}",
+            name,
+            locale.as_str()
+        ))
+    })
+}
+
+/// Lists the names of the category in the locales it is translated into
+#[get("/<product_category_id>/translations")]
+pub fn get_translations(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    product_category_id: i32,
+) -> Result<GetResponder<Vec<CategoryTranslation>>, DbError> {
+    if db_conn.category(product_category_id)?.is_none() {
+        return Ok(GetResponder::NotFound(()));
+    }
+    Ok(GetResponder::Found(Json(
+        db_conn.translations_of_category(product_category_id)?,
+    )))
+}
+
+type PutTranslationResponse = Result<
+    Result<GetResponder<CategoryTranslation>, Result<Conflict<String>, BadRequest<String>>>,
+    DbError,
+>;
+
+#[put(
+    "/<product_category_id>/translations/<locale>",
+    format = "json",
+    data = "<translation>"
+)]
+pub fn put_translation(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    locales: State<Locales>,
+    product_category_id: i32,
+    locale: String,
+    translation: Json<CategoryTranslationBody>,
+) -> PutTranslationResponse {
+    match translate_category(
+        &db_conn,
+        &locales,
+        product_category_id,
+        &locale,
+        &translation.name,
+    )? {
+        Translating::Translated(translation) => Ok(Ok(GetResponder::Found(Json(translation)))),
+        Translating::UnknownCategory => Ok(Ok(GetResponder::NotFound(()))),
+        Translating::Rejected(message) => Ok(Err(Err(BadRequest(Some(message))))),
+        Translating::Taken(message) => Ok(Err(Ok(Conflict(Some(message))))),
+    }
+}
+
+/// Removes the name of the category in the locale, so that it falls back to the default one
+#[delete("/<product_category_id>/translations/<locale>")]
+pub fn delete_translation(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    product_category_id: i32,
+    locale: String,
+) -> Result<Status, DbError> {
+    if db_conn.delete_category_translation(product_category_id, &normalize(&locale))? {
+        Ok(Status::Ok)
+    } else {
+        Ok(Status::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_or_get_in, localized, or_taken, translate_category, Creating, Translating};
+    use crate::{
+        auth::Role,
+        entities::Product,
+        localization::{entities::CategoryTranslation, Locale, Locales},
+        product_category::entities::{CategoryWithBreadcrumbs, ProductCategory},
+        repository::{CategoryRepository, MemoryRepository},
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::http::{ContentType, Header, Status};
+
+    fn german() -> Locale {
+        Locale::new("de", &Locales::default())
+    }
+
+    #[test]
+    fn names_are_unique_within_each_locale() {
+        let repository = MemoryRepository::new();
+        let locales = Locales::default();
+        let gifts = repository.insert_category("Gift").unwrap();
+        translate_category(&repository, &locales, gifts.id, "de", "Geschenke").unwrap();
+        repository.insert_category("Hemden").unwrap();
+
+        let rejected = create_or_get_in(&repository, &german(), "Gift", None).unwrap();
+        let created = create_or_get_in(&repository, &german(), "Gift", Some("Poison")).unwrap();
+        let again = create_or_get_in(&repository, &german(), "Geschenke", None).unwrap();
+        let shirts = create_or_get_in(&repository, &german(), "Hemden", None).unwrap();
+        let poisons = match created {
+            Creating::Created(poisons) => poisons,
+            other => panic!("Expected a created category, got {:?}", other),
+        };
+        let taken = translate_category(&repository, &locales, poisons.id, "de", "Geschenke");
+
+        assert_eq!(
+            Creating::Rejected(format!(
+                "Category name Gift is taken in the default locale by category {}, \
+                 give another default_name for the new category",
+                gifts.id
+            )),
+            rejected
+        );
+        assert_eq!("Gift", poisons.name);
+        assert_eq!(
+            "Poison",
+            repository.category(poisons.id).unwrap().unwrap().name
+        );
+        match again {
+            Creating::Existed(again) => {
+                assert_eq!((gifts.id, "Geschenke"), (again.id, again.name.as_str()))
+            }
+            other => panic!("Expected the existing category, got {:?}", other),
+        }
+        assert!(matches!(shirts, Creating::Existed(shirts) if shirts.name == "Hemden"));
+        assert_eq!(
+            Translating::Taken(format!(
+                "Category name Geschenke is taken in locale de by category {}",
+                gifts.id
+            )),
+            taken.unwrap()
+        );
+    }
+
+    #[test]
+    fn names_taken_by_concurrent_requests_are_conflicts() {
+        let repository = MemoryRepository::new();
+        let gifts = repository.insert_category("Gift").unwrap();
+        let presents = repository.insert_category("Present").unwrap();
+        let translation = |category_id| CategoryTranslation {
+            category_id,
+            locale: "de".to_string(),
+            name: "Geschenke".to_string(),
+        };
+        repository
+            .save_category_translation(&translation(gifts.id))
+            .unwrap();
+
+        let concurrent = repository
+            .save_category_translation(&translation(presents.id))
+            .map(|_| Translating::Translated(translation(presents.id)));
+
+        assert_eq!(
+            Translating::Taken("Geschenke".into()),
+            or_taken(concurrent, || Translating::Taken("Geschenke".into())).unwrap()
+        );
+    }
+
+    #[test]
+    fn only_other_locales_than_the_default_one_are_translated() {
+        let repository = MemoryRepository::new();
+        let locales = Locales::default();
+        let gifts = repository.insert_category("Gift").unwrap();
+
+        assert_eq!(
+            Translating::Rejected("Texts in the default locale en are not translations".into()),
+            translate_category(&repository, &locales, gifts.id, "en", "Presents").unwrap()
+        );
+        assert_eq!(
+            Translating::Rejected("Locale fr is not supported, expected one of en, de, hr".into()),
+            translate_category(&repository, &locales, gifts.id, "fr", "Cadeaux").unwrap()
+        );
+        assert_eq!(
+            Translating::UnknownCategory,
+            translate_category(&repository, &locales, 99, "hr", "Pokloni").unwrap()
+        );
+    }
+
+    #[test]
+    fn untranslated_names_fall_back_to_the_default_locale() {
+        let repository = MemoryRepository::new();
+        let locales = Locales::default();
+        let clothes = repository.insert_category("clothes").unwrap();
+        let shirts = repository.insert_category("shirts").unwrap();
+        repository.insert_rollup(clothes.id, shirts.id).unwrap();
+        translate_category(&repository, &locales, clothes.id, "hr", "odjeća").unwrap();
+
+        let category = super::super::hierarchy::with_breadcrumbs(&repository, shirts).unwrap();
+        let category: CategoryWithBreadcrumbs =
+            localized(&repository, &Locale::new("hr", &locales), category).unwrap();
+
+        assert_eq!("shirts", category.category.name);
+        assert_eq!(
+            vec![vec!["odjeća", "shirts"]],
+            category
+                .breadcrumbs
+                .iter()
+                .map(|path| path.iter().map(|crumb| crumb.name.as_str()).collect())
+                .collect::<Vec<Vec<&str>>>()
+        );
+    }
+
+    backend_test!(
+        texts_are_served_in_the_requested_locale,
+        |client, repository| {
+            let clothes = repository.insert_category("clothes")?;
+            let hats = repository.insert_category("hats")?;
+            let shirt = repository.insert_product("white shirt")?;
+            repository.classify(shirt.id, clothes.id, true)?;
+            let put = |path: String, body: &str| {
+                client
+                    .put(path)
+                    .header(ContentType::JSON)
+                    .header(bearer_token(Role::Admin))
+                    .body(body)
+                    .dispatch()
+                    .status()
+            };
+            let get = |path: String, accept_language: &str| {
+                let mut response = client
+                    .get(path)
+                    .header(bearer_token(Role::Viewer))
+                    .header(Header::new("Accept-Language", accept_language.to_string()))
+                    .dispatch();
+                (
+                    response.status(),
+                    response.body_string().unwrap_or_default(),
+                )
+            };
+            let post = |query: &str, body: &str| {
+                let mut response = client
+                    .post(format!("/productcategory{}", query))
+                    .header(ContentType::JSON)
+                    .header(bearer_token(Role::Admin))
+                    .body(body)
+                    .dispatch();
+                (
+                    response.status(),
+                    response.body_string().unwrap_or_default(),
+                )
+            };
+
+            let translated = put(
+                format!("/productcategory/{}/translations/de", clothes.id),
+                r#"{"name":"Kleidung"}"#,
+            );
+            let default_rejected = put(
+                format!("/productcategory/{}/translations/en", clothes.id),
+                r#"{"name":"Clothing"}"#,
+            );
+            let described = put(
+                format!("/product/{}/translations/de", shirt.id),
+                r#"{"description":"weißes Hemd"}"#,
+            );
+            let (_, negotiated) = get(
+                format!("/productcategory/{}", clothes.id),
+                "fr, de-AT;q=0.9",
+            );
+            let (_, queried) = get(format!("/productcategory/{}?locale=en", clothes.id), "de");
+            let (unsupported, _) = get(format!("/productcategory/{}?locale=fr", clothes.id), "");
+            let (_, products) = get(format!("/productcategory/{}/products", clothes.id), "de");
+            let (existing_status, existing) = post("?locale=de", r#"{"name":"Kleidung"}"#);
+            let (conflict_status, conflict) = post("?locale=de", r#"{"name":"clothes"}"#);
+            let (created_status, created) = post(
+                "?locale=de",
+                r#"{"name":"clothes","default_name":"used clothes"}"#,
+            );
+            let (default_status, _) = post("", r#"{"name":"clothes"}"#);
+            let (_, translations) =
+                get(format!("/productcategory/{}/translations", clothes.id), "");
+            let language_headers = |query: &str| {
+                let response = client
+                    .get(format!("/productcategory/{}{}", clothes.id, query))
+                    .header(bearer_token(Role::Viewer))
+                    .header(Header::new("Accept-Language", "de"))
+                    .dispatch();
+                let headers = response.headers();
+                (
+                    headers.get_one("Content-Language").map(str::to_string),
+                    headers.get("Vary").map(str::to_string).collect::<Vec<_>>(),
+                )
+            };
+            let taken_status = put(
+                format!("/productcategory/{}/translations/de", hats.id),
+                r#"{"name":"Kleidung"}"#,
+            );
+
+            assert_eq!(Status::Ok, translated);
+            assert_eq!(Status::BadRequest, default_rejected);
+            assert_eq!(Status::Ok, described);
+            let negotiated: CategoryWithBreadcrumbs = serde_json::from_str(&negotiated)?;
+            assert_eq!("Kleidung", negotiated.category.name);
+            let queried: CategoryWithBreadcrumbs = serde_json::from_str(&queried)?;
+            assert_eq!("clothes", queried.category.name);
+            assert_eq!(Status::BadRequest, unsupported);
+            let products: Vec<Product> = serde_json::from_str(&products)?;
+            assert_eq!("weißes Hemd", products[0].description);
+            assert_eq!(Status::Ok, existing_status);
+            assert!(existing.contains(&format!("\"id\":{}", clothes.id)));
+            assert_eq!(Status::Conflict, conflict_status);
+            assert!(conflict.contains("default_name"));
+            assert_eq!(Status::Created, created_status);
+            let created: ProductCategory = serde_json::from_str(&created)?;
+            assert_eq!("clothes", created.name);
+            assert_eq!(
+                Some("used clothes".to_string()),
+                repository
+                    .category(created.id)?
+                    .map(|category| category.name)
+            );
+            assert_eq!(Status::Ok, default_status);
+            assert!(translations.contains("Kleidung"));
+            assert_eq!(
+                (Some("de".to_string()), vec!["Accept-Language".to_string()]),
+                language_headers("")
+            );
+            assert_eq!(
+                (Some("hr".to_string()), vec![]),
+                language_headers("?locale=hr")
+            );
+            assert_eq!(Status::Conflict, taken_status);
+            Ok(())
+        }
+    );
+}
@@ -0,0 +1,647 @@
+use super::{
+    memory::{MemoryDatabase, MemoryRepository},
+    ApiKeyRepository, CategoryRepository, EventLog, FacetRepository, IdempotencyRepository,
+    OutboxRepository, ProductRepository, SearchRepository, StatisticsRepository, StockRepository,
+    Transactional, WarehouseRepository, WebhookRepository,
+};
+#[cfg(feature = "postgres")]
+use crate::tenancy::TenantConn;
+use crate::{
+    auth::{entities::ApiKey, Role},
+    catalog::{
+        entities::ProductAttribute,
+        facets::{FacetCounts, ListingFilters},
+    },
+    entities::{
+        InventoryItem, Product, ProductCategoryClassification, ProductCategoryRollup, Warehouse,
+    },
+    events::broadcaster::StreamPosition,
+    idempotency::store::{Claim, StoredResponse},
+    inventory::reports::StockLevel,
+    localization::entities::{CategoryTranslation, ProductTranslation},
+    metrics::gauges::CatalogCounts,
+    outbox::{entities::OutboxEvent, EventType},
+    product_category::{
+        entities::{ProductCategory, RelatedCategory},
+        statistics::CategoryStatistics,
+    },
+    search::{ProductSearch, SearchHit},
+    tenancy::TenantError,
+    webhooks::entities::{DeliveryAttempt, WebhookDelivery, WebhookSubscription},
+};
+use chrono::{DateTime, Utc};
+use diesel::QueryResult;
+use rocket::{
+    request::{self, FromRequest},
+    Request,
+};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Connection to the database of the configured [`crate::settings::Backend`]
+///
+/// Routes which are served on every backend take this guard instead of a [`TenantConn`] and
+/// only use it through the repository traits. On Postgres the connection is scoped to the tenant
+/// of the request, while an SQLite or in-memory database belongs to a single site, so that its
+/// requests only have to be authenticated.
+pub enum BackendConn {
+    #[cfg(feature = "postgres")]
+    Postgres(TenantConn),
+    #[cfg(feature = "sqlite")]
+    Sqlite(crate::SqliteDbConn),
+    Memory(MemoryRepository),
+}
+
+/// Authenticates a request to the database of a single site
+fn authenticate(request: &Request) -> request::Outcome<(), TenantError> {
+    use crate::auth::Principal;
+    use rocket::Outcome;
+
+    match request.guard::<Principal>() {
+        Outcome::Success(_) => Outcome::Success(()),
+        Outcome::Failure((status, auth_error)) => {
+            Outcome::Failure((status, TenantError::Unauthenticated(auth_error)))
+        }
+        Outcome::Forward(()) => Outcome::Forward(()),
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for BackendConn {
+    type Error = TenantError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        use rocket::State;
+
+        if let Some(database) = request.guard::<State<MemoryDatabase>>().succeeded() {
+            return authenticate(request).map(|()| BackendConn::Memory(database.connect()));
+        }
+        #[cfg(feature = "sqlite")]
+        if request
+            .guard::<State<crate::SqliteDbConnPool>>()
+            .succeeded()
+            .is_some()
+        {
+            use rocket::{http::Status, Outcome};
+
+            if let Outcome::Failure(failure) = authenticate(request) {
+                return Outcome::Failure(failure);
+            }
+            return match request.guard::<crate::SqliteDbConn>().succeeded() {
+                Some(db_conn) if super::sqlite::prepare(&db_conn).is_ok() => {
+                    Outcome::Success(BackendConn::Sqlite(db_conn))
+                }
+                _ => Outcome::Failure((Status::ServiceUnavailable, TenantError::Unavailable)),
+            };
+        }
+        tenant_conn(request)
+    }
+}
+
+/// Connection to the Postgres database of all tenants, scoped to the tenant of the request
+#[cfg(feature = "postgres")]
+fn tenant_conn(request: &Request) -> request::Outcome<BackendConn, TenantError> {
+    request.guard::<TenantConn>().map(BackendConn::Postgres)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn tenant_conn(_request: &Request) -> request::Outcome<BackendConn, TenantError> {
+    rocket::Outcome::Failure((
+        rocket::http::Status::ServiceUnavailable,
+        TenantError::Unavailable,
+    ))
+}
+
+/// Runs the expression on the connection of whichever backend the guard holds
+macro_rules! on_backend {
+    ($backend_conn:expr, |$connection:ident| $body:expr) => {
+        match $backend_conn {
+            #[cfg(feature = "postgres")]
+            BackendConn::Postgres(tenant_conn) => {
+                let $connection: &diesel::PgConnection = tenant_conn;
+                $body
+            }
+            #[cfg(feature = "sqlite")]
+            BackendConn::Sqlite(db_conn) => {
+                let $connection: &diesel::SqliteConnection = db_conn;
+                $body
+            }
+            BackendConn::Memory(repository) => {
+                let $connection: &MemoryRepository = repository;
+                $body
+            }
+        }
+    };
+}
+
+impl Transactional for BackendConn {
+    fn atomically<T, F>(&self, f: F) -> QueryResult<T>
+    where
+        F: FnOnce() -> QueryResult<T>,
+    {
+        on_backend!(self, |connection| connection.atomically(f))
+    }
+
+    fn begin_snapshot(&self) -> QueryResult<()> {
+        on_backend!(self, |connection| connection.begin_snapshot())
+    }
+
+    fn end_snapshot(&self) -> QueryResult<()> {
+        on_backend!(self, |connection| connection.end_snapshot())
+    }
+}
+
+impl EventLog for BackendConn {
+    fn record_all<T: Serialize>(
+        &self,
+        event_type: EventType,
+        payloads: &[T],
+    ) -> QueryResult<usize> {
+        on_backend!(self, |connection| connection
+            .record_all(event_type, payloads))
+    }
+}
+
+impl CategoryRepository for BackendConn {
+    fn categories(&self) -> QueryResult<Vec<ProductCategory>> {
+        on_backend!(self, |connection| connection.categories())
+    }
+
+    fn category(&self, id: i32) -> QueryResult<Option<ProductCategory>> {
+        on_backend!(self, |connection| connection.category(id))
+    }
+
+    fn categories_named(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        on_backend!(self, |connection| connection.categories_named(names))
+    }
+
+    fn categories_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<ProductCategory>> {
+        on_backend!(self, |connection| connection.categories_with_ids(ids))
+    }
+
+    fn categories_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<ProductCategory>> {
+        on_backend!(self, |connection| connection
+            .categories_after(after_id, limit))
+    }
+
+    fn lock_categories(&self, ids: &[i32], names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        on_backend!(self, |connection| connection.lock_categories(ids, names))
+    }
+
+    fn insert_category(&self, name: &str) -> QueryResult<ProductCategory> {
+        on_backend!(self, |connection| connection.insert_category(name))
+    }
+
+    fn insert_missing_categories(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        on_backend!(self, |connection| connection
+            .insert_missing_categories(names))
+    }
+
+    fn save_categories(&self, categories: &[ProductCategory]) -> QueryResult<()> {
+        on_backend!(self, |connection| connection.save_categories(categories))
+    }
+
+    fn delete_categories(&self, ids: &[i32]) -> QueryResult<Vec<i32>> {
+        on_backend!(self, |connection| connection.delete_categories(ids))
+    }
+
+    fn rollups(&self) -> QueryResult<Vec<(i32, i32)>> {
+        on_backend!(self, |connection| connection.rollups())
+    }
+
+    fn rollups_of(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, i32)>> {
+        on_backend!(self, |connection| connection.rollups_of(category_ids))
+    }
+
+    fn rollup_links(&self) -> QueryResult<Vec<ProductCategoryRollup>> {
+        on_backend!(self, |connection| connection.rollup_links())
+    }
+
+    fn insert_rollup(&self, upper_category_id: i32, lower_category_id: i32) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .insert_rollup(upper_category_id, lower_category_id))
+    }
+
+    fn set_rollup_positions(
+        &self,
+        upper_category_id: i32,
+        lower_category_ids: &[i32],
+    ) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .set_rollup_positions(upper_category_id, lower_category_ids))
+    }
+
+    fn delete_rollups_of(&self, category_id: i32) -> QueryResult<usize> {
+        on_backend!(self, |connection| connection.delete_rollups_of(category_id))
+    }
+
+    fn slugs_below(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, String)>> {
+        on_backend!(self, |connection| connection.slugs_below(category_ids))
+    }
+
+    fn set_slug(&self, category_id: i32, slug: &str) -> QueryResult<()> {
+        on_backend!(self, |connection| connection.set_slug(category_id, slug))
+    }
+
+    fn archive_category(&self, category_id: i32) -> QueryResult<()> {
+        on_backend!(self, |connection| connection.archive_category(category_id))
+    }
+
+    fn category_translations(&self, locale: &str) -> QueryResult<Vec<CategoryTranslation>> {
+        on_backend!(self, |connection| connection.category_translations(locale))
+    }
+
+    fn translations_of_category(&self, category_id: i32) -> QueryResult<Vec<CategoryTranslation>> {
+        on_backend!(self, |connection| connection
+            .translations_of_category(category_id))
+    }
+
+    fn save_category_translation(&self, translation: &CategoryTranslation) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .save_category_translation(translation))
+    }
+
+    fn delete_category_translation(&self, category_id: i32, locale: &str) -> QueryResult<bool> {
+        on_backend!(self, |connection| connection
+            .delete_category_translation(category_id, locale))
+    }
+
+    fn descendants(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>> {
+        on_backend!(self, |connection| connection
+            .descendants(category_id, max_depth))
+    }
+
+    fn ancestors(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>> {
+        on_backend!(self, |connection| connection
+            .ancestors(category_id, max_depth))
+    }
+}
+
+impl ProductRepository for BackendConn {
+    fn products(&self) -> QueryResult<Vec<Product>> {
+        on_backend!(self, |connection| connection.products())
+    }
+
+    fn product(&self, id: i32) -> QueryResult<Option<Product>> {
+        on_backend!(self, |connection| connection.product(id))
+    }
+
+    fn products_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Product>> {
+        on_backend!(self, |connection| connection.products_with_ids(ids))
+    }
+
+    fn insert_product(&self, description: &str) -> QueryResult<Product> {
+        on_backend!(self, |connection| connection.insert_product(description))
+    }
+
+    fn products_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<Product>> {
+        on_backend!(self, |connection| connection
+            .products_after(after_id, limit))
+    }
+
+    fn set_product_description(&self, product_id: i32, description: &str) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .set_product_description(product_id, description))
+    }
+
+    fn classify(
+        &self,
+        product_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<ProductCategoryClassification> {
+        on_backend!(self, |connection| connection.classify(
+            product_id,
+            category_id,
+            is_primary
+        ))
+    }
+
+    fn update_classification(
+        &self,
+        classification_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<()> {
+        on_backend!(self, |connection| connection.update_classification(
+            classification_id,
+            category_id,
+            is_primary
+        ))
+    }
+
+    fn delete_classifications(&self, classification_ids: &[i32]) -> QueryResult<usize> {
+        on_backend!(self, |connection| connection
+            .delete_classifications(classification_ids))
+    }
+
+    fn classifications(&self) -> QueryResult<Vec<ProductCategoryClassification>> {
+        on_backend!(self, |connection| connection.classifications())
+    }
+
+    fn classifications_in(
+        &self,
+        category_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        on_backend!(self, |connection| connection
+            .classifications_in(category_ids))
+    }
+
+    fn classifications_of(
+        &self,
+        product_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        on_backend!(self, |connection| connection
+            .classifications_of(product_ids))
+    }
+
+    fn products_in_subtree(&self, category_id: i32) -> QueryResult<Vec<Product>> {
+        on_backend!(self, |connection| connection
+            .products_in_subtree(category_id))
+    }
+
+    fn product_translations(&self, locale: &str) -> QueryResult<Vec<ProductTranslation>> {
+        on_backend!(self, |connection| connection.product_translations(locale))
+    }
+
+    fn translations_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductTranslation>> {
+        on_backend!(self, |connection| connection
+            .translations_of_product(product_id))
+    }
+
+    fn save_product_translation(&self, translation: &ProductTranslation) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .save_product_translation(translation))
+    }
+
+    fn delete_product_translation(&self, product_id: i32, locale: &str) -> QueryResult<bool> {
+        on_backend!(self, |connection| connection
+            .delete_product_translation(product_id, locale))
+    }
+
+    fn set_product_price(&self, product_id: i32, price: Option<i32>) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .set_product_price(product_id, price))
+    }
+
+    fn attributes_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductAttribute>> {
+        on_backend!(self, |connection| connection
+            .attributes_of_product(product_id))
+    }
+
+    fn save_product_attribute(&self, attribute: &ProductAttribute) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .save_product_attribute(attribute))
+    }
+
+    fn delete_product_attribute(&self, product_id: i32, name: &str) -> QueryResult<bool> {
+        on_backend!(self, |connection| connection
+            .delete_product_attribute(product_id, name))
+    }
+}
+
+impl StockRepository for BackendConn {
+    fn insert_inventory_item(
+        &self,
+        product_id: i32,
+        warehouse_id: Option<i32>,
+        instance_description: Option<&str>,
+    ) -> QueryResult<InventoryItem> {
+        on_backend!(self, |connection| connection.insert_inventory_item(
+            product_id,
+            warehouse_id,
+            instance_description
+        ))
+    }
+
+    fn inventory_item(&self, id: i32) -> QueryResult<Option<InventoryItem>> {
+        on_backend!(self, |connection| connection.inventory_item(id))
+    }
+
+    fn inventory_items_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<InventoryItem>> {
+        on_backend!(self, |connection| connection
+            .inventory_items_after(after_id, limit))
+    }
+
+    fn inventory_items(&self) -> QueryResult<Vec<InventoryItem>> {
+        on_backend!(self, |connection| connection.inventory_items())
+    }
+
+    fn inventory_items_of(&self, product_ids: &[i32]) -> QueryResult<Vec<InventoryItem>> {
+        on_backend!(self, |connection| connection
+            .inventory_items_of(product_ids))
+    }
+
+    fn inventory_items_in(&self, warehouse_ids: &[i32]) -> QueryResult<Vec<InventoryItem>> {
+        on_backend!(self, |connection| connection
+            .inventory_items_in(warehouse_ids))
+    }
+
+    fn update_inventory_item(&self, item: &InventoryItem) -> QueryResult<()> {
+        on_backend!(self, |connection| connection.update_inventory_item(item))
+    }
+
+    fn stock_levels(&self) -> QueryResult<Vec<StockLevel>> {
+        on_backend!(self, |connection| connection.stock_levels())
+    }
+}
+
+impl StatisticsRepository for BackendConn {
+    fn category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>> {
+        on_backend!(self, |connection| connection.category_statistics())
+    }
+
+    fn category_statistics_of(&self, category_id: i32) -> QueryResult<Option<CategoryStatistics>> {
+        on_backend!(self, |connection| connection
+            .category_statistics_of(category_id))
+    }
+
+    fn store_category_statistics(&self, statistics: &[CategoryStatistics]) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .store_category_statistics(statistics))
+    }
+
+    fn stored_category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>> {
+        on_backend!(self, |connection| connection.stored_category_statistics())
+    }
+
+    fn stored_category_statistics_of(
+        &self,
+        category_id: i32,
+    ) -> QueryResult<Option<CategoryStatistics>> {
+        on_backend!(self, |connection| connection
+            .stored_category_statistics_of(category_id))
+    }
+
+    fn catalog_counts(&self) -> QueryResult<CatalogCounts> {
+        on_backend!(self, |connection| connection.catalog_counts())
+    }
+}
+
+impl SearchRepository for BackendConn {
+    fn search_products(&self, search: &ProductSearch) -> QueryResult<Vec<SearchHit>> {
+        on_backend!(self, |connection| connection.search_products(search))
+    }
+}
+
+impl FacetRepository for BackendConn {
+    fn facet_counts(
+        &self,
+        filters: &ListingFilters,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<FacetCounts> {
+        on_backend!(self, |connection| connection
+            .facet_counts(filters, limit, offset))
+    }
+}
+
+impl WarehouseRepository for BackendConn {
+    fn warehouses(&self) -> QueryResult<Vec<Warehouse>> {
+        on_backend!(self, |connection| connection.warehouses())
+    }
+
+    fn warehouse(&self, id: i32) -> QueryResult<Option<Warehouse>> {
+        on_backend!(self, |connection| connection.warehouse(id))
+    }
+
+    fn warehouses_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Warehouse>> {
+        on_backend!(self, |connection| connection.warehouses_with_ids(ids))
+    }
+
+    fn insert_warehouse(&self, description: &str) -> QueryResult<Warehouse> {
+        on_backend!(self, |connection| connection.insert_warehouse(description))
+    }
+}
+
+impl ApiKeyRepository for BackendConn {
+    fn api_key(&self, key_hash: &str) -> QueryResult<Option<ApiKey>> {
+        on_backend!(self, |connection| connection.api_key(key_hash))
+    }
+
+    fn insert_api_key(&self, key_hash: &str, role: Role, description: &str) -> QueryResult<ApiKey> {
+        on_backend!(self, |connection| connection.insert_api_key(
+            key_hash,
+            role,
+            description
+        ))
+    }
+}
+
+impl IdempotencyRepository for BackendConn {
+    fn claim_idempotency_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        retention: Duration,
+        claim_timeout: Duration,
+    ) -> QueryResult<Claim> {
+        on_backend!(self, |connection| connection.claim_idempotency_key(
+            key,
+            fingerprint,
+            retention,
+            claim_timeout
+        ))
+    }
+
+    fn complete_idempotency_key(
+        &self,
+        key: &str,
+        claimed_at: DateTime<Utc>,
+        response: &StoredResponse,
+    ) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .complete_idempotency_key(key, claimed_at, response))
+    }
+
+    fn release_idempotency_key(&self, key: &str, claimed_at: DateTime<Utc>) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .release_idempotency_key(key, claimed_at))
+    }
+}
+
+impl OutboxRepository for BackendConn {
+    fn outbox_event(&self, id: i64) -> QueryResult<Option<OutboxEvent>> {
+        on_backend!(self, |connection| connection.outbox_event(id))
+    }
+
+    fn outbox_tenant_id(&self) -> QueryResult<Option<i32>> {
+        on_backend!(self, |connection| connection.outbox_tenant_id())
+    }
+
+    fn stream_horizon(&self) -> QueryResult<i64> {
+        on_backend!(self, |connection| connection.stream_horizon())
+    }
+
+    fn events_after(
+        &self,
+        position: StreamPosition,
+        horizon: i64,
+        limit: i64,
+    ) -> QueryResult<Vec<OutboxEvent>> {
+        on_backend!(self, |connection| connection
+            .events_after(position, horizon, limit))
+    }
+
+    fn first_transaction_after(&self, event_id: i64) -> QueryResult<Option<i64>> {
+        on_backend!(self, |connection| connection
+            .first_transaction_after(event_id))
+    }
+}
+
+impl WebhookRepository for BackendConn {
+    fn webhook_subscriptions(&self) -> QueryResult<Vec<WebhookSubscription>> {
+        on_backend!(self, |connection| connection.webhook_subscriptions())
+    }
+
+    fn webhook_subscription(&self, id: i32) -> QueryResult<Option<WebhookSubscription>> {
+        on_backend!(self, |connection| connection.webhook_subscription(id))
+    }
+
+    fn insert_webhook_subscription(
+        &self,
+        url: &str,
+        event_types: &[String],
+        secret: &str,
+    ) -> QueryResult<WebhookSubscription> {
+        on_backend!(self, |connection| connection.insert_webhook_subscription(
+            url,
+            event_types,
+            secret
+        ))
+    }
+
+    fn delete_webhook_subscription(&self, id: i32) -> QueryResult<bool> {
+        on_backend!(self, |connection| connection
+            .delete_webhook_subscription(id))
+    }
+
+    fn webhook_deliveries(&self) -> QueryResult<Vec<WebhookDelivery>> {
+        on_backend!(self, |connection| connection.webhook_deliveries())
+    }
+
+    fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        leased_until: DateTime<Utc>,
+    ) -> QueryResult<Vec<WebhookDelivery>> {
+        on_backend!(self, |connection| connection
+            .claim_due_deliveries(limit, leased_until))
+    }
+
+    fn record_delivery_attempt(
+        &self,
+        delivery: &WebhookDelivery,
+        attempt: &DeliveryAttempt,
+    ) -> QueryResult<()> {
+        on_backend!(self, |connection| connection
+            .record_delivery_attempt(delivery, attempt))
+    }
+}
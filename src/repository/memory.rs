@@ -0,0 +1,1599 @@
+//! Implementation of the repositories which keeps the aggregates of a single tenant in memory
+//!
+//! The constraints of the database schema which the domain relies on are checked as well, so
+//! that violations fail with the same errors as on the database. A [`MemoryDatabase`] serves the
+//! API without a database server, for trying it out and for tests, and loses its data when the
+//! process ends.
+
+use super::{
+    ApiKeyRepository, CategoryRepository, EventLog, FacetRepository, IdempotencyRepository,
+    OutboxRepository, ProductRepository, SearchRepository, StatisticsRepository, StockRepository,
+    Transactional, WarehouseRepository, WebhookRepository,
+};
+use crate::{
+    auth::{entities::ApiKey, Role},
+    catalog::{
+        entities::ProductAttribute,
+        facets::{self, CatalogIndex, FacetCounts, ListingFilters},
+    },
+    entities::{
+        InventoryItem, Product, ProductCategoryClassification, ProductCategoryRollup, Warehouse,
+    },
+    events::broadcaster::StreamPosition,
+    idempotency::store::{before_now, Claim, StoredResponse},
+    inventory::reports::StockLevel,
+    localization::entities::{CategoryTranslation, ProductTranslation},
+    metrics::gauges::CatalogCounts,
+    outbox::{entities::OutboxEvent, EventType},
+    product_category::{
+        entities::{ProductCategory, RelatedCategory},
+        statistics::{CategoryStatistics, WarehouseStock},
+    },
+    search::{self, ProductSearch, SearchHit},
+    webhooks::entities::{DeliveryAttempt, WebhookDelivery, WebhookSubscription, DELIVERY_PENDING},
+};
+use chrono::{DateTime, Utc};
+use diesel::{
+    result::{DatabaseErrorKind, Error},
+    QueryResult,
+};
+use serde::Serialize;
+use std::{
+    cell::{Ref, RefCell},
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    ops::{Deref, DerefMut},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, TryLockError},
+    thread::{self, ThreadId},
+    time::Duration,
+};
+
+#[derive(Clone, Default)]
+struct State {
+    categories: BTreeMap<i32, ProductCategory>,
+    rollups: Vec<ProductCategoryRollup>,
+    products: BTreeMap<i32, Product>,
+    classifications: Vec<ProductCategoryClassification>,
+    warehouses: BTreeMap<i32, Warehouse>,
+    inventory_items: Vec<InventoryItem>,
+    outbox_events: Vec<OutboxEvent>,
+    statistics: Vec<CategoryStatistics>,
+    category_translations: Vec<CategoryTranslation>,
+    product_translations: Vec<ProductTranslation>,
+    product_attributes: Vec<ProductAttribute>,
+    api_keys: Vec<ApiKey>,
+    idempotency_keys: BTreeMap<String, IdempotencyKey>,
+    webhook_subscriptions: BTreeMap<i32, WebhookSubscription>,
+    webhook_deliveries: Vec<WebhookDelivery>,
+}
+
+#[derive(Clone)]
+struct IdempotencyKey {
+    fingerprint: String,
+    created_at: DateTime<Utc>,
+    response: Option<StoredResponse>,
+}
+
+impl State {
+    fn category_named(&self, name: &str) -> Option<&ProductCategory> {
+        self.categories
+            .values()
+            .find(|category| category.name == name)
+    }
+
+    fn new_category(&mut self, name: &str) -> ProductCategory {
+        let category = ProductCategory {
+            id: next_id(self.categories.keys().copied()),
+            name: name.to_string(),
+            slug: String::new(),
+            description: None,
+            image_ref: None,
+            is_visible: true,
+            is_archived: false,
+        };
+        self.categories.insert(category.id, category.clone());
+        category
+    }
+
+    /// Queues the event for delivery to the active subscriptions interested in it
+    fn queue_webhook_deliveries(&mut self, outbox_event_id: i64, event_type: EventType) {
+        let subscription_ids: Vec<i32> = self
+            .webhook_subscriptions
+            .values()
+            .filter(|subscription| subscription.active)
+            .filter(|subscription| {
+                subscription
+                    .event_types
+                    .iter()
+                    .any(|subscribed| subscribed == event_type.as_str())
+            })
+            .map(|subscription| subscription.id)
+            .collect();
+        for webhook_subscription_id in subscription_ids {
+            let id = self
+                .webhook_deliveries
+                .last()
+                .map_or(0, |delivery| delivery.id)
+                + 1;
+            self.webhook_deliveries.push(WebhookDelivery {
+                id,
+                outbox_event_id,
+                webhook_subscription_id,
+                status: DELIVERY_PENDING.to_string(),
+                attempts: 0,
+                next_attempt_at: Utc::now(),
+                last_error: None,
+            });
+        }
+    }
+
+    /// Categories related to the start through the rollups, with the length of the shortest
+    /// path to them, found by a breadth first search downwards or upwards the hierarchy
+    fn related_categories(&self, start: i32, downwards: bool) -> Vec<RelatedCategory> {
+        let mut depths: BTreeMap<i32, i32> = BTreeMap::new();
+        depths.insert(start, 0);
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some(category_id) = queue.pop_front() {
+            let depth = depths[&category_id];
+            for rollup in &self.rollups {
+                let (from, to) = if downwards {
+                    (rollup.upper_category_id, rollup.lower_category_id)
+                } else {
+                    (rollup.lower_category_id, rollup.upper_category_id)
+                };
+                if from == category_id && !depths.contains_key(&to) {
+                    depths.insert(to, depth + 1);
+                    queue.push_back(to);
+                }
+            }
+        }
+        let mut related: Vec<RelatedCategory> = depths
+            .into_iter()
+            .filter(|(id, _)| *id != start)
+            .filter_map(|(id, depth)| {
+                self.categories.get(&id).map(|category| RelatedCategory {
+                    id,
+                    name: category.name.clone(),
+                    slug: category.slug.clone(),
+                    description: category.description.clone(),
+                    image_ref: category.image_ref.clone(),
+                    is_visible: category.is_visible,
+                    depth,
+                })
+            })
+            .collect();
+        related.sort_by_key(|category| (category.depth, category.id));
+        related
+    }
+}
+
+fn within_depth(related: Vec<RelatedCategory>, max_depth: Option<i32>) -> Vec<RelatedCategory> {
+    related
+        .into_iter()
+        .filter(|category| max_depth.map_or(true, |max_depth| category.depth <= max_depth))
+        .collect()
+}
+
+/// Id which follows the largest of the ids, like a sequence which nobody skipped
+fn next_id(ids: impl Iterator<Item = i32>) -> i32 {
+    ids.max().unwrap_or(0) + 1
+}
+
+fn violation(kind: DatabaseErrorKind, message: String) -> Error {
+    Error::DatabaseError(kind, Box::new(message))
+}
+
+/// State shared by the repositories of a [`MemoryDatabase`]
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Thread which is using the state, and how many of its accesses are running
+    owner: Mutex<Option<(ThreadId, usize)>>,
+    released: Condvar,
+}
+
+impl Shared {
+    /// Waits until no other thread uses the state, like a transaction waits for the locks
+    ///
+    /// A thread may own the state several times over, so that the accesses within an
+    /// [`Transactional::atomically`] block are not locked out by the block itself.
+    fn own(&self) -> Ownership {
+        let thread = thread::current().id();
+        let mut owner = self.owner.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            match &mut *owner {
+                Some((owning_thread, accesses)) if *owning_thread == thread => {
+                    *accesses += 1;
+                    break;
+                }
+                Some(_) => {
+                    owner = self
+                        .released
+                        .wait(owner)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+                None => {
+                    *owner = Some((thread, 1));
+                    break;
+                }
+            }
+        }
+        Ownership { shared: self }
+    }
+}
+
+struct Ownership<'a> {
+    shared: &'a Shared,
+}
+
+impl Drop for Ownership<'_> {
+    fn drop(&mut self) {
+        let mut owner = self
+            .shared
+            .owner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, accesses)) = &mut *owner {
+            *accesses -= 1;
+            if *accesses == 0 {
+                *owner = None;
+                self.shared.released.notify_all();
+            }
+        }
+    }
+}
+
+/// State borrowed by a repository, which no other thread uses until it is dropped
+struct StateGuard<'a> {
+    state: MutexGuard<'a, State>,
+    _ownership: Ownership<'a>,
+}
+
+impl Deref for StateGuard<'_> {
+    type Target = State;
+
+    fn deref(&self) -> &State {
+        &self.state
+    }
+}
+
+impl DerefMut for StateGuard<'_> {
+    fn deref_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+}
+
+/// State read by a repository, either the snapshot it took or the shared one
+enum StateRef<'a> {
+    Snapshot(Ref<'a, State>),
+    Shared(StateGuard<'a>),
+}
+
+impl Deref for StateRef<'_> {
+    type Target = State;
+
+    fn deref(&self) -> &State {
+        match self {
+            StateRef::Snapshot(state) => state,
+            StateRef::Shared(state) => state,
+        }
+    }
+}
+
+/// Database which keeps its data in the memory of the process
+///
+/// Rocket manages it in place of a connection pool for the [`crate::settings::Backend::Memory`]
+/// backend, and every request and background worker connects a repository of its own.
+#[derive(Clone, Default)]
+pub struct MemoryDatabase {
+    shared: Arc<Shared>,
+}
+
+impl MemoryDatabase {
+    pub fn connect(&self) -> MemoryRepository {
+        MemoryRepository {
+            shared: self.shared.clone(),
+            snapshot: RefCell::new(None),
+        }
+    }
+}
+
+/// Repositories backed by plain collections
+///
+/// Changes made within a failing [`Transactional::atomically`] block are rolled back, and other
+/// threads wait for the block to finish.
+pub struct MemoryRepository {
+    shared: Arc<Shared>,
+    /// State read between [`Transactional::begin_snapshot`] and [`Transactional::end_snapshot`]
+    snapshot: RefCell<Option<State>>,
+}
+
+impl Default for MemoryRepository {
+    fn default() -> Self {
+        MemoryDatabase::default().connect()
+    }
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        MemoryRepository::default()
+    }
+
+    /// Events recorded so far, in the order of recording
+    pub fn events(&self) -> Vec<(EventType, serde_json::Value)> {
+        self.read()
+            .outbox_events
+            .iter()
+            .map(|event| {
+                let event_type = event.event_type.parse().expect("recorded event type");
+                (event_type, event.payload.clone())
+            })
+            .collect()
+    }
+
+    fn read(&self) -> StateRef {
+        match Ref::filter_map(self.snapshot.borrow(), Option::as_ref) {
+            Ok(snapshot) => StateRef::Snapshot(snapshot),
+            Err(_) => StateRef::Shared(self.write()),
+        }
+    }
+
+    /// Borrows the shared state, panicking if the thread already borrows it, like a `RefCell`
+    fn write(&self) -> StateGuard {
+        let ownership = self.shared.own();
+        let state = match self.shared.state.try_lock() {
+            Ok(state) => state,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => panic!("memory state is already borrowed"),
+        };
+        StateGuard {
+            state,
+            _ownership: ownership,
+        }
+    }
+
+    fn rollups_of_any(&self, matches: impl Fn(i32) -> bool) -> QueryResult<Vec<(i32, i32)>> {
+        let state = self.read();
+        let mut rollups: Vec<&ProductCategoryRollup> = state
+            .rollups
+            .iter()
+            .filter(|rollup| matches(rollup.upper_category_id) || matches(rollup.lower_category_id))
+            .collect();
+        rollups.sort_by_key(|rollup| (rollup.upper_category_id, rollup.position, rollup.id));
+        Ok(rollups
+            .into_iter()
+            .map(|rollup| (rollup.upper_category_id, rollup.lower_category_id))
+            .collect())
+    }
+}
+
+impl Transactional for MemoryRepository {
+    fn atomically<T, F>(&self, f: F) -> QueryResult<T>
+    where
+        F: FnOnce() -> QueryResult<T>,
+    {
+        let _ownership = self.shared.own();
+        let snapshot = self.write().clone();
+        let result = f();
+        if result.is_err() {
+            *self.write() = snapshot;
+        }
+        result
+    }
+
+    /// Reads are served from a copy of the state until the snapshot ends
+    fn begin_snapshot(&self) -> QueryResult<()> {
+        let snapshot = self.write().clone();
+        *self.snapshot.borrow_mut() = Some(snapshot);
+        Ok(())
+    }
+
+    fn end_snapshot(&self) -> QueryResult<()> {
+        *self.snapshot.borrow_mut() = None;
+        Ok(())
+    }
+}
+
+impl EventLog for MemoryRepository {
+    fn record_all<T: Serialize>(
+        &self,
+        event_type: EventType,
+        payloads: &[T],
+    ) -> QueryResult<usize> {
+        let payloads = payloads
+            .iter()
+            .map(|payload| {
+                serde_json::to_value(payload)
+                    .map_err(|error| Error::SerializationError(Box::new(error)))
+            })
+            .collect::<QueryResult<Vec<_>>>()?;
+        let recorded = payloads.len();
+        let mut state = self.write();
+        for payload in payloads {
+            let id = state.outbox_events.last().map_or(0, |event| event.id) + 1;
+            state.outbox_events.push(OutboxEvent {
+                id,
+                event_type: event_type.as_str().to_string(),
+                payload,
+                created_at: Utc::now(),
+                request_id: None,
+                // Transactions take turns, like on SQLite
+                transaction_id: id,
+            });
+            state.queue_webhook_deliveries(id, event_type);
+        }
+        Ok(recorded)
+    }
+}
+
+impl CategoryRepository for MemoryRepository {
+    fn categories(&self) -> QueryResult<Vec<ProductCategory>> {
+        Ok(self.read().categories.values().cloned().collect())
+    }
+
+    fn category(&self, id: i32) -> QueryResult<Option<ProductCategory>> {
+        Ok(self.read().categories.get(&id).cloned())
+    }
+
+    fn categories_named(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        Ok(self
+            .read()
+            .categories
+            .values()
+            .filter(|category| names.contains(&category.name.as_str()))
+            .cloned()
+            .collect())
+    }
+
+    fn categories_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<ProductCategory>> {
+        Ok(self
+            .read()
+            .categories
+            .values()
+            .filter(|category| ids.contains(&category.id))
+            .cloned()
+            .collect())
+    }
+
+    fn categories_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<ProductCategory>> {
+        Ok(self
+            .read()
+            .categories
+            .range(after_id + 1..)
+            .map(|(_, category)| category.clone())
+            .take(limit as usize)
+            .collect())
+    }
+
+    fn lock_categories(&self, ids: &[i32], names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        Ok(self
+            .read()
+            .categories
+            .values()
+            .filter(|category| {
+                ids.contains(&category.id) || names.contains(&category.name.as_str())
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn insert_category(&self, name: &str) -> QueryResult<ProductCategory> {
+        let id = {
+            let mut state = self.write();
+            if state.category_named(name).is_some() {
+                return Err(violation(
+                    DatabaseErrorKind::UniqueViolation,
+                    format!("Category name {} is taken", name),
+                ));
+            }
+            state.new_category(name).id
+        };
+        self.refresh_slug(id)?;
+        Ok(self.read().categories[&id].clone())
+    }
+
+    fn insert_missing_categories(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        let mut created = Vec::new();
+        for name in names {
+            if self.read().category_named(name).is_none() {
+                created.push(self.insert_category(name)?);
+            }
+        }
+        Ok(created)
+    }
+
+    fn save_categories(&self, categories: &[ProductCategory]) -> QueryResult<()> {
+        for category in categories {
+            {
+                let mut state = self.write();
+                if let Some(owner) = state.category_named(&category.name) {
+                    if owner.id != category.id {
+                        return Err(violation(
+                            DatabaseErrorKind::UniqueViolation,
+                            format!("Category name {} is taken", category.name),
+                        ));
+                    }
+                }
+                let (slug, is_archived) = state
+                    .categories
+                    .get(&category.id)
+                    .map_or((String::new(), false), |existing| {
+                        (existing.slug.clone(), existing.is_archived)
+                    });
+                state.categories.insert(
+                    category.id,
+                    ProductCategory {
+                        slug,
+                        is_archived,
+                        ..category.clone()
+                    },
+                );
+            }
+            self.refresh_slug(category.id)?;
+        }
+        Ok(())
+    }
+
+    fn delete_categories(&self, ids: &[i32]) -> QueryResult<Vec<i32>> {
+        let mut state = self.write();
+        let referenced =
+            ids.iter().find(|id| {
+                state.rollups.iter().any(|rollup| {
+                    rollup.upper_category_id == **id || rollup.lower_category_id == **id
+                }) || state
+                    .classifications
+                    .iter()
+                    .any(|classification| classification.product_category_id == **id)
+            });
+        if let Some(id) = referenced {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Category {} is still referenced", id),
+            ));
+        }
+        state
+            .category_translations
+            .retain(|translation| !ids.contains(&translation.category_id));
+        Ok(ids
+            .iter()
+            .filter_map(|id| state.categories.remove(id).map(|category| category.id))
+            .collect())
+    }
+
+    fn rollups(&self) -> QueryResult<Vec<(i32, i32)>> {
+        self.rollups_of_any(|_| true)
+    }
+
+    fn rollups_of(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, i32)>> {
+        self.rollups_of_any(|id| category_ids.contains(&id))
+    }
+
+    fn rollup_links(&self) -> QueryResult<Vec<ProductCategoryRollup>> {
+        let mut rollups = self.read().rollups.clone();
+        rollups.sort_by_key(|rollup| rollup.id);
+        Ok(rollups)
+    }
+
+    fn insert_rollup(&self, upper_category_id: i32, lower_category_id: i32) -> QueryResult<()> {
+        let mut state = self.write();
+        for id in &[upper_category_id, lower_category_id] {
+            if !state.categories.contains_key(id) {
+                return Err(violation(
+                    DatabaseErrorKind::ForeignKeyViolation,
+                    format!("Category {} does not exist", id),
+                ));
+            }
+        }
+        let position = state
+            .rollups
+            .iter()
+            .filter(|rollup| rollup.upper_category_id == upper_category_id)
+            .map(|rollup| rollup.position + 1)
+            .max()
+            .unwrap_or(0);
+        let rollup = ProductCategoryRollup {
+            id: next_id(state.rollups.iter().map(|rollup| rollup.id)),
+            upper_category_id,
+            lower_category_id,
+            position,
+        };
+        state.rollups.push(rollup);
+        drop(state);
+        self.refresh_slug(lower_category_id)
+    }
+
+    fn set_rollup_positions(
+        &self,
+        upper_category_id: i32,
+        lower_category_ids: &[i32],
+    ) -> QueryResult<()> {
+        let mut state = self.write();
+        for rollup in state
+            .rollups
+            .iter_mut()
+            .filter(|rollup| rollup.upper_category_id == upper_category_id)
+        {
+            if let Some(index) = lower_category_ids
+                .iter()
+                .position(|lower_id| *lower_id == rollup.lower_category_id)
+            {
+                rollup.position = index as i32;
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_rollups_of(&self, category_id: i32) -> QueryResult<usize> {
+        let mut state = self.write();
+        let before = state.rollups.len();
+        state.rollups.retain(|rollup| {
+            rollup.upper_category_id != category_id && rollup.lower_category_id != category_id
+        });
+        Ok(before - state.rollups.len())
+    }
+
+    fn slugs_below(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, String)>> {
+        let state = self.read();
+        let is_below = |id: i32| {
+            let mut uppers = state
+                .rollups
+                .iter()
+                .filter(|rollup| rollup.lower_category_id == id)
+                .map(|rollup| rollup.upper_category_id)
+                .peekable();
+            if category_ids.is_empty() {
+                uppers.peek().is_none()
+            } else {
+                uppers.any(|upper| category_ids.contains(&upper))
+            }
+        };
+        Ok(state
+            .categories
+            .values()
+            .filter(|category| !category.is_archived && is_below(category.id))
+            .map(|category| (category.id, category.slug.clone()))
+            .collect())
+    }
+
+    fn set_slug(&self, category_id: i32, slug: &str) -> QueryResult<()> {
+        if let Some(category) = self.write().categories.get_mut(&category_id) {
+            category.slug = slug.to_string();
+        }
+        Ok(())
+    }
+
+    fn archive_category(&self, category_id: i32) -> QueryResult<()> {
+        if let Some(category) = self.write().categories.get_mut(&category_id) {
+            category.is_archived = true;
+        }
+        Ok(())
+    }
+
+    fn category_translations(&self, locale: &str) -> QueryResult<Vec<CategoryTranslation>> {
+        let mut translations: Vec<CategoryTranslation> = self
+            .read()
+            .category_translations
+            .iter()
+            .filter(|translation| translation.locale == locale)
+            .cloned()
+            .collect();
+        translations.sort_by_key(|translation| translation.category_id);
+        Ok(translations)
+    }
+
+    fn translations_of_category(&self, category_id: i32) -> QueryResult<Vec<CategoryTranslation>> {
+        let mut translations: Vec<CategoryTranslation> = self
+            .read()
+            .category_translations
+            .iter()
+            .filter(|translation| translation.category_id == category_id)
+            .cloned()
+            .collect();
+        translations.sort_by(|a, b| a.locale.cmp(&b.locale));
+        Ok(translations)
+    }
+
+    fn save_category_translation(&self, translation: &CategoryTranslation) -> QueryResult<()> {
+        let mut state = self.write();
+        if !state.categories.contains_key(&translation.category_id) {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Category {} does not exist", translation.category_id),
+            ));
+        }
+        if state.category_translations.iter().any(|existing| {
+            existing.locale == translation.locale
+                && existing.name == translation.name
+                && existing.category_id != translation.category_id
+        }) {
+            return Err(violation(
+                DatabaseErrorKind::UniqueViolation,
+                format!(
+                    "Category name {} is taken in locale {}",
+                    translation.name, translation.locale
+                ),
+            ));
+        }
+        state.category_translations.retain(|existing| {
+            existing.category_id != translation.category_id || existing.locale != translation.locale
+        });
+        state.category_translations.push(translation.clone());
+        Ok(())
+    }
+
+    fn delete_category_translation(&self, category_id: i32, locale: &str) -> QueryResult<bool> {
+        let translations = &mut self.write().category_translations;
+        let count = translations.len();
+        translations.retain(|translation| {
+            translation.category_id != category_id || translation.locale != locale
+        });
+        Ok(translations.len() < count)
+    }
+
+    fn descendants(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>> {
+        let related = self.read().related_categories(category_id, true);
+        Ok(within_depth(related, max_depth))
+    }
+
+    fn ancestors(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>> {
+        let related = self.read().related_categories(category_id, false);
+        Ok(within_depth(related, max_depth))
+    }
+}
+
+impl ProductRepository for MemoryRepository {
+    fn products(&self) -> QueryResult<Vec<Product>> {
+        Ok(self.read().products.values().cloned().collect())
+    }
+
+    fn product(&self, id: i32) -> QueryResult<Option<Product>> {
+        Ok(self.read().products.get(&id).cloned())
+    }
+
+    fn products_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Product>> {
+        Ok(self
+            .read()
+            .products
+            .values()
+            .filter(|product| ids.contains(&product.id))
+            .cloned()
+            .collect())
+    }
+
+    fn insert_product(&self, description: &str) -> QueryResult<Product> {
+        let mut state = self.write();
+        let product = Product {
+            id: next_id(state.products.keys().copied()),
+            description: description.to_string(),
+            price: None,
+        };
+        state.products.insert(product.id, product.clone());
+        Ok(product)
+    }
+
+    fn products_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<Product>> {
+        Ok(self
+            .read()
+            .products
+            .range(after_id + 1..)
+            .map(|(_, product)| product.clone())
+            .take(limit as usize)
+            .collect())
+    }
+
+    fn set_product_description(&self, product_id: i32, description: &str) -> QueryResult<()> {
+        match self.write().products.get_mut(&product_id) {
+            Some(product) => {
+                product.description = description.to_string();
+                Ok(())
+            }
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn classify(
+        &self,
+        product_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<ProductCategoryClassification> {
+        let mut state = self.write();
+        if !state.products.contains_key(&product_id) {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Product {} does not exist", product_id),
+            ));
+        }
+        if !state.categories.contains_key(&category_id) {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Category {} does not exist", category_id),
+            ));
+        }
+        let classification = ProductCategoryClassification {
+            id: next_id(state.classifications.iter().map(|existing| existing.id)),
+            product_id,
+            product_category_id: category_id,
+            is_primary_classification: is_primary,
+        };
+        state.classifications.push(classification.clone());
+        Ok(classification)
+    }
+
+    fn update_classification(
+        &self,
+        classification_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<()> {
+        let mut state = self.write();
+        if !state.categories.contains_key(&category_id) {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Category {} does not exist", category_id),
+            ));
+        }
+        if let Some(classification) = state
+            .classifications
+            .iter_mut()
+            .find(|classification| classification.id == classification_id)
+        {
+            classification.product_category_id = category_id;
+            classification.is_primary_classification = is_primary;
+        }
+        Ok(())
+    }
+
+    fn delete_classifications(&self, classification_ids: &[i32]) -> QueryResult<usize> {
+        let mut state = self.write();
+        let before = state.classifications.len();
+        state
+            .classifications
+            .retain(|classification| !classification_ids.contains(&classification.id));
+        Ok(before - state.classifications.len())
+    }
+
+    fn classifications(&self) -> QueryResult<Vec<ProductCategoryClassification>> {
+        Ok(self.read().classifications.clone())
+    }
+
+    fn classifications_in(
+        &self,
+        category_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        Ok(self
+            .read()
+            .classifications
+            .iter()
+            .filter(|classification| category_ids.contains(&classification.product_category_id))
+            .cloned()
+            .collect())
+    }
+
+    fn classifications_of(
+        &self,
+        product_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        Ok(self
+            .read()
+            .classifications
+            .iter()
+            .filter(|classification| product_ids.contains(&classification.product_id))
+            .cloned()
+            .collect())
+    }
+
+    fn products_in_subtree(&self, category_id: i32) -> QueryResult<Vec<Product>> {
+        let state = self.read();
+        let mut subtree: Vec<i32> = state
+            .related_categories(category_id, true)
+            .into_iter()
+            .map(|category| category.id)
+            .collect();
+        subtree.push(category_id);
+        Ok(state
+            .products
+            .values()
+            .filter(|product| {
+                state.classifications.iter().any(|classification| {
+                    classification.product_id == product.id
+                        && subtree.contains(&classification.product_category_id)
+                })
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn product_translations(&self, locale: &str) -> QueryResult<Vec<ProductTranslation>> {
+        let mut translations: Vec<ProductTranslation> = self
+            .read()
+            .product_translations
+            .iter()
+            .filter(|translation| translation.locale == locale)
+            .cloned()
+            .collect();
+        translations.sort_by_key(|translation| translation.product_id);
+        Ok(translations)
+    }
+
+    fn translations_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductTranslation>> {
+        let mut translations: Vec<ProductTranslation> = self
+            .read()
+            .product_translations
+            .iter()
+            .filter(|translation| translation.product_id == product_id)
+            .cloned()
+            .collect();
+        translations.sort_by(|a, b| a.locale.cmp(&b.locale));
+        Ok(translations)
+    }
+
+    fn save_product_translation(&self, translation: &ProductTranslation) -> QueryResult<()> {
+        let mut state = self.write();
+        if !state.products.contains_key(&translation.product_id) {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Product {} does not exist", translation.product_id),
+            ));
+        }
+        state.product_translations.retain(|existing| {
+            existing.product_id != translation.product_id || existing.locale != translation.locale
+        });
+        state.product_translations.push(translation.clone());
+        Ok(())
+    }
+
+    fn delete_product_translation(&self, product_id: i32, locale: &str) -> QueryResult<bool> {
+        let translations = &mut self.write().product_translations;
+        let count = translations.len();
+        translations.retain(|translation| {
+            translation.product_id != product_id || translation.locale != locale
+        });
+        Ok(translations.len() < count)
+    }
+
+    fn set_product_price(&self, product_id: i32, price: Option<i32>) -> QueryResult<()> {
+        match self.write().products.get_mut(&product_id) {
+            Some(product) => {
+                product.price = price;
+                Ok(())
+            }
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn attributes_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductAttribute>> {
+        let mut attributes: Vec<ProductAttribute> = self
+            .read()
+            .product_attributes
+            .iter()
+            .filter(|attribute| attribute.product_id == product_id)
+            .cloned()
+            .collect();
+        attributes.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(attributes)
+    }
+
+    fn save_product_attribute(&self, attribute: &ProductAttribute) -> QueryResult<()> {
+        let mut state = self.write();
+        if !state.products.contains_key(&attribute.product_id) {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Product {} does not exist", attribute.product_id),
+            ));
+        }
+        state.product_attributes.retain(|existing| {
+            existing.product_id != attribute.product_id || existing.name != attribute.name
+        });
+        state.product_attributes.push(attribute.clone());
+        Ok(())
+    }
+
+    fn delete_product_attribute(&self, product_id: i32, name: &str) -> QueryResult<bool> {
+        let attributes = &mut self.write().product_attributes;
+        let count = attributes.len();
+        attributes.retain(|attribute| attribute.product_id != product_id || attribute.name != name);
+        Ok(attributes.len() < count)
+    }
+}
+
+impl StockRepository for MemoryRepository {
+    fn insert_inventory_item(
+        &self,
+        product_id: i32,
+        warehouse_id: Option<i32>,
+        instance_description: Option<&str>,
+    ) -> QueryResult<InventoryItem> {
+        let mut state = self.write();
+        if !state.products.contains_key(&product_id) {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Product {} does not exist", product_id),
+            ));
+        }
+        if let Some(warehouse_id) = warehouse_id.filter(|id| !state.warehouses.contains_key(id)) {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Warehouse {} does not exist", warehouse_id),
+            ));
+        }
+        let item = InventoryItem {
+            id: next_id(state.inventory_items.iter().map(|item| item.id)),
+            product_id,
+            instance_description: instance_description.map(String::from),
+            warehouse_id,
+        };
+        state.inventory_items.push(item.clone());
+        Ok(item)
+    }
+
+    fn inventory_item(&self, id: i32) -> QueryResult<Option<InventoryItem>> {
+        Ok(self
+            .read()
+            .inventory_items
+            .iter()
+            .find(|item| item.id == id)
+            .cloned())
+    }
+
+    fn inventory_items_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<InventoryItem>> {
+        Ok(self
+            .read()
+            .inventory_items
+            .iter()
+            .filter(|item| item.id > after_id)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    fn inventory_items(&self) -> QueryResult<Vec<InventoryItem>> {
+        Ok(self.read().inventory_items.clone())
+    }
+
+    fn inventory_items_of(&self, product_ids: &[i32]) -> QueryResult<Vec<InventoryItem>> {
+        Ok(self
+            .read()
+            .inventory_items
+            .iter()
+            .filter(|item| product_ids.contains(&item.product_id))
+            .cloned()
+            .collect())
+    }
+
+    fn inventory_items_in(&self, warehouse_ids: &[i32]) -> QueryResult<Vec<InventoryItem>> {
+        Ok(self
+            .read()
+            .inventory_items
+            .iter()
+            .filter(|item| {
+                item.warehouse_id
+                    .map_or(false, |warehouse_id| warehouse_ids.contains(&warehouse_id))
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn update_inventory_item(&self, item: &InventoryItem) -> QueryResult<()> {
+        let mut state = self.write();
+        if !state.products.contains_key(&item.product_id) {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Product {} does not exist", item.product_id),
+            ));
+        }
+        if let Some(warehouse_id) = item
+            .warehouse_id
+            .filter(|id| !state.warehouses.contains_key(id))
+        {
+            return Err(violation(
+                DatabaseErrorKind::ForeignKeyViolation,
+                format!("Warehouse {} does not exist", warehouse_id),
+            ));
+        }
+        match state
+            .inventory_items
+            .iter_mut()
+            .find(|existing| existing.id == item.id)
+        {
+            Some(existing) => {
+                *existing = item.clone();
+                Ok(())
+            }
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn stock_levels(&self) -> QueryResult<Vec<StockLevel>> {
+        let state = self.read();
+        // Items without a warehouse sort last, like nulls in the database
+        let mut quantities: BTreeMap<(i32, bool, Option<i32>), i64> = BTreeMap::new();
+        for item in &state.inventory_items {
+            *quantities
+                .entry((
+                    item.product_id,
+                    item.warehouse_id.is_none(),
+                    item.warehouse_id,
+                ))
+                .or_default() += 1;
+        }
+        Ok(quantities
+            .into_iter()
+            .map(|((product_id, _, warehouse_id), quantity)| StockLevel {
+                product_id,
+                product_description: state.products[&product_id].description.clone(),
+                warehouse_id,
+                warehouse_description: warehouse_id
+                    .map(|id| state.warehouses[&id].description.clone()),
+                quantity,
+            })
+            .collect())
+    }
+}
+
+impl StatisticsRepository for MemoryRepository {
+    fn category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>> {
+        let state = self.read();
+        let products_in = |category_ids: &[i32]| -> BTreeSet<i32> {
+            state
+                .classifications
+                .iter()
+                .filter(|classification| category_ids.contains(&classification.product_category_id))
+                .map(|classification| classification.product_id)
+                .collect()
+        };
+        Ok(state
+            .categories
+            .keys()
+            .map(|category_id| {
+                let mut subtree: Vec<i32> = state
+                    .related_categories(*category_id, true)
+                    .into_iter()
+                    .map(|category| category.id)
+                    .filter(|id| !state.categories[id].is_archived)
+                    .collect();
+                subtree.push(*category_id);
+                let products = products_in(&subtree);
+                // Items without a warehouse sort last, like nulls in the database
+                let mut quantities: BTreeMap<(bool, Option<i32>), i64> = BTreeMap::new();
+                for item in &state.inventory_items {
+                    if products.contains(&item.product_id) {
+                        *quantities
+                            .entry((item.warehouse_id.is_none(), item.warehouse_id))
+                            .or_default() += 1;
+                    }
+                }
+                let stock: Vec<WarehouseStock> = quantities
+                    .into_iter()
+                    .map(|((_, warehouse_id), quantity)| WarehouseStock {
+                        warehouse_id,
+                        warehouse_description: warehouse_id
+                            .map(|id| state.warehouses[&id].description.clone()),
+                        quantity,
+                    })
+                    .collect();
+                CategoryStatistics {
+                    category_id: *category_id,
+                    direct_products: products_in(&[*category_id]).len() as i64,
+                    products: products.len() as i64,
+                    units_in_stock: stock.iter().map(|stock| stock.quantity).sum(),
+                    stock,
+                    refreshed_at: None,
+                }
+            })
+            .collect())
+    }
+
+    fn category_statistics_of(&self, category_id: i32) -> QueryResult<Option<CategoryStatistics>> {
+        Ok(self
+            .category_statistics()?
+            .into_iter()
+            .find(|statistics| statistics.category_id == category_id))
+    }
+
+    fn store_category_statistics(&self, statistics: &[CategoryStatistics]) -> QueryResult<()> {
+        let refreshed_at = chrono::Utc::now();
+        self.write().statistics = statistics
+            .iter()
+            .map(|statistics| CategoryStatistics {
+                refreshed_at: Some(refreshed_at),
+                ..statistics.clone()
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn stored_category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>> {
+        let state = self.read();
+        Ok(state
+            .statistics
+            .iter()
+            .filter(|statistics| state.categories.contains_key(&statistics.category_id))
+            .cloned()
+            .collect())
+    }
+
+    fn stored_category_statistics_of(
+        &self,
+        category_id: i32,
+    ) -> QueryResult<Option<CategoryStatistics>> {
+        Ok(self
+            .stored_category_statistics()?
+            .into_iter()
+            .find(|statistics| statistics.category_id == category_id))
+    }
+
+    fn catalog_counts(&self) -> QueryResult<CatalogCounts> {
+        let state = self.read();
+        // Items without a warehouse are counted last, like nulls in the database
+        let mut warehouse_items: BTreeMap<(bool, Option<i32>), i64> = BTreeMap::new();
+        for item in &state.inventory_items {
+            *warehouse_items
+                .entry((item.warehouse_id.is_none(), item.warehouse_id))
+                .or_default() += 1;
+        }
+        Ok(CatalogCounts {
+            categories: state.categories.len() as i64,
+            products: state.products.len() as i64,
+            warehouse_items: warehouse_items
+                .into_iter()
+                .map(|((_, warehouse_id), items)| (warehouse_id, items))
+                .collect(),
+        })
+    }
+}
+
+impl SearchRepository for MemoryRepository {
+    fn search_products(&self, search: &ProductSearch) -> QueryResult<Vec<SearchHit>> {
+        let state = self.read();
+        let subtree: Option<Vec<i32>> = search.category_id.map(|category_id| {
+            let mut subtree: Vec<i32> = state
+                .related_categories(category_id, true)
+                .into_iter()
+                .map(|category| category.id)
+                .collect();
+            subtree.push(category_id);
+            subtree
+        });
+        let products: Vec<Product> = state
+            .products
+            .values()
+            .filter(|product| {
+                subtree.as_ref().map_or(true, |subtree| {
+                    state.classifications.iter().any(|classification| {
+                        classification.product_id == product.id
+                            && subtree.contains(&classification.product_category_id)
+                    })
+                })
+            })
+            .filter(|product| {
+                let items = || {
+                    state
+                        .inventory_items
+                        .iter()
+                        .filter(|item| item.product_id == product.id)
+                };
+                search.warehouse_id.map_or(true, |warehouse_id| {
+                    items().any(|item| item.warehouse_id == Some(warehouse_id))
+                }) && (!search.in_stock || items().next().is_some())
+            })
+            .cloned()
+            .collect();
+        let category_names: Vec<(i32, String)> = state
+            .classifications
+            .iter()
+            .map(|classification| {
+                (
+                    classification.product_id,
+                    state.categories[&classification.product_category_id]
+                        .name
+                        .clone(),
+                )
+            })
+            .collect();
+        Ok(search::rank(products, &category_names, search))
+    }
+}
+
+impl FacetRepository for MemoryRepository {
+    fn facet_counts(
+        &self,
+        filters: &ListingFilters,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<FacetCounts> {
+        let state = self.read();
+        let mut placements = BTreeSet::new();
+        for classification in &state.classifications {
+            let category_id = classification.product_category_id;
+            placements.insert((classification.product_id, category_id));
+            for ancestor in state.related_categories(category_id, false) {
+                placements.insert((classification.product_id, ancestor.id));
+            }
+        }
+        let stocked: BTreeSet<(i32, i32)> = state
+            .inventory_items
+            .iter()
+            .filter_map(|item| Some((item.product_id, item.warehouse_id?)))
+            .collect();
+        let mut attributes = state.product_attributes.clone();
+        attributes.sort_by(|a, b| (a.product_id, &a.name).cmp(&(b.product_id, &b.name)));
+        let index = CatalogIndex {
+            products: state.products.values().cloned().collect(),
+            placements: placements.into_iter().collect(),
+            stocked: stocked.into_iter().collect(),
+            attributes,
+        };
+        Ok(facets::count_facets(&index, filters, limit, offset))
+    }
+}
+
+impl WarehouseRepository for MemoryRepository {
+    fn warehouses(&self) -> QueryResult<Vec<Warehouse>> {
+        Ok(self.read().warehouses.values().cloned().collect())
+    }
+
+    fn warehouse(&self, id: i32) -> QueryResult<Option<Warehouse>> {
+        Ok(self.read().warehouses.get(&id).cloned())
+    }
+
+    fn warehouses_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Warehouse>> {
+        Ok(self
+            .read()
+            .warehouses
+            .values()
+            .filter(|warehouse| ids.contains(&warehouse.id))
+            .cloned()
+            .collect())
+    }
+
+    fn insert_warehouse(&self, description: &str) -> QueryResult<Warehouse> {
+        let mut state = self.write();
+        let warehouse = Warehouse {
+            id: next_id(state.warehouses.keys().copied()),
+            description: description.to_string(),
+        };
+        state.warehouses.insert(warehouse.id, warehouse.clone());
+        Ok(warehouse)
+    }
+}
+
+impl ApiKeyRepository for MemoryRepository {
+    fn api_key(&self, key_hash: &str) -> QueryResult<Option<ApiKey>> {
+        Ok(self
+            .read()
+            .api_keys
+            .iter()
+            .find(|api_key| api_key.key_hash == key_hash)
+            .cloned())
+    }
+
+    fn insert_api_key(&self, key_hash: &str, role: Role, description: &str) -> QueryResult<ApiKey> {
+        let mut state = self.write();
+        if state
+            .api_keys
+            .iter()
+            .any(|api_key| api_key.key_hash == key_hash)
+        {
+            return Err(violation(
+                DatabaseErrorKind::UniqueViolation,
+                "API key is taken".to_string(),
+            ));
+        }
+        let api_key = ApiKey {
+            id: next_id(state.api_keys.iter().map(|api_key| api_key.id)),
+            key_hash: key_hash.to_string(),
+            role: role.as_str().to_string(),
+            description: description.to_string(),
+            tenant_id: None,
+        };
+        state.api_keys.push(api_key.clone());
+        Ok(api_key)
+    }
+}
+
+impl IdempotencyRepository for MemoryRepository {
+    fn claim_idempotency_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        retention: Duration,
+        claim_timeout: Duration,
+    ) -> QueryResult<Claim> {
+        let mut state = self.write();
+        let (retained_since, claimed_since) = (before_now(retention), before_now(claim_timeout));
+        state.idempotency_keys.retain(|_, stored| {
+            stored.created_at >= retained_since
+                && (stored.response.is_some() || stored.created_at >= claimed_since)
+        });
+        let stored = match state.idempotency_keys.get(key) {
+            Some(stored) => stored,
+            None => {
+                let claimed_at = Utc::now();
+                state.idempotency_keys.insert(
+                    key.to_string(),
+                    IdempotencyKey {
+                        fingerprint: fingerprint.to_string(),
+                        created_at: claimed_at,
+                        response: None,
+                    },
+                );
+                return Ok(Claim::Claimed(claimed_at));
+            }
+        };
+        if stored.fingerprint != fingerprint {
+            return Ok(Claim::Mismatch);
+        }
+        Ok(match &stored.response {
+            Some(response) => Claim::Completed(response.clone()),
+            None => Claim::InProgress,
+        })
+    }
+
+    fn complete_idempotency_key(
+        &self,
+        key: &str,
+        claimed_at: DateTime<Utc>,
+        response: &StoredResponse,
+    ) -> QueryResult<()> {
+        let mut state = self.write();
+        if let Some(stored) = state.idempotency_keys.get_mut(key) {
+            if stored.created_at == claimed_at && stored.response.is_none() {
+                stored.response = Some(response.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn release_idempotency_key(&self, key: &str, claimed_at: DateTime<Utc>) -> QueryResult<()> {
+        let mut state = self.write();
+        let claimed = state.idempotency_keys.get(key).map_or(false, |stored| {
+            stored.created_at == claimed_at && stored.response.is_none()
+        });
+        if claimed {
+            state.idempotency_keys.remove(key);
+        }
+        Ok(())
+    }
+}
+
+impl OutboxRepository for MemoryRepository {
+    fn outbox_event(&self, id: i64) -> QueryResult<Option<OutboxEvent>> {
+        Ok(self
+            .read()
+            .outbox_events
+            .iter()
+            .find(|event| event.id == id)
+            .cloned())
+    }
+
+    fn outbox_tenant_id(&self) -> QueryResult<Option<i32>> {
+        Ok(None)
+    }
+
+    fn stream_horizon(&self) -> QueryResult<i64> {
+        Ok(self.read().outbox_events.last().map_or(0, |event| event.id) + 1)
+    }
+
+    fn events_after(
+        &self,
+        position: StreamPosition,
+        horizon: i64,
+        limit: i64,
+    ) -> QueryResult<Vec<OutboxEvent>> {
+        Ok(self
+            .read()
+            .outbox_events
+            .iter()
+            .filter(|event| event.transaction_id < horizon)
+            .filter(|event| StreamPosition::of(event) > position)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    fn first_transaction_after(&self, event_id: i64) -> QueryResult<Option<i64>> {
+        Ok(self
+            .read()
+            .outbox_events
+            .iter()
+            .find(|event| event.id > event_id)
+            .map(|event| event.transaction_id))
+    }
+}
+
+impl WebhookRepository for MemoryRepository {
+    fn webhook_subscriptions(&self) -> QueryResult<Vec<WebhookSubscription>> {
+        Ok(self
+            .read()
+            .webhook_subscriptions
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn webhook_subscription(&self, id: i32) -> QueryResult<Option<WebhookSubscription>> {
+        Ok(self.read().webhook_subscriptions.get(&id).cloned())
+    }
+
+    fn insert_webhook_subscription(
+        &self,
+        url: &str,
+        event_types: &[String],
+        secret: &str,
+    ) -> QueryResult<WebhookSubscription> {
+        let mut state = self.write();
+        let subscription = WebhookSubscription {
+            id: next_id(state.webhook_subscriptions.keys().copied()),
+            url: url.to_string(),
+            event_types: event_types.to_vec(),
+            secret: secret.to_string(),
+            active: true,
+        };
+        state
+            .webhook_subscriptions
+            .insert(subscription.id, subscription.clone());
+        Ok(subscription)
+    }
+
+    fn delete_webhook_subscription(&self, id: i32) -> QueryResult<bool> {
+        let mut state = self.write();
+        state
+            .webhook_deliveries
+            .retain(|delivery| delivery.webhook_subscription_id != id);
+        Ok(state.webhook_subscriptions.remove(&id).is_some())
+    }
+
+    fn webhook_deliveries(&self) -> QueryResult<Vec<WebhookDelivery>> {
+        Ok(self.read().webhook_deliveries.clone())
+    }
+
+    fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        leased_until: DateTime<Utc>,
+    ) -> QueryResult<Vec<WebhookDelivery>> {
+        let mut state = self.write();
+        let now = Utc::now();
+        let mut claimed = Vec::new();
+        for delivery in state
+            .webhook_deliveries
+            .iter_mut()
+            .filter(|delivery| delivery.status == DELIVERY_PENDING)
+            .filter(|delivery| delivery.next_attempt_at <= now)
+            .take(limit as usize)
+        {
+            claimed.push(delivery.clone());
+            delivery.next_attempt_at = leased_until;
+        }
+        Ok(claimed)
+    }
+
+    fn record_delivery_attempt(
+        &self,
+        delivery: &WebhookDelivery,
+        attempt: &DeliveryAttempt,
+    ) -> QueryResult<()> {
+        let mut state = self.write();
+        let claimed_delivery = state
+            .webhook_deliveries
+            .iter_mut()
+            .find(|stored| stored.id == delivery.id && stored.attempts == delivery.attempts);
+        if let Some(stored) = claimed_delivery {
+            stored.status = attempt.status.to_string();
+            stored.attempts = delivery.attempts + 1;
+            stored.last_error = attempt.last_error.clone();
+            if let Some(next_attempt_at) = attempt.next_attempt_at {
+                stored.next_attempt_at = next_attempt_at;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1479 @@
+//! Storage of the aggregates behind one trait per aggregate
+//!
+//! Controllers and domain services only depend on these traits. [`PgConnection`] implements all
+//! of them against the database, while [`memory::MemoryRepository`] keeps the aggregates in
+//! memory, so that business rules can be tested without a database. With the `sqlite` feature
+//! `SqliteConnection` implements them as well, and routes reach either database through a
+//! [`BackendConn`].
+//!
+//! [`PgConnection`]: diesel::PgConnection
+
+mod conn;
+pub mod memory;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use conn::BackendConn;
+pub use memory::MemoryRepository;
+
+use crate::{
+    auth::{entities::ApiKey, Role},
+    catalog::{
+        entities::ProductAttribute,
+        facets::{FacetCounts, ListingFilters},
+    },
+    entities::{
+        InventoryItem, Product, ProductCategoryClassification, ProductCategoryRollup, Warehouse,
+    },
+    events::broadcaster::StreamPosition,
+    idempotency::store::{Claim, StoredResponse},
+    inventory::reports::StockLevel,
+    localization::entities::{CategoryTranslation, ProductTranslation},
+    metrics::gauges::CatalogCounts,
+    outbox::{entities::OutboxEvent, EventType},
+    product_category::{
+        entities::{ProductCategory, RelatedCategory},
+        slug,
+        statistics::CategoryStatistics,
+    },
+    search::{ProductSearch, SearchHit},
+    webhooks::entities::{DeliveryAttempt, WebhookDelivery, WebhookSubscription},
+};
+use chrono::{DateTime, Utc};
+use diesel::QueryResult;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Groups changes, so that either all or none of them are kept
+pub trait Transactional {
+    /// Runs the closure and keeps its changes only if it succeeds
+    ///
+    /// Calls may be nested, in which case a failing inner closure only discards its own changes.
+    fn atomically<T, F>(&self, f: F) -> QueryResult<T>
+    where
+        F: FnOnce() -> QueryResult<T>;
+
+    /// Starts a read-only transaction in which every query sees the data as of the first one, for
+    /// reads which span several calls, like a streamed export
+    ///
+    /// The transaction lasts until [`Transactional::end_snapshot`] is called.
+    fn begin_snapshot(&self) -> QueryResult<()>;
+
+    fn end_snapshot(&self) -> QueryResult<()>;
+}
+
+/// Domain events which are published for the changes of the aggregates
+pub trait EventLog {
+    /// Records one event of the same type per payload
+    ///
+    /// Has to be called within the same [`Transactional::atomically`] block as the changes
+    /// described by the events.
+    fn record_all<T: Serialize>(&self, event_type: EventType, payloads: &[T])
+        -> QueryResult<usize>;
+
+    /// Records a single event
+    fn record<T: Serialize>(&self, event_type: EventType, payload: &T) -> QueryResult<()> {
+        self.record_all(event_type, std::slice::from_ref(payload))
+            .map(|_| ())
+    }
+}
+
+/// Product categories and the hierarchy they are rolled up into
+pub trait CategoryRepository: Transactional + EventLog {
+    /// All categories, ordered by id
+    fn categories(&self) -> QueryResult<Vec<ProductCategory>>;
+
+    fn category(&self, id: i32) -> QueryResult<Option<ProductCategory>>;
+
+    fn categories_named(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>>;
+
+    /// Categories with one of the ids, ordered by id
+    fn categories_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<ProductCategory>>;
+
+    /// Page of the categories with ids above `after_id`, ordered by id
+    fn categories_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<ProductCategory>>;
+
+    /// Categories with one of the ids or names, which are locked until the end of the transaction
+    fn lock_categories(&self, ids: &[i32], names: &[&str]) -> QueryResult<Vec<ProductCategory>>;
+
+    /// Fails with a unique violation if the name is taken
+    fn insert_category(&self, name: &str) -> QueryResult<ProductCategory>;
+
+    /// Creates the categories whose names are not taken yet and returns only those
+    fn insert_missing_categories(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>>;
+
+    /// Creates the categories with their ids, or renames them and replaces their display metadata
+    /// if they exist
+    fn save_categories(&self, categories: &[ProductCategory]) -> QueryResult<()>;
+
+    /// Deletes the categories and returns the ids of those which existed
+    ///
+    /// Fails with a foreign key violation if a category is still rolled up or classifies a
+    /// product.
+    fn delete_categories(&self, ids: &[i32]) -> QueryResult<Vec<i32>>;
+
+    /// All `(upper, lower)` links of the hierarchy, ordered by the upper category and the position
+    /// of the link
+    fn rollups(&self) -> QueryResult<Vec<(i32, i32)>>;
+
+    /// Links of the hierarchy in which one of the categories is the upper or lower category, in the
+    /// order of [`CategoryRepository::rollups`]
+    fn rollups_of(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, i32)>>;
+
+    /// All links of the hierarchy with their ids and positions, ordered by id
+    fn rollup_links(&self) -> QueryResult<Vec<ProductCategoryRollup>>;
+
+    /// Links the categories and renumbers the slug of the lower category if the upper category
+    /// has a subcategory with the same slug
+    ///
+    /// The link is positioned after the other subcategories of the upper category.
+    fn insert_rollup(&self, upper_category_id: i32, lower_category_id: i32) -> QueryResult<()>;
+
+    /// Positions the links from the category to the lower categories in the given order
+    fn set_rollup_positions(
+        &self,
+        upper_category_id: i32,
+        lower_category_ids: &[i32],
+    ) -> QueryResult<()>;
+
+    /// Removes every link in which the category is the upper or lower category and returns how
+    /// many there were
+    fn delete_rollups_of(&self, category_id: i32) -> QueryResult<usize>;
+
+    /// `(id, slug)` of the categories rolled up into any of the categories, ordered by id
+    ///
+    /// Without any category, the root categories are returned, which are not rolled up at all.
+    /// Archived categories are left out, so that their slugs can be taken again.
+    fn slugs_below(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, String)>>;
+
+    fn set_slug(&self, category_id: i32, slug: &str) -> QueryResult<()>;
+
+    /// Marks the category as archived, which is never undone
+    fn archive_category(&self, category_id: i32) -> QueryResult<()>;
+
+    /// Names of the categories translated into the locale, ordered by category id
+    fn category_translations(&self, locale: &str) -> QueryResult<Vec<CategoryTranslation>>;
+
+    /// Names of the category in every locale it is translated into, ordered by locale
+    fn translations_of_category(&self, category_id: i32) -> QueryResult<Vec<CategoryTranslation>>;
+
+    /// Adds the name of the category in the locale or replaces it
+    ///
+    /// Fails with a unique violation if another category has the name in the locale.
+    fn save_category_translation(&self, translation: &CategoryTranslation) -> QueryResult<()>;
+
+    /// Removes the name of the category in the locale and returns whether there was one
+    fn delete_category_translation(&self, category_id: i32, locale: &str) -> QueryResult<bool>;
+
+    /// Gives the category a slug which belongs to its name and which none of its siblings has
+    ///
+    /// Called by the implementations whenever a category is created, renamed or rolled up.
+    fn refresh_slug(&self, category_id: i32) -> QueryResult<()> {
+        let category = match self.category(category_id)? {
+            Some(category) => category,
+            None => return Ok(()),
+        };
+        let parent_ids: Vec<i32> = self
+            .rollups_of(&[category_id])?
+            .into_iter()
+            .filter(|(_, lower_id)| *lower_id == category_id)
+            .map(|(upper_id, _)| upper_id)
+            .collect();
+        let taken: Vec<String> = self
+            .slugs_below(&parent_ids)?
+            .into_iter()
+            .filter(|(sibling_id, _)| *sibling_id != category_id)
+            .map(|(_, slug)| slug)
+            .collect();
+        let slug = slug::choose(&category.name, &category.slug, &taken);
+        if slug != category.slug {
+            self.set_slug(category_id, &slug)?;
+        }
+        Ok(())
+    }
+
+    /// Categories rolled up into the category, directly or through others, ordered by depth and id
+    ///
+    /// If a maximal depth is given, only categories up to that many links below the category are
+    /// returned.
+    fn descendants(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>>;
+
+    /// Categories the category is rolled up into, like [`CategoryRepository::descendants`]
+    fn ancestors(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>>;
+}
+
+/// Products and their classification into categories
+pub trait ProductRepository: Transactional + EventLog {
+    /// All products, ordered by id
+    fn products(&self) -> QueryResult<Vec<Product>>;
+
+    fn product(&self, id: i32) -> QueryResult<Option<Product>>;
+
+    /// Products with one of the ids, ordered by id
+    fn products_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Product>>;
+
+    fn insert_product(&self, description: &str) -> QueryResult<Product>;
+
+    /// Page of the products with ids above `after_id`, ordered by id
+    fn products_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<Product>>;
+
+    /// Fails with [`diesel::result::Error::NotFound`] if the product does not exist
+    fn set_product_description(&self, product_id: i32, description: &str) -> QueryResult<()>;
+
+    /// Fails with a foreign key violation if the product or category does not exist
+    fn classify(
+        &self,
+        product_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<ProductCategoryClassification>;
+
+    /// Moves the classification into the category and sets whether it is primary
+    ///
+    /// Fails with a foreign key violation if the category does not exist.
+    fn update_classification(
+        &self,
+        classification_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<()>;
+
+    /// Deletes the classifications and returns how many of them existed
+    fn delete_classifications(&self, classification_ids: &[i32]) -> QueryResult<usize>;
+
+    /// All classifications, ordered by id
+    fn classifications(&self) -> QueryResult<Vec<ProductCategoryClassification>>;
+
+    /// Classifications into any of the categories, ordered by id
+    fn classifications_in(
+        &self,
+        category_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>>;
+
+    /// Classifications of any of the products, ordered by id
+    fn classifications_of(
+        &self,
+        product_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>>;
+
+    /// Products classified into the category or any category rolled up into it, ordered by id
+    fn products_in_subtree(&self, category_id: i32) -> QueryResult<Vec<Product>>;
+
+    /// Descriptions of the products translated into the locale, ordered by product id
+    fn product_translations(&self, locale: &str) -> QueryResult<Vec<ProductTranslation>>;
+
+    /// Descriptions of the product in every locale it is translated into, ordered by locale
+    fn translations_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductTranslation>>;
+
+    /// Adds the description of the product in the locale or replaces it
+    fn save_product_translation(&self, translation: &ProductTranslation) -> QueryResult<()>;
+
+    /// Removes the description of the product in the locale and returns whether there was one
+    fn delete_product_translation(&self, product_id: i32, locale: &str) -> QueryResult<bool>;
+
+    /// Fails with [`diesel::result::Error::NotFound`] if the product does not exist
+    fn set_product_price(&self, product_id: i32, price: Option<i32>) -> QueryResult<()>;
+
+    /// Attribute values of the product, ordered by attribute name
+    fn attributes_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductAttribute>>;
+
+    /// Adds the value of the attribute of the product or replaces it
+    fn save_product_attribute(&self, attribute: &ProductAttribute) -> QueryResult<()>;
+
+    /// Removes the value of the attribute of the product and returns whether there was one
+    fn delete_product_attribute(&self, product_id: i32, name: &str) -> QueryResult<bool>;
+}
+
+/// Inventory items and the stock they add up to
+pub trait StockRepository: Transactional {
+    /// Fails with a foreign key violation if the product or warehouse does not exist
+    fn insert_inventory_item(
+        &self,
+        product_id: i32,
+        warehouse_id: Option<i32>,
+        instance_description: Option<&str>,
+    ) -> QueryResult<InventoryItem>;
+
+    fn inventory_item(&self, id: i32) -> QueryResult<Option<InventoryItem>>;
+
+    /// Page of the inventory items with ids above `after_id`, ordered by id
+    fn inventory_items_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<InventoryItem>>;
+
+    /// All inventory items, ordered by id
+    fn inventory_items(&self) -> QueryResult<Vec<InventoryItem>>;
+
+    /// Inventory items of any of the products, ordered by id
+    fn inventory_items_of(&self, product_ids: &[i32]) -> QueryResult<Vec<InventoryItem>>;
+
+    /// Inventory items stocked in any of the warehouses, ordered by id
+    fn inventory_items_in(&self, warehouse_ids: &[i32]) -> QueryResult<Vec<InventoryItem>>;
+
+    /// Moves the item to its product and warehouse and replaces its description
+    ///
+    /// Fails with [`diesel::result::Error::NotFound`] if the item does not exist, and with a
+    /// foreign key violation if the product or warehouse does not exist.
+    fn update_inventory_item(&self, item: &InventoryItem) -> QueryResult<()>;
+
+    /// Stock of every product per warehouse, ordered by product and warehouse
+    ///
+    /// Items without a warehouse are counted last.
+    fn stock_levels(&self) -> QueryResult<Vec<StockLevel>>;
+}
+
+/// Product counts and stock of the categories, computed live or kept as a snapshot
+pub trait StatisticsRepository: Transactional {
+    /// Statistics of every category computed from the catalog and inventory, ordered by category
+    /// id
+    ///
+    /// Archived categories below a category are not counted, so that the products of a merged
+    /// category are only counted in the one it was merged into.
+    fn category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>>;
+
+    /// Statistics of the category computed from its subtree only, or `None` if it does not exist
+    fn category_statistics_of(&self, category_id: i32) -> QueryResult<Option<CategoryStatistics>>;
+
+    /// Replaces the snapshot with the statistics
+    fn store_category_statistics(&self, statistics: &[CategoryStatistics]) -> QueryResult<()>;
+
+    /// Statistics of the snapshot with the time it was taken, ordered by category id
+    ///
+    /// Categories and warehouses deleted since then are left out.
+    fn stored_category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>>;
+
+    /// Statistics of the category in the snapshot, or `None` if it is not part of it
+    fn stored_category_statistics_of(
+        &self,
+        category_id: i32,
+    ) -> QueryResult<Option<CategoryStatistics>>;
+
+    /// Numbers of categories, products and inventory items, computed live
+    fn catalog_counts(&self) -> QueryResult<CatalogCounts>;
+}
+
+/// Search of the products by the words of their texts
+pub trait SearchRepository: Transactional {
+    /// Products matching the words of the search and its filters, the most relevant first
+    fn search_products(&self, search: &ProductSearch) -> QueryResult<Vec<SearchHit>>;
+}
+
+/// Facts by which product listings are filtered and counted
+pub trait FacetRepository: Transactional {
+    /// Page of the products matching the filters, ordered by id, with the product counts of
+    /// every facet
+    fn facet_counts(
+        &self,
+        filters: &ListingFilters,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<FacetCounts>;
+}
+
+/// Locations at which the inventory is stocked
+pub trait WarehouseRepository: Transactional {
+    /// All warehouses, ordered by id
+    fn warehouses(&self) -> QueryResult<Vec<Warehouse>>;
+
+    fn warehouse(&self, id: i32) -> QueryResult<Option<Warehouse>>;
+
+    /// Warehouses with one of the ids, ordered by id
+    fn warehouses_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Warehouse>>;
+
+    fn insert_warehouse(&self, description: &str) -> QueryResult<Warehouse>;
+}
+
+/// API keys, which are looked up before the tenant of a request is known
+pub trait ApiKeyRepository {
+    /// Key with the SHA-256 hash, whichever tenant it is bound to
+    fn api_key(&self, key_hash: &str) -> QueryResult<Option<ApiKey>>;
+
+    /// Stores a key by its hash, bound to the tenant the connection is scoped to on Postgres
+    fn insert_api_key(&self, key_hash: &str, role: Role, description: &str) -> QueryResult<ApiKey>;
+}
+
+/// Keys of requests made through [`crate::idempotency::Idempotency`], with their responses
+pub trait IdempotencyRepository {
+    /// Claims the key for the request with the fingerprint
+    ///
+    /// Keys older than the retention period are discarded first, so that they can be claimed
+    /// again. So are claims which are still in progress after the claim timeout, since the
+    /// request which made them most likely died without completing or releasing the key.
+    fn claim_idempotency_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        retention: Duration,
+        claim_timeout: Duration,
+    ) -> QueryResult<Claim>;
+
+    /// Stores the response of the request which claimed the key at the time
+    ///
+    /// Nothing is stored if the claim timed out and the key was claimed again in the meantime.
+    fn complete_idempotency_key(
+        &self,
+        key: &str,
+        claimed_at: DateTime<Utc>,
+        response: &StoredResponse,
+    ) -> QueryResult<()>;
+
+    /// Frees the key again, for requests which failed in a way that is worth retrying
+    ///
+    /// Only the claim made at the time is freed, not one which replaced it after a timeout.
+    fn release_idempotency_key(&self, key: &str, claimed_at: DateTime<Utc>) -> QueryResult<()>;
+}
+
+/// Domain events as they were recorded through the [`EventLog`]
+///
+/// Events are streamed in the order of their [`StreamPosition`], but only those of transactions
+/// before the stream horizon, which have all finished.
+pub trait OutboxRepository {
+    fn outbox_event(&self, id: i64) -> QueryResult<Option<OutboxEvent>>;
+
+    /// Tenant whose events are read, which is `None` for an SQLite database
+    fn outbox_tenant_id(&self) -> QueryResult<Option<i32>>;
+
+    /// Transaction before which every transaction recording events has finished
+    fn stream_horizon(&self) -> QueryResult<i64>;
+
+    /// Up to `limit` events following the position, of the transactions before the horizon
+    fn events_after(
+        &self,
+        position: StreamPosition,
+        horizon: i64,
+        limit: i64,
+    ) -> QueryResult<Vec<OutboxEvent>>;
+
+    /// Transaction of the first event recorded with an id above the given one
+    fn first_transaction_after(&self, event_id: i64) -> QueryResult<Option<i64>>;
+}
+
+/// Endpoints subscribed to the domain events, and the deliveries of the events to them
+///
+/// Deliveries are queued by the database itself whenever an event is recorded, for every active
+/// subscription to its type.
+pub trait WebhookRepository {
+    /// All subscriptions, ordered by id
+    fn webhook_subscriptions(&self) -> QueryResult<Vec<WebhookSubscription>>;
+
+    fn webhook_subscription(&self, id: i32) -> QueryResult<Option<WebhookSubscription>>;
+
+    fn insert_webhook_subscription(
+        &self,
+        url: &str,
+        event_types: &[String],
+        secret: &str,
+    ) -> QueryResult<WebhookSubscription>;
+
+    /// Deletes the subscription together with its deliveries, returning whether it existed
+    fn delete_webhook_subscription(&self, id: i32) -> QueryResult<bool>;
+
+    /// All deliveries, ordered by id
+    fn webhook_deliveries(&self) -> QueryResult<Vec<WebhookDelivery>>;
+
+    /// Claims up to `limit` pending deliveries which are due, by moving their next attempt to the
+    /// end of the lease
+    ///
+    /// Deliveries which another worker is claiming at the same time are skipped.
+    fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        leased_until: DateTime<Utc>,
+    ) -> QueryResult<Vec<WebhookDelivery>>;
+
+    /// Records the attempt of the claimed delivery
+    ///
+    /// Nothing is recorded if another worker attempted the delivery since it was claimed, which
+    /// happens when the lease ran out.
+    fn record_delivery_attempt(
+        &self,
+        delivery: &WebhookDelivery,
+        attempt: &DeliveryAttempt,
+    ) -> QueryResult<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    //! Expectations which every implementation has to meet, checked against each of them
+
+    use super::*;
+    use diesel::result::{DatabaseErrorKind, Error};
+    #[cfg(feature = "postgres")]
+    use testcontainers::clients::Cli;
+
+    const RETENTION: Duration = Duration::from_secs(3600);
+    const CLAIM_TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn categories_keep_their_rules<R: CategoryRepository>(repository: &R) {
+        let shirts = repository.insert_category("shirts").unwrap();
+        let clothes = repository.insert_category("clothes").unwrap();
+        repository.insert_rollup(clothes.id, shirts.id).unwrap();
+
+        assert!(matches!(
+            repository.insert_category("shirts"),
+            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _))
+        ));
+        let created: Vec<String> = repository
+            .insert_missing_categories(&["shirts", "books"])
+            .unwrap()
+            .into_iter()
+            .map(|category| category.name)
+            .collect();
+        assert_eq!(vec!["books"], created);
+        assert_eq!(
+            vec![clothes.clone()],
+            repository.categories_named(&["clothes"]).unwrap()
+        );
+        assert_eq!(
+            vec![(clothes.id, shirts.id)],
+            repository.rollups_of(&[shirts.id]).unwrap()
+        );
+        assert!(matches!(
+            repository.delete_categories(&[shirts.id]),
+            Err(Error::DatabaseError(
+                DatabaseErrorKind::ForeignKeyViolation,
+                _
+            ))
+        ));
+        assert_eq!(3, repository.categories().unwrap().len());
+    }
+
+    fn failed_changes_are_discarded<R: CategoryRepository>(repository: &R) {
+        let result: QueryResult<()> = repository.atomically(|| {
+            repository.insert_category("shirts")?;
+            repository.record(EventType::CategoryCreated, &"shirts")?;
+            Err(Error::RollbackTransaction)
+        });
+
+        assert!(result.is_err());
+        assert!(repository.categories().unwrap().is_empty());
+    }
+
+    fn hierarchy_is_queried_at_any_depth<R>(repository: &R)
+    where
+        R: CategoryRepository + ProductRepository,
+    {
+        let clothes = repository.insert_category("apparel").unwrap();
+        let tops = repository.insert_category("tops").unwrap();
+        let shirts = repository.insert_category("blouses").unwrap();
+        let sale = repository.insert_category("clearance").unwrap();
+        repository.insert_rollup(clothes.id, tops.id).unwrap();
+        repository.insert_rollup(tops.id, shirts.id).unwrap();
+        repository.insert_rollup(sale.id, shirts.id).unwrap();
+        let shirt = repository.insert_product("silk blouse").unwrap();
+        repository.insert_product("red dress").unwrap();
+        repository.classify(shirt.id, shirts.id, true).unwrap();
+        let depths = |categories: QueryResult<Vec<RelatedCategory>>| -> Vec<(i32, i32)> {
+            categories
+                .unwrap()
+                .into_iter()
+                .map(|category| (category.id, category.depth))
+                .collect()
+        };
+
+        assert_eq!(
+            vec![(tops.id, 1), (shirts.id, 2)],
+            depths(repository.descendants(clothes.id, None))
+        );
+        assert_eq!(
+            vec![(tops.id, 1)],
+            depths(repository.descendants(clothes.id, Some(1)))
+        );
+        assert_eq!(
+            vec![(tops.id, 1), (sale.id, 1), (clothes.id, 2)],
+            depths(repository.ancestors(shirts.id, None))
+        );
+        assert_eq!(
+            vec![shirt],
+            repository.products_in_subtree(clothes.id).unwrap()
+        );
+        assert!(repository
+            .products_in_subtree(sale.id + 1)
+            .unwrap()
+            .is_empty());
+
+        repository.insert_rollup(clothes.id, shirts.id).unwrap();
+        assert_eq!(
+            vec![(tops.id, 1), (shirts.id, 1)],
+            depths(repository.descendants(clothes.id, None))
+        );
+    }
+
+    fn removed_links_lengthen_the_paths_through_them<R: CategoryRepository>(repository: &R) {
+        let clothes = repository.insert_category("wardrobe").unwrap();
+        let tops = repository.insert_category("upper wear").unwrap();
+        let shirts = repository.insert_category("button-ups").unwrap();
+        let polos = repository.insert_category("polo shirts").unwrap();
+        let sale = repository.insert_category("markdowns").unwrap();
+        repository.insert_rollup(clothes.id, tops.id).unwrap();
+        repository.insert_rollup(tops.id, shirts.id).unwrap();
+        repository.insert_rollup(shirts.id, polos.id).unwrap();
+        repository.insert_rollup(clothes.id, sale.id).unwrap();
+        repository.insert_rollup(sale.id, polos.id).unwrap();
+        let depths = |categories: Vec<RelatedCategory>| -> Vec<(i32, i32)> {
+            categories
+                .into_iter()
+                .map(|category| (category.id, category.depth))
+                .collect()
+        };
+        let before_removal = depths(repository.descendants(clothes.id, None).unwrap());
+
+        assert_eq!(2, repository.delete_rollups_of(sale.id).unwrap());
+        assert_eq!(
+            vec![(tops.id, 1), (sale.id, 1), (shirts.id, 2), (polos.id, 2)],
+            before_removal
+        );
+        assert_eq!(
+            vec![(tops.id, 1), (shirts.id, 2), (polos.id, 3)],
+            depths(repository.descendants(clothes.id, None).unwrap())
+        );
+        assert_eq!(
+            vec![(shirts.id, 1), (tops.id, 2), (clothes.id, 3)],
+            depths(repository.ancestors(polos.id, None).unwrap())
+        );
+        assert!(repository.descendants(sale.id, None).unwrap().is_empty());
+    }
+
+    fn slugs_are_unique_among_siblings<R: CategoryRepository>(repository: &R) {
+        let clothes = repository.insert_category("Clothing").unwrap();
+        let tops = repository.insert_category("Tops").unwrap();
+        repository.insert_rollup(clothes.id, tops.id).unwrap();
+        let upper_tops = repository.insert_category("TOPS").unwrap();
+        let lower_tops = repository.insert_category("tops!").unwrap();
+
+        assert_eq!("clothing", clothes.slug);
+        assert_eq!("tops", upper_tops.slug);
+        assert_eq!("tops-2", lower_tops.slug);
+
+        repository.insert_rollup(clothes.id, upper_tops.id).unwrap();
+        assert_eq!(
+            vec![
+                (tops.id, "tops".to_string()),
+                (upper_tops.id, "tops-2".to_string())
+            ],
+            repository.slugs_below(&[clothes.id]).unwrap()
+        );
+        let roots = repository.slugs_below(&[]).unwrap();
+        assert!(roots.contains(&(lower_tops.id, "tops-2".to_string())));
+        assert!(!roots.iter().any(|(id, _)| *id == upper_tops.id));
+
+        repository
+            .save_categories(&[ProductCategory {
+                name: "Tees".to_string(),
+                ..upper_tops
+            }])
+            .unwrap();
+        assert_eq!(
+            "tees",
+            repository.category(upper_tops.id).unwrap().unwrap().slug
+        );
+    }
+
+    fn subcategories_keep_their_positions<R: CategoryRepository>(repository: &R) {
+        let outerwear = repository.insert_category("outerwear").unwrap();
+        let coats = repository.insert_category("coats").unwrap();
+        let jackets = repository.insert_category("jackets").unwrap();
+        let vests = repository.insert_category("vests").unwrap();
+        repository.insert_rollup(outerwear.id, coats.id).unwrap();
+        repository.insert_rollup(outerwear.id, jackets.id).unwrap();
+
+        repository
+            .set_rollup_positions(outerwear.id, &[jackets.id, coats.id])
+            .unwrap();
+        repository.insert_rollup(outerwear.id, vests.id).unwrap();
+
+        assert_eq!(
+            vec![
+                (outerwear.id, jackets.id),
+                (outerwear.id, coats.id),
+                (outerwear.id, vests.id)
+            ],
+            repository.rollups_of(&[outerwear.id]).unwrap()
+        );
+    }
+
+    fn translations_are_kept_per_locale<R>(repository: &R)
+    where
+        R: CategoryRepository + ProductRepository,
+    {
+        let gifts = repository.insert_category("gifts").unwrap();
+        let poisons = repository.insert_category("poisons").unwrap();
+        let scarf = repository.insert_product("silk scarf").unwrap();
+        let translation = |category_id: i32, locale: &str, name: &str| CategoryTranslation {
+            category_id,
+            locale: locale.to_string(),
+            name: name.to_string(),
+        };
+
+        repository
+            .save_category_translation(&translation(gifts.id, "de", "Gift"))
+            .unwrap();
+        repository
+            .save_category_translation(&translation(gifts.id, "de", "Geschenke"))
+            .unwrap();
+        repository
+            .save_category_translation(&translation(poisons.id, "de", "Gift"))
+            .unwrap();
+        repository
+            .save_category_translation(&translation(poisons.id, "hr", "Otrovi"))
+            .unwrap();
+        assert!(matches!(
+            repository.save_category_translation(&translation(gifts.id, "de", "Gift")),
+            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _))
+        ));
+        repository
+            .save_product_translation(&ProductTranslation {
+                product_id: scarf.id,
+                locale: "hr".to_string(),
+                description: "svileni šal".to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                translation(poisons.id, "de", "Gift"),
+                translation(poisons.id, "hr", "Otrovi")
+            ],
+            repository.translations_of_category(poisons.id).unwrap()
+        );
+        assert!(repository
+            .category_translations("de")
+            .unwrap()
+            .contains(&translation(gifts.id, "de", "Geschenke")));
+        assert_eq!(
+            vec!["svileni šal"],
+            repository
+                .translations_of_product(scarf.id)
+                .unwrap()
+                .into_iter()
+                .map(|translation| translation.description)
+                .collect::<Vec<_>>()
+        );
+        assert!(repository
+            .delete_category_translation(poisons.id, "hr")
+            .unwrap());
+        assert!(!repository
+            .delete_category_translation(poisons.id, "hr")
+            .unwrap());
+        repository.delete_categories(&[poisons.id]).unwrap();
+        assert!(!repository
+            .category_translations("de")
+            .unwrap()
+            .iter()
+            .any(|translation| translation.category_id == poisons.id));
+    }
+
+    fn stock_is_counted_per_warehouse<R>(repository: &R)
+    where
+        R: ProductRepository + StockRepository + WarehouseRepository,
+    {
+        let shirt = repository.insert_product("white shirt").unwrap();
+        let north = repository.insert_warehouse("north").unwrap();
+        repository
+            .insert_inventory_item(shirt.id, None, None)
+            .unwrap();
+        repository
+            .insert_inventory_item(shirt.id, Some(north.id), Some("damaged"))
+            .unwrap();
+
+        let levels: Vec<(i32, Option<String>, i64)> = repository
+            .stock_levels()
+            .unwrap()
+            .into_iter()
+            .map(|level| {
+                (
+                    level.product_id,
+                    level.warehouse_description,
+                    level.quantity,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (shirt.id, Some("north".to_string()), 1),
+                (shirt.id, None, 1)
+            ],
+            levels
+        );
+        assert!(matches!(
+            repository.insert_inventory_item(shirt.id + 1, None, None),
+            Err(Error::DatabaseError(
+                DatabaseErrorKind::ForeignKeyViolation,
+                _
+            ))
+        ));
+    }
+
+    fn inventory_items_are_paged_and_moved<R>(repository: &R)
+    where
+        R: ProductRepository + StockRepository + WarehouseRepository,
+    {
+        let shirt = repository.insert_product("white shirt").unwrap();
+        let north = repository.insert_warehouse("north").unwrap();
+        let first = repository
+            .insert_inventory_item(shirt.id, None, None)
+            .unwrap();
+        let second = repository
+            .insert_inventory_item(shirt.id, None, None)
+            .unwrap();
+        let moved = InventoryItem {
+            warehouse_id: Some(north.id),
+            instance_description: Some("damaged".to_string()),
+            ..first.clone()
+        };
+        repository.update_inventory_item(&moved).unwrap();
+
+        assert_eq!(
+            vec![moved.clone()],
+            repository.inventory_items_after(first.id - 1, 1).unwrap()
+        );
+        assert_eq!(
+            vec![second.clone()],
+            repository.inventory_items_after(first.id, 10).unwrap()
+        );
+        assert_eq!(Some(moved), repository.inventory_item(first.id).unwrap());
+        assert!(matches!(
+            repository.update_inventory_item(&InventoryItem {
+                product_id: shirt.id + 1,
+                ..second.clone()
+            }),
+            Err(Error::DatabaseError(
+                DatabaseErrorKind::ForeignKeyViolation,
+                _
+            ))
+        ));
+        assert!(matches!(
+            repository.update_inventory_item(&InventoryItem {
+                id: second.id + 1,
+                ..second
+            }),
+            Err(Error::NotFound)
+        ));
+    }
+
+    fn records_are_fetched_in_batches_by_id<R>(repository: &R)
+    where
+        R: CategoryRepository + ProductRepository + StockRepository + WarehouseRepository,
+    {
+        let clothes = repository.insert_category("batched clothes").unwrap();
+        let shirts = repository.insert_category("batched shirts").unwrap();
+        repository.insert_rollup(clothes.id, shirts.id).unwrap();
+        let shirt = repository.insert_product("batched shirt").unwrap();
+        let trousers = repository.insert_product("batched trousers").unwrap();
+        let classification = repository.classify(shirt.id, shirts.id, true).unwrap();
+        let north = repository.insert_warehouse("batched north").unwrap();
+        let south = repository.insert_warehouse("batched south").unwrap();
+        let stocked = repository
+            .insert_inventory_item(shirt.id, Some(north.id), None)
+            .unwrap();
+        let unstocked = repository
+            .insert_inventory_item(shirt.id, None, None)
+            .unwrap();
+
+        assert_eq!(
+            vec![clothes.id, shirts.id],
+            repository
+                .categories_with_ids(&[shirts.id, clothes.id])
+                .unwrap()
+                .into_iter()
+                .map(|category| category.id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![shirt.clone(), trousers.clone()],
+            repository
+                .products_with_ids(&[trousers.id, shirt.id])
+                .unwrap()
+        );
+        assert_eq!(
+            vec![south.clone()],
+            repository.warehouses_with_ids(&[south.id]).unwrap()
+        );
+        assert_eq!(
+            vec![stocked.clone(), unstocked.clone()],
+            repository.inventory_items_of(&[shirt.id]).unwrap()
+        );
+        assert!(repository
+            .inventory_items_of(&[trousers.id])
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            vec![stocked.clone()],
+            repository
+                .inventory_items_in(&[north.id, south.id])
+                .unwrap()
+        );
+        assert!(repository.inventory_items().unwrap().contains(&unstocked));
+        assert!(repository
+            .classifications()
+            .unwrap()
+            .contains(&classification));
+        assert!(repository.rollup_links().unwrap().iter().any(|rollup| {
+            (rollup.upper_category_id, rollup.lower_category_id) == (clothes.id, shirts.id)
+        }));
+    }
+
+    fn categories_are_counted_with_their_subcategories<R>(repository: &R)
+    where
+        R: CategoryRepository
+            + ProductRepository
+            + StatisticsRepository
+            + StockRepository
+            + WarehouseRepository,
+    {
+        let apparel = repository.insert_category("garments").unwrap();
+        let shirts = repository.insert_category("button-downs").unwrap();
+        let sale = repository.insert_category("outlet").unwrap();
+        repository.insert_rollup(apparel.id, shirts.id).unwrap();
+        repository.insert_rollup(sale.id, shirts.id).unwrap();
+        let shirt = repository.insert_product("oxford shirt").unwrap();
+        let coat = repository.insert_product("trench coat").unwrap();
+        repository.classify(shirt.id, shirts.id, true).unwrap();
+        repository.classify(shirt.id, apparel.id, false).unwrap();
+        repository.classify(coat.id, apparel.id, true).unwrap();
+        let east = repository.insert_warehouse("east").unwrap();
+        for (product_id, warehouse_id) in &[
+            (shirt.id, Some(east.id)),
+            (shirt.id, None),
+            (coat.id, Some(east.id)),
+            (coat.id, Some(east.id)),
+        ] {
+            repository
+                .insert_inventory_item(*product_id, *warehouse_id, None)
+                .unwrap();
+        }
+
+        let statistics = repository.category_statistics().unwrap();
+        let of_category = |category_id: i32| {
+            statistics
+                .iter()
+                .find(|statistics| statistics.category_id == category_id)
+                .map(|statistics| {
+                    (
+                        statistics.direct_products,
+                        statistics.products,
+                        statistics.units_in_stock,
+                        statistics
+                            .stock
+                            .iter()
+                            .map(|stock| (stock.warehouse_description.clone(), stock.quantity))
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .unwrap()
+        };
+
+        assert_eq!(
+            (2, 2, 4, vec![(Some("east".to_string()), 3), (None, 1)]),
+            of_category(apparel.id)
+        );
+        assert_eq!(
+            (0, 1, 2, vec![(Some("east".to_string()), 1), (None, 1)]),
+            of_category(sale.id)
+        );
+        assert!(statistics
+            .iter()
+            .all(|statistics| statistics.refreshed_at.is_none()));
+        for category_id in &[apparel.id, shirts.id, sale.id] {
+            assert_eq!(
+                statistics
+                    .iter()
+                    .find(|statistics| statistics.category_id == *category_id),
+                repository
+                    .category_statistics_of(*category_id)
+                    .unwrap()
+                    .as_ref()
+            );
+        }
+        assert_eq!(
+            None,
+            repository.category_statistics_of(sale.id + 1).unwrap()
+        );
+
+        repository.store_category_statistics(&statistics).unwrap();
+        repository.classify(coat.id, sale.id, false).unwrap();
+        let stored = repository.stored_category_statistics().unwrap();
+        assert!(stored
+            .iter()
+            .all(|statistics| statistics.refreshed_at.is_some()));
+        assert_eq!(
+            stored
+                .iter()
+                .find(|statistics| statistics.category_id == sale.id),
+            repository
+                .stored_category_statistics_of(sale.id)
+                .unwrap()
+                .as_ref()
+        );
+        assert_eq!(
+            None,
+            repository
+                .stored_category_statistics_of(sale.id + 1)
+                .unwrap()
+        );
+        let stored_without_time: Vec<CategoryStatistics> = stored
+            .into_iter()
+            .map(|stored| CategoryStatistics {
+                refreshed_at: None,
+                ..stored
+            })
+            .collect();
+        assert_eq!(statistics, stored_without_time);
+
+        let clearance = repository.insert_category("clearance rack").unwrap();
+        repository.insert_rollup(sale.id, clearance.id).unwrap();
+        let scarf = repository.insert_product("wool scarf").unwrap();
+        repository.classify(scarf.id, clearance.id, true).unwrap();
+        let products_of_sale = || {
+            repository
+                .category_statistics_of(sale.id)
+                .unwrap()
+                .map(|statistics| statistics.products)
+        };
+        let with_clearance = products_of_sale();
+        repository.archive_category(clearance.id).unwrap();
+        assert_eq!((Some(3), Some(2)), (with_clearance, products_of_sale()));
+        assert!(repository
+            .category_statistics()
+            .unwrap()
+            .iter()
+            .any(|statistics| statistics.category_id == sale.id && statistics.products == 2));
+    }
+
+    fn products_are_found_by_their_words<R>(repository: &R)
+    where
+        R: CategoryRepository
+            + ProductRepository
+            + SearchRepository
+            + StockRepository
+            + WarehouseRepository,
+    {
+        let furnishings = repository.insert_category("furnishings").unwrap();
+        let textiles = repository.insert_category("soft textiles").unwrap();
+        repository
+            .insert_rollup(furnishings.id, textiles.id)
+            .unwrap();
+        let cushion = repository.insert_product("velvet cushion").unwrap();
+        let cover = repository.insert_product("cushion cover").unwrap();
+        let throw = repository.insert_product("linen throw").unwrap();
+        repository.classify(cushion.id, textiles.id, true).unwrap();
+        repository.classify(throw.id, textiles.id, true).unwrap();
+        let attic = repository.insert_warehouse("attic").unwrap();
+        repository
+            .insert_inventory_item(cover.id, Some(attic.id), None)
+            .unwrap();
+        repository
+            .insert_inventory_item(throw.id, None, None)
+            .unwrap();
+        let found = |text: &str, category_id: Option<i32>, warehouse_id: Option<i32>, in_stock| {
+            repository
+                .search_products(&ProductSearch {
+                    text: text.to_string(),
+                    category_id,
+                    warehouse_id,
+                    in_stock,
+                    limit: 10,
+                })
+                .unwrap()
+                .into_iter()
+                .map(|hit| (hit.product_id, hit.highlight))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![
+                (
+                    cushion.id,
+                    "<mark>velvet</mark> <mark>cushion</mark>".to_string()
+                ),
+                (cover.id, "<mark>cushion</mark> cover".to_string())
+            ],
+            found("velvet cushion", None, None, false)
+        );
+        assert_eq!(
+            vec![(cushion.id, "velvet cushion".to_string())],
+            found("velvett", None, None, false)
+        );
+        assert_eq!(
+            vec![cushion.id, throw.id],
+            found("textile", Some(furnishings.id), None, false)
+                .into_iter()
+                .map(|(product_id, _)| product_id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(cover.id, "<mark>cushion</mark> cover".to_string())],
+            found("cushion", None, Some(attic.id), false)
+        );
+        assert_eq!(
+            vec![(throw.id, "linen throw".to_string())],
+            found(&throw.id.to_string(), None, None, true)
+        );
+        assert!(found("cushion", Some(furnishings.id), None, true).is_empty());
+
+        let bolster = repository
+            .insert_product("bolster <img src=x onerror=alert(1)> & 'case'")
+            .unwrap();
+        assert_eq!(
+            vec![(
+                bolster.id,
+                "<mark>bolster</mark> &lt;img src=x onerror=alert(1)&gt; &amp; &#39;case&#39;"
+                    .to_string()
+            )],
+            found("bolster", None, None, false)
+        );
+    }
+
+    fn facets_are_counted_among_the_matching_products<R>(repository: &R)
+    where
+        R: CategoryRepository
+            + FacetRepository
+            + ProductRepository
+            + StockRepository
+            + WarehouseRepository,
+    {
+        let footwear = repository.insert_category("footwear").unwrap();
+        let boots = repository.insert_category("hiking boots").unwrap();
+        repository.insert_rollup(footwear.id, boots.id).unwrap();
+        let boot = repository.insert_product("leather boot").unwrap();
+        let laces = repository.insert_product("boot laces").unwrap();
+        repository.classify(boot.id, boots.id, true).unwrap();
+        let cellar = repository.insert_warehouse("cellar").unwrap();
+        for warehouse_id in &[Some(cellar.id), Some(cellar.id), None] {
+            repository
+                .insert_inventory_item(boot.id, *warehouse_id, None)
+                .unwrap();
+        }
+        repository
+            .insert_inventory_item(laces.id, None, None)
+            .unwrap();
+        repository.set_product_price(boot.id, Some(8999)).unwrap();
+        let attribute = |product_id: i32, name: &str, value: &str| ProductAttribute {
+            product_id,
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        repository
+            .save_product_attribute(&attribute(boot.id, "size", "42"))
+            .unwrap();
+        repository
+            .save_product_attribute(&attribute(boot.id, "size", "43"))
+            .unwrap();
+        repository
+            .save_product_attribute(&attribute(boot.id, "color", "brown"))
+            .unwrap();
+        repository
+            .save_product_attribute(&attribute(laces.id, "color", "black"))
+            .unwrap();
+
+        assert!(repository
+            .delete_product_attribute(laces.id, "color")
+            .unwrap());
+        assert!(!repository
+            .delete_product_attribute(laces.id, "color")
+            .unwrap());
+        assert_eq!(
+            vec![
+                attribute(boot.id, "color", "brown"),
+                attribute(boot.id, "size", "43")
+            ],
+            repository.attributes_of_product(boot.id).unwrap()
+        );
+        assert!(matches!(
+            repository.set_product_price(laces.id + 1, Some(100)),
+            Err(Error::NotFound)
+        ));
+        assert!(matches!(
+            repository.save_product_attribute(&attribute(laces.id + 1, "size", "1")),
+            Err(Error::DatabaseError(
+                DatabaseErrorKind::ForeignKeyViolation,
+                _
+            ))
+        ));
+
+        let mut filters = ListingFilters {
+            categories: vec![footwear.id],
+            warehouses: vec![cellar.id],
+            ..ListingFilters::default()
+        };
+        filters
+            .attributes
+            .insert("color".to_string(), vec!["brown".to_string()]);
+        let counts = repository.facet_counts(&filters, 10, 0).unwrap();
+        let beyond_the_page = repository.facet_counts(&filters, 10, 1).unwrap();
+        let mut laces_filters = ListingFilters::default();
+        laces_filters
+            .attributes
+            .insert("color".to_string(), vec!["black".to_string()]);
+        let laces_counts = repository.facet_counts(&laces_filters, 10, 0).unwrap();
+
+        assert_eq!(1, counts.total);
+        assert_eq!(
+            vec![(boot.id, Some(8999))],
+            counts
+                .products
+                .iter()
+                .map(|product| (product.id, product.price))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(footwear.id, 1), (boots.id, 1)],
+            counts.categories.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(cellar.id, 1)],
+            counts.warehouses.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!((Some(8999), Some(8999), 1), {
+            let price = &counts.price;
+            (price.min, price.max, price.count)
+        });
+        assert_eq!(
+            vec![
+                ("color".to_string(), vec![("brown".to_string(), 1)]),
+                ("size".to_string(), vec![("43".to_string(), 1)])
+            ],
+            counts
+                .attributes
+                .into_iter()
+                .map(|(name, values)| (name, values.into_iter().collect()))
+                .collect::<Vec<(String, Vec<_>)>>()
+        );
+        assert_eq!(1, beyond_the_page.total);
+        assert!(beyond_the_page.products.is_empty());
+        assert_eq!(0, laces_counts.total);
+    }
+
+    fn idempotency_keys_are_claimed_once_until_they_expire<R: IdempotencyRepository>(
+        repository: &R,
+    ) {
+        let response = StoredResponse {
+            status: 201,
+            content_type: Some("application/json".to_string()),
+            body: b"{}".to_vec(),
+        };
+
+        let first_claim = repository
+            .claim_idempotency_key("key", "a", RETENTION, CLAIM_TIMEOUT)
+            .unwrap();
+        let concurrent_claim = repository
+            .claim_idempotency_key("key", "a", RETENTION, CLAIM_TIMEOUT)
+            .unwrap();
+        if let Claim::Claimed(claimed_at) = first_claim {
+            repository
+                .complete_idempotency_key("key", claimed_at, &response)
+                .unwrap();
+            repository
+                .release_idempotency_key("key", claimed_at)
+                .unwrap();
+        }
+        let repeated_claim = repository
+            .claim_idempotency_key("key", "a", RETENTION, CLAIM_TIMEOUT)
+            .unwrap();
+        let mismatched_claim = repository
+            .claim_idempotency_key("key", "b", RETENTION, CLAIM_TIMEOUT)
+            .unwrap();
+        let claim_after_expiry = repository
+            .claim_idempotency_key("key", "b", Duration::from_secs(0), CLAIM_TIMEOUT)
+            .unwrap();
+
+        assert!(matches!(first_claim, Claim::Claimed(_)));
+        assert_eq!(Claim::InProgress, concurrent_claim);
+        assert_eq!(Claim::Completed(response), repeated_claim);
+        assert_eq!(Claim::Mismatch, mismatched_claim);
+        assert!(matches!(claim_after_expiry, Claim::Claimed(_)));
+    }
+
+    fn claims_in_progress_are_taken_over_after_the_timeout<R: IdempotencyRepository>(
+        repository: &R,
+    ) {
+        let response = StoredResponse {
+            status: 201,
+            content_type: None,
+            body: Vec::new(),
+        };
+
+        let stale_claim = repository
+            .claim_idempotency_key("stale-key", "a", RETENTION, CLAIM_TIMEOUT)
+            .unwrap();
+        let taking_over_claim = repository
+            .claim_idempotency_key("stale-key", "b", RETENTION, Duration::from_secs(0))
+            .unwrap();
+        if let Claim::Claimed(stale_claimed_at) = stale_claim {
+            repository
+                .complete_idempotency_key("stale-key", stale_claimed_at, &response)
+                .unwrap();
+            repository
+                .release_idempotency_key("stale-key", stale_claimed_at)
+                .unwrap();
+        }
+        let claim_after_stale_request = repository
+            .claim_idempotency_key("stale-key", "b", RETENTION, CLAIM_TIMEOUT)
+            .unwrap();
+
+        assert!(matches!(stale_claim, Claim::Claimed(_)));
+        assert!(matches!(taking_over_claim, Claim::Claimed(_)));
+        assert_eq!(Claim::InProgress, claim_after_stale_request);
+    }
+
+    #[test]
+    fn memory_repository_meets_the_expectations() {
+        categories_keep_their_rules(&MemoryRepository::new());
+        failed_changes_are_discarded(&MemoryRepository::new());
+        stock_is_counted_per_warehouse(&MemoryRepository::new());
+        inventory_items_are_paged_and_moved(&MemoryRepository::new());
+        records_are_fetched_in_batches_by_id(&MemoryRepository::new());
+        hierarchy_is_queried_at_any_depth(&MemoryRepository::new());
+        removed_links_lengthen_the_paths_through_them(&MemoryRepository::new());
+        slugs_are_unique_among_siblings(&MemoryRepository::new());
+        subcategories_keep_their_positions(&MemoryRepository::new());
+        categories_are_counted_with_their_subcategories(&MemoryRepository::new());
+        translations_are_kept_per_locale(&MemoryRepository::new());
+        products_are_found_by_their_words(&MemoryRepository::new());
+        facets_are_counted_among_the_matching_products(&MemoryRepository::new());
+        idempotency_keys_are_claimed_once_until_they_expire(&MemoryRepository::new());
+        claims_in_progress_are_taken_over_after_the_timeout(&MemoryRepository::new());
+    }
+
+    #[test]
+    fn memory_database_runs_one_transaction_at_a_time() {
+        use crate::repository::memory::MemoryDatabase;
+        use std::{sync::mpsc::channel, thread, time::Duration};
+
+        let database = MemoryDatabase::default();
+        let (started, transaction_started) = channel();
+        let failing_transaction = {
+            let database = database.clone();
+            thread::spawn(move || {
+                let repository = database.connect();
+                let result: QueryResult<()> = repository.atomically(|| {
+                    repository.insert_category("shirts")?;
+                    started.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(100));
+                    Err(Error::RollbackTransaction)
+                });
+                assert!(result.is_err());
+            })
+        };
+        transaction_started.recv().unwrap();
+        let repository = database.connect();
+        repository.insert_category("dresses").unwrap();
+        failing_transaction.join().unwrap();
+
+        let names: Vec<String> = repository
+            .categories()
+            .unwrap()
+            .into_iter()
+            .map(|category| category.name)
+            .collect();
+        assert_eq!(vec!["dresses"], names);
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_repository_meets_the_expectations() -> Result<(), crate::migrations::MigrationError>
+    {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (_, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+
+        failed_changes_are_discarded(&connection);
+        categories_keep_their_rules(&connection);
+        stock_is_counted_per_warehouse(&connection);
+        inventory_items_are_paged_and_moved(&connection);
+        records_are_fetched_in_batches_by_id(&connection);
+        hierarchy_is_queried_at_any_depth(&connection);
+        removed_links_lengthen_the_paths_through_them(&connection);
+        slugs_are_unique_among_siblings(&connection);
+        subcategories_keep_their_positions(&connection);
+        categories_are_counted_with_their_subcategories(&connection);
+        translations_are_kept_per_locale(&connection);
+        products_are_found_by_their_words(&connection);
+        facets_are_counted_among_the_matching_products(&connection);
+        idempotency_keys_are_claimed_once_until_they_expire(&connection);
+        claims_in_progress_are_taken_over_after_the_timeout(&connection);
+        Ok(())
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_closure_follows_removed_links() -> Result<(), crate::migrations::MigrationError> {
+        use crate::schema::product_category_rollup::dsl::*;
+        use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (_, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+        let clothes = connection.insert_category("clothes")?;
+        let tops = connection.insert_category("tops")?;
+        let shirts = connection.insert_category("shirts")?;
+        connection.insert_rollup(clothes.id, tops.id)?;
+        connection.insert_rollup(tops.id, shirts.id)?;
+        connection.insert_rollup(clothes.id, shirts.id)?;
+
+        diesel::delete(
+            product_category_rollup
+                .filter(upper_category_id.eq(clothes.id))
+                .filter(lower_category_id.eq(shirts.id)),
+        )
+        .execute(&connection)?;
+        let after_shortcut_removal = connection.descendants(clothes.id, None)?;
+        let sale = connection.insert_category("sale")?;
+        diesel::update(product_category_rollup.filter(lower_category_id.eq(shirts.id)))
+            .set(upper_category_id.eq(sale.id))
+            .execute(&connection)?;
+        let after_move = connection.ancestors(shirts.id, None)?;
+        diesel::delete(product_category_rollup.filter(lower_category_id.eq(shirts.id)))
+            .execute(&connection)?;
+
+        assert_eq!(
+            vec![(tops.id, 1), (shirts.id, 2)],
+            after_shortcut_removal
+                .into_iter()
+                .map(|category| (category.id, category.depth))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(sale.id, 1)],
+            after_move
+                .into_iter()
+                .map(|category| (category.id, category.depth))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, connection.descendants(clothes.id, None)?.len());
+        assert!(connection.ancestors(shirts.id, None)?.is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_repository_meets_the_expectations() -> Result<(), crate::migrations::MigrationError> {
+        let database = crate::test_utils::SqliteDatabase::create();
+        let (_, connection) = crate::test_utils::start_rocket_with_sqlite(&database)?;
+
+        failed_changes_are_discarded(&connection);
+        categories_keep_their_rules(&connection);
+        stock_is_counted_per_warehouse(&connection);
+        inventory_items_are_paged_and_moved(&connection);
+        records_are_fetched_in_batches_by_id(&connection);
+        hierarchy_is_queried_at_any_depth(&connection);
+        removed_links_lengthen_the_paths_through_them(&connection);
+        slugs_are_unique_among_siblings(&connection);
+        subcategories_keep_their_positions(&connection);
+        categories_are_counted_with_their_subcategories(&connection);
+        translations_are_kept_per_locale(&connection);
+        products_are_found_by_their_words(&connection);
+        facets_are_counted_among_the_matching_products(&connection);
+        idempotency_keys_are_claimed_once_until_they_expire(&connection);
+        claims_in_progress_are_taken_over_after_the_timeout(&connection);
+        Ok(())
+    }
+}
@@ -0,0 +1,1402 @@
+//! Implementation of the repositories on a tenant scoped database connection
+
+use super::{
+    ApiKeyRepository, CategoryRepository, EventLog, FacetRepository, IdempotencyRepository,
+    OutboxRepository, ProductRepository, SearchRepository, StatisticsRepository, StockRepository,
+    Transactional, WarehouseRepository, WebhookRepository,
+};
+use crate::{
+    auth::{entities::ApiKey, Role},
+    catalog::{
+        entities::ProductAttribute,
+        facets::{FacetCounts, ListingFilters, PriceFacet},
+    },
+    entities::{
+        InventoryItem, Product, ProductCategoryClassification, ProductCategoryRollup, Warehouse,
+    },
+    events::broadcaster::StreamPosition,
+    idempotency::{
+        entities::IdempotencyRecord,
+        store::{before_now, Claim, StoredResponse},
+    },
+    inventory::reports::StockLevel,
+    localization::entities::{CategoryTranslation, ProductTranslation},
+    metrics::gauges::CatalogCounts,
+    outbox::{self, entities::OutboxEvent, EventType},
+    product_category::{
+        entities::{ProductCategory, RelatedCategory},
+        statistics::{self, CategoryStatistics},
+    },
+    search::{ProductSearch, SearchHit},
+    webhooks::entities::{DeliveryAttempt, WebhookDelivery, WebhookSubscription, DELIVERY_PENDING},
+};
+use chrono::{DateTime, Utc};
+use diesel::{
+    connection::{SimpleConnection, TransactionManager},
+    dsl::{min, sql},
+    insert_into,
+    pg::{upsert::excluded, Pg},
+    query_dsl::GroupByDsl,
+    sql_query,
+    sql_types::{Array, BigInt, Integer, Nullable},
+    BoolExpressionMethods, Connection, ExpressionMethods, JoinOnDsl, NullableExpressionMethods,
+    OptionalExtension, PgConnection, QueryDsl, QueryResult, RunQueryDsl,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, time::Duration};
+
+impl Transactional for PgConnection {
+    fn atomically<T, F>(&self, f: F) -> QueryResult<T>
+    where
+        F: FnOnce() -> QueryResult<T>,
+    {
+        self.transaction(f)
+    }
+
+    fn begin_snapshot(&self) -> QueryResult<()> {
+        self.transaction_manager().begin_transaction(self)?;
+        self.batch_execute("set transaction isolation level repeatable read, read only")
+    }
+
+    fn end_snapshot(&self) -> QueryResult<()> {
+        self.transaction_manager().commit_transaction(self)
+    }
+}
+
+impl EventLog for PgConnection {
+    fn record_all<T: Serialize>(
+        &self,
+        event_type: EventType,
+        payloads: &[T],
+    ) -> QueryResult<usize> {
+        outbox::record_all(self, event_type, payloads)
+    }
+}
+
+impl CategoryRepository for PgConnection {
+    fn categories(&self) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category.order(dsl::id).load(self)
+    }
+
+    fn category(&self, id: i32) -> QueryResult<Option<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category.find(id).first(self).optional()
+    }
+
+    fn categories_named(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category
+            .filter(dsl::name.eq_any(names))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn categories_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category
+            .filter(dsl::id.eq_any(ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn categories_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category
+            .filter(dsl::id.gt(after_id))
+            .order(dsl::id)
+            .limit(limit)
+            .load(self)
+    }
+
+    fn lock_categories(&self, ids: &[i32], names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category
+            .filter(dsl::id.eq_any(ids).or(dsl::name.eq_any(names)))
+            .for_update()
+            .load(self)
+    }
+
+    fn insert_category(&self, name: &str) -> QueryResult<ProductCategory> {
+        use crate::schema::product_category::dsl;
+        let id = insert_into(dsl::product_category)
+            .values((dsl::name.eq(name), dsl::slug.eq("")))
+            .returning(dsl::id)
+            .get_result(self)?;
+        self.refresh_slug(id)?;
+        dsl::product_category.find(id).first(self)
+    }
+
+    fn insert_missing_categories(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let created_ids: Vec<i32> = insert_into(dsl::product_category)
+            .values(
+                names
+                    .iter()
+                    .map(|name| (dsl::name.eq(*name), dsl::slug.eq("")))
+                    .collect::<Vec<_>>(),
+            )
+            .on_conflict_do_nothing()
+            .returning(dsl::id)
+            .get_results(self)?;
+        for id in &created_ids {
+            self.refresh_slug(*id)?;
+        }
+        dsl::product_category
+            .filter(dsl::id.eq_any(&created_ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn save_categories(&self, categories: &[ProductCategory]) -> QueryResult<()> {
+        use crate::schema::product_category::dsl;
+        if categories.is_empty() {
+            return Ok(());
+        }
+        insert_into(dsl::product_category)
+            .values(
+                categories
+                    .iter()
+                    .map(|category| {
+                        (
+                            dsl::id.eq(category.id),
+                            dsl::name.eq(&category.name),
+                            dsl::slug.eq(""),
+                            dsl::description.eq(&category.description),
+                            dsl::image_ref.eq(&category.image_ref),
+                            dsl::is_visible.eq(category.is_visible),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .on_conflict(dsl::id)
+            .do_update()
+            .set((
+                dsl::name.eq(excluded(dsl::name)),
+                dsl::description.eq(excluded(dsl::description)),
+                dsl::image_ref.eq(excluded(dsl::image_ref)),
+                dsl::is_visible.eq(excluded(dsl::is_visible)),
+            ))
+            .execute(self)?;
+        for category in categories {
+            self.refresh_slug(category.id)?;
+        }
+        Ok(())
+    }
+
+    fn delete_categories(&self, ids: &[i32]) -> QueryResult<Vec<i32>> {
+        use crate::schema::product_category::dsl;
+        diesel::delete(dsl::product_category.filter(dsl::id.eq_any(ids)))
+            .returning(dsl::id)
+            .get_results(self)
+    }
+
+    fn rollups(&self) -> QueryResult<Vec<(i32, i32)>> {
+        use crate::schema::product_category_rollup::dsl::*;
+        product_category_rollup
+            .select((upper_category_id, lower_category_id))
+            .order((upper_category_id, position, id))
+            .load(self)
+    }
+
+    fn rollups_of(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, i32)>> {
+        use crate::schema::product_category_rollup::dsl::*;
+        product_category_rollup
+            .select((upper_category_id, lower_category_id))
+            .filter(
+                upper_category_id
+                    .eq_any(category_ids)
+                    .or(lower_category_id.eq_any(category_ids)),
+            )
+            .order((upper_category_id, position, id))
+            .load(self)
+    }
+
+    fn rollup_links(&self) -> QueryResult<Vec<ProductCategoryRollup>> {
+        use crate::schema::product_category_rollup::dsl;
+        dsl::product_category_rollup.order(dsl::id).load(self)
+    }
+
+    fn insert_rollup(&self, upper: i32, lower: i32) -> QueryResult<()> {
+        use crate::schema::product_category_rollup::dsl::*;
+        let last_position: Option<i32> = product_category_rollup
+            .filter(upper_category_id.eq(upper))
+            .select(diesel::dsl::max(position))
+            .first(self)?;
+        let next_position = last_position.map_or(0, |last_position| last_position + 1);
+        insert_into(product_category_rollup)
+            .values((
+                upper_category_id.eq(upper),
+                lower_category_id.eq(lower),
+                position.eq(next_position),
+            ))
+            .execute(self)?;
+        self.refresh_slug(lower)
+    }
+
+    fn set_rollup_positions(&self, upper: i32, lower_ids: &[i32]) -> QueryResult<()> {
+        sql_query(
+            "update product_category_rollup
+                set position = ordered.position - 1
+               from unnest($1::integer[]) with ordinality as ordered(lower_category_id, position)
+              where product_category_rollup.upper_category_id = $2
+                and product_category_rollup.lower_category_id = ordered.lower_category_id",
+        )
+        .bind::<Array<Integer>, _>(lower_ids)
+        .bind::<Integer, _>(upper)
+        .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_rollups_of(&self, category_id: i32) -> QueryResult<usize> {
+        use crate::schema::product_category_rollup::dsl::*;
+        diesel::delete(
+            product_category_rollup.filter(
+                upper_category_id
+                    .eq(category_id)
+                    .or(lower_category_id.eq(category_id)),
+            ),
+        )
+        .execute(self)
+    }
+
+    fn slugs_below(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, String)>> {
+        use crate::schema::{product_category as category, product_category_rollup as rollup};
+        let lower_ids = rollup::table.select(rollup::lower_category_id);
+        let query = category::table
+            .select((category::id, category::slug))
+            .filter(category::is_archived.eq(false))
+            .order(category::id)
+            .into_boxed();
+        if category_ids.is_empty() {
+            query.filter(category::id.ne_all(lower_ids)).load(self)
+        } else {
+            query
+                .filter(
+                    category::id
+                        .eq_any(lower_ids.filter(rollup::upper_category_id.eq_any(category_ids))),
+                )
+                .load(self)
+        }
+    }
+
+    fn set_slug(&self, category_id: i32, slug: &str) -> QueryResult<()> {
+        use crate::schema::product_category::dsl;
+        diesel::update(dsl::product_category.find(category_id))
+            .set(dsl::slug.eq(slug))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn archive_category(&self, category_id: i32) -> QueryResult<()> {
+        use crate::schema::product_category::dsl;
+        diesel::update(dsl::product_category.find(category_id))
+            .set(dsl::is_archived.eq(true))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn category_translations(&self, locale: &str) -> QueryResult<Vec<CategoryTranslation>> {
+        use crate::schema::product_category_translation::dsl;
+        dsl::product_category_translation
+            .filter(dsl::locale.eq(locale))
+            .order(dsl::category_id)
+            .load(self)
+    }
+
+    fn translations_of_category(&self, category_id: i32) -> QueryResult<Vec<CategoryTranslation>> {
+        use crate::schema::product_category_translation::dsl;
+        dsl::product_category_translation
+            .filter(dsl::category_id.eq(category_id))
+            .order(dsl::locale)
+            .load(self)
+    }
+
+    fn save_category_translation(&self, translation: &CategoryTranslation) -> QueryResult<()> {
+        use crate::schema::product_category_translation::dsl;
+        insert_into(dsl::product_category_translation)
+            .values(translation)
+            .on_conflict((dsl::category_id, dsl::locale))
+            .do_update()
+            .set(dsl::name.eq(excluded(dsl::name)))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_category_translation(&self, category_id: i32, locale: &str) -> QueryResult<bool> {
+        use crate::schema::product_category_translation::dsl;
+        let deleted = diesel::delete(
+            dsl::product_category_translation.find((category_id, locale.to_string())),
+        )
+        .execute(self)?;
+        Ok(deleted > 0)
+    }
+
+    fn descendants(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>> {
+        use crate::schema::{product_category as category, product_category_closure as closure};
+        let mut query = closure::table
+            .inner_join(category::table.on(category::id.eq(closure::descendant_id)))
+            .select((
+                category::id,
+                category::name,
+                category::slug,
+                category::description,
+                category::image_ref,
+                category::is_visible,
+                closure::depth,
+            ))
+            .filter(closure::ancestor_id.eq(category_id))
+            .filter(closure::depth.gt(0))
+            .order((closure::depth, category::id))
+            .into_boxed();
+        if let Some(max_depth) = max_depth {
+            query = query.filter(closure::depth.le(max_depth));
+        }
+        query.load(self)
+    }
+
+    fn ancestors(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>> {
+        use crate::schema::{product_category as category, product_category_closure as closure};
+        let mut query = closure::table
+            .inner_join(category::table.on(category::id.eq(closure::ancestor_id)))
+            .select((
+                category::id,
+                category::name,
+                category::slug,
+                category::description,
+                category::image_ref,
+                category::is_visible,
+                closure::depth,
+            ))
+            .filter(closure::descendant_id.eq(category_id))
+            .filter(closure::depth.gt(0))
+            .order((closure::depth, category::id))
+            .into_boxed();
+        if let Some(max_depth) = max_depth {
+            query = query.filter(closure::depth.le(max_depth));
+        }
+        query.load(self)
+    }
+}
+
+impl ProductRepository for PgConnection {
+    fn products(&self) -> QueryResult<Vec<Product>> {
+        use crate::schema::product::dsl;
+        dsl::product.order(dsl::id).load(self)
+    }
+
+    fn product(&self, id: i32) -> QueryResult<Option<Product>> {
+        use crate::schema::product::dsl;
+        dsl::product.find(id).first(self).optional()
+    }
+
+    fn products_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Product>> {
+        use crate::schema::product::dsl;
+        dsl::product
+            .filter(dsl::id.eq_any(ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn insert_product(&self, description: &str) -> QueryResult<Product> {
+        use crate::schema::product::dsl;
+        insert_into(dsl::product)
+            .values(dsl::description.eq(description))
+            .get_result(self)
+    }
+
+    fn products_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<Product>> {
+        use crate::schema::product::dsl;
+        dsl::product
+            .filter(dsl::id.gt(after_id))
+            .order(dsl::id)
+            .limit(limit)
+            .load(self)
+    }
+
+    fn set_product_description(&self, product_id: i32, description: &str) -> QueryResult<()> {
+        use crate::schema::product::dsl;
+        match diesel::update(dsl::product.find(product_id))
+            .set(dsl::description.eq(description))
+            .execute(self)?
+        {
+            0 => Err(diesel::result::Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    fn classify(
+        &self,
+        product_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<ProductCategoryClassification> {
+        use crate::schema::product_category_classification::dsl;
+        insert_into(dsl::product_category_classification)
+            .values((
+                dsl::product_id.eq(product_id),
+                dsl::product_category_id.eq(category_id),
+                dsl::is_primary_classification.eq(is_primary),
+            ))
+            .get_result(self)
+    }
+
+    fn update_classification(
+        &self,
+        classification_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<()> {
+        use crate::schema::product_category_classification::dsl;
+        diesel::update(dsl::product_category_classification.find(classification_id))
+            .set((
+                dsl::product_category_id.eq(category_id),
+                dsl::is_primary_classification.eq(is_primary),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_classifications(&self, classification_ids: &[i32]) -> QueryResult<usize> {
+        use crate::schema::product_category_classification::dsl;
+        diesel::delete(
+            dsl::product_category_classification.filter(dsl::id.eq_any(classification_ids)),
+        )
+        .execute(self)
+    }
+
+    fn classifications(&self) -> QueryResult<Vec<ProductCategoryClassification>> {
+        use crate::schema::product_category_classification::dsl;
+        dsl::product_category_classification
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn classifications_in(
+        &self,
+        category_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        use crate::schema::product_category_classification::dsl;
+        dsl::product_category_classification
+            .filter(dsl::product_category_id.eq_any(category_ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn classifications_of(
+        &self,
+        product_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        use crate::schema::product_category_classification::dsl;
+        dsl::product_category_classification
+            .filter(dsl::product_id.eq_any(product_ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn products_in_subtree(&self, category_id: i32) -> QueryResult<Vec<Product>> {
+        use crate::schema::{
+            product, product_category_classification as classification,
+            product_category_closure as closure,
+        };
+        let subtree = closure::table
+            .select(closure::descendant_id)
+            .filter(closure::ancestor_id.eq(category_id));
+        let classified_products = classification::table
+            .select(classification::product_id)
+            .filter(classification::product_category_id.eq_any(subtree));
+        product::table
+            .filter(product::id.eq_any(classified_products))
+            .order(product::id)
+            .load(self)
+    }
+
+    fn product_translations(&self, locale: &str) -> QueryResult<Vec<ProductTranslation>> {
+        use crate::schema::product_translation::dsl;
+        dsl::product_translation
+            .filter(dsl::locale.eq(locale))
+            .order(dsl::product_id)
+            .load(self)
+    }
+
+    fn translations_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductTranslation>> {
+        use crate::schema::product_translation::dsl;
+        dsl::product_translation
+            .filter(dsl::product_id.eq(product_id))
+            .order(dsl::locale)
+            .load(self)
+    }
+
+    fn save_product_translation(&self, translation: &ProductTranslation) -> QueryResult<()> {
+        use crate::schema::product_translation::dsl;
+        insert_into(dsl::product_translation)
+            .values(translation)
+            .on_conflict((dsl::product_id, dsl::locale))
+            .do_update()
+            .set(dsl::description.eq(excluded(dsl::description)))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_product_translation(&self, product_id: i32, locale: &str) -> QueryResult<bool> {
+        use crate::schema::product_translation::dsl;
+        let deleted =
+            diesel::delete(dsl::product_translation.find((product_id, locale.to_string())))
+                .execute(self)?;
+        Ok(deleted > 0)
+    }
+
+    fn set_product_price(&self, product_id: i32, price: Option<i32>) -> QueryResult<()> {
+        use crate::schema::product::dsl;
+        match diesel::update(dsl::product.find(product_id))
+            .set(dsl::price.eq(price))
+            .execute(self)?
+        {
+            0 => Err(diesel::result::Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    fn attributes_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductAttribute>> {
+        use crate::schema::product_attribute::dsl;
+        dsl::product_attribute
+            .filter(dsl::product_id.eq(product_id))
+            .order(dsl::name)
+            .load(self)
+    }
+
+    fn save_product_attribute(&self, attribute: &ProductAttribute) -> QueryResult<()> {
+        use crate::schema::product_attribute::dsl;
+        insert_into(dsl::product_attribute)
+            .values(attribute)
+            .on_conflict((dsl::product_id, dsl::name))
+            .do_update()
+            .set(dsl::value.eq(excluded(dsl::value)))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_product_attribute(&self, product_id: i32, name: &str) -> QueryResult<bool> {
+        use crate::schema::product_attribute::dsl;
+        let deleted = diesel::delete(dsl::product_attribute.find((product_id, name.to_string())))
+            .execute(self)?;
+        Ok(deleted > 0)
+    }
+}
+
+impl StockRepository for PgConnection {
+    fn insert_inventory_item(
+        &self,
+        product_id: i32,
+        warehouse_id: Option<i32>,
+        instance_description: Option<&str>,
+    ) -> QueryResult<InventoryItem> {
+        use crate::schema::inventory_item::dsl;
+        insert_into(dsl::inventory_item)
+            .values((
+                dsl::product_id.eq(product_id),
+                dsl::warehouse_id.eq(warehouse_id),
+                dsl::instance_description.eq(instance_description),
+            ))
+            .get_result(self)
+    }
+
+    fn inventory_item(&self, id: i32) -> QueryResult<Option<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item.find(id).first(self).optional()
+    }
+
+    fn inventory_items_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item
+            .filter(dsl::id.gt(after_id))
+            .order(dsl::id)
+            .limit(limit)
+            .load(self)
+    }
+
+    fn inventory_items(&self) -> QueryResult<Vec<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item.order(dsl::id).load(self)
+    }
+
+    fn inventory_items_of(&self, product_ids: &[i32]) -> QueryResult<Vec<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item
+            .filter(dsl::product_id.eq_any(product_ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn inventory_items_in(&self, warehouse_ids: &[i32]) -> QueryResult<Vec<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item
+            .filter(dsl::warehouse_id.eq_any(warehouse_ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn update_inventory_item(&self, item: &InventoryItem) -> QueryResult<()> {
+        use crate::schema::inventory_item::dsl;
+        match diesel::update(dsl::inventory_item.find(item.id))
+            .set((
+                dsl::product_id.eq(item.product_id),
+                dsl::warehouse_id.eq(item.warehouse_id),
+                dsl::instance_description.eq(&item.instance_description),
+            ))
+            .execute(self)?
+        {
+            0 => Err(diesel::result::Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    fn stock_levels(&self) -> QueryResult<Vec<StockLevel>> {
+        sql_query(
+            "select product.id as product_id,
+                    product.description as product_description,
+                    warehouse.id as warehouse_id,
+                    warehouse.description as warehouse_description,
+                    count(*) as quantity
+               from inventory_item
+               join product on product.id = inventory_item.product_id
+               left join warehouse on warehouse.id = inventory_item.warehouse_id
+              group by product.id, product.description, warehouse.id, warehouse.description
+              order by product.id, warehouse.id nulls last",
+        )
+        .load(self)
+    }
+}
+
+/// Reads the snapshot of every category, or only of the one given
+fn stored_statistics(
+    connection: &PgConnection,
+    category_id: Option<i32>,
+) -> QueryResult<Vec<CategoryStatistics>> {
+    use crate::schema::{
+        product_category_statistics as snapshot,
+        product_category_statistics_stock as snapshot_stock, warehouse,
+    };
+    let mut counts = snapshot::table
+        .select((
+            snapshot::category_id,
+            snapshot::direct_products,
+            snapshot::products,
+        ))
+        .order(snapshot::category_id)
+        .into_boxed();
+    let mut stock = snapshot_stock::table
+        .left_join(warehouse::table)
+        .select((
+            snapshot_stock::category_id,
+            snapshot_stock::warehouse_id,
+            warehouse::description.nullable(),
+            snapshot_stock::quantity,
+        ))
+        .order((snapshot_stock::category_id, snapshot_stock::id))
+        .into_boxed();
+    if let Some(category_id) = category_id {
+        counts = counts.filter(snapshot::category_id.eq(category_id));
+        stock = stock.filter(snapshot_stock::category_id.eq(category_id));
+    }
+    let refreshed_at = snapshot::table
+        .select(snapshot::refreshed_at)
+        .first(connection)
+        .optional()?;
+    Ok(statistics::assemble(
+        counts.load(connection)?,
+        stock.load(connection)?,
+        refreshed_at,
+    ))
+}
+
+impl StatisticsRepository for PgConnection {
+    fn category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>> {
+        let counts = sql_query(
+            "select category.id as category_id,
+                    coalesce(direct.products, 0) as direct_products,
+                    coalesce(subtree.products, 0) as products
+               from product_category category
+               left join (select product_category_id as category_id,
+                                 count(distinct product_id) as products
+                            from product_category_classification
+                           group by product_category_id) direct
+                      on direct.category_id = category.id
+               left join (select closure.ancestor_id as category_id,
+                                 count(distinct classification.product_id) as products
+                            from product_category_closure closure
+                            join product_category_classification classification
+                              on classification.product_category_id = closure.descendant_id
+                            join product_category descendant
+                              on descendant.id = closure.descendant_id
+                           where (closure.depth = 0 or not descendant.is_archived)
+                           group by closure.ancestor_id) subtree
+                      on subtree.category_id = category.id
+              order by category.id",
+        )
+        .load(self)?;
+        let stock = sql_query(
+            "select subtree.category_id,
+                    warehouse.id as warehouse_id,
+                    warehouse.description as warehouse_description,
+                    count(*) as quantity
+               from (select distinct closure.ancestor_id as category_id,
+                                     classification.product_id
+                       from product_category_closure closure
+                       join product_category_classification classification
+                         on classification.product_category_id = closure.descendant_id
+                       join product_category descendant
+                         on descendant.id = closure.descendant_id
+                      where (closure.depth = 0 or not descendant.is_archived)) subtree
+               join inventory_item on inventory_item.product_id = subtree.product_id
+               left join warehouse on warehouse.id = inventory_item.warehouse_id
+              group by subtree.category_id, warehouse.id, warehouse.description
+              order by subtree.category_id, warehouse.id nulls last",
+        )
+        .load(self)?;
+        Ok(statistics::assemble(counts, stock, None))
+    }
+
+    fn category_statistics_of(&self, category_id: i32) -> QueryResult<Option<CategoryStatistics>> {
+        let counts = sql_query(
+            "select category.id as category_id,
+                    (select count(distinct product_id)
+                       from product_category_classification
+                      where product_category_id = category.id) as direct_products,
+                    (select count(distinct classification.product_id)
+                       from product_category_closure closure
+                       join product_category_classification classification
+                         on classification.product_category_id = closure.descendant_id
+                       join product_category descendant
+                         on descendant.id = closure.descendant_id
+                      where closure.ancestor_id = category.id
+                        and (closure.depth = 0 or not descendant.is_archived)) as products
+               from product_category category
+              where category.id = $1",
+        )
+        .bind::<Integer, _>(category_id)
+        .load(self)?;
+        let stock = sql_query(
+            "select subtree.category_id,
+                    warehouse.id as warehouse_id,
+                    warehouse.description as warehouse_description,
+                    count(*) as quantity
+               from (select distinct closure.ancestor_id as category_id,
+                                     classification.product_id
+                       from product_category_closure closure
+                       join product_category_classification classification
+                         on classification.product_category_id = closure.descendant_id
+                       join product_category descendant
+                         on descendant.id = closure.descendant_id
+                      where closure.ancestor_id = $1
+                        and (closure.depth = 0 or not descendant.is_archived)) subtree
+               join inventory_item on inventory_item.product_id = subtree.product_id
+               left join warehouse on warehouse.id = inventory_item.warehouse_id
+              group by subtree.category_id, warehouse.id, warehouse.description
+              order by warehouse.id nulls last",
+        )
+        .bind::<Integer, _>(category_id)
+        .load(self)?;
+        Ok(statistics::assemble(counts, stock, None).pop())
+    }
+
+    fn store_category_statistics(&self, statistics: &[CategoryStatistics]) -> QueryResult<()> {
+        use crate::schema::{
+            product_category_statistics as snapshot,
+            product_category_statistics_stock as snapshot_stock,
+        };
+        diesel::delete(snapshot_stock::table).execute(self)?;
+        diesel::delete(snapshot::table).execute(self)?;
+        if statistics.is_empty() {
+            return Ok(());
+        }
+        insert_into(snapshot::table)
+            .values(
+                statistics
+                    .iter()
+                    .map(|statistics| {
+                        (
+                            snapshot::category_id.eq(statistics.category_id),
+                            snapshot::direct_products.eq(statistics.direct_products),
+                            snapshot::products.eq(statistics.products),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .execute(self)?;
+        let stock: Vec<_> = statistics
+            .iter()
+            .flat_map(|statistics| {
+                statistics.stock.iter().map(move |stock| {
+                    (
+                        snapshot_stock::category_id.eq(statistics.category_id),
+                        snapshot_stock::warehouse_id.eq(stock.warehouse_id),
+                        snapshot_stock::quantity.eq(stock.quantity),
+                    )
+                })
+            })
+            .collect();
+        if !stock.is_empty() {
+            insert_into(snapshot_stock::table)
+                .values(stock)
+                .execute(self)?;
+        }
+        Ok(())
+    }
+
+    fn stored_category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>> {
+        stored_statistics(self, None)
+    }
+
+    fn stored_category_statistics_of(
+        &self,
+        category_id: i32,
+    ) -> QueryResult<Option<CategoryStatistics>> {
+        Ok(stored_statistics(self, Some(category_id))?.pop())
+    }
+
+    fn catalog_counts(&self) -> QueryResult<CatalogCounts> {
+        use crate::schema::{inventory_item, product, product_category};
+        let mut warehouse_items: Vec<(Option<i32>, i64)> = inventory_item::table
+            .group_by(inventory_item::warehouse_id)
+            .select((inventory_item::warehouse_id, sql::<BigInt>("count(*)")))
+            .load(self)?;
+        // Items without a warehouse are counted last
+        warehouse_items.sort_by_key(|(warehouse_id, _)| (warehouse_id.is_none(), *warehouse_id));
+        Ok(CatalogCounts {
+            categories: product_category::table.count().get_result(self)?,
+            products: product::table.count().get_result(self)?,
+            warehouse_items,
+        })
+    }
+}
+
+impl SearchRepository for PgConnection {
+    fn search_products(&self, search: &ProductSearch) -> QueryResult<Vec<SearchHit>> {
+        use diesel::sql_types::{Bool, Text};
+        let text = search.text.trim();
+        let product_id = text
+            .parse::<i32>()
+            .ok()
+            .filter(|product_id| product_id.to_string() == text);
+        // The candidates are found through the indexes of the descriptions and of the category
+        // names each, and only they are ranked. The descriptions are escaped like by
+        // `search::escape_html` before they are highlighted.
+        sql_query(
+            "with candidate as (
+                 select product.id as product_id
+                   from product
+                  where to_tsvector('simple', product.description) @@ to_tsquery('simple', $2)
+                 union
+                 select product.id
+                   from product
+                  where $1 <% product.description
+                 union
+                 select classification.product_id
+                   from product_category category
+                   join product_category_classification classification
+                     on classification.product_category_id = category.id
+                  where to_tsvector('simple', category.name) @@ to_tsquery('simple', $2)
+                     or $1 <% category.name
+                 union
+                 select product.id
+                   from product
+                  where product.id = $7)
+             select product.id as product_id,
+                    product.description,
+                    cast(ts_rank_cd(to_tsvector('simple', product.description), query)
+                         + 0.5 * ts_rank_cd(to_tsvector('simple', coalesce(names.names, '')), query)
+                         + greatest(word_similarity($1, product.description),
+                                    0.5 * coalesce(names.similarity, 0))
+                         + case when cast(product.id as text) = $1 then 1 else 0 end
+                         as real) as rank,
+                    ts_headline('simple',
+                                replace(replace(replace(replace(replace(product.description,
+                                    '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '\"', '&quot;'),
+                                    '''', '&#39;'),
+                                query,
+                                'StartSel=<mark>, StopSel=</mark>, HighlightAll=true') as highlight
+               from candidate
+               join product on product.id = candidate.product_id
+               left join lateral (select string_agg(category.name, ' ') as names,
+                                         max(word_similarity($1, category.name)) as similarity
+                                    from product_category_classification classification
+                                    join product_category category
+                                      on category.id = classification.product_category_id
+                                   where classification.product_id = product.id) names on true
+              cross join to_tsquery('simple', $2) query
+              where ($3 is null
+                     or exists (select 1
+                                  from product_category_closure closure
+                                  join product_category_classification classification
+                                    on classification.product_category_id = closure.descendant_id
+                                 where closure.ancestor_id = $3
+                                   and classification.product_id = product.id))
+                and ($4 is null
+                     or exists (select 1
+                                  from inventory_item
+                                 where inventory_item.product_id = product.id
+                                   and inventory_item.warehouse_id = $4))
+                and (not $5
+                     or exists (select 1
+                                  from inventory_item
+                                 where inventory_item.product_id = product.id))
+              order by rank desc, product.id
+              limit $6",
+        )
+        .bind::<Text, _>(text)
+        .bind::<Text, _>(search.ts_query())
+        .bind::<Nullable<Integer>, _>(search.category_id)
+        .bind::<Nullable<Integer>, _>(search.warehouse_id)
+        .bind::<Bool, _>(search.in_stock)
+        .bind::<BigInt, _>(search.limit)
+        .bind::<Nullable<Integer>, _>(product_id)
+        .load(self)
+    }
+}
+
+impl FacetRepository for PgConnection {
+    fn facet_counts(
+        &self,
+        filters: &ListingFilters,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<FacetCounts> {
+        use crate::schema::{
+            inventory_item, product, product_attribute,
+            product_category_classification as classification, product_category_closure as closure,
+        };
+        let without_categories = ListingFilters {
+            categories: Vec::new(),
+            ..filters.clone()
+        };
+        let without_warehouses = ListingFilters {
+            warehouses: Vec::new(),
+            ..filters.clone()
+        };
+        let without_price = ListingFilters {
+            min_price: None,
+            max_price: None,
+            ..filters.clone()
+        };
+        let filtered_names: Vec<String> = filters.attributes.keys().cloned().collect();
+        let mut attributes: Vec<(String, String, i64)> = product_attribute::table
+            .filter(product_attribute::product_id.eq_any(matching_products(filters)))
+            .filter(product_attribute::name.ne_all(filtered_names.clone()))
+            .group_by((product_attribute::name, product_attribute::value))
+            .select((
+                product_attribute::name,
+                product_attribute::value,
+                sql::<BigInt>("count(*)"),
+            ))
+            .load(self)?;
+        for name in filtered_names {
+            let mut without_name = filters.clone();
+            without_name.attributes.remove(&name);
+            attributes.extend(
+                product_attribute::table
+                    .filter(product_attribute::name.eq(name))
+                    .filter(product_attribute::product_id.eq_any(matching_products(&without_name)))
+                    .group_by((product_attribute::name, product_attribute::value))
+                    .select((
+                        product_attribute::name,
+                        product_attribute::value,
+                        sql::<BigInt>("count(*)"),
+                    ))
+                    .load::<(String, String, i64)>(self)?,
+            );
+        }
+        let categories: Vec<(i32, i64)> = classification::table
+            .inner_join(
+                closure::table.on(closure::descendant_id.eq(classification::product_category_id)),
+            )
+            .filter(classification::product_id.eq_any(matching_products(&without_categories)))
+            .group_by(closure::ancestor_id)
+            .select((
+                closure::ancestor_id,
+                sql::<BigInt>("count(distinct product_category_classification.product_id)"),
+            ))
+            .load(self)?;
+        let warehouses: Vec<(Option<i32>, i64)> = inventory_item::table
+            .filter(inventory_item::product_id.eq_any(matching_products(&without_warehouses)))
+            .filter(inventory_item::warehouse_id.is_not_null())
+            .group_by(inventory_item::warehouse_id)
+            .select((
+                inventory_item::warehouse_id,
+                sql::<BigInt>("count(distinct inventory_item.product_id)"),
+            ))
+            .load(self)?;
+        let (min_price, max_price, priced) = product::table
+            .filter(product::id.eq_any(matching_products(&without_price)))
+            .select(sql::<(Nullable<Integer>, Nullable<Integer>, BigInt)>(
+                "min(product.price), max(product.price), count(product.price)",
+            ))
+            .get_result(self)?;
+        Ok(FacetCounts {
+            total: product::table
+                .filter(product::id.eq_any(matching_products(filters)))
+                .count()
+                .get_result(self)?,
+            products: product::table
+                .filter(product::id.eq_any(matching_products(filters)))
+                .order(product::id)
+                .limit(limit)
+                .offset(offset)
+                .load(self)?,
+            categories: categories.into_iter().collect(),
+            warehouses: warehouses
+                .into_iter()
+                .filter_map(|(warehouse_id, count)| Some((warehouse_id?, count)))
+                .collect(),
+            price: PriceFacet {
+                min: min_price,
+                max: max_price,
+                count: priced,
+            },
+            attributes: attributes.into_iter().fold(
+                BTreeMap::new(),
+                |mut attributes, (name, value, count)| {
+                    attributes.entry(name).or_default().insert(value, count);
+                    attributes
+                },
+            ),
+        })
+    }
+}
+
+/// Ids of the products matching all filters of a listing
+fn matching_products(
+    filters: &ListingFilters,
+) -> crate::schema::product::BoxedQuery<'static, Pg, Integer> {
+    use crate::schema::{
+        inventory_item, product, product_attribute,
+        product_category_classification as classification, product_category_closure as closure,
+    };
+    let mut products = product::table.select(product::id).into_boxed();
+    if !filters.categories.is_empty() {
+        products = products.filter(
+            product::id.eq_any(
+                classification::table
+                    .inner_join(
+                        closure::table
+                            .on(closure::descendant_id.eq(classification::product_category_id)),
+                    )
+                    .filter(closure::ancestor_id.eq_any(filters.categories.clone()))
+                    .select(classification::product_id),
+            ),
+        );
+    }
+    if !filters.warehouses.is_empty() {
+        products = products.filter(
+            product::id.eq_any(
+                inventory_item::table
+                    .filter(inventory_item::warehouse_id.eq_any(filters.warehouses.clone()))
+                    .select(inventory_item::product_id),
+            ),
+        );
+    }
+    if let Some(min_price) = filters.min_price {
+        products = products.filter(product::price.ge(min_price));
+    }
+    if let Some(max_price) = filters.max_price {
+        products = products.filter(product::price.le(max_price));
+    }
+    for (name, values) in &filters.attributes {
+        products = products.filter(
+            product::id.eq_any(
+                product_attribute::table
+                    .filter(product_attribute::name.eq(name.clone()))
+                    .filter(product_attribute::value.eq_any(values.clone()))
+                    .select(product_attribute::product_id),
+            ),
+        );
+    }
+    products
+}
+
+impl WarehouseRepository for PgConnection {
+    fn warehouses(&self) -> QueryResult<Vec<Warehouse>> {
+        use crate::schema::warehouse::dsl;
+        dsl::warehouse.order(dsl::id).load(self)
+    }
+
+    fn warehouse(&self, id: i32) -> QueryResult<Option<Warehouse>> {
+        use crate::schema::warehouse::dsl;
+        dsl::warehouse.find(id).first(self).optional()
+    }
+
+    fn warehouses_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Warehouse>> {
+        use crate::schema::warehouse::dsl;
+        dsl::warehouse
+            .filter(dsl::id.eq_any(ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn insert_warehouse(&self, description: &str) -> QueryResult<Warehouse> {
+        use crate::schema::warehouse::dsl;
+        insert_into(dsl::warehouse)
+            .values(dsl::description.eq(description))
+            .get_result(self)
+    }
+}
+
+impl ApiKeyRepository for PgConnection {
+    fn api_key(&self, key_hash: &str) -> QueryResult<Option<ApiKey>> {
+        use crate::schema::api_key::dsl;
+        dsl::api_key
+            .select((
+                dsl::id,
+                dsl::key_hash,
+                dsl::role,
+                dsl::description,
+                dsl::tenant_id.nullable(),
+            ))
+            .filter(dsl::key_hash.eq(key_hash))
+            .first(self)
+            .optional()
+    }
+
+    fn insert_api_key(&self, key_hash: &str, role: Role, description: &str) -> QueryResult<ApiKey> {
+        use crate::schema::api_key::dsl;
+        insert_into(dsl::api_key)
+            .values((
+                dsl::key_hash.eq(key_hash),
+                dsl::role.eq(role.as_str()),
+                dsl::description.eq(description),
+                dsl::tenant_id.eq(sql::<Integer>("current_tenant_id()")),
+            ))
+            .returning((
+                dsl::id,
+                dsl::key_hash,
+                dsl::role,
+                dsl::description,
+                dsl::tenant_id.nullable(),
+            ))
+            .get_result(self)
+    }
+}
+
+impl IdempotencyRepository for PgConnection {
+    fn claim_idempotency_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        retention: Duration,
+        claim_timeout: Duration,
+    ) -> QueryResult<Claim> {
+        use crate::schema::idempotency_key::dsl;
+        self.transaction(|| {
+            diesel::delete(
+                dsl::idempotency_key.filter(
+                    dsl::created_at
+                        .lt(before_now(retention))
+                        .or(dsl::response_status
+                            .is_null()
+                            .and(dsl::created_at.lt(before_now(claim_timeout)))),
+                ),
+            )
+            .execute(self)?;
+            let claimed_at: Option<DateTime<Utc>> = insert_into(dsl::idempotency_key)
+                .values((dsl::key.eq(key), dsl::fingerprint.eq(fingerprint)))
+                .on_conflict_do_nothing()
+                .returning(dsl::created_at)
+                .get_result(self)
+                .optional()?;
+            if let Some(claimed_at) = claimed_at {
+                return Ok(Claim::Claimed(claimed_at));
+            }
+            let record: IdempotencyRecord =
+                dsl::idempotency_key.filter(dsl::key.eq(key)).first(self)?;
+            if record.fingerprint != fingerprint {
+                return Ok(Claim::Mismatch);
+            }
+            Ok(match record.response_status {
+                Some(status) => Claim::Completed(StoredResponse {
+                    status: status as u16,
+                    content_type: record.response_content_type,
+                    body: record.response_body.unwrap_or_default(),
+                }),
+                None => Claim::InProgress,
+            })
+        })
+    }
+
+    fn complete_idempotency_key(
+        &self,
+        key: &str,
+        claimed_at: DateTime<Utc>,
+        response: &StoredResponse,
+    ) -> QueryResult<()> {
+        use crate::schema::idempotency_key::dsl;
+        diesel::update(
+            dsl::idempotency_key
+                .filter(dsl::key.eq(key))
+                .filter(dsl::created_at.eq(claimed_at))
+                .filter(dsl::response_status.is_null()),
+        )
+        .set((
+            dsl::response_status.eq(response.status as i16),
+            dsl::response_content_type.eq(&response.content_type),
+            dsl::response_body.eq(&response.body),
+        ))
+        .execute(self)?;
+        Ok(())
+    }
+
+    fn release_idempotency_key(&self, key: &str, claimed_at: DateTime<Utc>) -> QueryResult<()> {
+        use crate::schema::idempotency_key::dsl;
+        diesel::delete(
+            dsl::idempotency_key
+                .filter(dsl::key.eq(key))
+                .filter(dsl::created_at.eq(claimed_at))
+                .filter(dsl::response_status.is_null()),
+        )
+        .execute(self)?;
+        Ok(())
+    }
+}
+
+impl OutboxRepository for PgConnection {
+    fn outbox_event(&self, id: i64) -> QueryResult<Option<OutboxEvent>> {
+        use crate::schema::outbox_event::dsl;
+        dsl::outbox_event.find(id).first(self).optional()
+    }
+
+    fn outbox_tenant_id(&self) -> QueryResult<Option<i32>> {
+        diesel::select(sql::<Nullable<Integer>>("current_tenant_id()")).get_result(self)
+    }
+
+    fn stream_horizon(&self) -> QueryResult<i64> {
+        diesel::select(sql::<BigInt>("txid_snapshot_xmin(txid_current_snapshot())"))
+            .get_result(self)
+    }
+
+    fn events_after(
+        &self,
+        position: StreamPosition,
+        horizon: i64,
+        limit: i64,
+    ) -> QueryResult<Vec<OutboxEvent>> {
+        use crate::schema::outbox_event::dsl::*;
+        outbox_event
+            .filter(transaction_id.lt(horizon))
+            .filter(
+                transaction_id.gt(position.transaction_id).or(transaction_id
+                    .eq(position.transaction_id)
+                    .and(id.gt(position.event_id))),
+            )
+            .order((transaction_id, id))
+            .limit(limit)
+            .load(self)
+    }
+
+    fn first_transaction_after(&self, event_id: i64) -> QueryResult<Option<i64>> {
+        use crate::schema::outbox_event::dsl::*;
+        outbox_event
+            .filter(id.gt(event_id))
+            .select(min(transaction_id))
+            .first(self)
+    }
+}
+
+impl WebhookRepository for PgConnection {
+    fn webhook_subscriptions(&self) -> QueryResult<Vec<WebhookSubscription>> {
+        use crate::schema::webhook_subscription::dsl;
+        dsl::webhook_subscription.order(dsl::id).load(self)
+    }
+
+    fn webhook_subscription(&self, id: i32) -> QueryResult<Option<WebhookSubscription>> {
+        use crate::schema::webhook_subscription::dsl;
+        dsl::webhook_subscription.find(id).first(self).optional()
+    }
+
+    fn insert_webhook_subscription(
+        &self,
+        url: &str,
+        event_types: &[String],
+        secret: &str,
+    ) -> QueryResult<WebhookSubscription> {
+        use crate::schema::webhook_subscription::dsl;
+        insert_into(dsl::webhook_subscription)
+            .values((
+                dsl::url.eq(url),
+                dsl::event_types.eq(event_types),
+                dsl::secret.eq(secret),
+            ))
+            .get_result(self)
+    }
+
+    fn delete_webhook_subscription(&self, id: i32) -> QueryResult<bool> {
+        use crate::schema::webhook_subscription::dsl;
+        let deleted = diesel::delete(dsl::webhook_subscription.find(id)).execute(self)?;
+        Ok(deleted > 0)
+    }
+
+    fn webhook_deliveries(&self) -> QueryResult<Vec<WebhookDelivery>> {
+        use crate::schema::webhook_delivery::dsl;
+        dsl::webhook_delivery.order(dsl::id).load(self)
+    }
+
+    fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        leased_until: DateTime<Utc>,
+    ) -> QueryResult<Vec<WebhookDelivery>> {
+        use crate::schema::webhook_delivery::dsl;
+        self.transaction(|| {
+            // Locked deliveries are being claimed by another instance and are skipped
+            let due_deliveries: Vec<WebhookDelivery> = dsl::webhook_delivery
+                .filter(dsl::status.eq(DELIVERY_PENDING))
+                .filter(dsl::next_attempt_at.le(Utc::now()))
+                .order(dsl::id)
+                .limit(limit)
+                .for_update()
+                .skip_locked()
+                .load(self)?;
+            diesel::update(
+                dsl::webhook_delivery
+                    .filter(dsl::id.eq_any(due_deliveries.iter().map(|delivery| delivery.id))),
+            )
+            .set(dsl::next_attempt_at.eq(leased_until))
+            .execute(self)?;
+            Ok(due_deliveries)
+        })
+    }
+
+    fn record_delivery_attempt(
+        &self,
+        delivery: &WebhookDelivery,
+        attempt: &DeliveryAttempt,
+    ) -> QueryResult<()> {
+        use crate::schema::webhook_delivery::dsl;
+        let claimed_delivery = dsl::webhook_delivery
+            .find(delivery.id)
+            .filter(dsl::attempts.eq(delivery.attempts));
+        let recorded = (
+            dsl::status.eq(attempt.status),
+            dsl::attempts.eq(delivery.attempts + 1),
+            dsl::last_error.eq(&attempt.last_error),
+        );
+        match attempt.next_attempt_at {
+            Some(next_attempt_at) => diesel::update(claimed_delivery)
+                .set((recorded, dsl::next_attempt_at.eq(next_attempt_at)))
+                .execute(self)?,
+            None => diesel::update(claimed_delivery)
+                .set(recorded)
+                .execute(self)?,
+        };
+        Ok(())
+    }
+}
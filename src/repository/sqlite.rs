@@ -0,0 +1,1602 @@
+//! Implementation of the repositories on the SQLite database of a single site
+//!
+//! SQLite knows neither `returning` nor row locks, so inserted rows are read back through their
+//! row id, and the whole database is locked by a writing transaction instead.
+
+use super::{
+    ApiKeyRepository, CategoryRepository, EventLog, FacetRepository, IdempotencyRepository,
+    OutboxRepository, ProductRepository, SearchRepository, StatisticsRepository, StockRepository,
+    Transactional, WarehouseRepository, WebhookRepository,
+};
+use crate::{
+    auth::{entities::ApiKey, Role},
+    catalog::{
+        entities::ProductAttribute,
+        facets::{FacetCounts, ListingFilters, PriceFacet},
+    },
+    entities::{
+        InventoryItem, Product, ProductCategoryClassification, ProductCategoryRollup, Warehouse,
+    },
+    events::broadcaster::StreamPosition,
+    idempotency::store::{before_now, Claim, StoredResponse},
+    inventory::reports::StockLevel,
+    localization::entities::{CategoryTranslation, ProductTranslation},
+    metrics::gauges::CatalogCounts,
+    outbox::{entities::OutboxEvent, EventType},
+    product_category::{
+        entities::{ProductCategory, RelatedCategory},
+        statistics::{self, CategoryStatistics},
+    },
+    search::{self, ProductSearch, SearchHit},
+    webhooks::entities::{DeliveryAttempt, WebhookDelivery, WebhookSubscription, DELIVERY_PENDING},
+};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use diesel::{
+    connection::{SimpleConnection, TransactionManager},
+    dsl::{max, min, sql},
+    insert_into,
+    query_dsl::GroupByDsl,
+    result::{DatabaseErrorKind, Error},
+    sql_query,
+    sql_types::{BigInt, Integer},
+    sqlite::Sqlite,
+    BoolExpressionMethods, Connection, ExpressionMethods, JoinOnDsl, NullableExpressionMethods,
+    OptionalExtension, QueryDsl, QueryResult, RunQueryDsl, SqliteConnection,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, time::Duration};
+
+/// Tables whose columns have other types on SQLite
+///
+/// Payloads of the outbox and the event types of webhook subscriptions are stored as JSON text,
+/// and times as UTC without a zone. API keys are not bound to a tenant.
+mod schema {
+    table! {
+        api_key (id) {
+            id -> Integer,
+            key_hash -> Varchar,
+            role -> Varchar,
+            description -> Text,
+        }
+    }
+
+    table! {
+        outbox_event (id) {
+            id -> BigInt,
+            event_type -> Text,
+            payload -> Text,
+            created_at -> Timestamp,
+            request_id -> Nullable<Text>,
+        }
+    }
+
+    table! {
+        idempotency_key (key) {
+            key -> Varchar,
+            fingerprint -> Varchar,
+            response_status -> Nullable<SmallInt>,
+            response_content_type -> Nullable<Varchar>,
+            response_body -> Nullable<Binary>,
+            created_at -> Timestamp,
+        }
+    }
+
+    table! {
+        product_category_statistics (category_id) {
+            category_id -> Integer,
+            direct_products -> BigInt,
+            products -> BigInt,
+            refreshed_at -> Timestamp,
+        }
+    }
+
+    table! {
+        webhook_delivery (id) {
+            id -> BigInt,
+            outbox_event_id -> BigInt,
+            webhook_subscription_id -> Integer,
+            status -> Varchar,
+            attempts -> Integer,
+            next_attempt_at -> Timestamp,
+            last_error -> Nullable<Text>,
+        }
+    }
+
+    table! {
+        webhook_subscription (id) {
+            id -> Integer,
+            url -> Text,
+            event_types -> Text,
+            secret -> Text,
+            active -> Bool,
+        }
+    }
+}
+
+no_arg_sql_function!(
+    last_insert_rowid,
+    Integer,
+    "Row id of the row inserted last on the connection"
+);
+
+/// Prepares a pooled connection for use
+///
+/// SQLite only enforces foreign keys if they are switched on for every connection. The
+/// write-ahead log, which is kept by the database file once it is switched on, lets writers go on
+/// while a snapshot is read, like during an export.
+pub fn prepare(connection: &SqliteConnection) -> QueryResult<()> {
+    connection.batch_execute(
+        "pragma foreign_keys = on; pragma busy_timeout = 5000; pragma journal_mode = wal;",
+    )
+}
+
+/// Classifies foreign key violations which SQLite reports with its primary result code only
+///
+/// Violations of `on delete restrict` actions are reported like that, which diesel can not tell
+/// apart from other errors.
+fn classify_violation(error: Error) -> Error {
+    match error {
+        Error::DatabaseError(DatabaseErrorKind::__Unknown, information)
+            if information.message() == "FOREIGN KEY constraint failed" =>
+        {
+            Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, information)
+        }
+        error => error,
+    }
+}
+
+/// Adds the pairs of a category and its descendants one level deeper than the given depth, which
+/// no shorter path connects yet
+const EXTEND_CLOSURE: &str = "
+    insert into product_category_closure(ancestor_id, descendant_id, depth)
+    select distinct path.ancestor_id, rollup.lower_category_id, path.depth + 1
+      from product_category_closure path
+      join product_category_rollup rollup on rollup.upper_category_id = path.descendant_id
+     where path.ancestor_id = ?
+       and path.depth = ?
+       and not exists (select 1
+                         from product_category_closure shorter
+                        where shorter.ancestor_id = path.ancestor_id
+                          and shorter.descendant_id = rollup.lower_category_id)";
+
+/// Recomputes the paths from the categories to their descendants, like the triggers do on
+/// Postgres
+///
+/// The closure is extended breadth first, so every pair is found once at its shortest depth.
+/// SQLite does not allow this loop in a trigger, so removing links has to be followed by this
+/// for the ancestors of their upper categories.
+fn refresh_closure_of(connection: &SqliteConnection, ancestor_ids: &[i32]) -> QueryResult<()> {
+    use crate::schema::product_category_closure::dsl::*;
+    diesel::delete(
+        product_category_closure
+            .filter(ancestor_id.eq_any(ancestor_ids))
+            .filter(depth.gt(0)),
+    )
+    .execute(connection)?;
+    for ancestor in ancestor_ids {
+        let mut current_depth = 0;
+        while sql_query(EXTEND_CLOSURE)
+            .bind::<Integer, _>(ancestor)
+            .bind::<Integer, _>(current_depth)
+            .execute(connection)?
+            > 0
+        {
+            current_depth += 1;
+        }
+    }
+    Ok(())
+}
+
+fn last_insert_id(connection: &SqliteConnection) -> QueryResult<i32> {
+    diesel::select(last_insert_rowid).get_result(connection)
+}
+
+impl Transactional for SqliteConnection {
+    fn atomically<T, F>(&self, f: F) -> QueryResult<T>
+    where
+        F: FnOnce() -> QueryResult<T>,
+    {
+        self.transaction(f)
+    }
+
+    /// Reads of an SQLite transaction always see a single state of the database
+    fn begin_snapshot(&self) -> QueryResult<()> {
+        self.transaction_manager().begin_transaction(self)
+    }
+
+    fn end_snapshot(&self) -> QueryResult<()> {
+        self.transaction_manager().commit_transaction(self)
+    }
+}
+
+impl EventLog for SqliteConnection {
+    fn record_all<T: Serialize>(
+        &self,
+        event_type: EventType,
+        payloads: &[T],
+    ) -> QueryResult<usize> {
+        use self::schema::outbox_event::dsl;
+        let rows = payloads
+            .iter()
+            .map(|payload| {
+                serde_json::to_string(payload)
+                    .map(|payload| {
+                        (
+                            dsl::event_type.eq(event_type.as_str()),
+                            dsl::payload.eq(payload),
+                        )
+                    })
+                    .map_err(|error| Error::SerializationError(Box::new(error)))
+            })
+            .collect::<QueryResult<Vec<_>>>()?;
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        insert_into(dsl::outbox_event).values(&rows).execute(self)
+    }
+}
+
+impl CategoryRepository for SqliteConnection {
+    fn categories(&self) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category.order(dsl::id).load(self)
+    }
+
+    fn category(&self, id: i32) -> QueryResult<Option<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category.find(id).first(self).optional()
+    }
+
+    fn categories_named(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category
+            .filter(dsl::name.eq_any(names))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn categories_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category
+            .filter(dsl::id.eq_any(ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn categories_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category
+            .filter(dsl::id.gt(after_id))
+            .order(dsl::id)
+            .limit(limit)
+            .load(self)
+    }
+
+    fn lock_categories(&self, ids: &[i32], names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        dsl::product_category
+            .filter(dsl::id.eq_any(ids))
+            .or_filter(dsl::name.eq_any(names))
+            .load(self)
+    }
+
+    fn insert_category(&self, name: &str) -> QueryResult<ProductCategory> {
+        use crate::schema::product_category::dsl;
+        insert_into(dsl::product_category)
+            .values(dsl::name.eq(name))
+            .execute(self)?;
+        let id = last_insert_id(self)?;
+        self.refresh_slug(id)?;
+        dsl::product_category.find(id).first(self)
+    }
+
+    fn insert_missing_categories(&self, names: &[&str]) -> QueryResult<Vec<ProductCategory>> {
+        use crate::schema::product_category::dsl;
+        let existing_names: Vec<String> = dsl::product_category
+            .select(dsl::name)
+            .filter(dsl::name.eq_any(names))
+            .load(self)?;
+        let mut created_categories = Vec::new();
+        for name in names {
+            if !existing_names.iter().any(|existing| existing == name)
+                && !created_categories
+                    .iter()
+                    .any(|created: &ProductCategory| created.name == *name)
+            {
+                created_categories.push(self.insert_category(name)?);
+            }
+        }
+        Ok(created_categories)
+    }
+
+    fn save_categories(&self, categories: &[ProductCategory]) -> QueryResult<()> {
+        use crate::schema::product_category::dsl;
+        for category in categories {
+            let updated_rows = diesel::update(dsl::product_category.find(category.id))
+                .set((
+                    dsl::name.eq(&category.name),
+                    dsl::description.eq(&category.description),
+                    dsl::image_ref.eq(&category.image_ref),
+                    dsl::is_visible.eq(category.is_visible),
+                ))
+                .execute(self)?;
+            if updated_rows == 0 {
+                insert_into(dsl::product_category)
+                    .values((
+                        dsl::id.eq(category.id),
+                        dsl::name.eq(&category.name),
+                        dsl::description.eq(&category.description),
+                        dsl::image_ref.eq(&category.image_ref),
+                        dsl::is_visible.eq(category.is_visible),
+                    ))
+                    .execute(self)?;
+            }
+            self.refresh_slug(category.id)?;
+        }
+        Ok(())
+    }
+
+    fn delete_categories(&self, ids: &[i32]) -> QueryResult<Vec<i32>> {
+        use crate::schema::product_category::dsl;
+        let existing_ids: Vec<i32> = dsl::product_category
+            .select(dsl::id)
+            .filter(dsl::id.eq_any(ids))
+            .load(self)?;
+        diesel::delete(dsl::product_category.filter(dsl::id.eq_any(&existing_ids)))
+            .execute(self)
+            .map_err(classify_violation)?;
+        Ok(existing_ids)
+    }
+
+    fn rollups(&self) -> QueryResult<Vec<(i32, i32)>> {
+        use crate::schema::product_category_rollup::dsl::*;
+        product_category_rollup
+            .select((upper_category_id, lower_category_id))
+            .order((upper_category_id, position, id))
+            .load(self)
+    }
+
+    fn rollups_of(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, i32)>> {
+        use crate::schema::product_category_rollup::dsl::*;
+        product_category_rollup
+            .select((upper_category_id, lower_category_id))
+            .filter(upper_category_id.eq_any(category_ids))
+            .or_filter(lower_category_id.eq_any(category_ids))
+            .order((upper_category_id, position, id))
+            .load(self)
+    }
+
+    fn rollup_links(&self) -> QueryResult<Vec<ProductCategoryRollup>> {
+        use crate::schema::product_category_rollup::dsl;
+        dsl::product_category_rollup.order(dsl::id).load(self)
+    }
+
+    fn insert_rollup(&self, upper: i32, lower: i32) -> QueryResult<()> {
+        use crate::schema::product_category_rollup::dsl::*;
+        let last_position: Option<i32> = product_category_rollup
+            .filter(upper_category_id.eq(upper))
+            .select(max(position))
+            .first(self)?;
+        let next_position = last_position.map_or(0, |last_position| last_position + 1);
+        insert_into(product_category_rollup)
+            .values((
+                upper_category_id.eq(upper),
+                lower_category_id.eq(lower),
+                position.eq(next_position),
+            ))
+            .execute(self)
+            .map_err(classify_violation)?;
+        self.refresh_slug(lower)
+    }
+
+    fn set_rollup_positions(&self, upper: i32, lower_ids: &[i32]) -> QueryResult<()> {
+        use crate::schema::product_category_rollup::dsl::*;
+        for (index, lower) in lower_ids.iter().enumerate() {
+            diesel::update(
+                product_category_rollup
+                    .filter(upper_category_id.eq(upper))
+                    .filter(lower_category_id.eq(lower)),
+            )
+            .set(position.eq(index as i32))
+            .execute(self)?;
+        }
+        Ok(())
+    }
+
+    fn delete_rollups_of(&self, category_id: i32) -> QueryResult<usize> {
+        use crate::schema::{product_category_closure as closure, product_category_rollup::dsl::*};
+        let deleted = diesel::delete(
+            product_category_rollup
+                .filter(upper_category_id.eq(category_id))
+                .or_filter(lower_category_id.eq(category_id)),
+        )
+        .execute(self)?;
+        if deleted > 0 {
+            // Every removed link starts at the category or one of its ancestors
+            let ancestor_ids: Vec<i32> = closure::table
+                .select(closure::ancestor_id)
+                .filter(closure::descendant_id.eq(category_id))
+                .load(self)?;
+            refresh_closure_of(self, &ancestor_ids)?;
+        }
+        Ok(deleted)
+    }
+
+    fn slugs_below(&self, category_ids: &[i32]) -> QueryResult<Vec<(i32, String)>> {
+        use crate::schema::{product_category as category, product_category_rollup as rollup};
+        let lower_ids = rollup::table.select(rollup::lower_category_id);
+        let query = category::table
+            .select((category::id, category::slug))
+            .filter(category::is_archived.eq(false))
+            .order(category::id)
+            .into_boxed();
+        if category_ids.is_empty() {
+            query.filter(category::id.ne_all(lower_ids)).load(self)
+        } else {
+            query
+                .filter(
+                    category::id
+                        .eq_any(lower_ids.filter(rollup::upper_category_id.eq_any(category_ids))),
+                )
+                .load(self)
+        }
+    }
+
+    fn set_slug(&self, category_id: i32, slug: &str) -> QueryResult<()> {
+        use crate::schema::product_category::dsl;
+        diesel::update(dsl::product_category.find(category_id))
+            .set(dsl::slug.eq(slug))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn archive_category(&self, category_id: i32) -> QueryResult<()> {
+        use crate::schema::product_category::dsl;
+        diesel::update(dsl::product_category.find(category_id))
+            .set(dsl::is_archived.eq(true))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn category_translations(&self, locale: &str) -> QueryResult<Vec<CategoryTranslation>> {
+        use crate::schema::product_category_translation::dsl;
+        dsl::product_category_translation
+            .filter(dsl::locale.eq(locale))
+            .order(dsl::category_id)
+            .load(self)
+    }
+
+    fn translations_of_category(&self, category_id: i32) -> QueryResult<Vec<CategoryTranslation>> {
+        use crate::schema::product_category_translation::dsl;
+        dsl::product_category_translation
+            .filter(dsl::category_id.eq(category_id))
+            .order(dsl::locale)
+            .load(self)
+    }
+
+    /// Updates the name first, since `replace` would also remove a translation of another
+    /// category with the name instead of failing
+    fn save_category_translation(&self, translation: &CategoryTranslation) -> QueryResult<()> {
+        use crate::schema::product_category_translation::dsl;
+        let updated = diesel::update(
+            dsl::product_category_translation
+                .find((translation.category_id, translation.locale.as_str())),
+        )
+        .set(dsl::name.eq(&translation.name))
+        .execute(self)?;
+        if updated == 0 {
+            insert_into(dsl::product_category_translation)
+                .values(translation)
+                .execute(self)?;
+        }
+        Ok(())
+    }
+
+    fn delete_category_translation(&self, category_id: i32, locale: &str) -> QueryResult<bool> {
+        use crate::schema::product_category_translation::dsl;
+        let deleted = diesel::delete(dsl::product_category_translation.find((category_id, locale)))
+            .execute(self)?;
+        Ok(deleted > 0)
+    }
+
+    fn descendants(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>> {
+        use crate::schema::{product_category as category, product_category_closure as closure};
+        let mut query = closure::table
+            .inner_join(category::table.on(category::id.eq(closure::descendant_id)))
+            .select((
+                category::id,
+                category::name,
+                category::slug,
+                category::description,
+                category::image_ref,
+                category::is_visible,
+                closure::depth,
+            ))
+            .filter(closure::ancestor_id.eq(category_id))
+            .filter(closure::depth.gt(0))
+            .order((closure::depth, category::id))
+            .into_boxed();
+        if let Some(max_depth) = max_depth {
+            query = query.filter(closure::depth.le(max_depth));
+        }
+        query.load(self)
+    }
+
+    fn ancestors(
+        &self,
+        category_id: i32,
+        max_depth: Option<i32>,
+    ) -> QueryResult<Vec<RelatedCategory>> {
+        use crate::schema::{product_category as category, product_category_closure as closure};
+        let mut query = closure::table
+            .inner_join(category::table.on(category::id.eq(closure::ancestor_id)))
+            .select((
+                category::id,
+                category::name,
+                category::slug,
+                category::description,
+                category::image_ref,
+                category::is_visible,
+                closure::depth,
+            ))
+            .filter(closure::descendant_id.eq(category_id))
+            .filter(closure::depth.gt(0))
+            .order((closure::depth, category::id))
+            .into_boxed();
+        if let Some(max_depth) = max_depth {
+            query = query.filter(closure::depth.le(max_depth));
+        }
+        query.load(self)
+    }
+}
+
+impl ProductRepository for SqliteConnection {
+    fn products(&self) -> QueryResult<Vec<Product>> {
+        use crate::schema::product::dsl;
+        dsl::product.order(dsl::id).load(self)
+    }
+
+    fn product(&self, id: i32) -> QueryResult<Option<Product>> {
+        use crate::schema::product::dsl;
+        dsl::product.find(id).first(self).optional()
+    }
+
+    fn products_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Product>> {
+        use crate::schema::product::dsl;
+        dsl::product
+            .filter(dsl::id.eq_any(ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn insert_product(&self, description: &str) -> QueryResult<Product> {
+        use crate::schema::product::dsl;
+        insert_into(dsl::product)
+            .values(dsl::description.eq(description))
+            .execute(self)?;
+        dsl::product.find(last_insert_id(self)?).first(self)
+    }
+
+    fn products_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<Product>> {
+        use crate::schema::product::dsl;
+        dsl::product
+            .filter(dsl::id.gt(after_id))
+            .order(dsl::id)
+            .limit(limit)
+            .load(self)
+    }
+
+    fn set_product_description(&self, product_id: i32, description: &str) -> QueryResult<()> {
+        use crate::schema::product::dsl;
+        match diesel::update(dsl::product.find(product_id))
+            .set(dsl::description.eq(description))
+            .execute(self)?
+        {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    fn classify(
+        &self,
+        product_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<ProductCategoryClassification> {
+        use crate::schema::product_category_classification::dsl;
+        insert_into(dsl::product_category_classification)
+            .values((
+                dsl::product_id.eq(product_id),
+                dsl::product_category_id.eq(category_id),
+                dsl::is_primary_classification.eq(is_primary),
+            ))
+            .execute(self)
+            .map_err(classify_violation)?;
+        dsl::product_category_classification
+            .find(last_insert_id(self)?)
+            .first(self)
+    }
+
+    fn update_classification(
+        &self,
+        classification_id: i32,
+        category_id: i32,
+        is_primary: bool,
+    ) -> QueryResult<()> {
+        use crate::schema::product_category_classification::dsl;
+        diesel::update(dsl::product_category_classification.find(classification_id))
+            .set((
+                dsl::product_category_id.eq(category_id),
+                dsl::is_primary_classification.eq(is_primary),
+            ))
+            .execute(self)
+            .map_err(classify_violation)?;
+        Ok(())
+    }
+
+    fn delete_classifications(&self, classification_ids: &[i32]) -> QueryResult<usize> {
+        use crate::schema::product_category_classification::dsl;
+        diesel::delete(
+            dsl::product_category_classification.filter(dsl::id.eq_any(classification_ids)),
+        )
+        .execute(self)
+    }
+
+    fn classifications(&self) -> QueryResult<Vec<ProductCategoryClassification>> {
+        use crate::schema::product_category_classification::dsl;
+        dsl::product_category_classification
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn classifications_in(
+        &self,
+        category_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        use crate::schema::product_category_classification::dsl;
+        dsl::product_category_classification
+            .filter(dsl::product_category_id.eq_any(category_ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn classifications_of(
+        &self,
+        product_ids: &[i32],
+    ) -> QueryResult<Vec<ProductCategoryClassification>> {
+        use crate::schema::product_category_classification::dsl;
+        dsl::product_category_classification
+            .filter(dsl::product_id.eq_any(product_ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn products_in_subtree(&self, category_id: i32) -> QueryResult<Vec<Product>> {
+        use crate::schema::{
+            product, product_category_classification as classification,
+            product_category_closure as closure,
+        };
+        let subtree = closure::table
+            .select(closure::descendant_id)
+            .filter(closure::ancestor_id.eq(category_id));
+        let classified_products = classification::table
+            .select(classification::product_id)
+            .filter(classification::product_category_id.eq_any(subtree));
+        product::table
+            .filter(product::id.eq_any(classified_products))
+            .order(product::id)
+            .load(self)
+    }
+
+    fn product_translations(&self, locale: &str) -> QueryResult<Vec<ProductTranslation>> {
+        use crate::schema::product_translation::dsl;
+        dsl::product_translation
+            .filter(dsl::locale.eq(locale))
+            .order(dsl::product_id)
+            .load(self)
+    }
+
+    fn translations_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductTranslation>> {
+        use crate::schema::product_translation::dsl;
+        dsl::product_translation
+            .filter(dsl::product_id.eq(product_id))
+            .order(dsl::locale)
+            .load(self)
+    }
+
+    fn save_product_translation(&self, translation: &ProductTranslation) -> QueryResult<()> {
+        use crate::schema::product_translation::dsl;
+        diesel::replace_into(dsl::product_translation)
+            .values(translation)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_product_translation(&self, product_id: i32, locale: &str) -> QueryResult<bool> {
+        use crate::schema::product_translation::dsl;
+        let deleted =
+            diesel::delete(dsl::product_translation.find((product_id, locale))).execute(self)?;
+        Ok(deleted > 0)
+    }
+
+    fn set_product_price(&self, product_id: i32, price: Option<i32>) -> QueryResult<()> {
+        use crate::schema::product::dsl;
+        match diesel::update(dsl::product.find(product_id))
+            .set(dsl::price.eq(price))
+            .execute(self)?
+        {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    fn attributes_of_product(&self, product_id: i32) -> QueryResult<Vec<ProductAttribute>> {
+        use crate::schema::product_attribute::dsl;
+        dsl::product_attribute
+            .filter(dsl::product_id.eq(product_id))
+            .order(dsl::name)
+            .load(self)
+    }
+
+    fn save_product_attribute(&self, attribute: &ProductAttribute) -> QueryResult<()> {
+        use crate::schema::product_attribute::dsl;
+        diesel::replace_into(dsl::product_attribute)
+            .values(attribute)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_product_attribute(&self, product_id: i32, name: &str) -> QueryResult<bool> {
+        use crate::schema::product_attribute::dsl;
+        let deleted =
+            diesel::delete(dsl::product_attribute.find((product_id, name))).execute(self)?;
+        Ok(deleted > 0)
+    }
+}
+
+impl StockRepository for SqliteConnection {
+    fn insert_inventory_item(
+        &self,
+        product_id: i32,
+        warehouse_id: Option<i32>,
+        instance_description: Option<&str>,
+    ) -> QueryResult<InventoryItem> {
+        use crate::schema::inventory_item::dsl;
+        insert_into(dsl::inventory_item)
+            .values((
+                dsl::product_id.eq(product_id),
+                dsl::warehouse_id.eq(warehouse_id),
+                dsl::instance_description.eq(instance_description),
+            ))
+            .execute(self)
+            .map_err(classify_violation)?;
+        dsl::inventory_item.find(last_insert_id(self)?).first(self)
+    }
+
+    fn inventory_item(&self, id: i32) -> QueryResult<Option<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item.find(id).first(self).optional()
+    }
+
+    fn inventory_items_after(&self, after_id: i32, limit: i64) -> QueryResult<Vec<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item
+            .filter(dsl::id.gt(after_id))
+            .order(dsl::id)
+            .limit(limit)
+            .load(self)
+    }
+
+    fn inventory_items(&self) -> QueryResult<Vec<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item.order(dsl::id).load(self)
+    }
+
+    fn inventory_items_of(&self, product_ids: &[i32]) -> QueryResult<Vec<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item
+            .filter(dsl::product_id.eq_any(product_ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn inventory_items_in(&self, warehouse_ids: &[i32]) -> QueryResult<Vec<InventoryItem>> {
+        use crate::schema::inventory_item::dsl;
+        dsl::inventory_item
+            .filter(dsl::warehouse_id.eq_any(warehouse_ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn update_inventory_item(&self, item: &InventoryItem) -> QueryResult<()> {
+        use crate::schema::inventory_item::dsl;
+        match diesel::update(dsl::inventory_item.find(item.id))
+            .set((
+                dsl::product_id.eq(item.product_id),
+                dsl::warehouse_id.eq(item.warehouse_id),
+                dsl::instance_description.eq(&item.instance_description),
+            ))
+            .execute(self)
+            .map_err(classify_violation)?
+        {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    fn stock_levels(&self) -> QueryResult<Vec<StockLevel>> {
+        sql_query(
+            "select product.id as product_id,
+                    product.description as product_description,
+                    warehouse.id as warehouse_id,
+                    warehouse.description as warehouse_description,
+                    count(*) as quantity
+               from inventory_item
+               join product on product.id = inventory_item.product_id
+               left join warehouse on warehouse.id = inventory_item.warehouse_id
+              group by product.id, product.description, warehouse.id, warehouse.description
+              order by product.id, warehouse.id is null, warehouse.id",
+        )
+        .load(self)
+    }
+}
+
+/// Reads the snapshot of every category, or only of the one given
+fn stored_statistics(
+    connection: &SqliteConnection,
+    category_id: Option<i32>,
+) -> QueryResult<Vec<CategoryStatistics>> {
+    use self::schema::product_category_statistics as snapshot;
+    use crate::schema::{product_category_statistics_stock as snapshot_stock, warehouse};
+    let mut counts = snapshot::table
+        .select((
+            snapshot::category_id,
+            snapshot::direct_products,
+            snapshot::products,
+        ))
+        .order(snapshot::category_id)
+        .into_boxed();
+    let mut stock = snapshot_stock::table
+        .left_join(warehouse::table)
+        .select((
+            snapshot_stock::category_id,
+            snapshot_stock::warehouse_id,
+            warehouse::description.nullable(),
+            snapshot_stock::quantity,
+        ))
+        .order((snapshot_stock::category_id, snapshot_stock::id))
+        .into_boxed();
+    if let Some(category_id) = category_id {
+        counts = counts.filter(snapshot::category_id.eq(category_id));
+        stock = stock.filter(snapshot_stock::category_id.eq(category_id));
+    }
+    let refreshed_at: Option<NaiveDateTime> = snapshot::table
+        .select(snapshot::refreshed_at)
+        .first(connection)
+        .optional()?;
+    Ok(statistics::assemble(
+        counts.load(connection)?,
+        stock.load(connection)?,
+        refreshed_at.map(|refreshed_at| Utc.from_utc_datetime(&refreshed_at)),
+    ))
+}
+
+impl StatisticsRepository for SqliteConnection {
+    fn category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>> {
+        let counts = sql_query(
+            "select category.id as category_id,
+                    coalesce(direct.products, 0) as direct_products,
+                    coalesce(subtree.products, 0) as products
+               from product_category category
+               left join (select product_category_id as category_id,
+                                 count(distinct product_id) as products
+                            from product_category_classification
+                           group by product_category_id) direct
+                      on direct.category_id = category.id
+               left join (select closure.ancestor_id as category_id,
+                                 count(distinct classification.product_id) as products
+                            from product_category_closure closure
+                            join product_category_classification classification
+                              on classification.product_category_id = closure.descendant_id
+                            join product_category descendant
+                              on descendant.id = closure.descendant_id
+                           where (closure.depth = 0 or not descendant.is_archived)
+                           group by closure.ancestor_id) subtree
+                      on subtree.category_id = category.id
+              order by category.id",
+        )
+        .load(self)?;
+        let stock = sql_query(
+            "select subtree.category_id,
+                    warehouse.id as warehouse_id,
+                    warehouse.description as warehouse_description,
+                    count(*) as quantity
+               from (select distinct closure.ancestor_id as category_id,
+                                     classification.product_id
+                       from product_category_closure closure
+                       join product_category_classification classification
+                         on classification.product_category_id = closure.descendant_id
+                       join product_category descendant
+                         on descendant.id = closure.descendant_id
+                      where (closure.depth = 0 or not descendant.is_archived)) subtree
+               join inventory_item on inventory_item.product_id = subtree.product_id
+               left join warehouse on warehouse.id = inventory_item.warehouse_id
+              group by subtree.category_id, warehouse.id, warehouse.description
+              order by subtree.category_id, warehouse.id is null, warehouse.id",
+        )
+        .load(self)?;
+        Ok(statistics::assemble(counts, stock, None))
+    }
+
+    fn category_statistics_of(&self, category_id: i32) -> QueryResult<Option<CategoryStatistics>> {
+        let counts = sql_query(
+            "select category.id as category_id,
+                    (select count(distinct product_id)
+                       from product_category_classification
+                      where product_category_id = category.id) as direct_products,
+                    (select count(distinct classification.product_id)
+                       from product_category_closure closure
+                       join product_category_classification classification
+                         on classification.product_category_id = closure.descendant_id
+                       join product_category descendant
+                         on descendant.id = closure.descendant_id
+                      where closure.ancestor_id = category.id
+                        and (closure.depth = 0 or not descendant.is_archived)) as products
+               from product_category category
+              where category.id = ?",
+        )
+        .bind::<Integer, _>(category_id)
+        .load(self)?;
+        let stock = sql_query(
+            "select subtree.category_id,
+                    warehouse.id as warehouse_id,
+                    warehouse.description as warehouse_description,
+                    count(*) as quantity
+               from (select distinct closure.ancestor_id as category_id,
+                                     classification.product_id
+                       from product_category_closure closure
+                       join product_category_classification classification
+                         on classification.product_category_id = closure.descendant_id
+                       join product_category descendant
+                         on descendant.id = closure.descendant_id
+                      where closure.ancestor_id = ?
+                        and (closure.depth = 0 or not descendant.is_archived)) subtree
+               join inventory_item on inventory_item.product_id = subtree.product_id
+               left join warehouse on warehouse.id = inventory_item.warehouse_id
+              group by subtree.category_id, warehouse.id, warehouse.description
+              order by warehouse.id is null, warehouse.id",
+        )
+        .bind::<Integer, _>(category_id)
+        .load(self)?;
+        Ok(statistics::assemble(counts, stock, None).pop())
+    }
+
+    fn store_category_statistics(&self, statistics: &[CategoryStatistics]) -> QueryResult<()> {
+        use self::schema::product_category_statistics as snapshot;
+        use crate::schema::product_category_statistics_stock as snapshot_stock;
+        diesel::delete(snapshot_stock::table).execute(self)?;
+        diesel::delete(snapshot::table).execute(self)?;
+        if statistics.is_empty() {
+            return Ok(());
+        }
+        insert_into(snapshot::table)
+            .values(
+                statistics
+                    .iter()
+                    .map(|statistics| {
+                        (
+                            snapshot::category_id.eq(statistics.category_id),
+                            snapshot::direct_products.eq(statistics.direct_products),
+                            snapshot::products.eq(statistics.products),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .execute(self)?;
+        let stock: Vec<_> = statistics
+            .iter()
+            .flat_map(|statistics| {
+                statistics.stock.iter().map(move |stock| {
+                    (
+                        snapshot_stock::category_id.eq(statistics.category_id),
+                        snapshot_stock::warehouse_id.eq(stock.warehouse_id),
+                        snapshot_stock::quantity.eq(stock.quantity),
+                    )
+                })
+            })
+            .collect();
+        if !stock.is_empty() {
+            insert_into(snapshot_stock::table)
+                .values(&stock)
+                .execute(self)?;
+        }
+        Ok(())
+    }
+
+    fn stored_category_statistics(&self) -> QueryResult<Vec<CategoryStatistics>> {
+        stored_statistics(self, None)
+    }
+
+    fn stored_category_statistics_of(
+        &self,
+        category_id: i32,
+    ) -> QueryResult<Option<CategoryStatistics>> {
+        Ok(stored_statistics(self, Some(category_id))?.pop())
+    }
+
+    fn catalog_counts(&self) -> QueryResult<CatalogCounts> {
+        use crate::schema::{inventory_item, product, product_category};
+        let mut warehouse_items: Vec<(Option<i32>, i64)> = inventory_item::table
+            .group_by(inventory_item::warehouse_id)
+            .select((inventory_item::warehouse_id, sql::<BigInt>("count(*)")))
+            .load(self)?;
+        // Items without a warehouse are counted last
+        warehouse_items.sort_by_key(|(warehouse_id, _)| (warehouse_id.is_none(), *warehouse_id));
+        Ok(CatalogCounts {
+            categories: product_category::table.count().get_result(self)?,
+            products: product::table.count().get_result(self)?,
+            warehouse_items,
+        })
+    }
+}
+
+impl SearchRepository for SqliteConnection {
+    fn search_products(&self, search: &ProductSearch) -> QueryResult<Vec<SearchHit>> {
+        use crate::schema::{
+            inventory_item, product, product_category,
+            product_category_classification as classification, product_category_closure as closure,
+        };
+        let mut products = product::table.order(product::id).into_boxed();
+        if let Some(category_id) = search.category_id {
+            let subtree = closure::table
+                .select(closure::descendant_id)
+                .filter(closure::ancestor_id.eq(category_id));
+            products = products.filter(
+                product::id.eq_any(
+                    classification::table
+                        .select(classification::product_id)
+                        .filter(classification::product_category_id.eq_any(subtree)),
+                ),
+            );
+        }
+        if let Some(warehouse_id) = search.warehouse_id {
+            products = products.filter(
+                product::id.eq_any(
+                    inventory_item::table
+                        .select(inventory_item::product_id)
+                        .filter(inventory_item::warehouse_id.eq(warehouse_id)),
+                ),
+            );
+        }
+        if search.in_stock {
+            products = products.filter(
+                product::id.eq_any(inventory_item::table.select(inventory_item::product_id)),
+            );
+        }
+        let products: Vec<Product> = products.load(self)?;
+        let category_names: Vec<(i32, String)> = classification::table
+            .inner_join(product_category::table)
+            .select((classification::product_id, product_category::name))
+            .load(self)?;
+        Ok(search::rank(products, &category_names, search))
+    }
+}
+
+impl FacetRepository for SqliteConnection {
+    fn facet_counts(
+        &self,
+        filters: &ListingFilters,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<FacetCounts> {
+        use crate::schema::{
+            inventory_item, product, product_attribute,
+            product_category_classification as classification, product_category_closure as closure,
+        };
+        use diesel::sql_types::Nullable;
+        let without_categories = ListingFilters {
+            categories: Vec::new(),
+            ..filters.clone()
+        };
+        let without_warehouses = ListingFilters {
+            warehouses: Vec::new(),
+            ..filters.clone()
+        };
+        let without_price = ListingFilters {
+            min_price: None,
+            max_price: None,
+            ..filters.clone()
+        };
+        let filtered_names: Vec<String> = filters.attributes.keys().cloned().collect();
+        let mut attributes: Vec<(String, String, i64)> = product_attribute::table
+            .filter(product_attribute::product_id.eq_any(matching_products(filters)))
+            .filter(product_attribute::name.ne_all(filtered_names.clone()))
+            .group_by((product_attribute::name, product_attribute::value))
+            .select((
+                product_attribute::name,
+                product_attribute::value,
+                sql::<BigInt>("count(*)"),
+            ))
+            .load(self)?;
+        for name in filtered_names {
+            let mut without_name = filters.clone();
+            without_name.attributes.remove(&name);
+            attributes.extend(
+                product_attribute::table
+                    .filter(product_attribute::name.eq(name))
+                    .filter(product_attribute::product_id.eq_any(matching_products(&without_name)))
+                    .group_by((product_attribute::name, product_attribute::value))
+                    .select((
+                        product_attribute::name,
+                        product_attribute::value,
+                        sql::<BigInt>("count(*)"),
+                    ))
+                    .load::<(String, String, i64)>(self)?,
+            );
+        }
+        let categories: Vec<(i32, i64)> = classification::table
+            .inner_join(
+                closure::table.on(closure::descendant_id.eq(classification::product_category_id)),
+            )
+            .filter(classification::product_id.eq_any(matching_products(&without_categories)))
+            .group_by(closure::ancestor_id)
+            .select((
+                closure::ancestor_id,
+                sql::<BigInt>("count(distinct product_category_classification.product_id)"),
+            ))
+            .load(self)?;
+        let warehouses: Vec<(Option<i32>, i64)> = inventory_item::table
+            .filter(inventory_item::product_id.eq_any(matching_products(&without_warehouses)))
+            .filter(inventory_item::warehouse_id.is_not_null())
+            .group_by(inventory_item::warehouse_id)
+            .select((
+                inventory_item::warehouse_id,
+                sql::<BigInt>("count(distinct inventory_item.product_id)"),
+            ))
+            .load(self)?;
+        let (min_price, max_price, priced) = product::table
+            .filter(product::id.eq_any(matching_products(&without_price)))
+            .select(sql::<(Nullable<Integer>, Nullable<Integer>, BigInt)>(
+                "min(product.price), max(product.price), count(product.price)",
+            ))
+            .get_result(self)?;
+        Ok(FacetCounts {
+            total: product::table
+                .filter(product::id.eq_any(matching_products(filters)))
+                .count()
+                .get_result(self)?,
+            products: product::table
+                .filter(product::id.eq_any(matching_products(filters)))
+                .order(product::id)
+                .limit(limit)
+                .offset(offset)
+                .load(self)?,
+            categories: categories.into_iter().collect(),
+            warehouses: warehouses
+                .into_iter()
+                .filter_map(|(warehouse_id, count)| Some((warehouse_id?, count)))
+                .collect(),
+            price: PriceFacet {
+                min: min_price,
+                max: max_price,
+                count: priced,
+            },
+            attributes: attributes.into_iter().fold(
+                BTreeMap::new(),
+                |mut attributes, (name, value, count)| {
+                    attributes.entry(name).or_default().insert(value, count);
+                    attributes
+                },
+            ),
+        })
+    }
+}
+
+/// Ids of the products matching all filters of a listing
+fn matching_products(
+    filters: &ListingFilters,
+) -> crate::schema::product::BoxedQuery<'static, Sqlite, Integer> {
+    use crate::schema::{
+        inventory_item, product, product_attribute,
+        product_category_classification as classification, product_category_closure as closure,
+    };
+    let mut products = product::table.select(product::id).into_boxed();
+    if !filters.categories.is_empty() {
+        products = products.filter(
+            product::id.eq_any(
+                classification::table
+                    .inner_join(
+                        closure::table
+                            .on(closure::descendant_id.eq(classification::product_category_id)),
+                    )
+                    .filter(closure::ancestor_id.eq_any(filters.categories.clone()))
+                    .select(classification::product_id),
+            ),
+        );
+    }
+    if !filters.warehouses.is_empty() {
+        products = products.filter(
+            product::id.eq_any(
+                inventory_item::table
+                    .filter(inventory_item::warehouse_id.eq_any(filters.warehouses.clone()))
+                    .select(inventory_item::product_id),
+            ),
+        );
+    }
+    if let Some(min_price) = filters.min_price {
+        products = products.filter(product::price.ge(min_price));
+    }
+    if let Some(max_price) = filters.max_price {
+        products = products.filter(product::price.le(max_price));
+    }
+    for (name, values) in &filters.attributes {
+        products = products.filter(
+            product::id.eq_any(
+                product_attribute::table
+                    .filter(product_attribute::name.eq(name.clone()))
+                    .filter(product_attribute::value.eq_any(values.clone()))
+                    .select(product_attribute::product_id),
+            ),
+        );
+    }
+    products
+}
+
+impl WarehouseRepository for SqliteConnection {
+    fn warehouses(&self) -> QueryResult<Vec<Warehouse>> {
+        use crate::schema::warehouse::dsl;
+        dsl::warehouse.order(dsl::id).load(self)
+    }
+
+    fn warehouse(&self, id: i32) -> QueryResult<Option<Warehouse>> {
+        use crate::schema::warehouse::dsl;
+        dsl::warehouse.find(id).first(self).optional()
+    }
+
+    fn warehouses_with_ids(&self, ids: &[i32]) -> QueryResult<Vec<Warehouse>> {
+        use crate::schema::warehouse::dsl;
+        dsl::warehouse
+            .filter(dsl::id.eq_any(ids))
+            .order(dsl::id)
+            .load(self)
+    }
+
+    fn insert_warehouse(&self, description: &str) -> QueryResult<Warehouse> {
+        use crate::schema::warehouse::dsl;
+        insert_into(dsl::warehouse)
+            .values(dsl::description.eq(description))
+            .execute(self)?;
+        dsl::warehouse.find(last_insert_id(self)?).first(self)
+    }
+}
+
+impl ApiKeyRepository for SqliteConnection {
+    fn api_key(&self, key_hash: &str) -> QueryResult<Option<ApiKey>> {
+        use self::schema::api_key::dsl;
+        let api_key: Option<(i32, String, String, String)> = dsl::api_key
+            .filter(dsl::key_hash.eq(key_hash))
+            .first(self)
+            .optional()?;
+        Ok(api_key.map(|(id, key_hash, role, description)| ApiKey {
+            id,
+            key_hash,
+            role,
+            description,
+            tenant_id: None,
+        }))
+    }
+
+    fn insert_api_key(&self, key_hash: &str, role: Role, description: &str) -> QueryResult<ApiKey> {
+        use self::schema::api_key::dsl;
+        insert_into(dsl::api_key)
+            .values((
+                dsl::key_hash.eq(key_hash),
+                dsl::role.eq(role.as_str()),
+                dsl::description.eq(description),
+            ))
+            .execute(self)?;
+        Ok(ApiKey {
+            id: last_insert_id(self)?,
+            key_hash: key_hash.to_string(),
+            role: role.as_str().to_string(),
+            description: description.to_string(),
+            tenant_id: None,
+        })
+    }
+}
+
+impl IdempotencyRepository for SqliteConnection {
+    fn claim_idempotency_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        retention: Duration,
+        claim_timeout: Duration,
+    ) -> QueryResult<Claim> {
+        use self::schema::idempotency_key::dsl;
+        self.transaction(|| {
+            diesel::delete(
+                dsl::idempotency_key.filter(
+                    dsl::created_at
+                        .lt(before_now(retention).naive_utc())
+                        .or(dsl::response_status
+                            .is_null()
+                            .and(dsl::created_at.lt(before_now(claim_timeout).naive_utc()))),
+                ),
+            )
+            .execute(self)?;
+            // Timed here, since the default time of SQLite has no fraction of a second to tell a
+            // claim apart from the one which took it over
+            let claimed_at = Utc::now().naive_utc();
+            let claimed_keys = diesel::insert_or_ignore_into(dsl::idempotency_key)
+                .values((
+                    dsl::key.eq(key),
+                    dsl::fingerprint.eq(fingerprint),
+                    dsl::created_at.eq(claimed_at),
+                ))
+                .execute(self)?;
+            if claimed_keys > 0 {
+                return Ok(Claim::Claimed(Utc.from_utc_datetime(&claimed_at)));
+            }
+            let (stored_fingerprint, status, content_type, body): (
+                String,
+                Option<i16>,
+                Option<String>,
+                Option<Vec<u8>>,
+            ) = dsl::idempotency_key
+                .select((
+                    dsl::fingerprint,
+                    dsl::response_status,
+                    dsl::response_content_type,
+                    dsl::response_body,
+                ))
+                .filter(dsl::key.eq(key))
+                .first(self)?;
+            if stored_fingerprint != fingerprint {
+                return Ok(Claim::Mismatch);
+            }
+            Ok(match status {
+                Some(status) => Claim::Completed(StoredResponse {
+                    status: status as u16,
+                    content_type,
+                    body: body.unwrap_or_default(),
+                }),
+                None => Claim::InProgress,
+            })
+        })
+    }
+
+    fn complete_idempotency_key(
+        &self,
+        key: &str,
+        claimed_at: DateTime<Utc>,
+        response: &StoredResponse,
+    ) -> QueryResult<()> {
+        use self::schema::idempotency_key::dsl;
+        diesel::update(
+            dsl::idempotency_key
+                .filter(dsl::key.eq(key))
+                .filter(dsl::created_at.eq(claimed_at.naive_utc()))
+                .filter(dsl::response_status.is_null()),
+        )
+        .set((
+            dsl::response_status.eq(response.status as i16),
+            dsl::response_content_type.eq(&response.content_type),
+            dsl::response_body.eq(&response.body),
+        ))
+        .execute(self)?;
+        Ok(())
+    }
+
+    fn release_idempotency_key(&self, key: &str, claimed_at: DateTime<Utc>) -> QueryResult<()> {
+        use self::schema::idempotency_key::dsl;
+        diesel::delete(
+            dsl::idempotency_key
+                .filter(dsl::key.eq(key))
+                .filter(dsl::created_at.eq(claimed_at.naive_utc()))
+                .filter(dsl::response_status.is_null()),
+        )
+        .execute(self)?;
+        Ok(())
+    }
+}
+
+type OutboxEventRow = (i64, String, String, NaiveDateTime, Option<String>);
+
+fn outbox_event_of(
+    (id, event_type, payload, created_at, request_id): OutboxEventRow,
+) -> QueryResult<OutboxEvent> {
+    Ok(OutboxEvent {
+        id,
+        event_type,
+        payload: serde_json::from_str(&payload)
+            .map_err(|error| Error::DeserializationError(Box::new(error)))?,
+        created_at: Utc.from_utc_datetime(&created_at),
+        request_id,
+        // Writing transactions take turns, so events become visible in the order of their ids
+        transaction_id: id,
+    })
+}
+
+impl OutboxRepository for SqliteConnection {
+    fn outbox_event(&self, id: i64) -> QueryResult<Option<OutboxEvent>> {
+        use self::schema::outbox_event::dsl;
+        let row: Option<OutboxEventRow> = dsl::outbox_event.find(id).first(self).optional()?;
+        row.map(outbox_event_of).transpose()
+    }
+
+    fn outbox_tenant_id(&self) -> QueryResult<Option<i32>> {
+        Ok(None)
+    }
+
+    fn stream_horizon(&self) -> QueryResult<i64> {
+        use self::schema::outbox_event::dsl;
+        let last_id: Option<i64> = dsl::outbox_event.select(max(dsl::id)).first(self)?;
+        Ok(last_id.unwrap_or(0) + 1)
+    }
+
+    fn events_after(
+        &self,
+        position: StreamPosition,
+        horizon: i64,
+        limit: i64,
+    ) -> QueryResult<Vec<OutboxEvent>> {
+        use self::schema::outbox_event::dsl::*;
+        // The transaction of an event is its id
+        let rows: Vec<OutboxEventRow> = outbox_event
+            .filter(id.lt(horizon))
+            .filter(
+                id.gt(position.transaction_id)
+                    .or(id.eq(position.transaction_id).and(id.gt(position.event_id))),
+            )
+            .order(id)
+            .limit(limit)
+            .load(self)?;
+        rows.into_iter().map(outbox_event_of).collect()
+    }
+
+    fn first_transaction_after(&self, event_id: i64) -> QueryResult<Option<i64>> {
+        use self::schema::outbox_event::dsl::*;
+        outbox_event
+            .filter(id.gt(event_id))
+            .select(min(id))
+            .first(self)
+    }
+}
+
+type SubscriptionRow = (i32, String, String, String, bool);
+
+fn subscription_of(
+    (id, url, event_types, secret, active): SubscriptionRow,
+) -> QueryResult<WebhookSubscription> {
+    Ok(WebhookSubscription {
+        id,
+        url,
+        event_types: serde_json::from_str(&event_types)
+            .map_err(|error| Error::DeserializationError(Box::new(error)))?,
+        secret,
+        active,
+    })
+}
+
+type DeliveryRow = (i64, i64, i32, String, i32, NaiveDateTime, Option<String>);
+
+fn delivery_of(
+    (id, outbox_event_id, webhook_subscription_id, status, attempts, next_attempt_at, last_error): DeliveryRow,
+) -> WebhookDelivery {
+    WebhookDelivery {
+        id,
+        outbox_event_id,
+        webhook_subscription_id,
+        status,
+        attempts,
+        next_attempt_at: Utc.from_utc_datetime(&next_attempt_at),
+        last_error,
+    }
+}
+
+impl WebhookRepository for SqliteConnection {
+    fn webhook_subscriptions(&self) -> QueryResult<Vec<WebhookSubscription>> {
+        use self::schema::webhook_subscription::dsl;
+        let rows: Vec<SubscriptionRow> = dsl::webhook_subscription.order(dsl::id).load(self)?;
+        rows.into_iter().map(subscription_of).collect()
+    }
+
+    fn webhook_subscription(&self, id: i32) -> QueryResult<Option<WebhookSubscription>> {
+        use self::schema::webhook_subscription::dsl;
+        let row: Option<SubscriptionRow> =
+            dsl::webhook_subscription.find(id).first(self).optional()?;
+        row.map(subscription_of).transpose()
+    }
+
+    fn insert_webhook_subscription(
+        &self,
+        url: &str,
+        event_types: &[String],
+        secret: &str,
+    ) -> QueryResult<WebhookSubscription> {
+        use self::schema::webhook_subscription::dsl;
+        let event_types = serde_json::to_string(event_types)
+            .map_err(|error| Error::SerializationError(Box::new(error)))?;
+        self.transaction(|| {
+            insert_into(dsl::webhook_subscription)
+                .values((
+                    dsl::url.eq(url),
+                    dsl::event_types.eq(event_types),
+                    dsl::secret.eq(secret),
+                ))
+                .execute(self)?;
+            let row: SubscriptionRow = dsl::webhook_subscription
+                .find(last_insert_id(self)?)
+                .first(self)?;
+            subscription_of(row)
+        })
+    }
+
+    fn delete_webhook_subscription(&self, id: i32) -> QueryResult<bool> {
+        use self::schema::webhook_subscription::dsl;
+        let deleted = diesel::delete(dsl::webhook_subscription.find(id)).execute(self)?;
+        Ok(deleted > 0)
+    }
+
+    fn webhook_deliveries(&self) -> QueryResult<Vec<WebhookDelivery>> {
+        use self::schema::webhook_delivery::dsl;
+        let rows: Vec<DeliveryRow> = dsl::webhook_delivery.order(dsl::id).load(self)?;
+        Ok(rows.into_iter().map(delivery_of).collect())
+    }
+
+    fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        leased_until: DateTime<Utc>,
+    ) -> QueryResult<Vec<WebhookDelivery>> {
+        use self::schema::webhook_delivery::dsl;
+        // Taking the write lock up front keeps other workers from claiming the same deliveries
+        self.immediate_transaction(|| {
+            let rows: Vec<DeliveryRow> = dsl::webhook_delivery
+                .filter(dsl::status.eq(DELIVERY_PENDING))
+                .filter(dsl::next_attempt_at.le(Utc::now().naive_utc()))
+                .order(dsl::id)
+                .limit(limit)
+                .load(self)?;
+            diesel::update(
+                dsl::webhook_delivery.filter(dsl::id.eq_any(rows.iter().map(|row| row.0))),
+            )
+            .set(dsl::next_attempt_at.eq(leased_until.naive_utc()))
+            .execute(self)?;
+            Ok(rows.into_iter().map(delivery_of).collect())
+        })
+    }
+
+    fn record_delivery_attempt(
+        &self,
+        delivery: &WebhookDelivery,
+        attempt: &DeliveryAttempt,
+    ) -> QueryResult<()> {
+        use self::schema::webhook_delivery::dsl;
+        let claimed_delivery = dsl::webhook_delivery
+            .find(delivery.id)
+            .filter(dsl::attempts.eq(delivery.attempts));
+        let recorded = (
+            dsl::status.eq(attempt.status),
+            dsl::attempts.eq(delivery.attempts + 1),
+            dsl::last_error.eq(&attempt.last_error),
+        );
+        match attempt.next_attempt_at {
+            Some(next_attempt_at) => diesel::update(claimed_delivery)
+                .set((
+                    recorded,
+                    dsl::next_attempt_at.eq(next_attempt_at.naive_utc()),
+                ))
+                .execute(self)?,
+            None => diesel::update(claimed_delivery)
+                .set(recorded)
+                .execute(self)?,
+        };
+        Ok(())
+    }
+}
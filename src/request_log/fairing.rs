@@ -0,0 +1,115 @@
+use super::{RequestId, REQUEST_ID_HEADER};
+use crate::auth::authenticated_principal;
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+    Data, Request, Response,
+};
+use serde::Serialize;
+use std::time::Instant;
+
+/// Log target of the entries written for every request
+pub const REQUEST_LOG_TARGET: &str = "warehouse_rs::requests";
+
+#[derive(Serialize)]
+/// Entry of the request log
+pub struct RequestLogEntry<'a> {
+    pub request_id: &'a str,
+    pub method: &'a str,
+    pub path: &'a str,
+    /// Route which served the request, or `None` if no route matched
+    pub route: Option<String>,
+    pub status: u16,
+    pub latency_ms: f64,
+    /// Subject of the authenticated principal
+    pub principal: Option<String>,
+}
+
+/// Assigns every request an id, returns it in the `X-Request-Id` header and logs the request
+pub fn fairing() -> impl Fairing {
+    RequestLogFairing
+}
+
+struct RequestLogFairing;
+
+/// Time at which Rocket started to handle a request
+struct RequestStart(Instant);
+
+impl Fairing for RequestLogFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        request.local_cache(|| RequestStart(Instant::now()));
+        RequestId::of(request);
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let request_id = RequestId::of(request);
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+        response.set_header(Header::new(REQUEST_ID_HEADER, request_id.0.clone()));
+        let entry = RequestLogEntry {
+            request_id: &request_id.0,
+            method: request.method().as_str(),
+            path: request.uri().path(),
+            route: request.route().map(|route| route.uri.path().to_string()),
+            status: response.status().code,
+            latency_ms: start.0.elapsed().as_secs_f64() * 1000.0,
+            principal: authenticated_principal(request).map(|principal| principal.subject),
+        };
+        if let Ok(entry) = serde_json::to_string(&entry) {
+            log::info!(target: REQUEST_LOG_TARGET, "{}", entry);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use crate::{auth::Role, request_log::REQUEST_ID_HEADER, test_utils::bearer_token};
+    use diesel::prelude::*;
+    use rocket::{
+        http::{ContentType, Header},
+        local::Client,
+    };
+    use testcontainers::clients::Cli;
+
+    #[test]
+    fn request_id_is_returned_and_recorded_with_events(
+    ) -> Result<(), crate::migrations::MigrationError> {
+        use crate::schema::outbox_event::dsl::*;
+
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (rocket, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+        let client = Client::new(rocket).expect("valid rocket instance");
+
+        let tagged_response = client
+            .post("/productcategory")
+            .header(ContentType::JSON)
+            .header(bearer_token(Role::Admin))
+            .header(Header::new(REQUEST_ID_HEADER, "lb-1234"))
+            .body("{\"name\":\"Shirts\"}")
+            .dispatch();
+        let untagged_response = client.get("/productcategory").dispatch();
+
+        let recorded_request_ids: Vec<Option<String>> =
+            outbox_event.select(request_id).load(&connection)?;
+        assert_eq!(
+            Some("lb-1234"),
+            tagged_response.headers().get_one(REQUEST_ID_HEADER)
+        );
+        assert_eq!(
+            Some(32),
+            untagged_response
+                .headers()
+                .get_one(REQUEST_ID_HEADER)
+                .map(str::len)
+        );
+        assert_eq!(vec![Some("lb-1234".to_string())], recorded_request_ids);
+        Ok(())
+    }
+}
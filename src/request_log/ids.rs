@@ -0,0 +1,92 @@
+#[cfg(feature = "postgres")]
+use diesel::{sql_query, sql_types::Text, PgConnection, QueryResult, RunQueryDsl};
+use rand::RngCore;
+use rocket::{
+    request::{self, FromRequest},
+    Outcome, Request,
+};
+use std::fmt;
+
+/// Header carrying the id of a request, both in requests and responses
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+/// Longest id which is taken over from a request header
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// Id which correlates everything happening while serving a request
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Takes over the id of a header if it is short and only contains letters, digits, `-`,
+    /// `_` and `.`, so that it can be logged and echoed safely
+    pub fn from_header(header: &str) -> Option<Self> {
+        let valid = !header.is_empty()
+            && header.len() <= MAX_REQUEST_ID_LENGTH
+            && header
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+        if valid {
+            Some(RequestId(header.to_string()))
+        } else {
+            None
+        }
+    }
+
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        RequestId(hex::encode(bytes))
+    }
+
+    /// Id of the request, which is assigned on first use and then kept for the whole request
+    pub fn of<'r>(request: &'r Request) -> &'r Self {
+        request.local_cache(|| {
+            request
+                .headers()
+                .get_one(REQUEST_ID_HEADER)
+                .and_then(RequestId::from_header)
+                .unwrap_or_else(RequestId::generate)
+        })
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequestId {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId::of(request).clone())
+    }
+}
+
+/// Sets the request on the database session, so that recorded events refer to it
+#[cfg(feature = "postgres")]
+pub fn tag_session(connection: &PgConnection, request_id: &RequestId) -> QueryResult<()> {
+    sql_query("select set_config('app.request_id', $1, false)")
+        .bind::<Text, _>(&request_id.0)
+        .execute(connection)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestId;
+
+    #[test]
+    fn only_safe_header_ids_are_taken_over() {
+        assert_eq!(
+            Some(RequestId("lb-7f3a_1.2".to_string())),
+            RequestId::from_header("lb-7f3a_1.2")
+        );
+        assert_eq!(None, RequestId::from_header(""));
+        assert_eq!(None, RequestId::from_header("id with spaces"));
+        assert_eq!(None, RequestId::from_header("\"quoted\""));
+        assert_eq!(None, RequestId::from_header(&"a".repeat(129)));
+        assert_eq!(32, RequestId::generate().0.len());
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::{SecondsFormat, Utc};
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use serde_json::{Map, Value};
+use std::io::Write;
+
+/// Logger writing every record as a line of JSON to standard output
+///
+/// Messages which are JSON objects themselves, such as the entries of the request log, are
+/// merged into the line instead of being nested as a string.
+pub struct JsonLogger {
+    level: LevelFilter,
+}
+
+impl JsonLogger {
+    /// Installs the logger for the whole process
+    ///
+    /// Has to be called before Rocket launches, which then keeps the logger instead of
+    /// installing its own.
+    pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
+        log::set_boxed_logger(Box::new(JsonLogger { level }))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+/// Formats a log record as a JSON object
+pub fn format_record(record: &Record) -> String {
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".to_string(),
+        Value::from(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+    );
+    line.insert("level".to_string(), Value::from(record.level().as_str()));
+    line.insert("target".to_string(), Value::from(record.target()));
+    let message = record.args().to_string();
+    match serde_json::from_str::<Value>(&message) {
+        Ok(Value::Object(fields)) => line.extend(fields),
+        _ => {
+            line.insert("message".to_string(), Value::from(message));
+        }
+    }
+    Value::Object(line).to_string()
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let _ = writeln!(std::io::stdout().lock(), "{}", format_record(record));
+        }
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_record;
+    use log::{Level, Record};
+    use serde_json::Value;
+
+    fn format(message: std::fmt::Arguments) -> Value {
+        let record = Record::builder()
+            .args(message)
+            .level(Level::Warn)
+            .target("warehouse_rs::requests")
+            .build();
+        serde_json::from_str(&format_record(&record)).unwrap()
+    }
+
+    #[test]
+    fn json_messages_are_merged_into_the_line() {
+        let plain_line = format(format_args!("pool is exhausted"));
+        let structured_line = format(format_args!("{}", "{\"request_id\":\"a1\",\"status\":500}"));
+
+        assert_eq!("WARN", plain_line["level"]);
+        assert_eq!("warehouse_rs::requests", plain_line["target"]);
+        assert_eq!("pool is exhausted", plain_line["message"]);
+        assert_eq!("a1", structured_line["request_id"]);
+        assert_eq!(500, structured_line["status"]);
+        assert!(structured_line.get("message").is_none());
+    }
+}
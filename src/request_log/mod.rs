@@ -0,0 +1,12 @@
+//! Correlation of requests with their logs, database errors and recorded events
+//!
+//! Every request is identified by the `X-Request-Id` header a client or proxy sent, or by a
+//! generated id. The id is returned with the response, written to the request log and set on
+//! the database session of the request, where the outbox picks it up for the recorded events.
+pub mod fairing;
+pub mod ids;
+pub mod logger;
+
+pub use fairing::fairing;
+pub use ids::{RequestId, REQUEST_ID_HEADER};
+pub use logger::JsonLogger;
@@ -1,3 +1,26 @@
+#[cfg(feature = "postgres")]
+table! {
+    api_key (id) {
+        id -> Int4,
+        key_hash -> Varchar,
+        role -> Varchar,
+        description -> Text,
+        tenant_id -> Int4,
+    }
+}
+
+#[cfg(feature = "postgres")]
+table! {
+    idempotency_key (key) {
+        key -> Varchar,
+        fingerprint -> Varchar,
+        response_status -> Nullable<Int2>,
+        response_content_type -> Nullable<Varchar>,
+        response_body -> Nullable<Bytea>,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     inventory_item (id) {
         id -> Int4,
@@ -7,10 +30,31 @@ table! {
     }
 }
 
+#[cfg(feature = "postgres")]
+table! {
+    outbox_event (id) {
+        id -> Int8,
+        event_type -> Varchar,
+        payload -> Jsonb,
+        created_at -> Timestamptz,
+        request_id -> Nullable<Varchar>,
+        transaction_id -> Int8,
+    }
+}
+
 table! {
     product (id) {
         id -> Int4,
         description -> Varchar,
+        price -> Nullable<Int4>,
+    }
+}
+
+table! {
+    product_attribute (product_id, name) {
+        product_id -> Int4,
+        name -> Varchar,
+        value -> Varchar,
     }
 }
 
@@ -18,6 +62,11 @@ table! {
     product_category (id) {
         id -> Int4,
         name -> Varchar,
+        slug -> Varchar,
+        description -> Nullable<Text>,
+        image_ref -> Nullable<Varchar>,
+        is_visible -> Bool,
+        is_archived -> Bool,
     }
 }
 
@@ -30,11 +79,62 @@ table! {
     }
 }
 
+table! {
+    product_category_closure (ancestor_id, descendant_id) {
+        ancestor_id -> Int4,
+        descendant_id -> Int4,
+        depth -> Int4,
+    }
+}
+
 table! {
     product_category_rollup (id) {
         id -> Int4,
         upper_category_id -> Int4,
         lower_category_id -> Int4,
+        position -> Int4,
+    }
+}
+
+#[cfg(feature = "postgres")]
+table! {
+    product_category_statistics (category_id) {
+        category_id -> Int4,
+        direct_products -> Int8,
+        products -> Int8,
+        refreshed_at -> Timestamptz,
+    }
+}
+
+table! {
+    product_category_statistics_stock (id) {
+        id -> Int4,
+        category_id -> Int4,
+        warehouse_id -> Nullable<Int4>,
+        quantity -> Int8,
+    }
+}
+
+table! {
+    product_category_translation (category_id, locale) {
+        category_id -> Int4,
+        locale -> Varchar,
+        name -> Varchar,
+    }
+}
+
+table! {
+    product_translation (product_id, locale) {
+        product_id -> Int4,
+        locale -> Varchar,
+        description -> Varchar,
+    }
+}
+
+table! {
+    tenant (id) {
+        id -> Int4,
+        name -> Varchar,
     }
 }
 
@@ -45,16 +145,82 @@ table! {
     }
 }
 
+#[cfg(feature = "postgres")]
+table! {
+    webhook_delivery (id) {
+        id -> Int8,
+        outbox_event_id -> Int8,
+        webhook_subscription_id -> Int4,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamptz,
+        last_error -> Nullable<Text>,
+    }
+}
+
+#[cfg(feature = "postgres")]
+table! {
+    webhook_subscription (id) {
+        id -> Int4,
+        url -> Text,
+        event_types -> Array<Text>,
+        secret -> Text,
+        active -> Bool,
+    }
+}
+
+#[cfg(feature = "postgres")]
+joinable!(api_key -> tenant (tenant_id));
 joinable!(inventory_item -> product (product_id));
 joinable!(inventory_item -> warehouse (warehouse_id));
+joinable!(product_attribute -> product (product_id));
 joinable!(product_category_classification -> product (product_id));
 joinable!(product_category_classification -> product_category (product_category_id));
+#[cfg(feature = "postgres")]
+joinable!(product_category_statistics_stock -> product_category_statistics (category_id));
+joinable!(product_category_statistics_stock -> warehouse (warehouse_id));
+joinable!(product_category_translation -> product_category (category_id));
+joinable!(product_translation -> product (product_id));
+#[cfg(feature = "postgres")]
+joinable!(webhook_delivery -> outbox_event (outbox_event_id));
+#[cfg(feature = "postgres")]
+joinable!(webhook_delivery -> webhook_subscription (webhook_subscription_id));
+
+#[cfg(feature = "postgres")]
+allow_tables_to_appear_in_same_query!(
+    api_key,
+    idempotency_key,
+    inventory_item,
+    outbox_event,
+    product,
+    product_attribute,
+    product_category,
+    product_category_classification,
+    product_category_closure,
+    product_category_rollup,
+    product_category_statistics,
+    product_category_statistics_stock,
+    product_category_translation,
+    product_translation,
+    tenant,
+    warehouse,
+    webhook_delivery,
+    webhook_subscription,
+);
 
+// Tables with types which only Postgres knows are left out of builds without it
+#[cfg(not(feature = "postgres"))]
 allow_tables_to_appear_in_same_query!(
     inventory_item,
     product,
+    product_attribute,
     product_category,
     product_category_classification,
+    product_category_closure,
     product_category_rollup,
+    product_category_statistics_stock,
+    product_category_translation,
+    product_translation,
+    tenant,
     warehouse,
 );
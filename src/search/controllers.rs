@@ -0,0 +1,114 @@
+use super::{words, ProductSearch, SearchHit};
+use crate::{
+    auth::{Authorized, Viewer},
+    repository::{BackendConn, CategoryRepository, SearchRepository, WarehouseRepository},
+    utilities::DbError,
+};
+use rocket::response::status::BadRequest;
+use rocket_contrib::json::Json;
+
+/// Number of products returned when the search does not limit them
+pub const DEFAULT_LIMIT: i64 = 20;
+/// Most products returned by a search
+pub const MAX_LIMIT: i64 = 100;
+
+/// Searches the products by words of their descriptions, identifiers and category names
+///
+/// `category` limits the search to the products below a category, `warehouse` to those stocked
+/// in a warehouse and `in_stock` to those with any items.
+#[get("/search?<q>&<category>&<warehouse>&<in_stock>&<limit>")]
+pub fn get_search(
+    db_conn: BackendConn,
+    _viewer: Authorized<Viewer>,
+    q: String,
+    category: Option<i32>,
+    warehouse: Option<i32>,
+    in_stock: Option<bool>,
+    limit: Option<i64>,
+) -> Result<Result<Json<Vec<SearchHit>>, BadRequest<String>>, DbError> {
+    let reject = |message: String| Ok(Err(BadRequest(Some(message))));
+    if words(&q).is_empty() {
+        return reject("Search text has no words".to_string());
+    }
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return reject(format!("Limit has to be between 1 and {}", MAX_LIMIT));
+    }
+    if let Some(category_id) = category {
+        if db_conn.category(category_id)?.is_none() {
+            return reject(format!("Category {} does not exist", category_id));
+        }
+    }
+    if let Some(warehouse_id) = warehouse {
+        if db_conn.warehouse(warehouse_id)?.is_none() {
+            return reject(format!("Warehouse {} does not exist", warehouse_id));
+        }
+    }
+    let search = ProductSearch {
+        text: q,
+        category_id: category,
+        warehouse_id: warehouse,
+        in_stock: in_stock.unwrap_or(false),
+        limit,
+    };
+    Ok(Ok(Json(db_conn.search_products(&search)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchHit;
+    use crate::{
+        auth::Role,
+        test_utils::{backend_test, bearer_token},
+    };
+    use rocket::http::Status;
+
+    backend_test!(products_are_searched_with_filters, |client, repository| {
+        let kitchen = repository.insert_category("kitchen")?;
+        let kettle = repository.insert_product("enamel kettle")?;
+        repository.insert_product("kettle descaler")?;
+        repository.classify(kettle.id, kitchen.id, true)?;
+        let search = |query: &str| {
+            let mut response = client
+                .get(format!("/product/search?{}", query))
+                .header(bearer_token(Role::Viewer))
+                .dispatch();
+            (
+                response.status(),
+                response.body_string().unwrap_or_default(),
+            )
+        };
+
+        let (status, body) = search("q=kettle");
+        let hits: Vec<SearchHit> = serde_json::from_str(&body)?;
+        let (filtered_status, filtered) = search(&format!("q=kettle&category={}", kitchen.id));
+        let filtered: Vec<SearchHit> = serde_json::from_str(&filtered)?;
+        let (empty_status, empty) = search("q=%20-%20");
+        let (unknown_status, unknown) = search("q=kettle&warehouse=404");
+        let (limit_status, _) = search("q=kettle&limit=1000");
+
+        assert_eq!(Status::Ok, status);
+        assert_eq!(2, hits.len());
+        assert_eq!(Status::Ok, filtered_status);
+        assert_eq!(
+            vec![(kettle.id, "enamel <mark>kettle</mark>".to_string())],
+            filtered
+                .into_iter()
+                .map(|hit| (hit.product_id, hit.highlight))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (Status::BadRequest, "Search text has no words".to_string()),
+            (empty_status, empty)
+        );
+        assert_eq!(
+            (
+                Status::BadRequest,
+                "Warehouse 404 does not exist".to_string()
+            ),
+            (unknown_status, unknown)
+        );
+        assert_eq!(Status::BadRequest, limit_status);
+        Ok(())
+    });
+}
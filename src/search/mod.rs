@@ -0,0 +1,275 @@
+//! Search of the products by the words of their descriptions, identifiers and categories
+//!
+//! On Postgres the words are matched through full-text search, with prefixes of words counting
+//! as matches, and misspelled words through the trigram similarity of `pg_trgm`. The other
+//! backends rank the products in the application the same way, with a simpler similarity.
+//! Matches are highlighted by enclosing them in `<mark>` tags, in the description escaped as HTML.
+pub mod controllers;
+
+pub use controllers::*;
+
+use crate::entities::Product;
+use diesel::sql_types::{Float4, Integer, Text};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Similarity above which a word counts as a misspelling of a searched one, like the default
+/// `pg_trgm.word_similarity_threshold`
+pub const WORD_SIMILARITY_THRESHOLD: f32 = 0.6;
+
+/// Weight of the matches in category names, relative to those in descriptions
+const CATEGORY_WEIGHT: f32 = 0.5;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// Words to search for and the products to search among
+pub struct ProductSearch {
+    pub text: String,
+    /// Only products classified into the category or any category below it
+    pub category_id: Option<i32>,
+    /// Only products with items in the warehouse
+    pub warehouse_id: Option<i32>,
+    /// Only products with items in stock, in a warehouse or not
+    pub in_stock: bool,
+    pub limit: i64,
+}
+
+impl ProductSearch {
+    /// Full-text query of Postgres matching any of the words or words starting with them
+    pub fn ts_query(&self) -> String {
+        words(&self.text)
+            .iter()
+            .map(|word| format!("{}:*", word))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+#[derive(QueryableByName, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+/// Product found by a search
+pub struct SearchHit {
+    #[sql_type = "Integer"]
+    pub product_id: i32,
+    #[sql_type = "Text"]
+    pub description: String,
+    /// Relevance of the product, the higher the better
+    #[sql_type = "Float4"]
+    pub rank: f32,
+    /// Description escaped as HTML, with the matched words enclosed in `<mark>` tags
+    #[sql_type = "Text"]
+    pub highlight: String,
+}
+
+/// Lowercased words of a text, split at everything but letters and digits
+pub fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Trigrams of a lowercased word, padded like by `pg_trgm`
+fn trigrams(word: &str) -> BTreeSet<String> {
+    let padded: Vec<char> = format!("  {} ", word).chars().collect();
+    padded
+        .windows(3)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+/// Share of the trigrams of two words which they have in common
+fn similarity(a: &str, b: &str) -> f32 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f32 / union as f32
+}
+
+/// How many of the searched words some word of the text starts with, and how similar the
+/// searched words are to their most similar words of the text on average
+fn word_scores(searched: &[String], text: &str) -> (usize, f32) {
+    let text_words = words(text);
+    let prefix_matches = searched
+        .iter()
+        .filter(|word| {
+            text_words
+                .iter()
+                .any(|text_word| text_word.starts_with(*word))
+        })
+        .count();
+    let similarities: f32 = searched
+        .iter()
+        .map(|word| {
+            text_words
+                .iter()
+                .map(|text_word| similarity(word, text_word))
+                .fold(0.0, f32::max)
+        })
+        .sum();
+    (prefix_matches, similarities / searched.len().max(1) as f32)
+}
+
+/// Escapes the characters of the text which are markup in HTML
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes the text as HTML and encloses its words which start with a searched word in `<mark>`
+/// tags
+pub fn highlight(text: &str, searched: &[String]) -> String {
+    let mut highlighted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(char::is_alphanumeric) {
+        let (before, from_word) = rest.split_at(start);
+        let end = from_word
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(from_word.len());
+        let (word, after) = from_word.split_at(end);
+        highlighted.push_str(&escape_html(before));
+        let lowercased = word.to_lowercase();
+        if searched
+            .iter()
+            .any(|searched| lowercased.starts_with(searched))
+        {
+            highlighted.push_str("<mark>");
+            highlighted.push_str(word);
+            highlighted.push_str("</mark>");
+        } else {
+            highlighted.push_str(word);
+        }
+        rest = after;
+    }
+    highlighted.push_str(&escape_html(rest));
+    highlighted
+}
+
+/// Ranks the products matching the words of the search, best first
+///
+/// The products have to be filtered by the category and stock of the search already. Their
+/// categories are given by the names of those they are classified into.
+pub fn rank(
+    products: Vec<Product>,
+    category_names: &[(i32, String)],
+    search: &ProductSearch,
+) -> Vec<SearchHit> {
+    let searched = words(&search.text);
+    let mut names_of_product: BTreeMap<i32, Vec<&str>> = BTreeMap::new();
+    for (product_id, name) in category_names {
+        names_of_product.entry(*product_id).or_default().push(name);
+    }
+    let mut hits: Vec<SearchHit> = products
+        .into_iter()
+        .filter_map(|product| {
+            let names = names_of_product
+                .get(&product.id)
+                .map(|names| names.join(" "))
+                .unwrap_or_default();
+            let (description_matches, description_similarity) =
+                word_scores(&searched, &product.description);
+            let (name_matches, name_similarity) = word_scores(&searched, &names);
+            let is_identified = product.id.to_string() == search.text.trim();
+            let is_match = description_matches > 0
+                || name_matches > 0
+                || description_similarity >= WORD_SIMILARITY_THRESHOLD
+                || name_similarity >= WORD_SIMILARITY_THRESHOLD
+                || is_identified;
+            if !is_match {
+                return None;
+            }
+            let rank = 0.1 * description_matches as f32
+                + CATEGORY_WEIGHT * 0.1 * name_matches as f32
+                + description_similarity.max(CATEGORY_WEIGHT * name_similarity)
+                + if is_identified { 1.0 } else { 0.0 };
+            Some(SearchHit {
+                product_id: product.id,
+                highlight: highlight(&product.description, &searched),
+                description: product.description,
+                rank,
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| {
+        b.rank
+            .partial_cmp(&a.rank)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.product_id.cmp(&b.product_id))
+    });
+    hits.truncate(search.limit.max(0) as usize);
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{highlight, rank, words, ProductSearch};
+    use crate::entities::Product;
+
+    fn search(text: &str) -> ProductSearch {
+        ProductSearch {
+            text: text.to_string(),
+            category_id: None,
+            warehouse_id: None,
+            in_stock: false,
+            limit: 10,
+        }
+    }
+
+    fn products(descriptions: &[&str]) -> Vec<Product> {
+        descriptions
+            .iter()
+            .enumerate()
+            .map(|(index, description)| Product {
+                id: index as i32 + 1,
+                description: description.to_string(),
+                price: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matched_words_are_highlighted() {
+        let searched = words("Silk, SCAR");
+
+        assert_eq!(vec!["silk", "scar"], searched);
+        assert_eq!(
+            "<mark>Silk</mark> <mark>scarf</mark> (red)",
+            highlight("Silk scarf (red)", &searched)
+        );
+        assert_eq!("A novel", highlight("A novel", &searched));
+        assert_eq!(
+            "&lt;b&gt;<mark>silk</mark>&lt;/b&gt; &amp; &quot;wool&quot;",
+            highlight("<b>silk</b> & \"wool\"", &searched)
+        );
+        assert_eq!("silk:* | scar:*", search("Silk, SCAR").ts_query());
+    }
+
+    #[test]
+    fn products_are_ranked_by_their_words_and_categories() {
+        let catalog = products(&["Silk scarf", "Scarf hanger", "Striped bikini", "A novel"]);
+        let names = vec![(3, "Swimwear".to_string()), (4, "Books".to_string())];
+        let found = |text: &str| -> Vec<i32> {
+            rank(catalog.clone(), &names, &search(text))
+                .into_iter()
+                .map(|hit| hit.product_id)
+                .collect()
+        };
+
+        assert_eq!(vec![1, 2], found("silk scarf"));
+        assert_eq!(vec![3], found("bikinni"));
+        assert_eq!(vec![3], found("swim"));
+        assert_eq!(vec![4], found("4"));
+        assert!(found("stapler").is_empty());
+    }
+}
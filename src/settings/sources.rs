@@ -0,0 +1,105 @@
+use super::{Settings, SettingsError};
+use rocket::config::Value;
+use std::{collections::HashMap, env, fs, io};
+
+/// Environment variable naming the configuration file
+pub const CONFIG_FILE_VARIABLE: &str = "WAREHOUSE_CONFIG";
+/// Configuration file read if it exists and no other file is named
+pub const DEFAULT_CONFIG_FILE: &str = "warehouse.toml";
+const VARIABLE_PREFIX: &str = "WAREHOUSE_";
+
+/// Loads and validates the settings from all sources
+///
+/// Variables in the `.env` file do not override variables which are already set, so that the
+/// environment takes precedence over the `.env` file, which in turn takes precedence over the
+/// configuration file.
+pub fn load() -> Result<Settings, SettingsError> {
+    dotenv::dotenv().ok();
+    let (path, required) = match env::var(CONFIG_FILE_VARIABLE) {
+        Ok(path) => (path, true),
+        Err(_) => (DEFAULT_CONFIG_FILE.to_string(), false),
+    };
+    let mut values = match fs::read_to_string(&path) {
+        Ok(text) => file_values(&text)
+            .map_err(|problem| SettingsError::single(format!("{}: {}", path, problem)))?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound && !required => HashMap::new(),
+        Err(error) => {
+            return Err(SettingsError::single(format!(
+                "{} can not be read: {}",
+                path, error
+            )))
+        }
+    };
+    values.extend(environment_values(env::vars()));
+    Settings::from_values(&values)
+}
+
+/// Reads the settings of a TOML configuration file
+pub fn file_values(text: &str) -> Result<HashMap<String, String>, String> {
+    let document: Value = text.parse().map_err(|error| format!("{}", error))?;
+    let table = match document {
+        Value::Table(table) => table,
+        _ => return Err("the file has to contain a table of settings".to_string()),
+    };
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => value,
+                Value::Integer(value) => value.to_string(),
+                Value::Boolean(value) => value.to_string(),
+                _ => return Err(format!("{} has to be a string, number or boolean", key)),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Picks the settings from environment variables, which are prefixed with `WAREHOUSE_`
+pub fn environment_values<I>(variables: I) -> HashMap<String, String>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    variables
+        .into_iter()
+        .filter(|(name, _)| name != CONFIG_FILE_VARIABLE)
+        .filter_map(|(name, value)| {
+            name.strip_prefix(VARIABLE_PREFIX)
+                .map(|key| (key.to_ascii_lowercase(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{environment_values, file_values};
+
+    #[test]
+    fn environment_overrides_file() {
+        let mut values = file_values(
+            "database_url = \"postgres://file@localhost/warehouse\"\n\
+             pool_size = 4\n\
+             enable_docs = false\n",
+        )
+        .unwrap();
+        values.extend(environment_values(vec![
+            (
+                "WAREHOUSE_DATABASE_URL".to_string(),
+                "postgres://env@localhost/warehouse".to_string(),
+            ),
+            ("WAREHOUSE_CONFIG".to_string(), "other.toml".to_string()),
+            ("DATABASE_URL".to_string(), "postgres://other".to_string()),
+        ]));
+
+        assert_eq!(3, values.len());
+        assert_eq!("postgres://env@localhost/warehouse", values["database_url"]);
+        assert_eq!("4", values["pool_size"]);
+        assert_eq!("false", values["enable_docs"]);
+    }
+
+    #[test]
+    fn nested_file_values_are_rejected() {
+        assert!(file_values("[databases]\nurl = \"postgres://localhost\"\n").is_err());
+        assert!(file_values("pool_size = ").is_err());
+    }
+}
@@ -0,0 +1,10 @@
+use crate::schema::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Identifiable, Queryable, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[table_name = "tenant"]
+/// Company or business unit whose data is isolated from all other tenants
+pub struct Tenant {
+    pub id: i32,
+    pub name: String,
+}
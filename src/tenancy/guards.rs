@@ -0,0 +1,313 @@
+use crate::auth::{AuthError, Principal};
+#[cfg(feature = "postgres")]
+use crate::{
+    request_log::{ids::tag_session, RequestId},
+    DbConn,
+};
+#[cfg(feature = "postgres")]
+use diesel::{sql_types::Integer, PgConnection, QueryResult, RunQueryDsl};
+#[cfg(feature = "postgres")]
+use rocket::{
+    http::Status,
+    request::{self, FromRequest},
+    Outcome, Request,
+};
+#[cfg(feature = "postgres")]
+use std::ops::Deref;
+
+/// Header in which global administrators name the tenant they act for
+pub const TENANT_HEADER: &str = "X-Tenant-Id";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TenantError {
+    Unauthenticated(AuthError),
+    MissingTenant,
+    InvalidTenant,
+    Forbidden,
+    Unavailable,
+}
+
+#[cfg(feature = "postgres")]
+impl TenantError {
+    fn status(&self) -> Status {
+        match self {
+            Self::Unauthenticated(auth_error) => auth_error.status(),
+            Self::MissingTenant | Self::InvalidTenant => Status::BadRequest,
+            Self::Forbidden => Status::Forbidden,
+            Self::Unavailable => Status::ServiceUnavailable,
+        }
+    }
+}
+
+/// Resolves the tenant for which a request is served
+///
+/// Principals bound to a tenant always act for their own tenant and may only repeat it in the
+/// header. Global administrators have to name a tenant in the header, and any other principal
+/// without a tenant is rejected, whatever the header says.
+pub fn resolve_tenant(principal: &Principal, header: Option<&str>) -> Result<i32, TenantError> {
+    let requested_tenant_id = match header {
+        Some(value) => Some(
+            value
+                .trim()
+                .parse::<i32>()
+                .map_err(|_| TenantError::InvalidTenant)?,
+        ),
+        None => None,
+    };
+    match (principal.tenant_id, requested_tenant_id) {
+        (Some(own), Some(requested)) if own != requested => Err(TenantError::Forbidden),
+        (Some(own), _) => Ok(own),
+        (None, Some(requested)) if principal.acts_for_any_tenant() => Ok(requested),
+        (None, _) => Err(TenantError::MissingTenant),
+    }
+}
+
+/// Scopes the session of the connection to the tenant
+///
+/// Returns `false` without changing the scope if the tenant does not exist.
+#[cfg(feature = "postgres")]
+pub fn scope_session(connection: &PgConnection, tenant_id: i32) -> QueryResult<bool> {
+    let scoped_rows = diesel::sql_query(
+        "select set_config('app.tenant_id', id::text, false) from tenant where id = $1",
+    )
+    .bind::<Integer, _>(tenant_id)
+    .execute(connection)?;
+    Ok(scoped_rows > 0)
+}
+
+/// Removes the tenant scope and the request from the session of the connection
+#[cfg(feature = "postgres")]
+pub fn clear_session_scope(connection: &PgConnection) -> QueryResult<()> {
+    diesel::sql_query(
+        "select set_config('app.tenant_id', '', false), set_config('app.request_id', '', false)",
+    )
+    .execute(connection)?;
+    Ok(())
+}
+
+/// Database connection whose session is scoped to the tenant of the request
+///
+/// While the guard is alive all queries on the connection only see and create rows of the
+/// tenant, and the session is tagged with the [`RequestId`]. The scope is cleared again when
+/// the guard is dropped, so that the pooled connection does not leak the tenant into the next
+/// request.
+#[cfg(feature = "postgres")]
+pub struct TenantConn {
+    db_conn: DbConn,
+    pub tenant_id: i32,
+}
+
+#[cfg(feature = "postgres")]
+impl TenantConn {
+    fn scope(db_conn: DbConn, tenant_id: i32, request_id: &RequestId) -> Result<Self, TenantError> {
+        match scope_session(&db_conn, tenant_id) {
+            Ok(true) => {
+                let tenant_conn = TenantConn { db_conn, tenant_id };
+                tag_session(&tenant_conn, request_id).map_err(|_| TenantError::Unavailable)?;
+                Ok(tenant_conn)
+            }
+            Ok(false) => Err(TenantError::Forbidden),
+            Err(_) => Err(TenantError::Unavailable),
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl Deref for TenantConn {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        &self.db_conn
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl Drop for TenantConn {
+    fn drop(&mut self) {
+        let _ = clear_session_scope(&self.db_conn);
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl<'a, 'r> FromRequest<'a, 'r> for TenantConn {
+    type Error = TenantError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let principal = match request.guard::<Principal>() {
+            Outcome::Success(principal) => principal,
+            Outcome::Failure((status, auth_error)) => {
+                return Outcome::Failure((status, TenantError::Unauthenticated(auth_error)))
+            }
+            Outcome::Forward(()) => return Outcome::Forward(()),
+        };
+        let tenant_connection =
+            resolve_tenant(&principal, request.headers().get_one(TENANT_HEADER)).and_then(
+                |tenant_id| {
+                    let db_conn = request
+                        .guard::<DbConn>()
+                        .succeeded()
+                        .ok_or(TenantError::Unavailable)?;
+                    TenantConn::scope(db_conn, tenant_id, RequestId::of(request))
+                },
+            );
+        match tenant_connection {
+            Ok(tenant_connection) => Outcome::Success(tenant_connection),
+            Err(error) => Outcome::Failure((error.status(), error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_tenant, TenantError};
+    use crate::auth::{Principal, Role};
+    #[cfg(feature = "postgres")]
+    use crate::{
+        product_category::entities::ProductCategory,
+        tenancy::TENANT_HEADER,
+        test_utils::{bearer_token, global_bearer_token, tenant_bearer_token},
+    };
+    #[cfg(feature = "postgres")]
+    use diesel::prelude::*;
+    #[cfg(feature = "postgres")]
+    use rocket::{
+        http::{ContentType, Header, Status},
+        local::Client,
+    };
+    #[cfg(feature = "postgres")]
+    use testcontainers::clients::Cli;
+
+    fn principal(role: Role, tenant_id: Option<i32>, global: bool) -> Principal {
+        Principal {
+            subject: "tester".to_string(),
+            role,
+            tenant_id,
+            global,
+        }
+    }
+
+    #[test]
+    fn principal_bound_to_tenant_acts_for_its_tenant() {
+        let bound = principal(Role::Viewer, Some(3), false);
+        assert_eq!(Ok(3), resolve_tenant(&bound, None));
+        assert_eq!(Ok(3), resolve_tenant(&bound, Some("3")));
+    }
+
+    #[test]
+    fn principal_bound_to_tenant_can_not_switch_tenant() {
+        assert_eq!(
+            Err(TenantError::Forbidden),
+            resolve_tenant(&principal(Role::Admin, Some(3), true), Some("4"))
+        );
+    }
+
+    #[test]
+    fn global_admin_names_tenant_in_header() {
+        let global_admin = principal(Role::Admin, None, true);
+        assert_eq!(Ok(4), resolve_tenant(&global_admin, Some(" 4 ")));
+        assert_eq!(
+            Err(TenantError::MissingTenant),
+            resolve_tenant(&global_admin, None)
+        );
+        assert_eq!(
+            Err(TenantError::InvalidTenant),
+            resolve_tenant(&global_admin, Some("acme"))
+        );
+    }
+
+    #[test]
+    fn principal_without_tenant_or_global_privilege_is_rejected() {
+        for unbound in &[
+            principal(Role::Admin, None, false),
+            principal(Role::Viewer, None, true),
+        ] {
+            assert_eq!(
+                Err(TenantError::MissingTenant),
+                resolve_tenant(unbound, Some("1"))
+            );
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn categories_are_isolated_per_tenant() -> Result<(), crate::migrations::MigrationError> {
+        use crate::schema::tenant::dsl::*;
+
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (rocket, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+        let client = Client::new(rocket).expect("valid rocket instance");
+        let other_tenant: i32 = diesel::insert_into(tenant)
+            .values(name.eq("other"))
+            .returning(id)
+            .get_result(&connection)?;
+
+        let default_tenant_response = client
+            .post("/productcategory")
+            .header(ContentType::JSON)
+            .header(bearer_token(Role::Admin))
+            .body("{\"name\":\"shirts\"}")
+            .dispatch();
+        let mut other_tenant_response = client
+            .post("/productcategory")
+            .header(ContentType::JSON)
+            .header(tenant_bearer_token(Role::Admin, Some(other_tenant)))
+            .body("{\"name\":\"shirts\"}")
+            .dispatch();
+        let other_tenant_category: ProductCategory =
+            serde_json::from_str(&other_tenant_response.body_string().unwrap()).unwrap();
+
+        let mut list_response = client
+            .get("/productcategory")
+            .header(tenant_bearer_token(Role::Viewer, Some(other_tenant)))
+            .dispatch();
+        let visible_categories: Vec<ProductCategory> =
+            serde_json::from_str(&list_response.body_string().unwrap()).unwrap();
+
+        assert_eq!(Status::Created, default_tenant_response.status());
+        assert_eq!(Status::Created, other_tenant_response.status());
+        assert_eq!(vec![other_tenant_category], visible_categories);
+        Ok(())
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn global_admin_selects_tenant_with_header() -> Result<(), crate::migrations::MigrationError> {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (rocket, _) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+        let client = Client::new(rocket).expect("valid rocket instance");
+
+        let with_header_response = client
+            .get("/productcategory")
+            .header(global_bearer_token(Role::Admin))
+            .header(Header::new(TENANT_HEADER, "1"))
+            .dispatch();
+        let without_header_response = client
+            .get("/productcategory")
+            .header(global_bearer_token(Role::Admin))
+            .dispatch();
+        let unknown_tenant_response = client
+            .get("/productcategory")
+            .header(global_bearer_token(Role::Admin))
+            .header(Header::new(TENANT_HEADER, "999"))
+            .dispatch();
+        let without_tenant_claim_response = client
+            .get("/productcategory")
+            .header(tenant_bearer_token(Role::Admin, None))
+            .header(Header::new(TENANT_HEADER, "1"))
+            .dispatch();
+        let global_viewer_response = client
+            .get("/productcategory")
+            .header(global_bearer_token(Role::Viewer))
+            .header(Header::new(TENANT_HEADER, "1"))
+            .dispatch();
+
+        assert_eq!(Status::Ok, with_header_response.status());
+        assert_eq!(Status::BadRequest, without_header_response.status());
+        assert_eq!(Status::Forbidden, unknown_tenant_response.status());
+        assert_eq!(Status::BadRequest, without_tenant_claim_response.status());
+        assert_eq!(Status::BadRequest, global_viewer_response.status());
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+//! Isolation of the data of several tenants sharing one deployment
+//!
+//! Every table holding warehouse data carries a `tenant_id` column which is managed entirely by
+//! the database. The column defaults to the tenant of the current session and row level security
+//! policies hide the rows of all other tenants. Because of this the column does not appear in
+//! `schema.rs`, and queries only need to run on a [`TenantConn`], which scopes the session to the
+//! tenant of the request.
+pub mod entities;
+pub mod guards;
+
+pub use guards::*;
@@ -1,17 +1,32 @@
+use crate::request_log::RequestId;
 use rocket::{
+    data::{self, FromDataSimple},
     http::{ContentType, Status},
     response,
     response::Responder,
-    Request,
+    Data, Outcome, Request,
 };
 use rocket_contrib::json::Json;
 use serde::Serialize;
+use serde_json::json;
+use std::io::Read;
+
+/// Largest uploaded file which is accepted unless the `upload` limit is configured
+pub const DEFAULT_UPLOAD_LIMIT: u64 = 10 * 1024 * 1024;
 
 pub enum GetResponder<T> {
     Found(Json<T>),
     NotFound(()),
 }
 
+impl<T> GetResponder<T> {
+    /// Statuses the responder answers with, as documented in the API specification
+    pub const STATUSES: &'static [(Status, &'static str)] = &[
+        (Status::Ok, "The resource was found"),
+        (Status::NotFound, "There is no resource with this id"),
+    ];
+}
+
 impl<'r, T: Serialize> Responder<'r> for GetResponder<T> {
     fn respond_to(self, request: &Request) -> response::Result<'r> {
         match self {
@@ -30,6 +45,17 @@ pub enum PostResponder<T> {
     Existed(Json<T>),
 }
 
+impl<T> PostResponder<T> {
+    /// Statuses the responder answers with, as documented in the API specification
+    pub const STATUSES: &'static [(Status, &'static str)] = &[
+        (Status::Created, "The resource was created"),
+        (
+            Status::Ok,
+            "The resource existed already and is returned unchanged",
+        ),
+    ];
+}
+
 impl<'r, T: Serialize> Responder<'r> for PostResponder<T> {
     fn respond_to(self, request: &Request) -> response::Result<'r> {
         match self {
@@ -43,6 +69,40 @@ impl<'r, T: Serialize> Responder<'r> for PostResponder<T> {
     }
 }
 
+#[derive(Debug)]
+/// Database error which failed a request
+///
+/// The error is logged together with the id of the request, and the client receives
+/// `500 Internal Server Error` with the id, so that a failed response can be tied to the failed
+/// query.
+pub struct DbError(pub diesel::result::Error);
+
+impl From<diesel::result::Error> for DbError {
+    fn from(error: diesel::result::Error) -> Self {
+        DbError(error)
+    }
+}
+
+impl<'r> Responder<'r> for DbError {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let request_id = RequestId::of(request);
+        log::error!(
+            "{}",
+            json!({ "request_id": request_id.0, "database_error": self.0.to_string() })
+        );
+        let body = Json(json!({
+            "error": "The request failed because of a database error",
+            "request_id": request_id.0,
+        }));
+        respond_with_status_header(
+            request,
+            body,
+            ContentType::JSON,
+            Status::InternalServerError,
+        )
+    }
+}
+
 fn respond_with_status_header<'r, T: Responder<'r>>(
     request: &Request,
     responder: T,
@@ -55,13 +115,48 @@ fn respond_with_status_header<'r, T: Responder<'r>>(
     Ok(intermediate_response)
 }
 
+#[derive(Debug)]
+/// Text of an uploaded file, such as a CSV file or a taxonomy
+///
+/// The body is rejected with `413 Payload Too Large` if it exceeds the `upload` limit instead of
+/// being cut off, and with `400 Bad Request` if it can not be read as UTF-8.
+pub struct UploadText(pub String);
+
+impl FromDataSimple for UploadText {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Self, Self::Error> {
+        let limit = request
+            .limits()
+            .get("upload")
+            .unwrap_or(DEFAULT_UPLOAD_LIMIT);
+        let mut text = String::new();
+        if let Err(error) = data.open().take(limit + 1).read_to_string(&mut text) {
+            return Outcome::Failure((
+                Status::BadRequest,
+                format!("Upload can not be read: {}", error),
+            ));
+        }
+        if text.len() as u64 > limit {
+            return Outcome::Failure((
+                Status::PayloadTooLarge,
+                format!("Upload exceeds the limit of {} bytes", limit),
+            ));
+        }
+        Outcome::Success(UploadText(text))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utilities::{GetResponder, PostResponder};
-    use rocket::http::{ContentType, Status};
-    use rocket::local::{Client, LocalRequest};
+    use crate::{
+        request_log::REQUEST_ID_HEADER,
+        utilities::{DbError, GetResponder, PostResponder},
+    };
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::Client;
     use rocket::response::Responder;
-    use rocket::{Request, Rocket};
+    use rocket::Rocket;
     use rocket_contrib::json::Json;
 
     #[test]
@@ -74,7 +169,7 @@ mod tests {
 
         match get_responder_under_test.respond_to(&request) {
             Ok(response) => assert_eq!(Status::Ok, response.status()),
-            Err(status) => panic!(format!("Failed with status: {}", status)),
+            Err(status) => panic!("Failed with status: {}", status),
         }
     }
 
@@ -88,10 +183,10 @@ mod tests {
 
         match get_responder_under_test.respond_to(request) {
             Ok(response) => assert_eq!(Some(ContentType::JSON), response.content_type()),
-            Err(status) => panic!(format!(
+            Err(status) => panic!(
                 "Failed because content type is not JSON with status {}",
                 status
-            )),
+            ),
         }
     }
 
@@ -105,10 +200,10 @@ mod tests {
 
         match get_responder_under_test.respond_to(request) {
             Ok(response) => assert_eq!(Status::NotFound, response.status()),
-            Err(status) => panic!(format!(
+            Err(status) => panic!(
                 "Failed because status code of responder is not 404 with status : {}",
                 status
-            )),
+            ),
         }
     }
 
@@ -122,10 +217,10 @@ mod tests {
 
         match get_responder_under_test.respond_to(request) {
             Ok(response) => assert_eq!(Some(ContentType::JSON), response.content_type()),
-            Err(status) => panic!(format!(
+            Err(status) => panic!(
                 "Failed with status {} because responder does not set content type to JSON",
                 status
-            )),
+            ),
         }
     }
 
@@ -139,9 +234,7 @@ mod tests {
 
         match post_responder_under_test.respond_to(request) {
             Ok(response) => assert_eq!(Status::Ok, response.status()),
-            Err(status) => panic!(format!(
-                "Test failed because PostResponder::Created does not have Status::Ok"
-            )),
+            Err(_) => panic!("Test failed because PostResponder::Created does not have Status::Ok"),
         }
     }
 
@@ -155,9 +248,9 @@ mod tests {
 
         match post_responder_under_test.respond_to(request) {
             Ok(response) => assert_eq!(Some(ContentType::JSON), response.content_type()),
-            Err(status) => panic!(format!(
-                "Test failed because PostResponder::Created does not have ContentType::Json"
-            )),
+            Err(_) => {
+                panic!("Test failed because PostResponder::Created does not have ContentType::Json")
+            }
         }
     }
 
@@ -171,9 +264,7 @@ mod tests {
 
         match post_responder_under_test.respond_to(request) {
             Ok(response) => assert_eq!(Status::Created, response.status()),
-            Err(status) => panic!(format!(
-                "Test failed because PostResponder::Created does not have Status::Ok"
-            )),
+            Err(_) => panic!("Test failed because PostResponder::Created does not have Status::Ok"),
         }
     }
 
@@ -187,9 +278,30 @@ mod tests {
 
         match post_responder_under_test.respond_to(request) {
             Ok(response) => assert_eq!(Some(ContentType::JSON), response.content_type()),
-            Err(status) => panic!(format!(
-                "Test failed because PostResponder::Created does not have ContentType::Json"
-            )),
+            Err(_) => {
+                panic!("Test failed because PostResponder::Created does not have ContentType::Json")
+            }
+        }
+    }
+
+    #[test]
+    fn db_error_returns_500_with_request_id() {
+        let rocket = Rocket::ignite();
+        let client = Client::new(rocket).unwrap();
+        let local_request = client
+            .get("/")
+            .header(Header::new(REQUEST_ID_HEADER, "failing-request"));
+        let request = local_request.inner();
+        let db_error_under_test = DbError(diesel::NotFound);
+
+        match db_error_under_test.respond_to(request) {
+            Ok(mut response) => {
+                let body: serde_json::Value =
+                    serde_json::from_str(&response.body_string().unwrap()).unwrap();
+                assert_eq!(Status::InternalServerError, response.status());
+                assert_eq!("failing-request", body["request_id"]);
+            }
+            Err(status) => panic!("Failed with status {}", status),
         }
     }
 }
@@ -0,0 +1,146 @@
+use super::{endpoints::validate_endpoint, entities::WebhookSubscription};
+use crate::{
+    auth::{Admin, Authorized},
+    idempotency::{Idempotency, Idempotent},
+    outbox::EventType,
+    repository::{BackendConn, WebhookRepository},
+    utilities::{DbError, PostResponder},
+};
+use rocket::{http::Status, response::status::BadRequest};
+use rocket_contrib::json::Json;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WebhookSubscriptionRequestBody {
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub secret: String,
+}
+
+impl WebhookSubscriptionRequestBody {
+    fn validate(&self) -> Result<(), String> {
+        validate_endpoint(&self.url)?;
+        if self.secret.is_empty() {
+            return Err("Webhook secret must not be empty".to_string());
+        }
+        if self.event_types.is_empty() {
+            return Err("Webhook must subscribe to at least one event type".to_string());
+        }
+        for event_type in &self.event_types {
+            event_type.parse::<EventType>()?;
+        }
+        Ok(())
+    }
+}
+
+#[get("/")]
+pub fn get_all(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+) -> Result<Json<Vec<WebhookSubscription>>, DbError> {
+    Ok(Json(db_conn.webhook_subscriptions()?))
+}
+
+type SubscriptionResponse =
+    Result<Result<PostResponder<WebhookSubscription>, BadRequest<String>>, DbError>;
+
+#[post("/", format = "json", data = "<new_subscription>")]
+pub fn post(
+    db_conn: BackendConn,
+    _admin: Authorized<Admin>,
+    idempotency: Idempotency,
+    new_subscription: Json<WebhookSubscriptionRequestBody>,
+) -> Idempotent<SubscriptionResponse> {
+    idempotency.run(&db_conn, &*new_subscription, || {
+        if let Err(message) = new_subscription.validate() {
+            return Ok(Err(BadRequest(Some(message))));
+        }
+        let subscription = db_conn.insert_webhook_subscription(
+            &new_subscription.url,
+            &new_subscription.event_types,
+            &new_subscription.secret,
+        )?;
+        Ok(Ok(PostResponder::Created(Json(subscription))))
+    })
+}
+
+#[delete("/<id>")]
+pub fn delete(db_conn: BackendConn, _admin: Authorized<Admin>, id: i32) -> Result<Status, DbError> {
+    db_conn.delete_webhook_subscription(id)?;
+    Ok(Status::Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WebhookSubscriptionRequestBody;
+    use crate::{
+        auth::Role,
+        product_category::entities::ProductCategory,
+        test_utils::{backend_test, bearer_token},
+        webhooks::entities::WebhookSubscription,
+    };
+    use rocket::http::{ContentType, Status};
+
+    fn subscription_body(event_types: &[&str]) -> String {
+        serde_json::to_string(&WebhookSubscriptionRequestBody {
+            url: "https://erp.example.com/hooks".to_string(),
+            event_types: event_types.iter().map(|t| t.to_string()).collect(),
+            secret: "shared-secret".to_string(),
+        })
+        .unwrap()
+    }
+
+    backend_test!(
+        subscribed_event_is_queued_for_delivery_with_the_change,
+        |client, repository| {
+            let mut subscription_response = client
+                .post("/webhooks")
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Admin))
+                .body(subscription_body(&["category.created"]))
+                .dispatch();
+            let subscription: WebhookSubscription =
+                serde_json::from_str(&subscription_response.body_string().unwrap())?;
+            let mut category_response = client
+                .post("/productcategory")
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Admin))
+                .body("{\"name\":\"shirts\"}")
+                .dispatch();
+            let category: ProductCategory =
+                serde_json::from_str(&category_response.body_string().unwrap())?;
+            client
+                .put(format!("/productcategory/{}", category.id))
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Admin))
+                .body("{\"name\":\"skirts\"}")
+                .dispatch();
+
+            let queued_subscriptions: Vec<i32> = repository
+                .webhook_deliveries()?
+                .into_iter()
+                .map(|delivery| delivery.webhook_subscription_id)
+                .collect();
+            assert_eq!(Status::Created, subscription_response.status());
+            assert_eq!(vec![subscription.id], queued_subscriptions);
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        subscription_to_unknown_event_is_rejected,
+        |client, repository| {
+            let response = client
+                .post("/webhooks")
+                .header(ContentType::JSON)
+                .header(bearer_token(Role::Admin))
+                .body(subscription_body(&["category.renamed"]))
+                .dispatch();
+
+            assert_eq!(Status::BadRequest, response.status());
+            assert!(repository.webhook_subscriptions()?.is_empty());
+            Ok(())
+        }
+    );
+}
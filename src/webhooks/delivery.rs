@@ -0,0 +1,581 @@
+use super::{
+    endpoints::resolve_public,
+    entities::{
+        DeliveryAttempt, WebhookDelivery, WebhookSubscription, DELIVERY_DEAD, DELIVERY_DELIVERED,
+        DELIVERY_PENDING,
+    },
+};
+#[cfg(feature = "postgres")]
+use crate::tenancy::{clear_session_scope, scope_session};
+use crate::{
+    outbox::entities::OutboxEvent,
+    repository::{memory::MemoryDatabase, OutboxRepository, WebhookRepository},
+    settings::Backend,
+};
+use chrono::{DateTime, Duration, Utc};
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use diesel::Connection;
+use diesel::{result::Error::NotFound, QueryResult};
+#[cfg(feature = "postgres")]
+use diesel::{PgConnection, QueryDsl, RunQueryDsl};
+use hmac::{Hmac, Mac, NewMac};
+use rocket::fairing::AdHoc;
+use serde::Serialize;
+use sha2::Sha256;
+use std::thread::{self, JoinHandle};
+
+/// Longest time a subscriber may take to answer a delivery
+const REQUEST_TIMEOUT_SECONDS: i64 = 10;
+
+/// Header carrying the HMAC-SHA256 signature of the request body
+pub const SIGNATURE_HEADER: &str = "X-Warehouse-Signature";
+pub const EVENT_HEADER: &str = "X-Warehouse-Event";
+pub const DELIVERY_HEADER: &str = "X-Warehouse-Delivery";
+
+/// Signs the payload with the secret of a subscription, in the form `sha256=<hex digest>`
+pub fn sign(secret: &str, payload: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(payload);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Clone, Copy, Debug)]
+/// How often and how late failed deliveries are retried
+///
+/// The delay doubles with every failed attempt, starting at `base_delay` and never exceeding
+/// `max_delay`. Deliveries which failed `max_attempts` times are dead-lettered.
+pub struct RetryPolicy {
+    pub max_attempts: i32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 8,
+            base_delay: Duration::seconds(30),
+            max_delay: Duration::hours(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retrying a delivery which failed `attempts` times, or `None` if it is dead
+    pub fn next_delay(&self, attempts: i32) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let exponent = (attempts - 1).clamp(0, 20) as u32;
+        Some(std::cmp::min(
+            self.base_delay * 2i32.pow(exponent),
+            self.max_delay,
+        ))
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    id: i64,
+    #[serde(rename = "type")]
+    event_type: &'a str,
+    created_at: DateTime<Utc>,
+    data: &'a serde_json::Value,
+}
+
+/// Posts pending deliveries to the endpoints of their subscriptions
+pub struct DeliveryWorker {
+    agent: ureq::Agent,
+    retry_policy: RetryPolicy,
+    batch_size: i64,
+}
+
+impl DeliveryWorker {
+    /// Creates a worker which only posts to public addresses
+    pub fn new(retry_policy: RetryPolicy) -> Self {
+        Self::with_resolver(retry_policy, resolve_public)
+    }
+
+    /// Redirects are not followed, since they could lead to addresses the resolver refuses
+    fn with_resolver(retry_policy: RetryPolicy, resolver: impl ureq::Resolver + 'static) -> Self {
+        DeliveryWorker {
+            agent: ureq::AgentBuilder::new()
+                .timeout(std::time::Duration::from_secs(
+                    REQUEST_TIMEOUT_SECONDS as u64,
+                ))
+                .redirects(0)
+                .resolver(resolver)
+                .build(),
+            retry_policy,
+            batch_size: 100,
+        }
+    }
+
+    /// Attempts every delivery which is due, of all tenants, and returns how many were attempted
+    #[cfg(feature = "postgres")]
+    pub fn deliver_due_of_tenants(&self, connection: &PgConnection) -> QueryResult<usize> {
+        use crate::schema::tenant::dsl::*;
+        let tenant_ids: Vec<i32> = tenant.select(id).load(connection)?;
+        let mut attempted = 0;
+        for tenant_id in tenant_ids {
+            if scope_session(connection, tenant_id)? {
+                attempted += self.deliver_due(connection)?;
+            }
+        }
+        clear_session_scope(connection)?;
+        Ok(attempted)
+    }
+
+    /// Attempts every delivery which is due, of the tenant the connection is scoped to or of an
+    /// SQLite database, and returns how many were attempted
+    pub fn deliver_due<R: OutboxRepository + WebhookRepository>(
+        &self,
+        repository: &R,
+    ) -> QueryResult<usize> {
+        let claimed_deliveries = self.claim_due(repository)?;
+        for delivery in &claimed_deliveries {
+            let event = repository
+                .outbox_event(delivery.outbox_event_id)?
+                .ok_or(NotFound)?;
+            let subscription = repository
+                .webhook_subscription(delivery.webhook_subscription_id)?
+                .ok_or(NotFound)?;
+            let outcome = self.post(delivery, &event, &subscription);
+            repository.record_delivery_attempt(delivery, &self.attempt(delivery, outcome))?;
+        }
+        Ok(claimed_deliveries.len())
+    }
+
+    /// Time for which claimed deliveries are not due for other workers
+    ///
+    /// It covers posting the whole batch, so only deliveries of a worker which died before
+    /// recording their attempts become due again.
+    fn lease(&self) -> Duration {
+        Duration::seconds(REQUEST_TIMEOUT_SECONDS * self.batch_size + 60)
+    }
+
+    /// Claims a batch of due deliveries by moving their next attempt past the lease
+    ///
+    /// The claim is committed right away, so that no transaction stays open while the
+    /// deliveries are posted.
+    fn claim_due<R: WebhookRepository>(&self, repository: &R) -> QueryResult<Vec<WebhookDelivery>> {
+        repository.claim_due_deliveries(self.batch_size, Utc::now() + self.lease())
+    }
+
+    fn post(
+        &self,
+        delivery: &WebhookDelivery,
+        event: &OutboxEvent,
+        subscription: &WebhookSubscription,
+    ) -> Result<(), String> {
+        let body = serde_json::to_vec(&WebhookPayload {
+            id: event.id,
+            event_type: &event.event_type,
+            created_at: event.created_at,
+            data: &event.payload,
+        })
+        .map_err(|error| error.to_string())?;
+        let response = self
+            .agent
+            .post(&subscription.url)
+            .set("Content-Type", "application/json")
+            .set(SIGNATURE_HEADER, &sign(&subscription.secret, &body))
+            .set(EVENT_HEADER, &event.event_type)
+            .set(DELIVERY_HEADER, &delivery.id.to_string())
+            .send_bytes(&body);
+        match response {
+            Ok(response) if response.status() < 300 => Ok(()),
+            Ok(response) => Err(format!(
+                "Endpoint responded with status {}, redirects are not followed",
+                response.status()
+            )),
+            Err(ureq::Error::Status(status, _)) => {
+                Err(format!("Endpoint responded with status {}", status))
+            }
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    /// Attempt to record for the outcome of posting a delivery
+    ///
+    /// Each attempt is recorded in a statement of its own, so that outcomes recorded before a
+    /// failure are kept.
+    fn attempt(&self, delivery: &WebhookDelivery, outcome: Result<(), String>) -> DeliveryAttempt {
+        match outcome {
+            Ok(()) => DeliveryAttempt {
+                status: DELIVERY_DELIVERED,
+                next_attempt_at: None,
+                last_error: None,
+            },
+            Err(error) => match self.retry_policy.next_delay(delivery.attempts + 1) {
+                Some(delay) => DeliveryAttempt {
+                    status: DELIVERY_PENDING,
+                    next_attempt_at: Some(Utc::now() + delay),
+                    last_error: Some(error),
+                },
+                None => DeliveryAttempt {
+                    status: DELIVERY_DEAD,
+                    next_attempt_at: None,
+                    last_error: Some(error),
+                },
+            },
+        }
+    }
+
+    /// Runs the worker on its own thread, polling the database of the backend for due
+    /// deliveries at the given interval
+    #[cfg_attr(
+        not(any(feature = "postgres", feature = "sqlite")),
+        allow(unused_variables)
+    )]
+    pub fn spawn(
+        self,
+        backend: Backend,
+        database_url: String,
+        poll_interval: std::time::Duration,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || match backend {
+            #[cfg(feature = "postgres")]
+            Backend::Postgres => self.poll(&database_url, poll_interval, |connection| {
+                self.deliver_due_of_tenants(connection)
+            }),
+            #[cfg(not(feature = "postgres"))]
+            Backend::Postgres => {
+                log::error!("Webhook worker is not started: Postgres is not built in")
+            }
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => self.poll(
+                &database_url,
+                poll_interval,
+                |connection: &diesel::SqliteConnection| {
+                    crate::repository::sqlite::prepare(connection)?;
+                    self.deliver_due(connection)
+                },
+            ),
+            #[cfg(not(feature = "sqlite"))]
+            Backend::Sqlite => log::error!("Webhook worker is not started: SQLite is not built in"),
+            Backend::Memory => {
+                log::error!("Webhook worker is not started: the in-memory database has no URL")
+            }
+        })
+    }
+
+    /// Runs the worker on its own thread, polling the in-memory database for due deliveries
+    pub fn spawn_on_memory(
+        self,
+        database: MemoryDatabase,
+        poll_interval: std::time::Duration,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let repository = database.connect();
+            loop {
+                if let Err(error) = self.deliver_due(&repository) {
+                    log::error!("Webhook delivery failed: {}", error);
+                }
+                thread::sleep(poll_interval);
+            }
+        })
+    }
+
+    /// Delivers on a connection of its own, which is established again after a failure
+    #[cfg(any(feature = "postgres", feature = "sqlite"))]
+    fn poll<C, F>(&self, database_url: &str, poll_interval: std::time::Duration, deliver: F) -> !
+    where
+        C: Connection,
+        F: Fn(&C) -> QueryResult<usize>,
+    {
+        let mut connection: Option<C> = None;
+        loop {
+            if connection.is_none() {
+                connection = C::establish(database_url)
+                    .map_err(|error| log::error!("Webhook worker can not connect: {}", error))
+                    .ok();
+            }
+            if let Some(established) = &connection {
+                if let Err(error) = deliver(established) {
+                    log::error!("Webhook delivery failed: {}", error);
+                    connection = None;
+                }
+            }
+            thread::sleep(poll_interval);
+        }
+    }
+}
+
+/// Starts the delivery worker when Rocket launches
+///
+/// The worker can be disabled with the `webhook_worker` configuration value, and its polling
+/// interval is set in seconds with `webhook_poll_interval`.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_launch("Webhook delivery", |rocket| {
+        let config = rocket.config();
+        if !config.get_bool("webhook_worker").unwrap_or(true) {
+            return;
+        }
+        let poll_interval = config.get_int("webhook_poll_interval").unwrap_or(5).max(1) as u64;
+        let backend = Backend::from_rocket_config(config);
+        if let Some(database) = rocket.state::<MemoryDatabase>() {
+            DeliveryWorker::new(RetryPolicy::default()).spawn_on_memory(
+                database.clone(),
+                std::time::Duration::from_secs(poll_interval),
+            );
+            return;
+        }
+        match backend.database_url(config) {
+            Ok(url) => {
+                DeliveryWorker::new(RetryPolicy::default()).spawn(
+                    backend,
+                    url,
+                    std::time::Duration::from_secs(poll_interval),
+                );
+            }
+            Err(error) => log::error!("Webhook worker is not started: {}", error),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, DeliveryWorker, RetryPolicy, SIGNATURE_HEADER};
+    use crate::{
+        outbox::EventType,
+        repository::{EventLog, WebhookRepository},
+        test_utils::backend_test,
+        webhooks::entities::{DELIVERY_DEAD, DELIVERY_DELIVERED},
+    };
+    use chrono::Duration;
+    use diesel::QueryResult;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, ToSocketAddrs},
+        sync::mpsc,
+        thread,
+    };
+    #[cfg(feature = "postgres")]
+    use testcontainers::clients::Cli;
+
+    struct StubRequest {
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl StubRequest {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// Starts a local HTTP endpoint which answers every request with the given status
+    fn start_stub_endpoint(status: u16) -> (String, mpsc::Receiver<StubRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = Vec::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some((name, value)) => headers.push((name.to_string(), value.to_string())),
+                        None => break,
+                    }
+                }
+                let request = StubRequest {
+                    headers,
+                    body: Vec::new(),
+                };
+                let content_length = request
+                    .header("Content-Length")
+                    .map_or(0, |length| length.parse().unwrap());
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Stub\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                if sender.send(StubRequest { body, ..request }).is_err() {
+                    break;
+                }
+            }
+        });
+        (url, receiver)
+    }
+
+    /// Worker which may post to the stub endpoints on the loopback address
+    fn local_worker(retry_policy: RetryPolicy) -> DeliveryWorker {
+        DeliveryWorker::with_resolver(retry_policy, |netloc: &str| {
+            netloc.to_socket_addrs().map(Iterator::collect)
+        })
+    }
+
+    fn subscribe_and_record_event<R: EventLog + WebhookRepository>(
+        repository: &R,
+        url: &str,
+    ) -> QueryResult<()> {
+        repository.insert_webhook_subscription(
+            url,
+            &["category.created".to_string()],
+            "shared-secret",
+        )?;
+        repository.record(
+            EventType::CategoryCreated,
+            &serde_json::json!({"id": 1, "name": "shirts"}),
+        )
+    }
+
+    #[test]
+    fn signature_is_hex_encoded_hmac_sha256_of_payload() {
+        assert_eq!(
+            "sha256=03def589620c813f198fd03d7967e292b163ef0435ebf43071ce0e9519763cb7",
+            sign("secret", b"{\"id\":1}")
+        );
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_maximum_until_attempts_run_out() {
+        let retry_policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::seconds(10),
+            max_delay: Duration::seconds(60),
+        };
+
+        assert_eq!(Some(Duration::seconds(10)), retry_policy.next_delay(1));
+        assert_eq!(Some(Duration::seconds(20)), retry_policy.next_delay(2));
+        assert_eq!(Some(Duration::seconds(40)), retry_policy.next_delay(3));
+        assert_eq!(Some(Duration::seconds(60)), retry_policy.next_delay(4));
+        assert_eq!(None, retry_policy.next_delay(5));
+    }
+
+    backend_test!(
+        due_delivery_is_posted_with_signature,
+        |_client, repository| {
+            let (url, requests) = start_stub_endpoint(200);
+            subscribe_and_record_event(repository, &url)?;
+
+            let attempted = local_worker(RetryPolicy::default()).deliver_due(repository)?;
+
+            let request = requests.recv()?;
+            let delivery = repository.webhook_deliveries()?.remove(0);
+            let payload: serde_json::Value = serde_json::from_slice(&request.body)?;
+            assert_eq!(1, attempted);
+            assert_eq!(
+                Some(sign("shared-secret", &request.body).as_str()),
+                request.header(SIGNATURE_HEADER)
+            );
+            assert_eq!("category.created", payload["type"]);
+            assert_eq!("shirts", payload["data"]["name"]);
+            assert_eq!(DELIVERY_DELIVERED, delivery.status);
+            Ok(())
+        }
+    );
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn due_deliveries_of_every_tenant_are_attempted(
+    ) -> Result<(), crate::migrations::MigrationError> {
+        let docker_cli = Cli::default();
+        let database_metadata = crate::test_utils::start_database(&docker_cli);
+        let (_, connection) = crate::test_utils::start_rocket_with_db(&database_metadata)?;
+        let (url, requests) = start_stub_endpoint(200);
+        subscribe_and_record_event(&connection, &url)?;
+        crate::tenancy::clear_session_scope(&connection)?;
+
+        let attempted = local_worker(RetryPolicy::default()).deliver_due_of_tenants(&connection)?;
+
+        assert!(requests.recv().is_ok());
+        assert_eq!(1, attempted);
+        Ok(())
+    }
+
+    backend_test!(
+        claimed_delivery_is_not_attempted_again_until_its_lease_runs_out,
+        |_client, repository| {
+            let (url, _requests) = start_stub_endpoint(200);
+            subscribe_and_record_event(repository, &url)?;
+            let worker = local_worker(RetryPolicy::default());
+
+            // A worker which dies after claiming never records an attempt
+            let claimed = worker.claim_due(repository)?;
+            let attempted_during_lease = worker.deliver_due(repository)?;
+            let leased_delivery = repository.webhook_deliveries()?.remove(0);
+
+            assert_eq!(1, claimed.len());
+            assert_eq!(0, attempted_during_lease);
+            assert_eq!(0, leased_delivery.attempts);
+            assert!(leased_delivery.next_attempt_at > chrono::Utc::now() + Duration::minutes(10));
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        failed_delivery_is_retried_later_and_finally_dead_lettered,
+        |_client, repository| {
+            let (url, _requests) = start_stub_endpoint(500);
+            subscribe_and_record_event(repository, &url)?;
+            let worker = local_worker(RetryPolicy {
+                max_attempts: 2,
+                base_delay: Duration::zero(),
+                max_delay: Duration::zero(),
+            });
+
+            worker.deliver_due(repository)?;
+            let retried_delivery = repository.webhook_deliveries()?.remove(0);
+            worker.deliver_due(repository)?;
+            let dead_delivery = repository.webhook_deliveries()?.remove(0);
+            let attempted_after_death = worker.deliver_due(repository)?;
+
+            assert_eq!(1, retried_delivery.attempts);
+            assert_eq!(
+                Some("Endpoint responded with status 500"),
+                retried_delivery.last_error.as_deref()
+            );
+            assert_eq!(DELIVERY_DEAD, dead_delivery.status);
+            assert_eq!(2, dead_delivery.attempts);
+            assert_eq!(0, attempted_after_death);
+            Ok(())
+        }
+    );
+
+    backend_test!(
+        delivery_to_internal_address_is_not_posted,
+        |_client, repository| {
+            let (url, requests) = start_stub_endpoint(200);
+            subscribe_and_record_event(repository, &url)?;
+
+            DeliveryWorker::new(RetryPolicy::default()).deliver_due(repository)?;
+
+            let delivery = repository.webhook_deliveries()?.remove(0);
+            assert!(requests.try_recv().is_err());
+            assert_eq!(1, delivery.attempts);
+            assert!(delivery
+                .last_error
+                .map_or(false, |error| error.contains("not public")));
+            Ok(())
+        }
+    );
+
+    backend_test!(redirect_is_not_followed, |_client, repository| {
+        let (url, _requests) = start_stub_endpoint(302);
+        subscribe_and_record_event(repository, &url)?;
+
+        local_worker(RetryPolicy::default()).deliver_due(repository)?;
+
+        let delivery = repository.webhook_deliveries()?.remove(0);
+        assert_eq!(
+            Some("Endpoint responded with status 302, redirects are not followed"),
+            delivery.last_error.as_deref()
+        );
+        Ok(())
+    });
+}
@@ -0,0 +1,104 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+};
+use url::{Host, Url};
+
+/// Whether deliveries may be posted to the address
+///
+/// Loopback, link-local, private and unspecified addresses are refused, so that subscriptions
+/// can not reach the services next to the warehouse, like the metadata endpoint of the cloud.
+pub fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            !(address.is_loopback()
+                || address.is_link_local()
+                || address.is_private()
+                || address.is_unspecified()
+                || address.is_broadcast())
+        }
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(mapped) => is_public(IpAddr::V4(mapped)),
+            None => {
+                let first_segment = address.segments()[0];
+                let unique_local = first_segment & 0xfe00 == 0xfc00;
+                let link_local = first_segment & 0xffc0 == 0xfe80;
+                !(address.is_loopback() || address.is_unspecified() || unique_local || link_local)
+            }
+        },
+    }
+}
+
+/// Checks the URL a subscription posts its deliveries to
+///
+/// Host names are only resolved when a delivery is posted, see [`resolve_public`].
+pub fn validate_endpoint(url: &str) -> Result<(), String> {
+    let parsed =
+        Url::parse(url).map_err(|error| format!("Invalid webhook URL {}: {}", url, error))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(format!("Webhook URL must use http or https: {}", url));
+    }
+    let public = match parsed.host() {
+        Some(Host::Ipv4(address)) => is_public(IpAddr::V4(address)),
+        Some(Host::Ipv6(address)) => is_public(IpAddr::V6(address)),
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.');
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        None => false,
+    };
+    if public {
+        Ok(())
+    } else {
+        Err(format!("Webhook URL must point to a public host: {}", url))
+    }
+}
+
+/// Resolves the host of an endpoint, failing if any of its addresses is not public
+///
+/// Checking the resolved addresses, which are the ones connected to, also covers host names
+/// pointing to internal addresses.
+pub fn resolve_public(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+    if addresses.iter().all(|address| is_public(address.ip())) {
+        Ok(addresses)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} resolves to an address which is not public", netloc),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_public, validate_endpoint};
+
+    #[test]
+    fn endpoints_on_internal_addresses_are_rejected() {
+        for url in &[
+            "http://127.0.0.1/hooks",
+            "http://localhost:8080/hooks",
+            "http://169.254.169.254/latest/meta-data",
+            "https://10.0.0.7/hooks",
+            "https://192.168.1.1/hooks",
+            "http://0.0.0.0/hooks",
+            "http://[::1]/hooks",
+            "http://[::ffff:127.0.0.1]/hooks",
+            "http://[fd00::1]/hooks",
+            "ftp://erp.example.com/hooks",
+            "not a url",
+        ] {
+            assert!(validate_endpoint(url).is_err(), "{} is accepted", url);
+        }
+        assert!(validate_endpoint("https://erp.example.com/hooks").is_ok());
+        assert!(validate_endpoint("https://93.184.216.34/hooks").is_ok());
+    }
+
+    #[test]
+    fn hosts_resolving_to_internal_addresses_are_not_connected_to() {
+        assert!(resolve_public("127.0.0.1:80").is_err());
+        assert!(resolve_public("[::1]:443").is_err());
+        assert!(resolve_public("93.184.216.34:443").is_ok());
+    }
+}
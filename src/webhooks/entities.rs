@@ -0,0 +1,59 @@
+#[cfg(feature = "postgres")]
+use crate::schema::*;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Queryable, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(Identifiable),
+    table_name = "webhook_subscription"
+)]
+/// Endpoint which is notified about domain events
+///
+/// Deliveries are signed with the secret of the subscription, so that the receiver can verify
+/// that they originate from this service. The secret is never returned by the API.
+pub struct WebhookSubscription {
+    pub id: i32,
+    pub url: String,
+    pub event_types: Vec<String>,
+    #[serde(skip_serializing, default)]
+    pub secret: String,
+    pub active: bool,
+}
+
+#[derive(Queryable, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(Identifiable, Associations),
+    belongs_to(WebhookSubscription, foreign_key = "webhook_subscription_id"),
+    belongs_to(crate::outbox::entities::OutboxEvent, foreign_key = "outbox_event_id"),
+    table_name = "webhook_delivery"
+)]
+/// Delivery of one outbox event to one subscription
+///
+/// Failed deliveries are retried until they either succeed or run out of attempts, at which point
+/// they are moved to the dead state.
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub outbox_event_id: i64,
+    pub webhook_subscription_id: i32,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+/// Outcome of an attempted delivery, as it is recorded
+pub struct DeliveryAttempt {
+    pub status: &'static str,
+    /// Time of the next attempt, for deliveries which are retried
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+pub const DELIVERY_PENDING: &str = "pending";
+pub const DELIVERY_DELIVERED: &str = "delivered";
+pub const DELIVERY_DEAD: &str = "dead";
@@ -0,0 +1,7 @@
+pub mod controllers;
+pub mod delivery;
+pub mod endpoints;
+pub mod entities;
+
+pub use controllers::*;
+pub use delivery::fairing;